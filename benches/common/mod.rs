@@ -0,0 +1,100 @@
+//! Programs shared by the benchmarks.
+
+pub const FIB: &str = "
+let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };
+fib(20)
+";
+
+pub const ARITHMETIC_LOOP: &str = "
+let step = fn(i, acc) {
+    if (i == 0) { acc } else { step(i - 1, (acc + i * 3) % 1000003) }
+};
+step(1000, 0)
+";
+
+pub const NESTED_CLOSURES: &str = "
+let wrap = fn(n) {
+    if (n == 0) {
+        fn() { 0 }
+    } else {
+        let inner = wrap(n - 1);
+        fn() { inner() + 1 }
+    }
+};
+wrap(200)()
+";
+
+// Recursion from inside a closure, so that each call enters the scopes the
+// function captured.
+pub const CAPTURED_RECURSION: &str = "
+let outer = fn(a, b, c, d) {
+    let e = a + b;
+    let f = c + d;
+    let count = fn(n, acc) { if (n == 0) { acc } else { count(n - 1, acc + e * f) } };
+    count(1000, 0)
+};
+outer(1, 2, 3, 4)
+";
+
+/// Generates a program of `lines` statements exercising most of the syntax.
+/// The output depends only on `lines`, so runs are comparable.
+pub fn generate_program(lines: usize) -> String {
+    // A linear congruential generator keeps this free of a `rand` dependency.
+    let mut seed: u64 = 0x2545_f491;
+    let mut next = move |bound: u64| {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+        (seed >> 33) % bound
+    };
+
+    // Identifiers cannot contain digits, so the index is spelled in letters.
+    // The `x` prefix keeps names like `if` (85) from becoming keywords.
+    let ident = |i: usize| -> String {
+        let digits = i.to_string();
+        let letters = digits.bytes().map(|digit| char::from(b'a' + digit - b'0'));
+        std::iter::once('x').chain(letters).collect()
+    };
+    let mut source = String::new();
+    for i in 0..lines {
+        let line = match next(5) {
+            0 => format!(
+                "let {} = {} * ({} + {}) - {};",
+                ident(i),
+                next(100),
+                next(100),
+                next(100),
+                next(100)
+            ),
+            1 => format!(
+                "let {} = fn(a, b) {{ if (a < b) {{ a + {} }} else {{ b - {} }} }};",
+                ident(i),
+                next(10),
+                next(10)
+            ),
+            2 => format!(
+                "let {} = \"item {}\" + \"{}\";",
+                ident(i),
+                next(1000),
+                next(1000)
+            ),
+            3 => format!(
+                "let {} = [{}, {}, {}, !true, -{}];",
+                ident(i),
+                next(10),
+                next(10),
+                next(10),
+                next(10)
+            ),
+            _ => format!(
+                "let {} = {{\"k{}\": {}, {}: [{}]}};",
+                ident(i),
+                next(50),
+                next(50),
+                next(50),
+                next(50)
+            ),
+        };
+        source.push_str(&line);
+        source.push('\n');
+    }
+    source
+}
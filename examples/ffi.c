@@ -0,0 +1,36 @@
+/*
+ * Embeds Monkey in a C program through the `ffi` feature.
+ *
+ *   cargo build --release --features ffi
+ *   cbindgen --config cbindgen.toml --output target/monkey.h
+ *   cc examples/ffi.c -Itarget -Ltarget/release -lmonkey_rust -o target/ffi
+ *   LD_LIBRARY_PATH=target/release ./target/ffi
+ */
+
+#include <stdio.h>
+
+#include "monkey.h"
+
+int main(void) {
+    const char *programs[] = {
+        "let greet = fn(name) { \"hello, \" + name };",
+        "greet(\"c\")",
+        "1 / 0",
+    };
+
+    MonkeyInterp *interp = monkey_interp_new();
+    if (interp == NULL) {
+        fprintf(stderr, "failed to create an interpreter\n");
+        return 1;
+    }
+
+    for (size_t i = 0; i < sizeof(programs) / sizeof(programs[0]); i++) {
+        /* The result belongs to us and must be freed by the library. */
+        char *result = monkey_eval(interp, programs[i]);
+        printf("%s\n", result);
+        monkey_string_free(result);
+    }
+
+    monkey_interp_free(interp);
+    return 0;
+}
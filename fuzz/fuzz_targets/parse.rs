@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use monkey_rust::token::TokenKind;
+use monkey_rust::{Lexer, MonkeyError, Parser};
+
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data);
+
+    // Every token consumes at least one byte, so anything longer than this
+    // means the lexer has stopped making progress.
+    let mut lexer = Lexer::new(&source);
+    let mut count = 0;
+    while lexer.next_token().kind != TokenKind::Eof {
+        count += 1;
+        assert!(count <= source.len(), "lexer did not reach Eof");
+    }
+
+    let mut parser = Parser::new(Lexer::new(&source));
+    if let Err(err) = parser.parse_program() {
+        assert!(
+            err.downcast_ref::<MonkeyError>().is_some(),
+            "parse failed with a foreign error: {}",
+            err
+        );
+    }
+});
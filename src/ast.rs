@@ -1,134 +1,1676 @@
 use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::rc::Rc;
 
-#[derive(Debug, Clone)]
+use crate::interner::Symbol;
+use crate::object::format_float;
+
+/// Refers to an expression in the `Arena` of its program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExprId(u32);
+
+/// Decides, for an expression of the arena being copied from, whether to put
+/// a different expression in its place; see `Arena::copy_expr_with`.
+pub type Replace<'a> = dyn FnMut(&mut Arena, ExprId) -> Option<ExprId> + 'a;
+
+/// Refers to a statement in the `Arena` of its program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StmtId(u32);
+
+/// Owns every node of a program. Nodes refer to their children by id, so a
+/// tree is two flat vectors rather than a box per sub-expression, and the
+/// evaluator shares it between function values by reference count instead of
+/// cloning bodies.
+///
+/// Ids are used instead of references into a bump allocator like `bumpalo`,
+/// which would give every node, and every function value holding a body, the
+/// lifetime of the arena. The REPL keeps functions from one line alive after
+/// that line's `Program` is dropped, which an `Rc<Arena>` allows.
+///
+/// Ids are only meaningful in the arena that handed them out; comparing nodes
+/// compares the ids of their children, not the children. Compare `Program`s,
+/// or use `Arena::expr_eq`, to compare structure.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Arena {
+    expressions: Vec<Expression>,
+    statements: Vec<Statement>,
+    // Where each statement starts, for statements that were parsed.
+    statement_spans: Vec<Span>,
+    source: Option<Rc<Source>>,
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alloc_expr(&mut self, expr: Expression) -> ExprId {
+        self.expressions.push(expr);
+        ExprId(self.expressions.len() as u32 - 1)
+    }
+
+    pub fn alloc_stmt(&mut self, stmt: Statement) -> StmtId {
+        self.statements.push(stmt);
+        self.statement_spans.push(Span::default());
+        StmtId(self.statements.len() as u32 - 1)
+    }
+
+    pub fn expr(&self, id: ExprId) -> &Expression {
+        &self.expressions[id.0 as usize]
+    }
+
+    pub fn stmt(&self, id: StmtId) -> &Statement {
+        &self.statements[id.0 as usize]
+    }
+
+    /// Where the statement `id` starts in the source. Statements built rather
+    /// than parsed start at offset 0.
+    pub fn stmt_span(&self, id: StmtId) -> Span {
+        self.statement_spans[id.0 as usize]
+    }
+
+    pub fn set_stmt_span(&mut self, id: StmtId, span: Span) {
+        self.statement_spans[id.0 as usize] = span;
+    }
+
+    /// The text the arena was parsed from, if known, which its spans are
+    /// offsets into.
+    pub fn source(&self) -> Option<&Rc<Source>> {
+        self.source.as_ref()
+    }
+
+    pub fn set_source(&mut self, source: Source) {
+        self.source = Some(Rc::new(source));
+    }
+
+    /// Every statement in the arena, in the order they were allocated.
+    pub fn statement_ids(&self) -> impl Iterator<Item = StmtId> {
+        (0..self.statements.len() as u32).map(StmtId)
+    }
+
+    /// Renders `node` fully parenthesized, like `Display` for `Program`.
+    pub fn display<T: Render>(&self, node: T) -> Show<'_, T> {
+        Show {
+            arena: self,
+            node,
+            pretty: false,
+        }
+    }
+
+    /// Renders `node` with only the parentheses needed to preserve its
+    /// meaning, e.g. `1 + 2 * 3` rather than `(1 + (2 * 3))`. Strings are
+    /// quoted, so the output parses back to the same tree.
+    pub fn pretty<T: Render>(&self, node: T) -> Show<'_, T> {
+        Show {
+            arena: self,
+            node,
+            pretty: true,
+        }
+    }
+
+    /// Copies the expression `id` of `from`, and everything under it, into
+    /// this arena.
+    pub fn copy_expr(&mut self, from: &Arena, id: ExprId) -> ExprId {
+        self.copy_expr_with(from, &mut |_, _| None, id)
+    }
+
+    /// Copies the statement `id` of `from`, and everything under it, into this
+    /// arena.
+    pub fn copy_stmt(&mut self, from: &Arena, id: StmtId) -> StmtId {
+        self.copy_stmt_with(from, &mut |_, _| None, id)
+    }
+
+    /// Copies the statements of `block`, which belongs to `from`, into this
+    /// arena.
+    pub fn copy_block(&mut self, from: &Arena, block: &BlockStatement) -> BlockStatement {
+        self.copy_block_with(from, &mut |_, _| None, block)
+    }
+
+    /// Like `copy_expr`, but each expression of `from` for which `replace`
+    /// returns an expression of this arena is replaced by it instead of being
+    /// copied.
+    pub fn copy_expr_with(
+        &mut self,
+        from: &Arena,
+        replace: &mut Replace<'_>,
+        id: ExprId,
+    ) -> ExprId {
+        if let Some(replacement) = replace(self, id) {
+            return replacement;
+        }
+        let expr = match &from[id] {
+            Expression::Spread(array) => {
+                Expression::Spread(self.copy_expr_with(from, replace, *array))
+            }
+            Expression::Prefix { op, right } => Expression::Prefix {
+                op: op.clone(),
+                right: self.copy_expr_with(from, replace, *right),
+            },
+            Expression::Infix {
+                left,
+                op,
+                right,
+                span,
+            } => Expression::Infix {
+                left: self.copy_expr_with(from, replace, *left),
+                op: op.clone(),
+                right: self.copy_expr_with(from, replace, *right),
+                span: *span,
+            },
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => Expression::If {
+                condition: self.copy_expr_with(from, replace, *condition),
+                consequence: self.copy_block_with(from, replace, consequence),
+                alternative: alternative
+                    .as_ref()
+                    .map(|block| self.copy_block_with(from, replace, block)),
+            },
+            Expression::Function { parameters, body } => Expression::Function {
+                parameters: parameters.clone(),
+                body: self.copy_block_with(from, replace, body),
+            },
+            Expression::Macro { parameters, body } => Expression::Macro {
+                parameters: parameters.clone(),
+                body: self.copy_block_with(from, replace, body),
+            },
+            Expression::Call {
+                function,
+                arguments,
+            } => Expression::Call {
+                function: self.copy_expr_with(from, replace, *function),
+                arguments: arguments
+                    .iter()
+                    .map(|arg| self.copy_expr_with(from, replace, *arg))
+                    .collect(),
+            },
+            Expression::Index { left, index } => Expression::Index {
+                left: self.copy_expr_with(from, replace, *left),
+                index: self.copy_expr_with(from, replace, *index),
+            },
+            Expression::Slice { target, start, end } => Expression::Slice {
+                target: self.copy_expr_with(from, replace, *target),
+                start: start.map(|start| self.copy_expr_with(from, replace, start)),
+                end: end.map(|end| self.copy_expr_with(from, replace, end)),
+            },
+            Expression::Array(elements) => Expression::Array(
+                elements
+                    .iter()
+                    .map(|element| self.copy_expr_with(from, replace, *element))
+                    .collect(),
+            ),
+            Expression::Hash(pairs) => Expression::Hash(
+                pairs
+                    .iter()
+                    .map(|(key, value)| {
+                        (
+                            self.copy_expr_with(from, replace, *key),
+                            self.copy_expr_with(from, replace, *value),
+                        )
+                    })
+                    .collect(),
+            ),
+            Expression::Block(block) => {
+                Expression::Block(self.copy_block_with(from, replace, block))
+            }
+            Expression::Try {
+                body,
+                error,
+                handler,
+            } => Expression::Try {
+                body: self.copy_block_with(from, replace, body),
+                error: *error,
+                handler: self.copy_block_with(from, replace, handler),
+            },
+            Expression::Loop(body) => Expression::Loop(self.copy_block_with(from, replace, body)),
+            Expression::While { condition, body } => Expression::While {
+                condition: self.copy_expr_with(from, replace, *condition),
+                body: self.copy_block_with(from, replace, body),
+            },
+            leaf => leaf.clone(),
+        };
+        self.alloc_expr(expr)
+    }
+
+    /// Like `copy_stmt`, replacing expressions the way `copy_expr_with` does.
+    pub fn copy_stmt_with(
+        &mut self,
+        from: &Arena,
+        replace: &mut Replace<'_>,
+        id: StmtId,
+    ) -> StmtId {
+        let stmt = match &from[id] {
+            Statement::Let { ident, value } => Statement::Let {
+                ident: self.copy_expr_with(from, replace, *ident),
+                value: self.copy_expr_with(from, replace, *value),
+            },
+            Statement::Const { ident, value } => Statement::Const {
+                ident: self.copy_expr_with(from, replace, *ident),
+                value: self.copy_expr_with(from, replace, *value),
+            },
+            Statement::Return(expr) => Statement::Return(self.copy_expr_with(from, replace, *expr)),
+            Statement::Break(value) => {
+                Statement::Break(value.map(|value| self.copy_expr_with(from, replace, value)))
+            }
+            Statement::Continue => Statement::Continue,
+            Statement::Expression(expr) => {
+                Statement::Expression(self.copy_expr_with(from, replace, *expr))
+            }
+            Statement::Import(path) => Statement::Import(path.clone()),
+            Statement::Test { name, body } => Statement::Test {
+                name: name.clone(),
+                body: self.copy_block_with(from, replace, body),
+            },
+        };
+        let copy = self.alloc_stmt(stmt);
+        self.set_stmt_span(copy, from.stmt_span(id));
+        copy
+    }
+
+    fn copy_block_with(
+        &mut self,
+        from: &Arena,
+        replace: &mut Replace<'_>,
+        block: &BlockStatement,
+    ) -> BlockStatement {
+        BlockStatement {
+            statements: block
+                .statements
+                .iter()
+                .map(|stmt| self.copy_stmt_with(from, replace, *stmt))
+                .collect(),
+        }
+    }
+
+    /// Whether the expression `id` has the same structure as the expression
+    /// `other_id` of `other`, which may be a different arena.
+    pub fn expr_eq(&self, id: ExprId, other: &Arena, other_id: ExprId) -> bool {
+        Equivalence(self, other).exprs(id, other_id)
+    }
+}
+
+impl Index<ExprId> for Arena {
+    type Output = Expression;
+
+    fn index(&self, id: ExprId) -> &Expression {
+        self.expr(id)
+    }
+}
+
+impl IndexMut<ExprId> for Arena {
+    fn index_mut(&mut self, id: ExprId) -> &mut Expression {
+        &mut self.expressions[id.0 as usize]
+    }
+}
+
+impl Index<StmtId> for Arena {
+    type Output = Statement;
+
+    fn index(&self, id: StmtId) -> &Statement {
+        self.stmt(id)
+    }
+}
+
+impl IndexMut<StmtId> for Arena {
+    fn index_mut(&mut self, id: StmtId) -> &mut Statement {
+        &mut self.statements[id.0 as usize]
+    }
+}
+
+// Compares nodes of two arenas by structure.
+struct Equivalence<'a>(&'a Arena, &'a Arena);
+
+impl Equivalence<'_> {
+    fn exprs(&self, a: ExprId, b: ExprId) -> bool {
+        match (&self.0[a], &self.1[b]) {
+            (Expression::Prefix { op, right }, Expression::Prefix { op: o, right: r }) => {
+                op == o && self.exprs(*right, *r)
+            }
+            (
+                Expression::Infix {
+                    left, op, right, ..
+                },
+                Expression::Infix {
+                    left: l,
+                    op: o,
+                    right: r,
+                    ..
+                },
+            ) => op == o && self.exprs(*left, *l) && self.exprs(*right, *r),
+            (
+                Expression::If {
+                    condition,
+                    consequence,
+                    alternative,
+                },
+                Expression::If {
+                    condition: c,
+                    consequence: cons,
+                    alternative: alt,
+                },
+            ) => {
+                self.exprs(*condition, *c)
+                    && self.blocks(consequence, cons)
+                    && match (alternative, alt) {
+                        (Some(a), Some(b)) => self.blocks(a, b),
+                        (None, None) => true,
+                        _ => false,
+                    }
+            }
+            (
+                Expression::Function { parameters, body },
+                Expression::Function {
+                    parameters: p,
+                    body: b,
+                },
+            ) => parameters == p && self.blocks(body, b),
+            (
+                Expression::Macro { parameters, body },
+                Expression::Macro {
+                    parameters: p,
+                    body: b,
+                },
+            ) => parameters == p && self.blocks(body, b),
+            (
+                Expression::Call {
+                    function,
+                    arguments,
+                },
+                Expression::Call {
+                    function: f,
+                    arguments: args,
+                },
+            ) => self.exprs(*function, *f) && self.lists(arguments, args),
+            (Expression::Index { left, index }, Expression::Index { left: l, index: i }) => {
+                self.exprs(*left, *l) && self.exprs(*index, *i)
+            }
+            (
+                Expression::Slice { target, start, end },
+                Expression::Slice {
+                    target: t,
+                    start: s,
+                    end: e,
+                },
+            ) => self.exprs(*target, *t) && self.options(*start, *s) && self.options(*end, *e),
+            (Expression::Array(a), Expression::Array(b)) => self.lists(a, b),
+            (Expression::Hash(a), Expression::Hash(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b)
+                        .all(|((k, v), (l, w))| self.exprs(*k, *l) && self.exprs(*v, *w))
+            }
+            (Expression::Block(a), Expression::Block(b)) => self.blocks(a, b),
+            (
+                Expression::Try {
+                    body,
+                    error,
+                    handler,
+                },
+                Expression::Try {
+                    body: b,
+                    error: e,
+                    handler: h,
+                },
+            ) => error == e && self.blocks(body, b) && self.blocks(handler, h),
+            (Expression::Loop(a), Expression::Loop(b)) => self.blocks(a, b),
+            (
+                Expression::While { condition, body },
+                Expression::While {
+                    condition: c,
+                    body: b,
+                },
+            ) => self.exprs(*condition, *c) && self.blocks(body, b),
+            (Expression::Spread(a), Expression::Spread(b)) => self.exprs(*a, *b),
+            // Leaves hold no ids, and spans always compare equal.
+            (a, b) => a == b,
+        }
+    }
+
+    fn stmts(&self, a: StmtId, b: StmtId) -> bool {
+        match (&self.0[a], &self.1[b]) {
+            (Statement::Let { ident, value }, Statement::Let { ident: i, value: v })
+            | (Statement::Const { ident, value }, Statement::Const { ident: i, value: v }) => {
+                self.exprs(*ident, *i) && self.exprs(*value, *v)
+            }
+            (Statement::Return(a), Statement::Return(b))
+            | (Statement::Expression(a), Statement::Expression(b)) => self.exprs(*a, *b),
+            (Statement::Break(a), Statement::Break(b)) => self.options(*a, *b),
+            (Statement::Continue, Statement::Continue) => true,
+            (Statement::Import(a), Statement::Import(b)) => a == b,
+            (Statement::Test { name, body }, Statement::Test { name: n, body: b }) => {
+                name == n && self.blocks(body, b)
+            }
+            _ => false,
+        }
+    }
+
+    fn blocks(&self, a: &BlockStatement, b: &BlockStatement) -> bool {
+        self.stmt_lists(&a.statements, &b.statements)
+    }
+
+    fn options(&self, a: Option<ExprId>, b: Option<ExprId>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => self.exprs(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    fn lists(&self, a: &[ExprId], b: &[ExprId]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| self.exprs(*a, *b))
+    }
+
+    fn stmt_lists(&self, a: &[StmtId], b: &[StmtId]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| self.stmts(*a, *b))
+    }
+}
+
+/// A parsed program: its top-level statements and the arena that holds them.
+/// The arena is shared with the function values evaluating it creates.
+#[derive(Debug, Clone, Default)]
 pub struct Program {
-    pub statements: Vec<Statement>,
+    pub arena: Rc<Arena>,
+    pub statements: Vec<StmtId>,
+}
+
+impl Program {
+    /// The arena for changing the tree, copied first if it is shared.
+    pub fn arena_mut(&mut self) -> &mut Arena {
+        Rc::make_mut(&mut self.arena)
+    }
+
+    pub fn expr(&self, id: ExprId) -> &Expression {
+        self.arena.expr(id)
+    }
+
+    pub fn stmt(&self, id: StmtId) -> &Statement {
+        self.arena.stmt(id)
+    }
+
+    /// The top-level statement at `index`.
+    pub fn statement(&self, index: usize) -> &Statement {
+        self.stmt(self.statements[index])
+    }
+
+    /// See `Arena::pretty`.
+    pub fn pretty(&self) -> Show<'_, &[StmtId]> {
+        self.arena.pretty(&self.statements[..])
+    }
+}
+
+/// Programs are equal when their trees have the same structure, whatever
+/// arenas they are in.
+impl PartialEq for Program {
+    fn eq(&self, other: &Program) -> bool {
+        Equivalence(&self.arena, &other.arena).stmt_lists(&self.statements, &other.statements)
+    }
 }
 
 impl fmt::Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for stmt in self.statements.iter() {
-            writeln!(f, "{}", stmt)?;
-        }
-        Ok(())
+        write!(f, "{}", self.arena.display(&self.statements[..]))
+    }
+}
+
+// An expression statement needs a `;` before whatever follows it, or `a` and
+// `(b)` would parse back as the call `a(b)`, and `a` and `[b]` as `a[b]`.
+fn terminator(stmt: &Statement) -> &'static str {
+    match stmt {
+        Statement::Expression(_) => ";",
+        _ => "",
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
+    /// `ident` is an `Ident`, or a pattern when the value is destructured: an
+    /// `Array` of patterns, as in `let [a, [b, c]] = value`, or a `Hash` from
+    /// string literals to patterns, as in `let {a, "b": [c]} = value`.
     Let {
-        ident: Expression,
-        value: Expression,
+        ident: ExprId,
+        value: ExprId,
+    },
+    /// `const ident = value`, where `ident` is always an `Ident`.
+    Const {
+        ident: ExprId,
+        value: ExprId,
+    },
+    Return(ExprId),
+    /// `break`, with the value of the loop if one is given.
+    Break(Option<ExprId>),
+    Continue,
+    Expression(ExprId),
+    Import(String),
+    /// `test "name" { ... }`, which only the test `runner` evaluates.
+    Test {
+        name: String,
+        body: BlockStatement,
     },
-    Return(Expression),
-    Expression(Expression),
 }
 
-impl fmt::Display for Statement {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Statement::Let { ident, value } => write!(f, "let {} = {};", ident, value),
-            Statement::Return(expr) => write!(f, "return {};", expr),
-            Statement::Expression(expr) => write!(f, "{}", expr),
-        }
+/// The text a tree was parsed from, and what to call it when reporting an
+/// error raised in it while another program is being run, like the path of
+/// an imported file.
+///
+/// Sources are ignored when comparing arenas, like spans.
+#[derive(Debug)]
+pub struct Source {
+    pub text: String,
+    pub name: Option<String>,
+}
+
+impl PartialEq for Source {
+    fn eq(&self, _: &Source) -> bool {
+        true
+    }
+}
+
+/// Where a node starts in the source, as a byte offset.
+///
+/// Spans are ignored when comparing nodes, so that a tree equals the same tree
+/// parsed from differently laid out text, or built by hand.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+}
+
+impl Span {
+    pub fn new(start: usize) -> Self {
+        Self { start }
+    }
+
+    /// The line and column of the span in `source`, both counted from one.
+    /// Columns count characters, not bytes.
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start.min(source.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line = before.matches('\n').count() + 1;
+        (line, before[line_start..].chars().count() + 1)
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _: &Span) -> bool {
+        true
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
-    Ident(String),
+    /// An identifier and where it appears.
+    Ident(Symbol, Span),
     Int(i64),
+    Float(f64),
+    String(String),
     Boolean(bool),
     Prefix {
         op: String,
-        right: Box<Expression>,
+        right: ExprId,
     },
+    /// A binary operation, with the span of its operator.
     Infix {
-        left: Box<Expression>,
+        left: ExprId,
         op: String,
-        right: Box<Expression>,
+        right: ExprId,
+        span: Span,
     },
     If {
-        condition: Box<Expression>,
+        condition: ExprId,
         consequence: BlockStatement,
         alternative: Option<BlockStatement>,
     },
     Function {
-        parameters: Vec<String>,
+        parameters: Vec<Symbol>,
+        body: BlockStatement,
+    },
+    /// `macro(parameters) { body }`, defined and expanded before the program
+    /// is evaluated.
+    Macro {
+        parameters: Vec<Symbol>,
         body: BlockStatement,
     },
     Call {
-        function: Box<Expression>,
-        arguments: Vec<Expression>,
+        function: ExprId,
+        arguments: Vec<ExprId>,
+    },
+    /// `left[index]`.
+    Index {
+        left: ExprId,
+        index: ExprId,
+    },
+    /// `target[start..end]`, where either bound may be left out.
+    Slice {
+        target: ExprId,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    },
+    Array(Vec<ExprId>),
+    Hash(Vec<(ExprId, ExprId)>),
+    Block(BlockStatement),
+    /// `try { body } catch (error) { handler }`.
+    Try {
+        body: BlockStatement,
+        error: Symbol,
+        handler: BlockStatement,
+    },
+    /// `loop { body }`, which runs until a `break`.
+    Loop(BlockStatement),
+    /// `while (condition) { body }`, which evaluates to the last value other
+    /// than null the body evaluated to.
+    While {
+        condition: ExprId,
+        body: BlockStatement,
     },
+    /// `...array` among the elements of an array literal or the arguments of
+    /// a call, which stands for the elements of the array.
+    Spread(ExprId),
+    /// `import("path")`, which evaluates to a hash of the top-level bindings
+    /// of the file at `path`. The span is that of `import`.
+    Import(String, Span),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockStatement {
+    pub statements: Vec<StmtId>,
+}
+
+impl BlockStatement {
+    /// Writes the block over several lines: each statement on its own, as
+    /// `Arena::pretty` shows it, indented four spaces for each of `indent + 1`
+    /// levels, and the closing brace indented `indent` levels.
+    pub fn fmt_indented(
+        &self,
+        arena: &Arena,
+        f: &mut fmt::Formatter<'_>,
+        indent: usize,
+    ) -> fmt::Result {
+        if self.statements.is_empty() {
+            return write!(f, "{{}}");
+        }
+        writeln!(f, "{{")?;
+        for (i, stmt) in self.statements.iter().enumerate() {
+            write!(f, "{}", "    ".repeat(indent + 1))?;
+            arena[*stmt].render(arena, true, f)?;
+            if i + 1 < self.statements.len() {
+                write!(f, "{}", terminator(&arena[*stmt]))?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "{}}}", "    ".repeat(indent))
+    }
+}
+
+#[derive(Debug, PartialEq, PartialOrd)]
+pub enum Precedence {
+    Lowest,
+    Equals,
+    Lessgreater,
+    Sum,
+    Product,
+    Prefix,
+    Call,
+}
+
+impl Precedence {
+    fn of_infix(op: &str) -> Precedence {
+        match op {
+            "==" | "!=" => Precedence::Equals,
+            "<" | ">" => Precedence::Lessgreater,
+            "+" | "-" => Precedence::Sum,
+            "*" | "/" | "%" => Precedence::Product,
+            _ => Precedence::Lowest,
+        }
+    }
+
+    fn of(expr: &Expression) -> Precedence {
+        match expr {
+            Expression::Infix { op, .. } => Precedence::of_infix(op),
+            Expression::Prefix { .. } => Precedence::Prefix,
+            _ => Precedence::Call,
+        }
+    }
+}
+
+/// A node that can be printed given the arena its children are in.
+fn join_names(names: &[Symbol]) -> String {
+    names
+        .iter()
+        .map(|name| name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
-impl fmt::Display for Expression {
+pub trait Render {
+    fn render(&self, arena: &Arena, pretty: bool, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+}
+
+/// A node paired with its arena for printing, made by `Arena::display` or
+/// `Arena::pretty`.
+pub struct Show<'a, T> {
+    arena: &'a Arena,
+    node: T,
+    pretty: bool,
+}
+
+impl<T: Render> fmt::Display for Show<'_, T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Expression::Ident(value) => write!(f, "{}", value),
-            Expression::Int(value) => write!(f, "{}", value),
-            Expression::Boolean(value) => write!(f, "{}", value),
-            Expression::Prefix { op, right } => write!(f, "({}{})", op, right),
-            Expression::Infix { left, op, right } => write!(f, "({} {} {})", left, op, right),
-            Expression::If {
-                condition,
-                consequence,
-                alternative,
-            } => {
-                write!(f, "if {} {{ {} }}", condition, consequence)?;
-                match alternative {
-                    Some(statements) => write!(f, "else {{ {} }}", statements)?,
-                    None => (),
-                };
+        self.node.render(self.arena, self.pretty, f)
+    }
+}
 
-                Ok(())
+impl<T: Render> fmt::Debug for Show<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl Render for ExprId {
+    fn render(&self, arena: &Arena, pretty: bool, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        arena[*self].render(arena, pretty, f)
+    }
+}
+
+impl Render for StmtId {
+    fn render(&self, arena: &Arena, pretty: bool, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        arena[*self].render(arena, pretty, f)
+    }
+}
+
+/// The top-level statements of a program, one per line.
+impl Render for &[StmtId] {
+    fn render(&self, arena: &Arena, pretty: bool, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for stmt in self.iter() {
+            let show = Show {
+                arena,
+                node: *stmt,
+                pretty,
+            };
+            writeln!(f, "{}{}", show, terminator(&arena[*stmt]))?;
+        }
+        Ok(())
+    }
+}
+
+impl Render for &Statement {
+    fn render(&self, arena: &Arena, pretty: bool, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (*self).render(arena, pretty, f)
+    }
+}
+
+impl Render for Statement {
+    fn render(&self, arena: &Arena, pretty: bool, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |id: &ExprId| Show {
+            arena,
+            node: *id,
+            pretty,
+        };
+        match self {
+            Statement::Let { ident, value } => {
+                write!(f, "let {} = {};", show(ident), show(value))
+            }
+            Statement::Const { ident, value } => {
+                write!(f, "const {} = {};", show(ident), show(value))
             }
-            Expression::Function { parameters, body } => {
-                let params = parameters.clone().join(", ");
+            Statement::Return(expr) => write!(f, "return {};", show(expr)),
+            Statement::Break(Some(value)) => write!(f, "break {};", show(value)),
+            Statement::Break(None) => write!(f, "break;"),
+            Statement::Continue => write!(f, "continue;"),
+            Statement::Expression(expr) => write!(f, "{}", show(expr)),
+            Statement::Import(path) => write!(f, "import \"{}\";", path),
+            Statement::Test { name, body } => {
+                write!(f, "test \"{}\" ", name)?;
+                body.render(arena, pretty, f)
+            }
+        }
+    }
+}
+
+impl Render for &BlockStatement {
+    fn render(&self, arena: &Arena, pretty: bool, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (*self).render(arena, pretty, f)
+    }
+}
 
-                write!(f, "fn ({}) {{ {} }}", params, body)?;
-                Ok(())
+/// The statements of a block between braces, as in `{ let x = 1; x }`, or
+/// `{}` when there are none.
+impl Render for BlockStatement {
+    fn render(&self, arena: &Arena, pretty: bool, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.statements.is_empty() {
+            return write!(f, "{{}}");
+        }
+        write!(f, "{{")?;
+        for (i, stmt) in self.statements.iter().enumerate() {
+            write!(f, " ")?;
+            arena[*stmt].render(arena, pretty, f)?;
+            if i + 1 < self.statements.len() {
+                write!(f, "{}", terminator(&arena[*stmt]))?;
             }
-            Expression::Call {
-                function,
-                arguments,
-            } => {
-                let mut args = vec![];
-                for a in arguments {
-                    args.push(a.to_string());
+        }
+        write!(f, " }}")
+    }
+}
+
+impl Render for &Expression {
+    fn render(&self, arena: &Arena, pretty: bool, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (*self).render(arena, pretty, f)
+    }
+}
+
+impl Render for Expression {
+    fn render(&self, arena: &Arena, pretty: bool, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if pretty {
+            render_pretty(self, arena, f)
+        } else {
+            render_full(self, arena, f)
+        }
+    }
+}
+
+fn render_full(expr: &Expression, arena: &Arena, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let show = |id: &ExprId| arena.display(*id);
+    match expr {
+        Expression::Ident(value, _) => write!(f, "{}", value),
+        Expression::Int(value) => write!(f, "{}", value),
+        Expression::Float(value) => write!(f, "{}", format_float(*value)),
+        Expression::String(value) => write!(f, "\"{}\"", value),
+        Expression::Boolean(value) => write!(f, "{}", value),
+        Expression::Import(path, _) => write!(f, "import(\"{}\")", path),
+        Expression::Prefix { op, right } => write!(f, "({}{})", op, show(right)),
+        Expression::Spread(array) => write!(f, "...{}", show(array)),
+        Expression::Infix {
+            left, op, right, ..
+        } => write!(f, "({} {} {})", show(left), op, show(right)),
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            match arena[*condition] {
+                Expression::Prefix { .. } | Expression::Infix { .. } => {
+                    write!(f, "if {} {}", show(condition), arena.display(consequence))?
                 }
-                let args = args.join(", ");
+                _ => write!(f, "if ({}) {}", show(condition), arena.display(consequence))?,
+            }
+            if let Some(statements) = alternative {
+                write!(f, " else {}", arena.display(statements))?;
+            }
+
+            Ok(())
+        }
+        Expression::Function { parameters, body } => {
+            let params = join_names(parameters);
 
-                write!(f, "{}({})", function.to_string(), args)?;
+            write!(f, "fn ({}) {}", params, arena.display(body))?;
+            Ok(())
+        }
+        Expression::Macro { parameters, body } => {
+            let params = join_names(parameters);
 
-                Ok(())
+            write!(f, "macro ({}) {}", params, arena.display(body))?;
+            Ok(())
+        }
+        Expression::Call {
+            function,
+            arguments,
+        } => {
+            let mut args = vec![];
+            for a in arguments {
+                args.push(show(a).to_string());
             }
+            let args = args.join(", ");
+
+            write!(f, "{}({})", show(function), args)?;
+
+            Ok(())
+        }
+        Expression::Index { left, index } => write!(f, "({}[{}])", show(left), show(index)),
+        Expression::Slice { target, start, end } => {
+            let bound = |id: &Option<ExprId>| id.map_or(String::new(), |id| show(&id).to_string());
+            write!(f, "({}[{}..{}])", show(target), bound(start), bound(end))
+        }
+        Expression::Array(elements) => {
+            let elements = elements
+                .iter()
+                .map(|e| show(e).to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            write!(f, "[{}]", elements)
+        }
+        Expression::Hash(pairs) => {
+            let pairs = pairs
+                .iter()
+                .map(|(key, value)| format!("{}: {}", show(key), show(value)))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            write!(f, "{{{}}}", pairs)
+        }
+        Expression::Block(block) => write!(f, "{}", arena.display(block)),
+        Expression::Try {
+            body,
+            error,
+            handler,
+        } => write!(
+            f,
+            "try {} catch ({}) {}",
+            arena.display(body),
+            error,
+            arena.display(handler)
+        ),
+        Expression::Loop(body) => write!(f, "loop {}", arena.display(body)),
+        Expression::While { condition, body } => {
+            write!(f, "while ({}) {}", show(condition), arena.display(body))
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct BlockStatement {
-    pub statements: Vec<Statement>,
+fn render_pretty(expr: &Expression, arena: &Arena, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let show = |id: &ExprId| arena.pretty(*id);
+    let operand = |f: &mut fmt::Formatter<'_>, id: &ExprId, parenthesize: bool| {
+        if parenthesize {
+            write!(f, "({})", show(id))
+        } else {
+            write!(f, "{}", show(id))
+        }
+    };
+    match expr {
+        Expression::String(value) => write!(f, "\"{}\"", value),
+        Expression::Spread(array) => write!(f, "...{}", show(array)),
+        Expression::Prefix { op, right } => {
+            write!(f, "{}", op)?;
+            operand(
+                f,
+                right,
+                Precedence::of(&arena[*right]) < Precedence::Prefix,
+            )
+        }
+        Expression::Infix {
+            left, op, right, ..
+        } => {
+            let precedence = Precedence::of_infix(op);
+            operand(f, left, Precedence::of(&arena[*left]) < precedence)?;
+            write!(f, " {} ", op)?;
+            // operators are left-associative, so an equal right operand
+            // needs parentheses too
+            operand(f, right, Precedence::of(&arena[*right]) <= precedence)
+        }
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            write!(f, "if ({}) {}", show(condition), arena.pretty(consequence))?;
+            if let Some(alternative) = alternative {
+                write!(f, " else {}", arena.pretty(alternative))?;
+            }
+            Ok(())
+        }
+        Expression::Function { parameters, body } => {
+            write!(f, "fn({}) {}", join_names(parameters), arena.pretty(body))
+        }
+        Expression::Macro { parameters, body } => {
+            write!(
+                f,
+                "macro({}) {}",
+                join_names(parameters),
+                arena.pretty(body)
+            )
+        }
+        Expression::Call {
+            function,
+            arguments,
+        } => {
+            operand(
+                f,
+                function,
+                Precedence::of(&arena[*function]) < Precedence::Call,
+            )?;
+            write!(f, "({})", pretty_list(arena, arguments))
+        }
+        Expression::Index { left, index } => {
+            operand(f, left, Precedence::of(&arena[*left]) < Precedence::Call)?;
+            write!(f, "[{}]", show(index))
+        }
+        Expression::Slice { target, start, end } => {
+            operand(
+                f,
+                target,
+                Precedence::of(&arena[*target]) < Precedence::Call,
+            )?;
+            write!(f, "[")?;
+            if let Some(start) = start {
+                write!(f, "{}", show(start))?;
+            }
+            write!(f, "..")?;
+            if let Some(end) = end {
+                write!(f, "{}", show(end))?;
+            }
+            write!(f, "]")
+        }
+        Expression::Array(elements) => write!(f, "[{}]", pretty_list(arena, elements)),
+        Expression::Hash(pairs) => {
+            let pairs = pairs
+                .iter()
+                .map(|(key, value)| format!("{}: {}", show(key), show(value)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            write!(f, "{{{}}}", pairs)
+        }
+        Expression::Block(block) => write!(f, "{}", arena.pretty(block)),
+        Expression::Try {
+            body,
+            error,
+            handler,
+        } => write!(
+            f,
+            "try {} catch ({}) {}",
+            arena.pretty(body),
+            error,
+            arena.pretty(handler)
+        ),
+        Expression::Loop(body) => write!(f, "loop {}", arena.pretty(body)),
+        Expression::While { condition, body } => {
+            write!(f, "while ({}) {}", show(condition), arena.pretty(body))
+        }
+        Expression::Ident(..)
+        | Expression::Int(_)
+        | Expression::Float(_)
+        | Expression::Boolean(_)
+        | Expression::Import(..) => render_full(expr, arena, f),
+    }
 }
 
-impl fmt::Display for BlockStatement {
+fn pretty_list(arena: &Arena, exprs: &[ExprId]) -> String {
+    exprs
+        .iter()
+        .map(|expr| arena.pretty(*expr).to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A reference to either kind of node, as passed to an evaluation hook.
+#[derive(Clone, Copy)]
+pub enum Node<'a> {
+    Statement(&'a Arena, StmtId),
+    Expression(&'a Arena, ExprId),
+}
+
+impl fmt::Display for Node<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for stmt in self.statements.iter() {
-            write!(f, "{}", stmt)?;
+        match self {
+            Node::Statement(arena, stmt) => write!(f, "{}", arena.display(*stmt)),
+            Node::Expression(arena, expr) => write!(f, "{}", arena.display(*expr)),
         }
-        Ok(())
     }
 }
 
-#[derive(Debug, PartialEq, PartialOrd)]
-pub enum Precedence {
-    Lowest,
-    Equals,
-    Lessgreater,
-    Sum,
-    Product,
-    Prefix,
-    Call,
+/// Read-only traversal hooks. Every hook is empty by default; the `walk_*`
+/// functions drive the traversal and call the hooks in pre-order: a node's hook
+/// runs before its children are walked, and children are walked in source
+/// order (e.g. `left` before `right`, a callee before its arguments, a hash key
+/// before its value).
+pub trait Visitor {
+    fn visit_statement(&mut self, _stmt: &Statement) {}
+    fn visit_expression(&mut self, _expr: &Expression) {}
+    fn visit_block(&mut self, _block: &BlockStatement) {}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for stmt in program.statements.iter() {
+        walk_statement(visitor, &program.arena, *stmt);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, arena: &Arena, stmt: StmtId) {
+    let stmt = &arena[stmt];
+    visitor.visit_statement(stmt);
+    match stmt {
+        Statement::Let { ident, value } | Statement::Const { ident, value } => {
+            walk_expression(visitor, arena, *ident);
+            walk_expression(visitor, arena, *value);
+        }
+        Statement::Return(expr) | Statement::Break(Some(expr)) | Statement::Expression(expr) => {
+            walk_expression(visitor, arena, *expr)
+        }
+        Statement::Break(None) | Statement::Continue | Statement::Import(_) => (),
+        Statement::Test { body, .. } => walk_block(visitor, arena, body),
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, arena: &Arena, block: &BlockStatement) {
+    visitor.visit_block(block);
+    for stmt in block.statements.iter() {
+        walk_statement(visitor, arena, *stmt);
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, arena: &Arena, expr: ExprId) {
+    let expr = &arena[expr];
+    visitor.visit_expression(expr);
+    match expr {
+        Expression::Ident(..)
+        | Expression::Int(_)
+        | Expression::Float(_)
+        | Expression::String(_)
+        | Expression::Boolean(_)
+        | Expression::Import(..) => (),
+        Expression::Prefix { right, .. } | Expression::Spread(right) => {
+            walk_expression(visitor, arena, *right)
+        }
+        Expression::Infix { left, right, .. } => {
+            walk_expression(visitor, arena, *left);
+            walk_expression(visitor, arena, *right);
+        }
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            walk_expression(visitor, arena, *condition);
+            walk_block(visitor, arena, consequence);
+            if let Some(alternative) = alternative {
+                walk_block(visitor, arena, alternative);
+            }
+        }
+        Expression::Function { body, .. }
+        | Expression::Macro { body, .. }
+        | Expression::Block(body)
+        | Expression::Loop(body) => walk_block(visitor, arena, body),
+        Expression::While { condition, body } => {
+            walk_expression(visitor, arena, *condition);
+            walk_block(visitor, arena, body);
+        }
+        Expression::Try { body, handler, .. } => {
+            walk_block(visitor, arena, body);
+            walk_block(visitor, arena, handler);
+        }
+        Expression::Call {
+            function,
+            arguments,
+        } => {
+            walk_expression(visitor, arena, *function);
+            for arg in arguments.iter() {
+                walk_expression(visitor, arena, *arg);
+            }
+        }
+        Expression::Index { left, index } => {
+            walk_expression(visitor, arena, *left);
+            walk_expression(visitor, arena, *index);
+        }
+        Expression::Slice { target, start, end } => {
+            walk_expression(visitor, arena, *target);
+            for bound in start.iter().chain(end.iter()) {
+                walk_expression(visitor, arena, *bound);
+            }
+        }
+        Expression::Array(elements) => {
+            for element in elements.iter() {
+                walk_expression(visitor, arena, *element);
+            }
+        }
+        Expression::Hash(pairs) => {
+            for (key, value) in pairs.iter() {
+                walk_expression(visitor, arena, *key);
+                walk_expression(visitor, arena, *value);
+            }
+        }
+    }
+}
+
+/// Mutating traversal hooks for AST transformations. Unlike `Visitor`, the
+/// `walk_*_mut` functions call the hooks in post-order: children are walked
+/// first, so a hook sees (and may replace) a node whose children have already
+/// been transformed. Hooks get the whole arena, to read the children of the
+/// node they are given.
+pub trait VisitorMut {
+    fn visit_statement_mut(&mut self, _arena: &mut Arena, _stmt: StmtId) {}
+    fn visit_expression_mut(&mut self, _arena: &mut Arena, _expr: ExprId) {}
+    fn visit_block_mut(&mut self, _arena: &mut Arena, _block: &mut BlockStatement) {}
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    let arena = Rc::make_mut(&mut program.arena);
+    for stmt in program.statements.iter() {
+        walk_statement_mut(visitor, arena, *stmt);
+    }
+}
+
+// The blocks under a node live in the node itself, so they are walked on a
+// copy that is then written back.
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, arena: &mut Arena, id: StmtId) {
+    match arena[id].clone() {
+        Statement::Let { ident, value } | Statement::Const { ident, value } => {
+            walk_expression_mut(visitor, arena, ident);
+            walk_expression_mut(visitor, arena, value);
+        }
+        Statement::Return(expr) | Statement::Break(Some(expr)) | Statement::Expression(expr) => {
+            walk_expression_mut(visitor, arena, expr)
+        }
+        Statement::Break(None) | Statement::Continue | Statement::Import(_) => (),
+        Statement::Test { name, mut body } => {
+            walk_block_mut(visitor, arena, &mut body);
+            arena[id] = Statement::Test { name, body };
+        }
+    }
+    visitor.visit_statement_mut(arena, id);
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    arena: &mut Arena,
+    block: &mut BlockStatement,
+) {
+    for stmt in block.statements.iter() {
+        walk_statement_mut(visitor, arena, *stmt);
+    }
+    visitor.visit_block_mut(arena, block);
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, arena: &mut Arena, id: ExprId) {
+    let mut expr = arena[id].clone();
+    match &mut expr {
+        Expression::Ident(..)
+        | Expression::Int(_)
+        | Expression::Float(_)
+        | Expression::String(_)
+        | Expression::Boolean(_)
+        | Expression::Import(..) => (),
+        Expression::Prefix { right, .. } | Expression::Spread(right) => {
+            walk_expression_mut(visitor, arena, *right)
+        }
+        Expression::Infix { left, right, .. } => {
+            walk_expression_mut(visitor, arena, *left);
+            walk_expression_mut(visitor, arena, *right);
+        }
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            walk_expression_mut(visitor, arena, *condition);
+            walk_block_mut(visitor, arena, consequence);
+            if let Some(alternative) = alternative {
+                walk_block_mut(visitor, arena, alternative);
+            }
+        }
+        Expression::Function { body, .. }
+        | Expression::Macro { body, .. }
+        | Expression::Block(body)
+        | Expression::Loop(body) => walk_block_mut(visitor, arena, body),
+        Expression::While { condition, body } => {
+            walk_expression_mut(visitor, arena, *condition);
+            walk_block_mut(visitor, arena, body);
+        }
+        Expression::Try { body, handler, .. } => {
+            walk_block_mut(visitor, arena, body);
+            walk_block_mut(visitor, arena, handler);
+        }
+        Expression::Call {
+            function,
+            arguments,
+        } => {
+            walk_expression_mut(visitor, arena, *function);
+            for arg in arguments.iter() {
+                walk_expression_mut(visitor, arena, *arg);
+            }
+        }
+        Expression::Index { left, index } => {
+            walk_expression_mut(visitor, arena, *left);
+            walk_expression_mut(visitor, arena, *index);
+        }
+        Expression::Slice { target, start, end } => {
+            walk_expression_mut(visitor, arena, *target);
+            for bound in start.iter().chain(end.iter()) {
+                walk_expression_mut(visitor, arena, *bound);
+            }
+        }
+        Expression::Array(elements) => {
+            for element in elements.iter() {
+                walk_expression_mut(visitor, arena, *element);
+            }
+        }
+        Expression::Hash(pairs) => {
+            for (key, value) in pairs.iter() {
+                walk_expression_mut(visitor, arena, *key);
+                walk_expression_mut(visitor, arena, *value);
+            }
+        }
+    }
+    arena[id] = expr;
+    visitor.visit_expression_mut(arena, id);
+}
+
+/// Names of all identifier expressions in `program`, in traversal order.
+/// Function parameters are not expressions and are not included.
+pub fn identifiers(program: &Program) -> Vec<String> {
+    struct Collector(Vec<String>);
+
+    impl Visitor for Collector {
+        fn visit_expression(&mut self, expr: &Expression) {
+            if let Expression::Ident(name, _) = expr {
+                self.0.push(name.to_string());
+            }
+        }
+    }
+
+    let mut collector = Collector(vec![]);
+    walk_program(&mut collector, program);
+    collector.0
+}
+
+/// Whether `a` and `b` have the same structure. Spans are ignored, as is
+/// whitespace around identifier and parameter names, so a tree built by hand
+/// can be compared with one parsed from source.
+pub fn programs_equivalent(a: &Program, b: &Program) -> bool {
+    canonical(a, false) == canonical(b, false)
+}
+
+/// Renders `program` in a canonical form for snapshot tests: the `Display`
+/// layout, with identifier names trimmed and the pairs of every hash literal
+/// sorted by key.
+pub fn normalized_display(program: &Program) -> String {
+    canonical(program, true).to_string()
+}
+
+fn canonical(program: &Program, sort_hashes: bool) -> Program {
+    struct Canonicalize {
+        sort_hashes: bool,
+    }
+
+    impl VisitorMut for Canonicalize {
+        fn visit_expression_mut(&mut self, arena: &mut Arena, id: ExprId) {
+            match &arena[id] {
+                Expression::Ident(name, span) => {
+                    arena[id] = Expression::Ident(Symbol::intern(name.as_str().trim()), *span)
+                }
+                Expression::Function { parameters, body } => {
+                    arena[id] = Expression::Function {
+                        parameters: parameters
+                            .iter()
+                            .map(|p| Symbol::intern(p.as_str().trim()))
+                            .collect(),
+                        body: body.clone(),
+                    }
+                }
+                Expression::Hash(pairs) if self.sort_hashes => {
+                    let mut pairs = pairs.clone();
+                    pairs.sort_by_cached_key(|(key, _)| arena.display(*key).to_string());
+                    arena[id] = Expression::Hash(pairs);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    let mut program = program.clone();
+    walk_program_mut(&mut Canonicalize { sort_hashes }, &mut program);
+    program
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+    use std::rc::Rc;
+
+    use crate::{
+        ast::{
+            identifiers, normalized_display, programs_equivalent, walk_program, walk_program_mut,
+            Arena, BlockStatement, ExprId, Expression, Program, Span, Statement, Visitor,
+            VisitorMut,
+        },
+        interner::Symbol,
+        lexer::Lexer,
+        parser::Parser,
+    };
+
+    fn parse(input: &str) -> Program {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse_program().unwrap()
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        statements: usize,
+        blocks: usize,
+        idents: usize,
+        ints: usize,
+        infixes: usize,
+        calls: usize,
+        functions: usize,
+        ifs: usize,
+    }
+
+    impl Visitor for Counter {
+        fn visit_statement(&mut self, _: &Statement) {
+            self.statements += 1;
+        }
+
+        fn visit_block(&mut self, _: &BlockStatement) {
+            self.blocks += 1;
+        }
+
+        fn visit_expression(&mut self, expr: &Expression) {
+            match expr {
+                Expression::Ident(..) => self.idents += 1,
+                Expression::Int(_) => self.ints += 1,
+                Expression::Infix { .. } => self.infixes += 1,
+                Expression::Call { .. } => self.calls += 1,
+                Expression::Function { .. } => self.functions += 1,
+                Expression::If { .. } => self.ifs += 1,
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn test_visitor_counts() {
+        let program =
+            parse("let add = fn(x, y) { x + y }; if (add(1, 2) > 2) { return 1; } else { [3, 4] }");
+        let mut counter = Counter::default();
+        walk_program(&mut counter, &program);
+
+        assert_eq!(counter.statements, 5);
+        assert_eq!(counter.blocks, 3);
+        assert_eq!(counter.idents, 4);
+        assert_eq!(counter.ints, 6);
+        assert_eq!(counter.infixes, 2);
+        assert_eq!(counter.calls, 1);
+        assert_eq!(counter.functions, 1);
+        assert_eq!(counter.ifs, 1);
+    }
+
+    #[test]
+    fn test_span_line_col() {
+        let source = "let a = 1;\n\tlet é = a;\n";
+        let tests = vec![
+            (0, (1, 1)),
+            (4, (1, 5)),
+            (11, (2, 1)),
+            (16, (2, 6)),
+            (100, (3, 1)),
+        ];
+
+        for (start, expect) in tests {
+            assert_eq!(Span::new(start).line_col(source), expect, "{}", start);
+        }
+    }
+
+    #[test]
+    fn test_programs_equivalent() {
+        let tests = vec![
+            ("let a = 1 + 2;", "let a=1+2", true),
+            ("(1 + 2) * 3", "((1 + 2) * 3)", true),
+            ("fn(x,y){x}", "fn (x, y) {\n  x\n}", true),
+            ("1 + 2 * 3", "(1 + 2) * 3", false),
+            (r#""a b""#, r#""a  b""#, false),
+            ("{1: 2, 3: 4}", "{3: 4, 1: 2}", false),
+            ("a; b", "a", false),
+        ];
+
+        for (a, b, expect) in tests {
+            assert_eq!(
+                programs_equivalent(&parse(a), &parse(b)),
+                expect,
+                "{} / {}",
+                a,
+                b
+            );
+        }
+
+        let mut arena = Arena::new();
+        let x = arena.alloc_expr(Expression::Ident(Symbol::intern("x\t"), Span::new(42)));
+        let body = BlockStatement {
+            statements: vec![arena.alloc_stmt(Statement::Expression(x))],
+        };
+        let function = arena.alloc_expr(Expression::Function {
+            parameters: vec![Symbol::intern(" x ")],
+            body,
+        });
+        let built = Program {
+            statements: vec![arena.alloc_stmt(Statement::Expression(function))],
+            arena: Rc::new(arena),
+        };
+        assert!(programs_equivalent(&built, &parse("fn(x) { x }")));
+    }
+
+    #[test]
+    fn test_normalized_display() {
+        let tests = vec![
+            ("let a=1+2", "let a = (1 + 2);\n"),
+            (
+                r#"{"b": 1, "a": {2: x, 1: y}}"#,
+                r#"{"a": {1: y, 2: x}, "b": 1};"#,
+            ),
+            ("if(x){ a }else{ b }", "if (x) { a } else { b };"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                normalized_display(&parse(input)).trim_end(),
+                expect.trim_end()
+            );
+        }
+        assert_eq!(
+            normalized_display(&parse("{2: 0, 1: 0}")),
+            normalized_display(&parse("{1: 0, 2: 0}"))
+        );
+    }
+
+    #[test]
+    fn test_identifiers() {
+        let program = parse("let a = b + c(d); fn(e) { f }");
+        assert_eq!(identifiers(&program), vec!["a", "b", "c", "d", "f"]);
+    }
+
+    #[test]
+    fn test_visitor_mut() {
+        struct Rename;
+
+        impl VisitorMut for Rename {
+            fn visit_expression_mut(&mut self, arena: &mut Arena, expr: ExprId) {
+                if let Expression::Ident(name, _) = &mut arena[expr] {
+                    *name = Symbol::intern(&name.as_str().to_uppercase());
+                }
+            }
+        }
+
+        let mut program = parse("let a = b + fn(x) { x * c }(1);");
+        walk_program_mut(&mut Rename, &mut program);
+        assert_eq!(
+            program.to_string(),
+            "let A = (B + fn (x) { (X * C) }(1));\n"
+        );
+    }
+
+    #[test]
+    fn test_pretty() {
+        let tests = vec![
+            ("1 + 2 + 3", "((1 + 2) + 3)", "1 + 2 + 3"),
+            ("1 + 2 * 3", "(1 + (2 * 3))", "1 + 2 * 3"),
+            ("(1 + 2) * 3", "((1 + 2) * 3)", "(1 + 2) * 3"),
+            ("a - (b - c)", "(a - (b - c))", "a - (b - c)"),
+            ("-a * b", "((-a) * b)", "-a * b"),
+            ("-(a + b)", "(-(a + b))", "-(a + b)"),
+            ("!(true == false)", "(!(true == false))", "!(true == false)"),
+            ("3 > 5 == false", "((3 > 5) == false)", "3 > 5 == false"),
+            ("add(a * (b + c))", "add((a * (b + c)))", "add(a * (b + c))"),
+            ("f(x)[-1][0]", "((f(x)[(-1)])[0])", "f(x)[-1][0]"),
+            ("(a + b)[0]", "((a + b)[0])", "(a + b)[0]"),
+            (
+                r#"let f = fn(x) { x + "!" }"#,
+                r#"let f = fn (x) { (x + "!") };"#,
+                r#"let f = fn(x) { x + "!" };"#,
+            ),
+            (
+                "if (a < b) { let c = a; c * 2 } else { b }",
+                "if (a < b) { let c = a; (c * 2) } else { b }",
+                "if (a < b) { let c = a; c * 2 } else { b }",
+            ),
+        ];
+
+        for (input, full, pretty) in tests {
+            let program = parse(input);
+            let stmt = program.statements[0];
+            assert_eq!(program.arena.display(stmt).to_string(), full);
+            assert_eq!(program.arena.pretty(stmt).to_string(), pretty);
+
+            // the pretty rendering parses back to the same tree
+            let reparsed = parse(&program.pretty().to_string());
+            assert_eq!(reparsed, program);
+        }
+    }
+
+    #[test]
+    fn test_block_display() {
+        let tests = vec![
+            ("fn() {}", "fn () {}"),
+            ("loop { break; }", "loop { break; }"),
+            ("if (x) { a; b } else {}", "if (x) { a; b } else {}"),
+            ("try { f() } catch (e) { e }", "try { f() } catch (e) { e }"),
+        ];
+
+        for (input, expect) in tests {
+            let program = parse(input);
+            assert_eq!(
+                program.arena.display(program.statements[0]).to_string(),
+                expect
+            );
+        }
+    }
+
+    #[test]
+    fn test_fmt_indented() {
+        struct Indented<'a>(&'a Arena, &'a BlockStatement, usize);
+
+        impl fmt::Display for Indented<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.1.fmt_indented(self.0, f, self.2)
+            }
+        }
+
+        let program = parse("fn(x) { let y = x * 2; puts(y); if (y) { y } }");
+        let body = match program.expr(match program.statement(0) {
+            Statement::Expression(expr) => *expr,
+            stmt => panic!("not an expression: {:?}", stmt),
+        }) {
+            Expression::Function { body, .. } => body,
+            expr => panic!("not a function: {:?}", expr),
+        };
+        assert_eq!(
+            Indented(&program.arena, body, 0).to_string(),
+            "{\n    let y = x * 2;\n    puts(y);\n    if (y) { y }\n}"
+        );
+        assert_eq!(
+            Indented(&program.arena, body, 1).to_string(),
+            "{\n        let y = x * 2;\n        puts(y);\n        if (y) { y }\n    }"
+        );
+        assert_eq!(
+            Indented(&program.arena, &BlockStatement::default(), 2).to_string(),
+            "{}"
+        );
+    }
 }
@@ -0,0 +1,56 @@
+//! Arbitrary-precision integers, enabled by the `bigint` feature.
+//!
+//! Integers that fit in an `i64` are still `Object::Int`. Arithmetic that would
+//! overflow it produces an `Object::BigInt` instead of failing, and results
+//! that fit again are narrowed back, so the two never hold the same value.
+
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+
+use crate::object::Object;
+
+/// Wraps `value` as an `Object::Int` if it fits, or an `Object::BigInt`.
+pub fn normalize(value: BigInt) -> Object {
+    match value.to_i64() {
+        Some(value) => Object::Int(value),
+        None => Object::BigInt(value),
+    }
+}
+
+pub(crate) fn to_f64(value: &BigInt) -> f64 {
+    value.to_f64().unwrap_or(f64::NAN)
+}
+
+pub(crate) fn evaluate_infix_expression(op: &str, left: BigInt, right: BigInt) -> Object {
+    match op {
+        "+" => normalize(left + right),
+        "-" => normalize(left - right),
+        "*" => normalize(left * right),
+        "/" | "%" if right.is_zero() => Object::error("division by zero".to_string()),
+        "/" => normalize(left / right),
+        "%" => normalize(left % right),
+        "<" => Object::Boolean(left < right),
+        ">" => Object::Boolean(left > right),
+        "==" => Object::Boolean(left == right),
+        "!=" => Object::Boolean(left != right),
+        _ => Object::error(format!("unknown operator: INTEGER {} INTEGER", op)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+
+    use crate::{bigint::normalize, object::Object};
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(BigInt::from(i64::MAX)), Object::Int(i64::MAX));
+        assert_eq!(
+            normalize(BigInt::from(i64::MAX) + 1).to_string(),
+            "9223372036854775808"
+        );
+        assert!(Object::Int(i64::MAX) < normalize(BigInt::from(i64::MAX) + 1));
+        assert!(normalize(BigInt::from(i64::MIN) - 1) < Object::Int(i64::MIN));
+    }
+}
@@ -0,0 +1,3508 @@
+#[cfg(feature = "rand")]
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs;
+use std::rc::Rc;
+
+#[cfg(feature = "rand")]
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+#[cfg(feature = "bigint")]
+use num_traits::Signed;
+
+use crate::ast::Source;
+#[cfg(feature = "bigint")]
+use crate::bigint;
+use crate::errors::MonkeyError;
+use crate::evaluator::Evaluator;
+use crate::lexer::Lexer;
+use crate::object::{
+    Builtin, BuiltinFunction, Emitter, HashPairs, IteratorState, LazyIterator, Memoized, Object,
+};
+use crate::parser::Parser;
+
+#[cfg(feature = "rand")]
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+const BUILTINS: &[(&str, BuiltinFunction)] = &[
+    ("abs", abs),
+    ("assert", assert),
+    ("ceil", ceil),
+    ("chr", chr),
+    ("compose", compose),
+    ("flatten", flatten),
+    ("flatten_deep", flatten_deep),
+    #[cfg(feature = "rand")]
+    ("choice", choice),
+    ("debug", debug),
+    ("deduplicate", deduplicate),
+    ("diff", diff),
+    ("each", each),
+    ("each_with_index", each_with_index),
+    ("emit", emit),
+    ("emitter", emitter),
+    ("entries", entries),
+    ("env", env),
+    ("error", error),
+    ("eval", eval),
+    ("floor", floor),
+    ("glob_filter", glob_filter),
+    ("glob_match", glob_match),
+    ("group_by", group_by),
+    ("intersection", intersection),
+    ("is_array", is_array),
+    ("is_bool", is_bool),
+    ("is_error", is_error),
+    ("is_float", is_float),
+    ("is_function", is_function),
+    ("is_hash", is_hash),
+    ("is_int", is_int),
+    ("is_null", is_null),
+    ("is_string", is_string),
+    ("iter", iter),
+    ("iter_collect", iter_collect),
+    ("iter_drop", iter_drop),
+    ("iter_filter", iter_filter),
+    ("iter_map", iter_map),
+    ("iter_take", iter_take),
+    ("len", len),
+    ("max", max),
+    ("max_arr", max_arr),
+    ("memoize", memoize),
+    ("merge", merge),
+    ("min", min),
+    ("min_arr", min_arr),
+    ("off", off),
+    ("on", on),
+    ("ord", ord),
+    ("pair", pair),
+    ("pair_key", pair_key),
+    ("pair_value", pair_value),
+    ("partial", partial),
+    ("partition", partition),
+    ("parse_float", parse_float),
+    ("parse_int", parse_int),
+    ("pow", pow),
+    ("profile_report", profile_report),
+    ("profile_start", profile_start),
+    ("puts", puts),
+    ("read_file", read_file),
+    #[cfg(feature = "rand")]
+    ("random", random),
+    #[cfg(feature = "rand")]
+    ("random_int", random_int),
+    #[cfg(feature = "rand")]
+    ("random_seed", random_seed),
+    ("repeat", repeat),
+    ("reverse", reverse),
+    ("round", round),
+    ("set", set),
+    #[cfg(feature = "rand")]
+    ("shuffle", shuffle),
+    ("substr", substr),
+    ("sqrt", sqrt),
+    ("sum", sum),
+    ("to_bin", to_bin),
+    ("to_hex", to_hex),
+    ("to_hex_upper", to_hex_upper),
+    ("to_oct", to_oct),
+    #[cfg(feature = "serde")]
+    ("to_json", to_json),
+    #[cfg(feature = "serde")]
+    ("from_json", from_json),
+    ("try", try_call),
+    ("try_or", try_or),
+    ("write_file", write_file),
+];
+
+/// Every builtin function, in the order they are defined in the table.
+pub fn builtins() -> impl Iterator<Item = (&'static str, Object)> {
+    BUILTINS
+        .iter()
+        .map(|&(name, func)| (name, Object::Builtin(Builtin { name, func })))
+}
+
+fn wrong_number_of_arguments(got: usize, want: usize) -> Object {
+    Object::error(format!(
+        "wrong number of arguments. got={}, want={}",
+        got, want
+    ))
+}
+
+/// Checks the `(array, function)` arguments shared by the higher-order builtins.
+fn array_and_function(name: &str, args: Vec<Object>) -> Result<(Vec<Object>, Object), Object> {
+    if args.len() != 2 {
+        return Err(wrong_number_of_arguments(args.len(), 2));
+    }
+    let mut args = args.into_iter();
+    match (args.next().unwrap(), args.next().unwrap()) {
+        (Object::Array(elements), func) if func.is_callable() => Ok((elements, func)),
+        (Object::Array(_), obj) => Err(Object::error(format!(
+            "argument to `{}` must be FUNCTION, got {}",
+            name,
+            obj.type_info()
+        ))),
+        (obj, _) => Err(Object::error(format!(
+            "argument to `{}` must be ARRAY, got {}",
+            name,
+            obj.type_info()
+        ))),
+    }
+}
+
+fn flatten(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::Array(elements) => Object::Array(flatten_elements(elements, false)),
+        obj => Object::error(format!(
+            "argument to `flatten` must be ARRAY, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+fn flatten_deep(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::Array(elements) => Object::Array(flatten_elements(elements, true)),
+        obj => Object::error(format!(
+            "argument to `flatten_deep` must be ARRAY, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+fn flatten_elements(elements: &[Object], deep: bool) -> Vec<Object> {
+    let mut result = vec![];
+    for element in elements {
+        match element {
+            Object::Array(inner) if deep => result.extend(flatten_elements(inner, deep)),
+            Object::Array(inner) => result.extend(inner.iter().cloned()),
+            _ => result.push(element.clone()),
+        }
+    }
+    result
+}
+
+fn group_by(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    let (elements, key_fn) = match array_and_function("group_by", args) {
+        Ok(args) => args,
+        Err(err) => return err,
+    };
+
+    let mut groups: Vec<(Object, Object)> = vec![];
+    for element in elements {
+        let key = evaluator.apply_function(key_fn.clone(), vec![element.clone()]);
+        if let Object::Error(_) = key {
+            return key;
+        }
+        if !key.is_hashable() {
+            return Object::error(format!("unusable as hash key: {}", key.type_info()));
+        }
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, Object::Array(bucket))) => bucket.push(element.clone()),
+            _ => groups.push((key, Object::Array(vec![element.clone()]))),
+        }
+    }
+    Object::Hash(groups.into())
+}
+
+// `*` matches any run of characters and `?` exactly one. When a later match
+// fails, the most recent `*` is retried with one more character.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn glob_match(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 2 {
+        return wrong_number_of_arguments(args.len(), 2);
+    }
+    match (&args[0], &args[1]) {
+        (Object::String(pattern), Object::String(text)) => {
+            Object::Boolean(glob_matches(pattern, text))
+        }
+        (pattern, text) => Object::error(format!(
+            "arguments to `glob_match` must be STRING, got {}, {}",
+            pattern.type_info(),
+            text.type_info()
+        )),
+    }
+}
+
+fn glob_filter(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 2 {
+        return wrong_number_of_arguments(args.len(), 2);
+    }
+    let (elements, pattern) = match (&args[0], &args[1]) {
+        (Object::Array(elements), Object::String(pattern)) => (elements, pattern),
+        (arr, pattern) => {
+            return Object::error(format!(
+                "arguments to `glob_filter` must be ARRAY, STRING, got {}, {}",
+                arr.type_info(),
+                pattern.type_info()
+            ))
+        }
+    };
+
+    let mut result = vec![];
+    for element in elements {
+        match element {
+            Object::String(text) if glob_matches(pattern, text) => result.push(element.clone()),
+            Object::String(_) => (),
+            obj => {
+                return Object::error(format!(
+                    "elements of `glob_filter` must be STRING, got {}",
+                    obj.type_info()
+                ))
+            }
+        }
+    }
+    Object::Array(result)
+}
+
+fn debug(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    Object::String(format!("{:?}", args[0]).into())
+}
+
+// Identity of a hashable element for `deduplicate`; equal keys mean equal
+// objects.
+#[derive(PartialEq, Eq, Hash)]
+enum ElementKey<'a> {
+    Int(i64),
+    String(&'a str),
+    Boolean(bool),
+    Null,
+}
+
+impl<'a> ElementKey<'a> {
+    fn of(obj: &'a Object) -> Option<Self> {
+        match obj {
+            Object::Int(value) => Some(ElementKey::Int(*value)),
+            Object::String(value) => Some(ElementKey::String(value)),
+            Object::Boolean(value) => Some(ElementKey::Boolean(*value)),
+            Object::Null => Some(ElementKey::Null),
+            _ => None,
+        }
+    }
+}
+
+fn deduplicate(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    let elements = match &args[0] {
+        Object::Array(elements) => elements,
+        obj => {
+            return Object::error(format!(
+                "argument to `deduplicate` must be ARRAY, got {}",
+                obj.type_info()
+            ))
+        }
+    };
+
+    // hashable elements are looked up in `seen`, anything else is compared
+    // against the unhashable elements kept so far
+    let mut seen = HashSet::new();
+    let mut others: Vec<&Object> = vec![];
+    let mut result = vec![];
+    for element in elements {
+        let unique = match ElementKey::of(element) {
+            Some(key) => seen.insert(key),
+            None if others.contains(&element) => false,
+            None => {
+                others.push(element);
+                true
+            }
+        };
+        if unique {
+            result.push(element.clone());
+        }
+    }
+    Object::Array(result)
+}
+
+fn each(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    let (elements, func) = match array_and_function("each", args) {
+        Ok(args) => args,
+        Err(err) => return err,
+    };
+    for element in elements {
+        let obj = evaluator.apply_function(func.clone(), vec![element]);
+        if let Object::Error(_) = obj {
+            return obj;
+        }
+    }
+    Object::Null
+}
+
+fn each_with_index(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    let (elements, func) = match array_and_function("each_with_index", args) {
+        Ok(args) => args,
+        Err(err) => return err,
+    };
+    for (i, element) in elements.into_iter().enumerate() {
+        let obj = evaluator.apply_function(func.clone(), vec![Object::Int(i as i64), element]);
+        if let Object::Error(_) = obj {
+            return obj;
+        }
+    }
+    Object::Null
+}
+
+fn numbers(name: &str, args: Vec<Object>) -> Result<Vec<Object>, Object> {
+    if args.len() != 1 {
+        return Err(wrong_number_of_arguments(args.len(), 1));
+    }
+    match &args[0] {
+        Object::Array(elements) => elements
+            .iter()
+            .map(|element| {
+                if is_integer(element) || matches!(element, Object::Float(_)) {
+                    Ok(element.clone())
+                } else {
+                    Err(Object::error(format!(
+                        "elements of `{}` must be INTEGER or FLOAT, got {}",
+                        name,
+                        element.type_info()
+                    )))
+                }
+            })
+            .collect(),
+        obj => Err(Object::error(format!(
+            "argument to `{}` must be ARRAY, got {}",
+            name,
+            obj.type_info()
+        ))),
+    }
+}
+
+fn is_integer(obj: &Object) -> bool {
+    match obj {
+        Object::Int(_) => true,
+        #[cfg(feature = "bigint")]
+        Object::BigInt(_) => true,
+        _ => false,
+    }
+}
+
+// Adds the elements the way `+` does: integers and floats may be mixed, and
+// with the `bigint` feature a total beyond `i64` becomes a big integer instead
+// of an overflow error.
+fn sum(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    let elements = match numbers("sum", args) {
+        Ok(elements) => elements,
+        Err(err) => return err,
+    };
+    let mut total = Object::Int(0);
+    for element in elements {
+        total = match Evaluator::evaluate_infix_expression("+".to_string(), total, element) {
+            Object::Error(_) => return Object::error("integer overflow in `sum`".to_string()),
+            total => total,
+        };
+    }
+    total
+}
+
+// Orders two numbers (integers and floats may be mixed) or two strings.
+fn compare(name: &str, a: &Object, b: &Object) -> Result<cmp::Ordering, Object> {
+    let ordering = match (a, b) {
+        (Object::Int(a), Object::Int(b)) => Some(a.cmp(b)),
+        (Object::Float(a), Object::Float(b)) => a.partial_cmp(b),
+        (Object::Int(a), Object::Float(b)) => (*a as f64).partial_cmp(b),
+        (Object::Float(a), Object::Int(b)) => a.partial_cmp(&(*b as f64)),
+        #[cfg(feature = "bigint")]
+        (Object::BigInt(_), Object::Int(_) | Object::BigInt(_))
+        | (Object::Int(_), Object::BigInt(_)) => a.partial_cmp(b),
+        #[cfg(feature = "bigint")]
+        (Object::BigInt(a), Object::Float(b)) => bigint::to_f64(a).partial_cmp(b),
+        #[cfg(feature = "bigint")]
+        (Object::Float(a), Object::BigInt(b)) => a.partial_cmp(&bigint::to_f64(b)),
+        (Object::String(a), Object::String(b)) => Some(a.cmp(b)),
+        _ => {
+            return Err(Object::error(format!(
+                "arguments to `{}` must be INTEGER, FLOAT or STRING, got {}, {}",
+                name,
+                a.type_info(),
+                b.type_info()
+            )))
+        }
+    };
+    ordering.ok_or_else(|| Object::error(format!("`{}` of NaN", name)))
+}
+
+// Picks `a` unless `b` is strictly further in the `wanted` direction.
+fn pick(name: &str, a: &Object, b: &Object, wanted: cmp::Ordering) -> Object {
+    match compare(name, a, b) {
+        Ok(ordering) if ordering == wanted.reverse() => b.clone(),
+        Ok(_) => a.clone(),
+        Err(err) => err,
+    }
+}
+
+// `min(arr)` and `max(arr)` reduce an array of numbers, integers and floats
+// mixed, while `min(a, b)` and `max(a, b)` pick one of two numbers or strings.
+fn min(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() == 2 {
+        return pick("min", &args[0], &args[1], cmp::Ordering::Less);
+    }
+    reduce_numbers("min", args, cmp::Ordering::Less)
+}
+
+fn max(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() == 2 {
+        return pick("max", &args[0], &args[1], cmp::Ordering::Greater);
+    }
+    reduce_numbers("max", args, cmp::Ordering::Greater)
+}
+
+// The first number of an array that no other is further from in the `wanted`
+// direction.
+fn reduce_numbers(name: &str, args: Vec<Object>, wanted: cmp::Ordering) -> Object {
+    let mut values = match numbers(name, args) {
+        Ok(values) => values.into_iter(),
+        Err(err) => return err,
+    };
+    let mut result = match values.next() {
+        Some(first) => first,
+        None => return Object::error(format!("`{}` of empty array", name)),
+    };
+    for value in values {
+        result = pick(name, &result, &value, wanted);
+        if let Object::Error(_) = result {
+            return result;
+        }
+    }
+    result
+}
+
+fn min_arr(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    extreme("min_arr", args, cmp::Ordering::Less)
+}
+
+fn max_arr(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    extreme("max_arr", args, cmp::Ordering::Greater)
+}
+
+// The first element of an array of integers, floats or strings that no other
+// element is further from in the `wanted` direction.
+fn extreme(name: &str, args: Vec<Object>, wanted: cmp::Ordering) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    let elements = match &args[0] {
+        Object::Array(elements) => elements,
+        obj => {
+            return Object::error(format!(
+                "argument to `{}` must be ARRAY, got {}",
+                name,
+                obj.type_info()
+            ))
+        }
+    };
+    let first = match elements.first() {
+        Some(first @ (Object::Int(_) | Object::Float(_) | Object::String(_))) => first,
+        Some(obj) => {
+            return Object::error(format!(
+                "elements of `{}` must be INTEGER, FLOAT or STRING, got {}",
+                name,
+                obj.type_info()
+            ))
+        }
+        None => return Object::error(format!("`{}` of empty array", name)),
+    };
+
+    let mut result = first.clone();
+    for element in elements.iter().skip(1) {
+        if element.type_info() != first.type_info() {
+            return Object::error(format!(
+                "elements of `{}` must have the same type, got {}, {}",
+                name,
+                first.type_info(),
+                element.type_info()
+            ));
+        }
+        result = pick(name, &result, element, wanted);
+        if let Object::Error(_) = result {
+            return result;
+        }
+    }
+    result
+}
+
+fn entries(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::Hash(pairs) => {
+            let mut pairs = pairs.to_vec();
+            pairs.sort_by_key(|(key, _)| key.to_string());
+            Object::Array(
+                pairs
+                    .into_iter()
+                    .map(|(key, value)| Object::Pair(Box::new(key), Box::new(value)))
+                    .collect(),
+            )
+        }
+        obj => Object::error(format!(
+            "argument to `entries` must be HASH, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+// The value of an environment variable, or `null` when it is not set.
+fn env(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    let name = match &args[0] {
+        Object::String(name) => name,
+        obj => {
+            return Object::error(format!(
+                "argument to `env` must be STRING, got {}",
+                obj.type_info()
+            ))
+        }
+    };
+    if evaluator.sandboxed() {
+        return Object::error("env disabled".to_string());
+    }
+    match std::env::var(&**name) {
+        Ok(value) => Object::String(value.into()),
+        Err(std::env::VarError::NotPresent) => Object::Null,
+        Err(std::env::VarError::NotUnicode(_)) => {
+            Object::error(format!("environment variable is not valid UTF-8: {}", name))
+        }
+    }
+}
+
+// What the file at `path` holds, as far as `Evaluator::set_fs_access` allows.
+fn read_file(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    let path = match &args[0] {
+        Object::String(path) => path,
+        obj => {
+            return Object::error(format!(
+                "argument to `read_file` must be STRING, got {}",
+                obj.type_info()
+            ))
+        }
+    };
+    let file = match evaluator.fs_access().resolve(path, false) {
+        Ok(file) => file,
+        Err(message) => return Object::error(message),
+    };
+    match fs::read_to_string(file) {
+        Ok(contents) => Object::String(contents.into()),
+        Err(err) => Object::error(format!("cannot read {}: {}", path, err)),
+    }
+}
+
+// Replaces the file at `path` with `contents`, as far as
+// `Evaluator::set_fs_access` allows.
+fn write_file(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 2 {
+        return wrong_number_of_arguments(args.len(), 2);
+    }
+    let (path, contents) = match (&args[0], &args[1]) {
+        (Object::String(path), Object::String(contents)) => (path, contents),
+        (path, contents) => {
+            return Object::error(format!(
+                "arguments to `write_file` must be STRING, got {}, {}",
+                path.type_info(),
+                contents.type_info()
+            ))
+        }
+    };
+    let file = match evaluator.fs_access().resolve(path, true) {
+        Ok(file) => file,
+        Err(message) => return Object::error(message),
+    };
+    match fs::write(file, &**contents) {
+        Ok(()) => Object::Null,
+        Err(err) => Object::error(format!("cannot write {}: {}", path, err)),
+    }
+}
+
+fn hashes(name: &str, args: Vec<Object>) -> Result<Vec<HashPairs>, Object> {
+    args.into_iter()
+        .map(|arg| match arg {
+            Object::Hash(pairs) => Ok(pairs),
+            obj => Err(Object::error(format!(
+                "arguments to `{}` must be HASH, got {}",
+                name,
+                obj.type_info()
+            ))),
+        })
+        .collect()
+}
+
+fn merge(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() < 2 {
+        return wrong_number_of_arguments(args.len(), 2);
+    }
+    let mut hashes = match hashes("merge", args) {
+        Ok(hashes) => hashes.into_iter(),
+        Err(err) => return err,
+    };
+    let mut merged = hashes.next().unwrap_or_default();
+    for pairs in hashes {
+        for (key, value) in pairs {
+            merged.insert(key, value);
+        }
+    }
+    Object::Hash(merged)
+}
+
+fn diff(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    filter_keys("diff", args, false)
+}
+
+fn intersection(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    filter_keys("intersection", args, true)
+}
+
+// The pairs of the first hash whose key is (or is not) in the second one.
+fn filter_keys(name: &str, args: Vec<Object>, keep_shared: bool) -> Object {
+    if args.len() != 2 {
+        return wrong_number_of_arguments(args.len(), 2);
+    }
+    let (first, second) = match hashes(name, args) {
+        Ok(mut hashes) => {
+            let second = hashes.pop().unwrap_or_default();
+            (hashes.pop().unwrap_or_default(), second)
+        }
+        Err(err) => return err,
+    };
+    Object::Hash(
+        first
+            .into_iter()
+            .filter(|(key, _)| second.iter().any(|(k, _)| k == key) == keep_shared)
+            .collect(),
+    )
+}
+
+fn pair(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 2 {
+        return wrong_number_of_arguments(args.len(), 2);
+    }
+    let mut args = args.into_iter();
+    Object::Pair(
+        Box::new(args.next().unwrap()),
+        Box::new(args.next().unwrap()),
+    )
+}
+
+fn pair_key(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::Pair(key, _) => *key.clone(),
+        obj => Object::error(format!(
+            "argument to `pair_key` must be PAIR, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+fn pair_value(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::Pair(_, value) => *value.clone(),
+        obj => Object::error(format!(
+            "argument to `pair_value` must be PAIR, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+fn abs(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::Int(value) => match value.checked_abs() {
+            Some(value) => Object::Int(value),
+            #[cfg(feature = "bigint")]
+            None => bigint::normalize(num_bigint::BigInt::from(*value).abs()),
+            #[cfg(not(feature = "bigint"))]
+            None => Object::error("integer overflow in `abs`".to_string()),
+        },
+        #[cfg(feature = "bigint")]
+        Object::BigInt(value) => bigint::normalize(value.abs()),
+        Object::Float(value) => Object::Float(value.abs()),
+        obj => Object::error(format!(
+            "argument to `abs` must be INTEGER or FLOAT, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+fn round_with(name: &str, args: Vec<Object>, round: fn(f64) -> f64) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::Float(value) => Object::Float(round(*value)),
+        Object::Int(value) => Object::Int(*value),
+        obj => Object::error(format!(
+            "argument to `{}` must be FLOAT or INTEGER, got {}",
+            name,
+            obj.type_info()
+        )),
+    }
+}
+
+fn floor(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    round_with("floor", args, f64::floor)
+}
+
+fn ceil(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    round_with("ceil", args, f64::ceil)
+}
+
+// Halfway cases round away from zero, so `round(2.5)` is `3.0`.
+fn round(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    round_with("round", args, f64::round)
+}
+
+fn pow(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 2 {
+        return wrong_number_of_arguments(args.len(), 2);
+    }
+    match (&args[0], &args[1]) {
+        (Object::Int(_), Object::Int(exp)) if *exp < 0 => {
+            Object::error(format!("negative exponent to `pow`: {}", exp))
+        }
+        (Object::Int(base), Object::Int(exp)) => match u32::try_from(*exp) {
+            Ok(exp) => match base.checked_pow(exp) {
+                Some(value) => Object::Int(value),
+                #[cfg(feature = "bigint")]
+                None => bigint::normalize(num_bigint::BigInt::from(*base).pow(exp)),
+                #[cfg(not(feature = "bigint"))]
+                None => Object::error("integer overflow in `pow`".to_string()),
+            },
+            Err(_) => Object::error("integer overflow in `pow`".to_string()),
+        },
+        #[cfg(feature = "bigint")]
+        (Object::BigInt(_), Object::Int(exp)) if *exp < 0 => {
+            Object::error(format!("negative exponent to `pow`: {}", exp))
+        }
+        #[cfg(feature = "bigint")]
+        (Object::BigInt(base), Object::Int(exp)) => match u32::try_from(*exp) {
+            Ok(exp) => bigint::normalize(base.pow(exp)),
+            Err(_) => Object::error("integer overflow in `pow`".to_string()),
+        },
+        (base, exp) => Object::error(format!(
+            "arguments to `pow` must be INTEGER, got {}, {}",
+            base.type_info(),
+            exp.type_info()
+        )),
+    }
+}
+
+/// Integer square root, rounded down.
+fn sqrt(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::Int(value) if *value < 0 => {
+            Object::error(format!("`sqrt` of negative number: {}", value))
+        }
+        Object::Int(value) => {
+            let mut root = (*value as f64).sqrt() as i64;
+            while root.checked_mul(root).is_none_or(|square| square > *value) {
+                root -= 1;
+            }
+            while (root + 1)
+                .checked_mul(root + 1)
+                .is_some_and(|square| square <= *value)
+            {
+                root += 1;
+            }
+            Object::Int(root)
+        }
+        obj => Object::error(format!(
+            "argument to `sqrt` must be INTEGER, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+// Negative numbers are rendered in 64-bit two's complement, so `to_hex(-1)` is
+// "ffffffffffffffff" rather than "-1". Big integers have no fixed width and
+// keep their sign instead.
+macro_rules! format_integer {
+    ($name:ident, $format:literal) => {
+        fn $name(_: &mut Evaluator, args: Vec<Object>) -> Object {
+            if args.len() != 1 {
+                return wrong_number_of_arguments(args.len(), 1);
+            }
+            match &args[0] {
+                Object::Int(value) => Object::String(format!($format, value).into()),
+                #[cfg(feature = "bigint")]
+                Object::BigInt(value) => Object::String(format!($format, value).into()),
+                obj => Object::error(format!(
+                    "argument to `{}` must be INTEGER, got {}",
+                    stringify!($name),
+                    obj.type_info()
+                )),
+            }
+        }
+    };
+}
+
+format_integer!(to_bin, "{:b}");
+format_integer!(to_oct, "{:o}");
+format_integer!(to_hex, "{:x}");
+format_integer!(to_hex_upper, "{:X}");
+
+// Parses a string argument, returning `[value, null]` on success and
+// `[null, message]` on failure so that scripts can check for errors.
+fn parse_number<F>(name: &str, args: Vec<Object>, parse: F) -> Object
+where
+    F: FnOnce(&str) -> Result<Object, MonkeyError>,
+{
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    let source = match &args[0] {
+        Object::String(source) => source,
+        obj => {
+            return Object::error(format!(
+                "argument to `{}` must be STRING, got {}",
+                name,
+                obj.type_info()
+            ))
+        }
+    };
+    match parse(source.trim()) {
+        Ok(value) => Object::Array(vec![value, Object::Null]),
+        Err(err) => Object::Array(vec![Object::Null, Object::String(err.to_string().into())]),
+    }
+}
+
+fn parse_int(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    parse_number("parse_int", args, |source| {
+        let invalid = || MonkeyError::InvalidInteger(source.to_string());
+        #[cfg(feature = "bigint")]
+        if let Ok(value) = source.parse::<num_bigint::BigInt>() {
+            return Ok(crate::bigint::normalize(value));
+        }
+        source.parse().map(Object::Int).map_err(|_| invalid())
+    })
+}
+
+fn parse_float(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    parse_number("parse_float", args, |source| match source.parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok(Object::Float(value)),
+        _ => Err(MonkeyError::InvalidFloat(source.to_string())),
+    })
+}
+
+macro_rules! type_predicate {
+    ($name:ident, $test:expr) => {
+        fn $name(_: &mut Evaluator, args: Vec<Object>) -> Object {
+            if args.len() != 1 {
+                return wrong_number_of_arguments(args.len(), 1);
+            }
+            Object::Boolean($test(&args[0]))
+        }
+    };
+}
+
+type_predicate!(is_int, is_integer);
+type_predicate!(is_float, |obj| matches!(obj, &Object::Float(_)));
+type_predicate!(is_string, |obj| matches!(obj, &Object::String(_)));
+type_predicate!(is_bool, |obj| matches!(obj, &Object::Boolean(_)));
+type_predicate!(is_null, |obj| matches!(obj, &Object::Null));
+type_predicate!(is_array, |obj| matches!(obj, &Object::Array(_)));
+type_predicate!(is_hash, |obj| matches!(obj, &Object::Hash(_)));
+type_predicate!(is_function, Object::is_callable);
+type_predicate!(is_error, |obj| matches!(obj, &Object::Error(_)));
+
+/// Evaluates a string of source in the caller's environment, so `let`
+/// bindings it makes stay visible afterwards.
+fn eval(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    let source = match &args[0] {
+        Object::String(source) => source,
+        obj => {
+            return Object::error(format!(
+                "argument to `eval` must be STRING, got {}",
+                obj.type_info()
+            ))
+        }
+    };
+    match Parser::new(Lexer::new(source)).parse_program() {
+        Ok(mut program) => {
+            program.arena_mut().set_source(Source {
+                text: source.to_string(),
+                name: Some("eval".to_string()),
+            });
+            evaluator.evaluate_nested(program)
+        }
+        Err(err) => Object::error(err.to_string()),
+    }
+}
+
+fn emitter(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if !args.is_empty() {
+        return wrong_number_of_arguments(args.len(), 0);
+    }
+    Object::Emitter(Emitter::default())
+}
+
+fn on(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    let (emitter, event, handler) = match emitter_event_and_handler("on", args) {
+        Ok(args) => args,
+        Err(err) => return err,
+    };
+    emitter
+        .0
+        .borrow_mut()
+        .entry(event)
+        .or_default()
+        .push(handler);
+    Object::Null
+}
+
+/// Removes every listener for the event equal to the handler, returning
+/// whether there were any.
+fn off(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    let (emitter, event, handler) = match emitter_event_and_handler("off", args) {
+        Ok(args) => args,
+        Err(err) => return err,
+    };
+    let mut listeners = emitter.0.borrow_mut();
+    let handlers = match listeners.get_mut(&event) {
+        Some(handlers) => handlers,
+        None => return Object::Boolean(false),
+    };
+    let count = handlers.len();
+    handlers.retain(|listener| *listener != handler);
+    Object::Boolean(handlers.len() < count)
+}
+
+/// Calls the listeners for the event with the remaining arguments, returning
+/// the result of the last one, or `null` if there are none.
+fn emit(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() < 2 {
+        return wrong_number_of_arguments(args.len(), 2);
+    }
+    let mut args = args.into_iter();
+    let (emitter, event) = match (args.next().unwrap(), args.next().unwrap()) {
+        (Object::Emitter(emitter), Object::String(event)) => (emitter, event),
+        (Object::Emitter(_), obj) => {
+            return Object::error(format!(
+                "argument to `emit` must be STRING, got {}",
+                obj.type_info()
+            ))
+        }
+        (obj, _) => {
+            return Object::error(format!(
+                "argument to `emit` must be EMITTER, got {}",
+                obj.type_info()
+            ))
+        }
+    };
+    let args = args.collect::<Vec<_>>();
+    // Listeners may add or remove listeners, which affects the next `emit`
+    // rather than this one.
+    let handlers = emitter.0.borrow().get(&*event).cloned().unwrap_or_default();
+    let mut obj = Object::Null;
+    for handler in handlers {
+        obj = evaluator.apply_function(handler, args.clone());
+        if let Object::Error(_) = obj {
+            return obj;
+        }
+    }
+    obj
+}
+
+fn emitter_event_and_handler(
+    name: &str,
+    args: Vec<Object>,
+) -> Result<(Emitter, String, Object), Object> {
+    if args.len() != 3 {
+        return Err(wrong_number_of_arguments(args.len(), 3));
+    }
+    let mut args = args.into_iter();
+    match (
+        args.next().unwrap(),
+        args.next().unwrap(),
+        args.next().unwrap(),
+    ) {
+        (Object::Emitter(emitter), Object::String(event), handler) if handler.is_callable() => {
+            Ok((emitter, event.to_string(), handler))
+        }
+        (Object::Emitter(_), Object::String(_), obj) => Err(Object::error(format!(
+            "argument to `{}` must be FUNCTION, got {}",
+            name,
+            obj.type_info()
+        ))),
+        (Object::Emitter(_), obj, _) => Err(Object::error(format!(
+            "argument to `{}` must be STRING, got {}",
+            name,
+            obj.type_info()
+        ))),
+        (obj, _, _) => Err(Object::error(format!(
+            "argument to `{}` must be EMITTER, got {}",
+            name,
+            obj.type_info()
+        ))),
+    }
+}
+
+/// Fails unless the condition is truthy, with the optional second argument
+/// appended to the message.
+fn assert(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    let (condition, message) = match args.as_slice() {
+        [condition] => (condition, None),
+        [condition, message] => (condition, Some(message)),
+        _ => return wrong_number_of_arguments(args.len(), 1),
+    };
+    if Evaluator::is_truthy(condition.clone()) {
+        return Object::Null;
+    }
+    match message {
+        Some(message) => Object::error(format!("assertion failed: {}", message)),
+        None => Object::error("assertion failed".to_string()),
+    }
+}
+
+// Raises an error with `message` as its text. Like any other error it ends the
+// program unless something such as `try` catches it.
+fn error(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    match args.as_slice() {
+        [message] => Object::error(message.to_string()),
+        _ => wrong_number_of_arguments(args.len(), 1),
+    }
+}
+
+fn profile_start(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if !args.is_empty() {
+        return wrong_number_of_arguments(args.len(), 0);
+    }
+    evaluator.profiler().borrow_mut().start();
+    Object::Null
+}
+
+fn profile_report(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if !args.is_empty() {
+        return wrong_number_of_arguments(args.len(), 0);
+    }
+    let profiler = evaluator.profiler();
+    let profiler = profiler.borrow();
+    if !profiler.is_enabled() {
+        return Object::error("profiling is not enabled; call `profile_start()` first".to_string());
+    }
+    profiler.report_object()
+}
+
+fn try_call(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.is_empty() {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    let mut args = args.into_iter();
+    let func = args.next().unwrap();
+    if !func.is_callable() {
+        return Object::error(format!(
+            "argument to `try` must be FUNCTION, got {}",
+            func.type_info()
+        ));
+    }
+    match evaluator.apply_function(func, args.collect()) {
+        Object::Error(_) => Object::Null,
+        obj => obj,
+    }
+}
+
+fn try_or(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() < 2 {
+        return wrong_number_of_arguments(args.len(), 2);
+    }
+    let mut args = args.into_iter();
+    let func = args.next().unwrap();
+    let default = args.next().unwrap();
+    if !func.is_callable() {
+        return Object::error(format!(
+            "argument to `try_or` must be FUNCTION, got {}",
+            func.type_info()
+        ));
+    }
+    match evaluator.apply_function(func, args.collect()) {
+        Object::Error(_) => default,
+        obj => obj,
+    }
+}
+
+fn len(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::String(value) => Object::Int(value.chars().count() as i64),
+        Object::Array(elements) => Object::Int(elements.len() as i64),
+        Object::Hash(pairs) => Object::Int(pairs.len() as i64),
+        obj => Object::error(format!(
+            "argument to `len` not supported, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+#[cfg(feature = "serde")]
+fn to_json(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match crate::json::to_value_lenient(&args[0]) {
+        Ok((value, stringified)) => {
+            for key in stringified {
+                evaluator.warn(&format!(
+                    "`to_json` converted hash key {} of type {} to a string",
+                    key,
+                    key.type_info()
+                ));
+            }
+            Object::String(value.to_string().into())
+        }
+        Err(err) => Object::error(err.to_string()),
+    }
+}
+
+#[cfg(feature = "serde")]
+fn from_json(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    let source = match &args[0] {
+        Object::String(source) => source,
+        obj => {
+            return Object::error(format!(
+                "argument to `from_json` must be STRING, got {}",
+                obj.type_info()
+            ))
+        }
+    };
+    match serde_json::from_str::<serde_json::Value>(source) {
+        Ok(value) => Object::from(value),
+        Err(err) => Object::error(format!("invalid JSON: {}", err)),
+    }
+}
+
+// Runs `f` with this thread's generator, which is shared by every evaluator.
+#[cfg(feature = "rand")]
+fn with_rng<F>(evaluator: &Evaluator, f: F) -> Object
+where
+    F: FnOnce(&mut StdRng) -> Object,
+{
+    if evaluator.sandboxed() {
+        return Object::error("random disabled".to_string());
+    }
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+#[cfg(feature = "rand")]
+fn random(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if !args.is_empty() {
+        return wrong_number_of_arguments(args.len(), 0);
+    }
+    with_rng(evaluator, |rng| Object::Float(rng.gen()))
+}
+
+#[cfg(feature = "rand")]
+fn random_int(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 2 {
+        return wrong_number_of_arguments(args.len(), 2);
+    }
+    match (&args[0], &args[1]) {
+        (Object::Int(low), Object::Int(high)) if low <= high => {
+            with_rng(evaluator, |rng| Object::Int(rng.gen_range(*low..=*high)))
+        }
+        (Object::Int(low), Object::Int(high)) => Object::error(format!(
+            "empty range in `random_int`: {} is greater than {}",
+            low, high
+        )),
+        (low, high) => Object::error(format!(
+            "arguments to `random_int` must be INTEGER, got {}, {}",
+            low.type_info(),
+            high.type_info()
+        )),
+    }
+}
+
+#[cfg(feature = "rand")]
+fn random_seed(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::Int(seed) => with_rng(evaluator, |rng| {
+            *rng = StdRng::seed_from_u64(*seed as u64);
+            Object::Null
+        }),
+        obj => Object::error(format!(
+            "argument to `random_seed` must be INTEGER, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+#[cfg(feature = "rand")]
+fn shuffle(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::Array(elements) => with_rng(evaluator, |rng| {
+            let mut elements = elements.clone();
+            elements.shuffle(rng);
+            Object::Array(elements)
+        }),
+        obj => Object::error(format!(
+            "argument to `shuffle` must be ARRAY, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+#[cfg(feature = "rand")]
+fn choice(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::Array(elements) => with_rng(evaluator, |rng| {
+            elements.choose(rng).cloned().unwrap_or(Object::Null)
+        }),
+        obj => Object::error(format!(
+            "argument to `choice` must be ARRAY, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+fn memoize(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        func @ Object::Function { .. } => Object::Memoized(Memoized {
+            func: Box::new(func.clone()),
+            cache: Rc::default(),
+        }),
+        obj => Object::error(format!(
+            "argument to `memoize` must be FUNCTION, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+// A function that applies each of `args`, from the last to the first.
+fn compose(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.is_empty() {
+        return wrong_number_of_arguments(0, 1);
+    }
+    if let Some(obj) = args.iter().find(|arg| !arg.is_callable()) {
+        return Object::error(format!(
+            "argument to `compose` must be FUNCTION, got {}",
+            obj.type_info()
+        ));
+    }
+    Object::Composed(args)
+}
+
+// A function that calls `args[0]` with the rest of `args` followed by the
+// arguments it is called with.
+fn partial(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    let mut args = args.into_iter();
+    match args.next() {
+        Some(func) if func.is_callable() => Object::Partial(Box::new(func), args.collect()),
+        Some(obj) => Object::error(format!(
+            "argument to `partial` must be FUNCTION, got {}",
+            obj.type_info()
+        )),
+        None => wrong_number_of_arguments(0, 1),
+    }
+}
+
+/// Calls a memoized function, caching its results in `memoized` by
+/// arguments. Only calls with integer arguments are cached.
+pub fn apply_memoized(evaluator: &mut Evaluator, memoized: &Memoized, args: Vec<Object>) -> Object {
+    let func = (*memoized.func).clone();
+    let key = args
+        .iter()
+        .map(|arg| match arg {
+            Object::Int(value) => Some(*value),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    let key = match key {
+        Some(key) => key,
+        None => return evaluator.apply_function(func, args),
+    };
+
+    if let Some(obj) = memoized.cache.borrow().get(&key) {
+        return obj.clone();
+    }
+    let obj = evaluator.apply_function(func, args);
+    if let Object::Error(_) = obj {
+        return obj;
+    }
+    memoized.cache.borrow_mut().insert(key, obj.clone());
+    obj
+}
+
+fn iter(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match args.into_iter().next().unwrap() {
+        Object::Array(elements) => iterator(IteratorState::Array { elements, index: 0 }),
+        obj => Object::error(format!(
+            "argument to `iter` must be ARRAY, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+fn iter_map(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    match iterator_and_function("iter_map", args) {
+        Ok((source, func)) => iterator(IteratorState::Map { source, func }),
+        Err(err) => err,
+    }
+}
+
+fn iter_filter(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    match iterator_and_function("iter_filter", args) {
+        Ok((source, func)) => iterator(IteratorState::Filter { source, func }),
+        Err(err) => err,
+    }
+}
+
+fn iter_take(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    match count_and_iterator("iter_take", args) {
+        Ok((remaining, source)) => iterator(IteratorState::Take { source, remaining }),
+        Err(err) => err,
+    }
+}
+
+fn iter_drop(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    match count_and_iterator("iter_drop", args) {
+        Ok((skip, source)) => iterator(IteratorState::Drop { source, skip }),
+        Err(err) => err,
+    }
+}
+
+/// Calls an iterator until it returns `null`. Never returns if the iterator is
+/// infinite.
+fn iter_collect(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    let source = args.into_iter().next().unwrap();
+    if !source.is_callable() {
+        return Object::error(format!(
+            "argument to `iter_collect` must be FUNCTION, got {}",
+            source.type_info()
+        ));
+    }
+    let mut elements = vec![];
+    loop {
+        match evaluator.apply_function(source.clone(), vec![]) {
+            Object::Null => return Object::Array(elements),
+            obj @ Object::Error(_) => return obj,
+            obj => elements.push(obj),
+        }
+    }
+}
+
+fn iterator(state: IteratorState) -> Object {
+    Object::Iterator(LazyIterator::new(state))
+}
+
+fn iterator_and_function(name: &str, args: Vec<Object>) -> Result<(Object, Object), Object> {
+    if args.len() != 2 {
+        return Err(wrong_number_of_arguments(args.len(), 2));
+    }
+    let mut args = args.into_iter();
+    match (args.next().unwrap(), args.next().unwrap()) {
+        (source, func) if source.is_callable() && func.is_callable() => Ok((source, func)),
+        (source, obj) => Err(Object::error(format!(
+            "argument to `{}` must be FUNCTION, got {}",
+            name,
+            if source.is_callable() { obj } else { source }.type_info()
+        ))),
+    }
+}
+
+fn count_and_iterator(name: &str, args: Vec<Object>) -> Result<(usize, Object), Object> {
+    if args.len() != 2 {
+        return Err(wrong_number_of_arguments(args.len(), 2));
+    }
+    let mut args = args.into_iter();
+    match (args.next().unwrap(), args.next().unwrap()) {
+        (Object::Int(n), _) if n < 0 => Err(Object::error(format!(
+            "argument to `{}` must not be negative, got {}",
+            name, n
+        ))),
+        (Object::Int(n), source) if source.is_callable() => Ok((n as usize, source)),
+        (Object::Int(_), obj) => Err(Object::error(format!(
+            "argument to `{}` must be FUNCTION, got {}",
+            name,
+            obj.type_info()
+        ))),
+        (obj, _) => Err(Object::error(format!(
+            "argument to `{}` must be INTEGER, got {}",
+            name,
+            obj.type_info()
+        ))),
+    }
+}
+
+/// Returns the next element of an iterator made by `iter` or an `iter_*`
+/// builtin, calling the functions it wraps only as far as needed.
+pub fn next_element(evaluator: &mut Evaluator, it: &LazyIterator, args: Vec<Object>) -> Object {
+    if !args.is_empty() {
+        return wrong_number_of_arguments(args.len(), 0);
+    }
+    // The state is not borrowed while calling out, since the functions called
+    // may advance this same iterator.
+    let mut state = it.0.borrow_mut();
+    match &mut *state {
+        IteratorState::Array { elements, index } => {
+            let element = elements.get(*index).cloned();
+            *index += 1;
+            element.unwrap_or(Object::Null)
+        }
+        IteratorState::Map { source, func } => {
+            let (source, func) = (source.clone(), func.clone());
+            drop(state);
+            match evaluator.apply_function(source, vec![]) {
+                obj @ (Object::Null | Object::Error(_)) => obj,
+                obj => evaluator.apply_function(func, vec![obj]),
+            }
+        }
+        IteratorState::Filter { source, func } => {
+            let (source, func) = (source.clone(), func.clone());
+            drop(state);
+            loop {
+                match evaluator.apply_function(source.clone(), vec![]) {
+                    obj @ (Object::Null | Object::Error(_)) => return obj,
+                    obj => {
+                        let keep = evaluator.apply_function(func.clone(), vec![obj.clone()]);
+                        if let Object::Error(_) = keep {
+                            return keep;
+                        }
+                        if Evaluator::is_truthy(keep) {
+                            return obj;
+                        }
+                    }
+                }
+            }
+        }
+        IteratorState::Take { source, remaining } => {
+            if *remaining == 0 {
+                return Object::Null;
+            }
+            *remaining -= 1;
+            let source = source.clone();
+            drop(state);
+            evaluator.apply_function(source, vec![])
+        }
+        IteratorState::Drop { source, skip } => {
+            let (source, skip) = (source.clone(), std::mem::take(skip));
+            drop(state);
+            for _ in 0..skip {
+                let obj = evaluator.apply_function(source.clone(), vec![]);
+                if matches!(obj, Object::Null | Object::Error(_)) {
+                    return obj;
+                }
+            }
+            evaluator.apply_function(source, vec![])
+        }
+    }
+}
+
+fn partition(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    let (elements, predicate) = match array_and_function("partition", args) {
+        Ok(args) => args,
+        Err(err) => return err,
+    };
+    let mut matched = vec![];
+    let mut rest = vec![];
+    for element in elements {
+        let obj = evaluator.apply_function(predicate.clone(), vec![element.clone()]);
+        if let Object::Error(_) = obj {
+            return obj;
+        }
+        if Evaluator::is_truthy(obj) {
+            matched.push(element);
+        } else {
+            rest.push(element);
+        }
+    }
+    Object::Array(vec![Object::Array(matched), Object::Array(rest)])
+}
+
+fn puts(evaluator: &mut Evaluator, args: Vec<Object>) -> Object {
+    let output = evaluator.output();
+    let mut output = output.borrow_mut();
+    for arg in args {
+        if let Err(err) = writeln!(output, "{}", arg) {
+            return Object::error(format!("could not write output: {}", err));
+        }
+    }
+    Object::Null
+}
+
+fn set(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 3 {
+        return wrong_number_of_arguments(args.len(), 3);
+    }
+    match (&args[0], &args[1]) {
+        (Object::Array(elements), Object::Int(index)) => {
+            if *index < 0 || *index as usize >= elements.len() {
+                return Object::error(format!("index out of range: {}", index));
+            }
+            let mut elements = elements.clone();
+            elements[*index as usize] = args[2].clone();
+            Object::Array(elements)
+        }
+        #[cfg(feature = "bigint")]
+        (Object::Array(_), Object::BigInt(index)) => {
+            Object::error(format!("index out of range: {}", index))
+        }
+        (Object::Array(_), obj) => Object::error(format!(
+            "index to `set` must be INTEGER, got {}",
+            obj.type_info()
+        )),
+        (obj, _) => Object::error(format!(
+            "argument to `set` must be ARRAY, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+fn substr(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 3 {
+        return wrong_number_of_arguments(args.len(), 3);
+    }
+    match (&args[0], &args[1], &args[2]) {
+        (Object::String(value), Object::Int(start), Object::Int(length))
+            if *start >= 0 && *length >= 0 =>
+        {
+            Object::String(
+                value
+                    .chars()
+                    .skip(*start as usize)
+                    .take(*length as usize)
+                    .collect::<String>()
+                    .into(),
+            )
+        }
+        (Object::String(_), Object::Int(_), Object::Int(_)) => {
+            Object::error("arguments to `substr` must not be negative".to_string())
+        }
+        (s, start, length) => Object::error(format!(
+            "arguments to `substr` must be STRING, INTEGER, INTEGER, got {}, {}, {}",
+            s.type_info(),
+            start.type_info(),
+            length.type_info()
+        )),
+    }
+}
+
+fn ord(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::String(value) => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Object::Int(c as i64),
+                _ => Object::error(format!(
+                    "argument to `ord` must be a single character, got \"{}\"",
+                    value
+                )),
+            }
+        }
+        obj => Object::error(format!(
+            "argument to `ord` must be STRING, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+fn chr(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::Int(value) => match u32::try_from(*value).ok().and_then(char::from_u32) {
+            Some(c) => Object::String(c.to_string().into()),
+            None => Object::error(format!(
+                "argument to `chr` is not a Unicode scalar value: {}",
+                value
+            )),
+        },
+        obj => Object::error(format!(
+            "argument to `chr` must be INTEGER, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+/// Repeats a string or the elements of an array `count` times.
+fn repeat(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 2 {
+        return wrong_number_of_arguments(args.len(), 2);
+    }
+    let count = match &args[1] {
+        Object::Int(count) if *count < 0 => {
+            return Object::error(format!("negative count to `repeat`: {}", count))
+        }
+        Object::Int(count) => *count as usize,
+        obj => {
+            return Object::error(format!(
+                "second argument to `repeat` must be INTEGER, got {}",
+                obj.type_info()
+            ))
+        }
+    };
+    match &args[0] {
+        Object::String(value) => Object::String(value.repeat(count).into()),
+        Object::Array(elements) => Object::Array(
+            elements
+                .iter()
+                .cycle()
+                .take(elements.len() * count)
+                .cloned()
+                .collect(),
+        ),
+        obj => Object::error(format!(
+            "first argument to `repeat` must be STRING or ARRAY, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+/// Reverses a string by Unicode scalar values, or the elements of an array.
+fn reverse(_: &mut Evaluator, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(args.len(), 1);
+    }
+    match &args[0] {
+        Object::String(value) => Object::String(value.chars().rev().collect::<String>().into()),
+        Object::Array(elements) => Object::Array(elements.iter().rev().cloned().collect()),
+        obj => Object::error(format!(
+            "argument to `reverse` must be STRING or ARRAY, got {}",
+            obj.type_info()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::fs;
+
+    use crate::{
+        environment::Environment,
+        evaluator::{Evaluator, FsAccess},
+        interpreter::Interpreter,
+        lexer::Lexer,
+        object::{Builtin, Object},
+        parser::Parser,
+    };
+
+    thread_local! {
+        static CALLS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn tick(_: &mut Evaluator, _: Vec<Object>) -> Object {
+        CALLS.with(|calls| calls.set(calls.get() + 1));
+        Object::Null
+    }
+
+    fn count_calls(input: &str) -> (Object, usize) {
+        CALLS.with(|calls| calls.set(0));
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program().unwrap();
+        let mut env = Environment::with_builtins();
+        env.set(
+            "tick",
+            Object::Builtin(Builtin {
+                name: "tick",
+                func: tick,
+            }),
+        );
+        let mut evaluator = Evaluator::new(&mut env);
+        let object = evaluator.evaluate(program);
+        (object, CALLS.with(|calls| calls.get()))
+    }
+
+    #[test]
+    fn test_flatten() {
+        let tests = vec![
+            ("flatten([[1, 2], [3, [4, 5]]])", "[1, 2, 3, [4, 5]]"),
+            ("flatten([1, [2, 3], 4])", "[1, 2, 3, 4]"),
+            ("flatten([1, 2, 3])", "[1, 2, 3]"),
+            ("flatten([])", "[]"),
+            ("flatten_deep([[1, [2, [3]]]])", "[1, 2, 3]"),
+            ("flatten_deep([1, [true, [2, [[]]]], 3])", "[1, true, 2, 3]"),
+            ("flatten_deep([])", "[]"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object.to_string(), expect);
+        }
+    }
+
+    #[test]
+    fn test_flatten_errors() {
+        let tests = vec![
+            (
+                "flatten(1)",
+                "argument to `flatten` must be ARRAY, got INTEGER",
+            ),
+            (
+                "flatten_deep(true)",
+                "argument to `flatten_deep` must be ARRAY, got BOOLEAN",
+            ),
+            (
+                "flatten([1], [2])",
+                "wrong number of arguments. got=2, want=1",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_group_by() {
+        let tests = vec![
+            (
+                "group_by([1, 2, 3, 4], fn(x) { x % 2 == 0 })",
+                "{false: [1, 3], true: [2, 4]}",
+            ),
+            (
+                r#"group_by(["apple", "avocado", "banana"], fn(s) { substr(s, 0, 1) })"#,
+                "{a: [apple, avocado], b: [banana]}",
+            ),
+            ("group_by([], fn(x) { x })", "{}"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object.to_string(), expect);
+        }
+
+        let object = test_evaluate("group_by([1, 2, 3, 4], fn(x) { x % 2 == 0 })");
+        assert_eq!(
+            object,
+            Object::Hash(
+                vec![
+                    (
+                        Object::Boolean(false),
+                        Object::Array(vec![Object::Int(1), Object::Int(3)])
+                    ),
+                    (
+                        Object::Boolean(true),
+                        Object::Array(vec![Object::Int(2), Object::Int(4)])
+                    ),
+                ]
+                .into()
+            )
+        );
+    }
+
+    #[test]
+    fn test_group_by_errors() {
+        let tests = vec![
+            (
+                "group_by(1, fn(x) { x })",
+                "argument to `group_by` must be ARRAY, got INTEGER",
+            ),
+            (
+                "group_by([1], 2)",
+                "argument to `group_by` must be FUNCTION, got INTEGER",
+            ),
+            (
+                "group_by([1], fn(x) { [x] })",
+                "unusable as hash key: ARRAY",
+            ),
+            (
+                "group_by([1], fn(x) { -true })",
+                "unknown operator: -BOOLEAN",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_each() {
+        let (object, calls) = count_calls("each([1, 2, 3], fn(x) { tick() })");
+        assert_eq!(object, Object::Null);
+        assert_eq!(calls, 3);
+
+        let (object, calls) = count_calls("each([], fn(x) { tick() })");
+        assert_eq!(object, Object::Null);
+        assert_eq!(calls, 0);
+
+        let (object, calls) = count_calls(
+            "each_with_index([10, 20, 30], fn(i, x) { if (x == (i + 1) * 10) { tick() } })",
+        );
+        assert_eq!(object, Object::Null);
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_each_errors() {
+        let (object, calls) =
+            count_calls("each([1, 2, 3], fn(x) { tick(); if (x == 2) { -true } })");
+        assert_eq!(
+            object,
+            Object::error("unknown operator: -BOOLEAN".to_string())
+        );
+        assert_eq!(calls, 2);
+
+        let tests = vec![
+            (
+                "each(1, fn(x) { x })",
+                "argument to `each` must be ARRAY, got INTEGER",
+            ),
+            (
+                "each_with_index([1], 1)",
+                "argument to `each_with_index` must be FUNCTION, got INTEGER",
+            ),
+            ("each([1])", "wrong number of arguments. got=1, want=2"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_partition() {
+        let tests = vec![
+            (
+                "partition([1, 2, 3, 4, 5], fn(x) { x > 3 })",
+                "[[4, 5], [1, 2, 3]]",
+            ),
+            ("partition([], fn(x) { x })", "[[], []]"),
+            ("partition([1, false, 0], fn(x) { x })", "[[1, 0], [false]]"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object.to_string(), expect);
+        }
+    }
+
+    #[test]
+    fn test_partition_errors() {
+        let (object, calls) =
+            count_calls("partition([1, 2, 3], fn(x) { tick(); if (x == 2) { -true } })");
+        assert_eq!(
+            object,
+            Object::error("unknown operator: -BOOLEAN".to_string())
+        );
+        assert_eq!(calls, 2);
+
+        let tests = vec![
+            (
+                "partition(1, fn(x) { x })",
+                "argument to `partition` must be ARRAY, got INTEGER",
+            ),
+            (
+                "partition([1], true)",
+                "argument to `partition` must be FUNCTION, got BOOLEAN",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_debug() {
+        let object = test_evaluate("debug([1, 2])");
+        assert_eq!(object, Object::String("Array([Int(1), Int(2)])".into()));
+
+        match test_evaluate("let secret = 42; let f = fn(x) { x + secret }; debug(f)") {
+            Object::String(debug) => {
+                assert!(debug.starts_with("Function {"));
+                assert!(debug.contains(r#"["f", "secret"]] }"#), "{}", debug);
+            }
+            obj => panic!("object is not String. got={}", obj),
+        }
+
+        assert_eq!(
+            test_evaluate("debug()"),
+            Object::error("wrong number of arguments. got=0, want=1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_glob() {
+        let tests = vec![
+            (r#"glob_match("*.rs", "main.rs")"#, "true"),
+            (r#"glob_match("?.rs", "main.rs")"#, "false"),
+            (r#"glob_match("test_*", "test_foo")"#, "true"),
+            (r#"glob_match("*", "")"#, "true"),
+            (r#"glob_match("?", "")"#, "false"),
+            (r#"glob_match("a*b*c", "axxbyybzc")"#, "true"),
+            (r#"glob_match("a*b?c", "abxbc")"#, "false"),
+            (r#"glob_match("*ab", "aab")"#, "true"),
+            (r#"glob_match("main.rs", "main.rs")"#, "true"),
+            (r#"glob_match("main.rs", "main.rsx")"#, "false"),
+            (r#"glob_filter(["foo.rs", "bar.txt"], "*.rs")"#, "[foo.rs]"),
+            (r#"glob_filter([], "*")"#, "[]"),
+            (
+                r#"glob_match(1, "a")"#,
+                "Error: arguments to `glob_match` must be STRING, got INTEGER, STRING",
+            ),
+            (
+                r#"glob_filter("a", "*")"#,
+                "Error: arguments to `glob_filter` must be ARRAY, STRING, got STRING, STRING",
+            ),
+            (
+                r#"glob_filter(["a", 1], "*")"#,
+                "Error: elements of `glob_filter` must be STRING, got INTEGER",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object.to_string(), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_deduplicate() {
+        let tests = vec![
+            ("deduplicate([1, 2, 1, 3, 2])", "[1, 2, 3]"),
+            (r#"deduplicate(["a", "b", "a"])"#, "[a, b]"),
+            ("deduplicate([])", "[]"),
+            (
+                "deduplicate([true, 1, true, if (false) { 1 }, 1])",
+                "[true, 1, null]",
+            ),
+            ("deduplicate([[1], [1], [2]])", "[[1], [2]]"),
+            (
+                "let f = fn(x) { x }; let g = fn(x) { x }; len(deduplicate([f, 1, f, g, 1]))",
+                "3",
+            ),
+            (
+                "deduplicate(1)",
+                "Error: argument to `deduplicate` must be ARRAY, got INTEGER",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object.to_string(), expect);
+        }
+    }
+
+    #[test]
+    fn test_hash_set_operations() {
+        let tests = vec![
+            (r#"merge({"a": 1}, {"b": 2})"#, "{a: 1, b: 2}"),
+            (r#"merge({"a": 1}, {"a": 2})"#, "{a: 2}"),
+            // keys keep the position they have in the first hash
+            (
+                r#"merge({"a": 1, "b": 2}, {"c": 3, "a": 4})"#,
+                "{a: 4, b: 2, c: 3}",
+            ),
+            (
+                r#"merge({"a": 1}, {"b": 2}, {"a": 3, "c": 4})"#,
+                "{a: 3, b: 2, c: 4}",
+            ),
+            (r#"diff({"a": 1, "b": 2}, {"b": 3})"#, "{a: 1}"),
+            (
+                r#"intersection({"a": 1, "b": 2}, {"b": 0, "c": 3})"#,
+                "{b: 2}",
+            ),
+            (r#"let h = {"a": 1}; merge(h, {"a": 2}); h"#, "{a: 1}"),
+            (
+                r#"merge({"a": 1}, [1])"#,
+                "Error: arguments to `merge` must be HASH, got ARRAY",
+            ),
+            (
+                r#"merge({"a": 1})"#,
+                "Error: wrong number of arguments. got=1, want=2",
+            ),
+            (
+                r#"diff(1, {})"#,
+                "Error: arguments to `diff` must be HASH, got INTEGER",
+            ),
+            (
+                r#"intersection({}, {}, {})"#,
+                "Error: wrong number of arguments. got=3, want=2",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object.to_string(), expect);
+        }
+    }
+
+    #[test]
+    fn test_set() {
+        let tests = vec![
+            ("set([1, 2, 3], 1, 5)", "[1, 5, 3]"),
+            ("set([1, 2, 3], 2, [4])", "[1, 2, [4]]"),
+            ("let a = [1, 2, 3]; let b = set(a, 0, 0); a", "[1, 2, 3]"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object.to_string(), expect);
+        }
+    }
+
+    #[test]
+    fn test_set_errors() {
+        let tests = vec![
+            ("set([1, 2, 3], 3, 0)", "index out of range: 3"),
+            ("set([1, 2, 3], -1, 0)", "index out of range: -1"),
+            (
+                "set(1, 0, 0)",
+                "argument to `set` must be ARRAY, got INTEGER",
+            ),
+            (
+                r#"set([1], "0", 0)"#,
+                "index to `set` must be INTEGER, got STRING",
+            ),
+            ("set([1], 0)", "wrong number of arguments. got=2, want=3"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_reductions() {
+        let tests = vec![
+            ("sum([1, 2, 3])", 6),
+            ("sum([-4])", -4),
+            ("sum([])", 0),
+            ("min([3, -1, 2])", -1),
+            ("min([7])", 7),
+            ("max([3, -1, 2])", 3),
+            ("max([-7, -8])", -7),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::Int(expect));
+        }
+
+        // integers and floats mix as they do with `+`
+        let tests = vec![
+            ("sum([1, 2.5])", 3.5),
+            ("sum([0.5, 0.25])", 0.75),
+            ("sum([0.5, 1, 2])", 3.5),
+            ("min([1.5, 2.5])", 1.5),
+            ("min([2, 0.5, 1])", 0.5),
+            ("max([1, 2.5])", 2.5),
+            ("max([-0.5, -1.5])", -0.5),
+        ];
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), Object::Float(expect), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_reductions_keep_type() {
+        // the extreme element is returned as it is, not converted
+        assert_eq!(test_evaluate("max([3, 2.5])"), Object::Int(3));
+        assert_eq!(test_evaluate("min([1, 1.0])"), Object::Int(1));
+        assert_eq!(test_evaluate("min([1.0, 1])"), Object::Float(1.0));
+    }
+
+    #[test]
+    fn test_reduction_errors() {
+        let tests = vec![
+            ("min([])", "`min` of empty array"),
+            ("max([])", "`max` of empty array"),
+            (
+                r#"sum([1, "2"])"#,
+                "elements of `sum` must be INTEGER or FLOAT, got STRING",
+            ),
+            ("sum(1)", "argument to `sum` must be ARRAY, got INTEGER"),
+            (
+                "max([true])",
+                "elements of `max` must be INTEGER or FLOAT, got BOOLEAN",
+            ),
+            ("min(1)", "argument to `min` must be ARRAY, got INTEGER"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_pairs() {
+        let tests = vec![
+            (r#"entries({"b": 2, "a": 1})"#, "[(a, 1), (b, 2)]"),
+            ("entries({})", "[]"),
+            (r#"pair_key(pair("x", 1))"#, "x"),
+            (r#"pair_value(pair("x", [1]))"#, "[1]"),
+            (
+                r#"group_by(entries({"a": 1, "b": 2, "c": 1}), fn(p) { pair_value(p) })"#,
+                "{1: [(a, 1), (c, 1)], 2: [(b, 2)]}",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object.to_string(), expect);
+        }
+
+        let object = test_evaluate(r#"entries({"a": 1})"#);
+        assert_eq!(
+            object,
+            Object::Array(vec![Object::Pair(
+                Box::new(Object::String("a".into())),
+                Box::new(Object::Int(1))
+            )])
+        );
+    }
+
+    #[test]
+    fn test_pair_errors() {
+        let tests = vec![
+            (
+                "entries([1])",
+                "argument to `entries` must be HASH, got ARRAY",
+            ),
+            (
+                "pair_key(1)",
+                "argument to `pair_key` must be PAIR, got INTEGER",
+            ),
+            (
+                "pair_value({})",
+                "argument to `pair_value` must be PAIR, got HASH",
+            ),
+            ("pair(1)", "wrong number of arguments. got=1, want=2"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_numeric() {
+        let tests = vec![
+            ("abs(-5)", 5),
+            ("abs(5)", 5),
+            ("abs(0)", 0),
+            ("pow(2, 10)", 1024),
+            ("pow(-3, 3)", -27),
+            ("pow(7, 0)", 1),
+            ("sqrt(16)", 4),
+            ("sqrt(15)", 3),
+            ("sqrt(0)", 0),
+            ("sqrt(9223372036854775807)", 3037000499),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::Int(expect));
+        }
+    }
+
+    #[test]
+    fn test_math() {
+        let tests = vec![
+            "abs(-5) == 5",
+            "abs(-2.5) == 2.5",
+            "min(3, 7) == 3",
+            "max(3, 7) == 7",
+            "min(2, 1.5) == 1.5",
+            "max(2, 1.5) == 2",
+            r#"min("b", "abc") == "abc""#,
+            "floor(3.7) == 3.0",
+            "ceil(3.2) == 4.0",
+            "round(3.5) == 4.0",
+            "round(-3.5) == -4.0",
+            "floor(3) == 3",
+            "is_float(floor(3.7))",
+            "is_int(ceil(3))",
+            "max_arr([1, 5, 3]) == 5",
+            "min_arr([1, 5, 3]) == 1",
+            "max_arr([0.5, 2.25]) == 2.25",
+            r#"min_arr(["pear", "apple"]) == "apple""#,
+        ];
+
+        for input in tests {
+            assert_eq!(test_evaluate(input), Object::Boolean(true), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_math_errors() {
+        let tests = vec![
+            (
+                "min(1, true)",
+                "arguments to `min` must be INTEGER, FLOAT or STRING, got INTEGER, BOOLEAN",
+            ),
+            (
+                r#"max("a", 1)"#,
+                "arguments to `max` must be INTEGER, FLOAT or STRING, got STRING, INTEGER",
+            ),
+            (
+                r#"floor("1.5")"#,
+                "argument to `floor` must be FLOAT or INTEGER, got STRING",
+            ),
+            ("max_arr([])", "`max_arr` of empty array"),
+            (
+                r#"min_arr([1, "2"])"#,
+                "elements of `min_arr` must have the same type, got INTEGER, STRING",
+            ),
+            (
+                "min_arr([true])",
+                "elements of `min_arr` must be INTEGER, FLOAT or STRING, got BOOLEAN",
+            ),
+            (
+                "max_arr(1)",
+                "argument to `max_arr` must be ARRAY, got INTEGER",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_numeric_errors() {
+        let tests = vec![
+            ("pow(2, -1)", "negative exponent to `pow`: -1"),
+            ("pow(2, 4294967296)", "integer overflow in `pow`"),
+            ("sqrt(-4)", "`sqrt` of negative number: -4"),
+            (
+                r#"abs("1")"#,
+                "argument to `abs` must be INTEGER or FLOAT, got STRING",
+            ),
+            (
+                "pow(true, 1)",
+                "arguments to `pow` must be INTEGER, got BOOLEAN, INTEGER",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()));
+        }
+    }
+
+    #[cfg(not(feature = "bigint"))]
+    #[test]
+    fn test_integer_overflow_errors() {
+        let tests = vec![
+            ("sum([9223372036854775807, 1])", "integer overflow in `sum`"),
+            ("pow(2, 64)", "integer overflow in `pow`"),
+            ("abs(-9223372036854775807 - 1)", "integer overflow in `abs`"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()));
+        }
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_bigint_builtins() {
+        let big = "(9223372036854775807 + 1)";
+        let tests = vec![
+            (format!("abs(-{})", big), "9223372036854775808"),
+            (
+                "abs(-9223372036854775807 - 1)".to_string(),
+                "9223372036854775808",
+            ),
+            (format!("sum([{}, 1])", big), "9223372036854775809"),
+            (
+                "sum([9223372036854775807, 1])".to_string(),
+                "9223372036854775808",
+            ),
+            (format!("sum([{}, -{}])", big, big), "0"),
+            ("pow(2, 100)".to_string(), "1267650600228229401496703205376"),
+            (
+                format!("pow({}, 2)", big),
+                "85070591730234615865843651857942052864",
+            ),
+            (format!("to_hex({})", big), "8000000000000000"),
+            (format!("to_hex_upper(-{} - 10)", big), "-800000000000000A"),
+            (format!("to_oct({})", big), "1000000000000000000000"),
+            (format!("is_int({})", big), "true"),
+            (format!("max([1, {}, 2])", big), "9223372036854775808"),
+            (format!("min([1, -{}])", big), "-9223372036854775808"),
+            (format!("max([1.5, {}])", big), "9223372036854775808"),
+            (format!("min({}, 0.5)", big), "0.5"),
+            (
+                format!("[1, 2][{}]", big),
+                "Error: index out of range: 9223372036854775808",
+            ),
+            (
+                format!(r#""ab"[-{} - 1]"#, big),
+                "Error: index out of range: -9223372036854775809",
+            ),
+            (
+                format!("set([1, 2], {}, 0)", big),
+                "Error: index out of range: 9223372036854775808",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(&input).to_string(), expect, "{}", input);
+        }
+        assert_eq!(
+            test_evaluate("sum([9223372036854775807, 1, -1])"),
+            Object::Int(i64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_number_formatting() {
+        let tests = vec![
+            ("to_hex(255)", "ff"),
+            ("to_hex_upper(255)", "FF"),
+            ("to_bin(10)", "1010"),
+            ("to_oct(8)", "10"),
+            ("to_bin(0)", "0"),
+            ("to_hex(-1)", "ffffffffffffffff"),
+            ("to_oct(-8)", "1777777777777777777770"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::String(expect.into()));
+        }
+
+        assert_eq!(
+            test_evaluate(r#"to_hex("ff")"#),
+            Object::error("argument to `to_hex` must be INTEGER, got STRING".to_string())
+        );
+        assert_eq!(
+            test_evaluate("to_bin(1, 2)"),
+            Object::error("wrong number of arguments. got=2, want=1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_chr_ord() {
+        let tests = vec![
+            (r#"ord("A")"#, Object::Int(65)),
+            (r#"ord(" ")"#, Object::Int(32)),
+            (r#"ord("é")"#, Object::Int(233)),
+            (r#"ord("🐒")"#, Object::Int(0x1F412)),
+            ("chr(65)", Object::String("A".into())),
+            ("chr(128018)", Object::String("🐒".into())),
+            (r#"chr(ord("a") + 1)"#, Object::String("b".into())),
+            (r#"chr(ord("é"))"#, Object::String("é".into())),
+            (r#"chr(ord("🐒"))"#, Object::String("🐒".into())),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_repeat() {
+        let string = |s: &str| Object::String(s.into());
+        let tests = vec![
+            (r#"repeat("ab", 3)"#, string("ababab")),
+            (r#"repeat("ab", 1)"#, string("ab")),
+            (r#"repeat("ab", 0)"#, string("")),
+            (r#"repeat("", 5)"#, string("")),
+            (
+                "repeat([0], 3)",
+                Object::Array(vec![Object::Int(0), Object::Int(0), Object::Int(0)]),
+            ),
+            (
+                "repeat([1, 2], 2)",
+                Object::Array(vec![
+                    Object::Int(1),
+                    Object::Int(2),
+                    Object::Int(1),
+                    Object::Int(2),
+                ]),
+            ),
+            ("repeat([1, 2], 0)", Object::Array(vec![])),
+            (
+                r#"repeat("a", -1)"#,
+                Object::error("negative count to `repeat`: -1".to_string()),
+            ),
+            (
+                "repeat([1], -2)",
+                Object::error("negative count to `repeat`: -2".to_string()),
+            ),
+            (
+                r#"repeat("a", "3")"#,
+                Object::error(
+                    "second argument to `repeat` must be INTEGER, got STRING".to_string(),
+                ),
+            ),
+            (
+                "repeat(1, 3)",
+                Object::error(
+                    "first argument to `repeat` must be STRING or ARRAY, got INTEGER".to_string(),
+                ),
+            ),
+            (
+                r#"repeat("a")"#,
+                Object::error("wrong number of arguments. got=1, want=2".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_reverse() {
+        let string = |s: &str| Object::String(s.into());
+        let tests = vec![
+            ("reverse([1, 2, 3])", test_evaluate("[3, 2, 1]")),
+            ("reverse([])", Object::Array(vec![])),
+            (r#"reverse("abc")"#, string("cba")),
+            (r#"reverse("")"#, string("")),
+            (r#"reverse("héllo🐒")"#, string("🐒olléh")),
+            // The argument is left as it was.
+            ("let a = [1, 2]; reverse(a); a", test_evaluate("[1, 2]")),
+            (r#"let s = "ab"; reverse(s); s"#, string("ab")),
+            (
+                "reverse(1)",
+                Object::error(
+                    "argument to `reverse` must be STRING or ARRAY, got INTEGER".to_string(),
+                ),
+            ),
+            (
+                "reverse([1], [2])",
+                Object::error("wrong number of arguments. got=2, want=1".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_chr_ord_errors() {
+        let tests = vec![
+            (
+                r#"ord("")"#,
+                r#"argument to `ord` must be a single character, got """#,
+            ),
+            (
+                r#"ord("ab")"#,
+                r#"argument to `ord` must be a single character, got "ab""#,
+            ),
+            ("ord(65)", "argument to `ord` must be STRING, got INTEGER"),
+            (
+                r#"chr("A")"#,
+                "argument to `chr` must be INTEGER, got STRING",
+            ),
+            (
+                "chr(-1)",
+                "argument to `chr` is not a Unicode scalar value: -1",
+            ),
+            (
+                "chr(55296)",
+                "argument to `chr` is not a Unicode scalar value: 55296",
+            ),
+            (
+                "chr(1114112)",
+                "argument to `chr` is not a Unicode scalar value: 1114112",
+            ),
+            ("chr(1, 2)", "wrong number of arguments. got=2, want=1"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                test_evaluate(input),
+                Object::error(expect.to_string()),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_number() {
+        let ok = |value| Object::Array(vec![value, Object::Null]);
+        let err = |message: &str| Object::Array(vec![Object::Null, Object::String(message.into())]);
+        let tests = vec![
+            (r#"parse_int("42")"#, ok(Object::Int(42))),
+            (r#"parse_int(" -7 ")"#, ok(Object::Int(-7))),
+            (
+                r#"parse_int("abc")"#,
+                err(r#"could not parse "abc" as integer"#),
+            ),
+            (r#"parse_int("")"#, err(r#"could not parse "" as integer"#)),
+            (
+                r#"parse_int("1.5")"#,
+                err(r#"could not parse "1.5" as integer"#),
+            ),
+            (r#"parse_float("2.75")"#, ok(Object::Float(2.75))),
+            (r#"parse_float("2")"#, ok(Object::Float(2.0))),
+            (
+                r#"parse_float("x1")"#,
+                err(r#"could not parse "x1" as float"#),
+            ),
+            (
+                r#"parse_float("inf")"#,
+                err(r#"could not parse "inf" as float"#),
+            ),
+            (
+                "parse_int(42)",
+                Object::error("argument to `parse_int` must be STRING, got INTEGER".to_string()),
+            ),
+            (
+                r#"parse_float("1", "2")"#,
+                Object::error("wrong number of arguments. got=2, want=1".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_assert() {
+        let tests = vec![
+            ("assert(true)", Object::Null),
+            ("assert(1 + 1 == 2, \"sum\")", Object::Null),
+            (
+                "assert(1 > 2)",
+                Object::error("assertion failed".to_string()),
+            ),
+            (
+                "assert(false, \"1 is not 2\")",
+                Object::error("assertion failed: 1 is not 2".to_string()),
+            ),
+            (
+                "assert(if (false) { 1 }, [1])",
+                Object::error("assertion failed: [1]".to_string()),
+            ),
+            (
+                "assert()",
+                Object::error("wrong number of arguments. got=0, want=1".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_error() {
+        let tests = vec![
+            (r#"error("boom")"#, Object::error("boom".to_string())),
+            ("error([1, 2])", Object::error("[1, 2]".to_string())),
+            (r#"error("first"); 1"#, Object::error("first".to_string())),
+            (
+                r#"let check = fn(x) { if (x < 0) { error("negative") } else { x } };
+                   let twice = fn(x) { check(x) * 2 };
+                   [twice(1), twice(-1), twice(2)]"#,
+                Object::error("negative".to_string()),
+            ),
+            (r#"try(fn() { error("boom") })"#, Object::Null),
+            (r#"is_error(error("boom"))"#, Object::Boolean(true)),
+            // re-raising from a catch block
+            (
+                "try { 1 / 0 } catch (e) { error(e) }",
+                Object::error("division by zero".to_string()),
+            ),
+            (
+                r#"try { try { 1 / 0 } catch (e) { error("again: " + e) } } catch (e) { e }"#,
+                Object::String("again: division by zero".into()),
+            ),
+            (
+                "error()",
+                Object::error("wrong number of arguments. got=0, want=1".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_type_predicates() {
+        let tests = vec![
+            ("is_int(5)", true),
+            (r#"is_int("hi")"#, false),
+            ("is_float(5)", false),
+            ("is_float(5.0)", true),
+            (r#"is_string("hi")"#, true),
+            ("is_string(5)", false),
+            ("is_bool(false)", true),
+            ("is_bool(0)", false),
+            ("is_null(if (false) { 1 })", true),
+            ("is_null(0)", false),
+            ("is_array([])", true),
+            ("is_array({})", false),
+            ("is_hash({})", true),
+            ("is_hash([])", false),
+            ("is_function(fn(x) { x })", true),
+            ("is_function(sum)", true),
+            ("is_function(memoize(fn(x) { x }))", true),
+            ("is_function(partial(fn(x) { x }, 1))", true),
+            ("is_function(compose(len))", true),
+            ("is_function(1)", false),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::Boolean(expect), "{}", input);
+        }
+
+        let object = test_evaluate("is_int(1, 2)");
+        assert_eq!(
+            object,
+            Object::error("wrong number of arguments. got=2, want=1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_try() {
+        let tests = vec![
+            ("try(fn() { 5 / 0 })", Object::Null),
+            ("try(fn(x, y) { x / y }, 10, 2)", Object::Int(5)),
+            ("try_or(fn() { 5 / 0 }, -1)", Object::Int(-1)),
+            ("try_or(fn(x) { x * 2 }, -1, 4)", Object::Int(8)),
+            ("try(sqrt, -1)", Object::Null),
+            ("is_error(5 / 0)", Object::Boolean(true)),
+            (
+                "let fail = fn() { -true }; is_error(fail())",
+                Object::Boolean(true),
+            ),
+            ("is_error(5)", Object::Boolean(false)),
+            (
+                "let f = fn() { is_error(5 / 0) }; f()",
+                Object::Boolean(true),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_try_errors() {
+        let tests = vec![
+            ("try(1)", "argument to `try` must be FUNCTION, got INTEGER"),
+            (
+                "try_or(fn() { 1 })",
+                "wrong number of arguments. got=1, want=2",
+            ),
+            ("try()", "wrong number of arguments. got=0, want=1"),
+            ("is_error(1, 2)", "wrong number of arguments. got=2, want=1"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_len() {
+        let tests = vec![
+            (r#"len("")"#, 0),
+            (r#"len("four")"#, 4),
+            (r#"len("héllo")"#, 5),
+            ("len([1, 2, 3])", 3),
+            (r#"len({"a": 1})"#, 1),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::Int(expect));
+        }
+
+        let object = test_evaluate("len(1)");
+        assert_eq!(
+            object,
+            Object::error("argument to `len` not supported, got INTEGER".to_string())
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json() {
+        let tests = vec![
+            (
+                r#"to_json({"a": 1, "b": [2, {"c": if (false) { 1 }}], "d": "x"})"#,
+                Object::String(r#"{"a":1,"b":[2,{"c":null}],"d":"x"}"#.into()),
+            ),
+            (r#"len(from_json("[1, [2, 3], []]"))"#, Object::Int(3)),
+            (
+                r#"to_json({"b": [2, 3], "a": 1})"#,
+                Object::String(r#"{"a":1,"b":[2,3]}"#.into()),
+            ),
+            (
+                "to_json(fn(x) { x })",
+                Object::error("cannot convert function to JSON value".to_string()),
+            ),
+            (
+                "to_json([len])",
+                Object::error("cannot convert function to JSON value".to_string()),
+            ),
+            (r#"from_json("1.5")"#, Object::Float(1.5)),
+            ("to_json([0.25])", Object::String("[0.25]".into())),
+            // a stringified key may not collide with another key
+            (
+                r#"to_json({1: 2, "1": 3})"#,
+                Object::error(
+                    r#"cannot convert hash to JSON: more than one key is "1""#.to_string(),
+                ),
+            ),
+            (
+                r#"to_json([{"true": 1, true: 2}])"#,
+                Object::error(
+                    r#"cannot convert hash to JSON: more than one key is "true""#.to_string(),
+                ),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, expect, "{}", input);
+        }
+
+        let inputs = vec![
+            "1",
+            "-2.5",
+            "true",
+            "if (false) { 1 }",
+            r#""hello world""#,
+            r#"[1, 2.0, [false], {}, "s"]"#,
+            r#"{"a": {"b": [1, {"c": 2}]}, "d": 0.5}"#,
+        ];
+
+        for input in inputs {
+            let object = test_evaluate(&format!("from_json(to_json({}))", input));
+            assert_eq!(object, test_evaluate(input), "{}", input);
+        }
+
+        let object = test_evaluate(r#"from_json(to_json({"k": [1, true, "s"]}))"#);
+        assert_eq!(
+            object,
+            Object::Hash(
+                vec![(
+                    Object::String("k".into()),
+                    Object::Array(vec![
+                        Object::Int(1),
+                        Object::Boolean(true),
+                        Object::String("s".into())
+                    ])
+                )]
+                .into()
+            )
+        );
+    }
+
+    #[cfg(all(feature = "serde", feature = "bigint"))]
+    #[test]
+    fn test_json_bigint() {
+        let tests = vec![
+            (
+                r#"from_json("18446744073709551615")"#,
+                "18446744073709551615",
+            ),
+            (
+                r#"from_json("[9223372036854775808]")[0] - 1"#,
+                "9223372036854775807",
+            ),
+            (
+                r#"to_json(from_json("[18446744073709551615]"))"#,
+                "[18446744073709551615]",
+            ),
+        ];
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input).to_string(), expect, "{}", input);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_to_json_stringifies_keys() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let warnings = Rc::new(RefCell::new(Vec::new()));
+        let program = Parser::new(Lexer::new(r#"to_json({1: "one", true: [{"x": 2}]})"#))
+            .parse_program()
+            .unwrap();
+        let mut env = Environment::with_builtins();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.set_warnings(warnings.clone());
+        assert_eq!(
+            evaluator.evaluate(program),
+            Object::String(r#"{"1":"one","true":[{"x":2}]}"#.into())
+        );
+        assert_eq!(
+            String::from_utf8(warnings.borrow().clone()).unwrap(),
+            "warning: `to_json` converted hash key 1 of type INTEGER to a string\n\
+             warning: `to_json` converted hash key true of type BOOLEAN to a string\n"
+        );
+    }
+
+    #[test]
+    fn test_memoize() {
+        let fib = "fn(n) { tick(); if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }";
+
+        let (object, plain_calls) = count_calls(&format!("let fib = {}; fib(20);", fib));
+        assert_eq!(object, Object::Int(6765));
+        assert_eq!(plain_calls, 21891);
+
+        let (object, memoized_calls) =
+            count_calls(&format!("let fib = memoize({}); fib(20);", fib));
+        assert_eq!(object, Object::Int(6765));
+        assert_eq!(memoized_calls, 21);
+
+        // each memoized function keeps its own results
+        let (object, calls) = count_calls(
+            "let square = fn(x) { tick(); x * x };
+             let f = memoize(square); let g = memoize(square);
+             [f(3), f(3), g(3), memoize(fn(x) { tick(); -x })(3)]",
+        );
+        assert_eq!(
+            object,
+            Object::Array(vec![
+                Object::Int(9),
+                Object::Int(9),
+                Object::Int(9),
+                Object::Int(-3)
+            ])
+        );
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_memoize_errors() {
+        let tests = vec![
+            (
+                "memoize(1)",
+                "argument to `memoize` must be FUNCTION, got INTEGER",
+            ),
+            (
+                "memoize(fn(x) { x }, 1)",
+                "wrong number of arguments. got=2, want=1",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_wrong_number_of_arguments() {
+        // builtins and functions report a wrong argument count alike
+        let tests = vec![
+            ("len()", "wrong number of arguments. got=0, want=1"),
+            ("abs(1, 2)", "wrong number of arguments. got=2, want=1"),
+            (
+                "each([1], fn(x, y) { x })",
+                "wrong number of arguments. got=1, want=2",
+            ),
+            (
+                "partial(fn(a, b) { a + b }, 1)()",
+                "wrong number of arguments. got=1, want=2",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_compose() {
+        let tests =
+            vec![
+            (
+                "let inc = fn(x) { x + 1 }; let double = fn(x) { x * 2 }; compose(inc, double)(5)",
+                "11",
+            ),
+            (
+                "let inc = fn(x) { x + 1 }; let double = fn(x) { x * 2 }; compose(double, inc)(5)",
+                "12",
+            ),
+            // right to left, with the last function taking every argument
+            (
+                "compose(fn(x) { x - 1 }, fn(x) { x * 10 }, fn(a, b) { a + b })(1, 2)",
+                "29",
+            ),
+            ("compose(len)(\"abc\")", "3"),
+            ("compose(len, fn(x) { x })", "compose(builtin function, fn (x) { x })"),
+            ("compose(fn(x) { x }, fn(x) { 1 / x })(0)", "Error: division by zero"),
+            (
+                "compose(len, 1)",
+                "Error: argument to `compose` must be FUNCTION, got INTEGER",
+            ),
+            ("compose()", "Error: wrong number of arguments. got=0, want=1"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object.to_string(), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_partial() {
+        let tests = vec![
+            (
+                "let add = fn(a, b) { a + b }; let inc = partial(add, 1); inc(41)",
+                "42",
+            ),
+            ("let add = fn(a, b) { a + b }; partial(add)(1, 2)", "3"),
+            ("let add = fn(a, b) { a + b }; partial(add, 1, 2)()", "3"),
+            (
+                "partial(partial(fn(a, b, c) { [a, b, c] }, 1), 2)(3)",
+                "[1, 2, 3]",
+            ),
+            ("partial(len, \"abc\")()", "3"),
+            (
+                "partial(fn(a, b) { a - b }, 10)",
+                "partial(fn (a, b) { (a - b) }, 10)",
+            ),
+            (
+                "partial(1, 2)",
+                "Error: argument to `partial` must be FUNCTION, got INTEGER",
+            ),
+            (
+                "partial()",
+                "Error: wrong number of arguments. got=0, want=1",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object.to_string(), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_emitter() {
+        let tests = vec![
+            (
+                r#"let e = emitter(); on(e, "click", fn(x) { x * 2 }); emit(e, "click", 21)"#,
+                "42",
+            ),
+            (
+                r#"let e = emitter(); on(e, "add", fn(a, b) { a + b }); on(e, "add", fn(a, b) { a * b }); emit(e, "add", 3, 4)"#,
+                "12",
+            ),
+            (r#"let e = emitter(); emit(e, "click")"#, "null"),
+            (
+                r#"let e = emitter(); let h = fn() { 1 }; on(e, "x", h); [off(e, "x", h), off(e, "x", h), emit(e, "x")]"#,
+                "[true, false, null]",
+            ),
+            (
+                r#"let e = emitter(); on(e, "x", fn() { 1 }); off(e, "x", fn() { 1 })"#,
+                "false",
+            ),
+            // Emitters are shared, not copied, when passed around.
+            (
+                r#"let e = emitter(); let listen = fn(em) { on(em, "x", fn() { 7 }) }; listen(e); emit(e, "x")"#,
+                "7",
+            ),
+            ("emitter()", "emitter"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object.to_string(), expect, "{}", input);
+        }
+
+        let (_, calls) = count_calls(
+            r#"let e = emitter(); on(e, "tick", tick); on(e, "tock", tick); emit(e, "tick"); emit(e, "tick")"#,
+        );
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_emitter_errors() {
+        let tests = vec![
+            (
+                r#"on(1, "x", fn() { 1 })"#,
+                "argument to `on` must be EMITTER, got INTEGER",
+            ),
+            (
+                "on(emitter(), 1, fn() { 1 })",
+                "argument to `on` must be STRING, got INTEGER",
+            ),
+            (
+                r#"off(emitter(), "x", 1)"#,
+                "argument to `off` must be FUNCTION, got INTEGER",
+            ),
+            (
+                r#"emit(1, "x")"#,
+                "argument to `emit` must be EMITTER, got INTEGER",
+            ),
+            (
+                "emit(emitter())",
+                "wrong number of arguments. got=1, want=2",
+            ),
+            ("emitter(1)", "wrong number of arguments. got=1, want=0"),
+            (
+                r#"let e = emitter(); on(e, "x", fn() { -true }); on(e, "x", fn() { 1 }); emit(e, "x")"#,
+                "unknown operator: -BOOLEAN",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        let tests = vec![
+            (r#"eval("1 + 2")"#, Object::Int(3)),
+            (r#"eval("let x = 4;"); x * 2"#, Object::Int(8)),
+            (r#"let x = 5; eval("x + 1")"#, Object::Int(6)),
+            (r#"let f = fn(n) { eval("n * 10") }; f(3)"#, Object::Int(30)),
+            (r#"eval("return 1; 2") + 1"#, Object::Int(2)),
+            (r#"eval("")"#, Object::Null),
+            (
+                r#"eval("1 +")"#,
+                Object::error(r#"invalid token "Token { kind: Eof, literal: "" }""#.to_string()),
+            ),
+            (
+                r#"eval("-true")"#,
+                Object::error("unknown operator: -BOOLEAN".to_string()),
+            ),
+            (
+                "eval(1)",
+                Object::error("argument to `eval` must be STRING, got INTEGER".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_iter() {
+        let tests = vec![
+            ("iter_collect(iter([1, 2, 3]))", "[1, 2, 3]"),
+            (
+                "iter_collect(iter_take(2, iter_map(iter([1, 2, 3]), fn(x) { x * 2 })))",
+                "[2, 4]",
+            ),
+            (
+                "iter_collect(iter_filter(iter([1, 2, 3, 4]), fn(x) { x % 2 == 0 }))",
+                "[2, 4]",
+            ),
+            ("iter_collect(iter_drop(2, iter([1, 2, 3])))", "[3]"),
+            ("iter_collect(iter_drop(5, iter([1, 2])))", "[]"),
+            ("iter_collect(iter_take(5, iter([1, 2])))", "[1, 2]"),
+            ("let it = iter([1]); [it(), it(), it()]", "[1, null, null]"),
+            // A function that never returns `null` is an infinite iterator.
+            (
+                "iter_collect(iter_take(3, iter_map(fn() { 1 }, fn(x) { x + 1 })))",
+                "[2, 2, 2]",
+            ),
+            ("iter([])", "iterator"),
+            ("is_function(iter([]))", "true"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object.to_string(), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_iter_is_lazy() {
+        let tests = vec![
+            (
+                "iter_collect(iter_take(2, iter_map(fn() { tick(); 1 }, fn(x) { x })))",
+                2,
+            ),
+            (
+                "iter_collect(iter_take(2, iter_filter(fn() { tick(); 1 }, fn(x) { true })))",
+                2,
+            ),
+            ("let it = iter_drop(3, fn() { tick(); 1 }); 0", 0),
+            ("let it = iter_drop(3, fn() { tick(); 1 }); it()", 4),
+        ];
+
+        for (input, expect) in tests {
+            let (object, calls) = count_calls(input);
+            assert!(!matches!(object, Object::Error(_)), "{}", input);
+            assert_eq!(calls, expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_iter_errors() {
+        let tests = vec![
+            ("iter(1)", "argument to `iter` must be ARRAY, got INTEGER"),
+            (
+                "iter_map(iter([]), 1)",
+                "argument to `iter_map` must be FUNCTION, got INTEGER",
+            ),
+            (
+                "iter_filter([], fn(x) { x })",
+                "argument to `iter_filter` must be FUNCTION, got ARRAY",
+            ),
+            (
+                "iter_take(-1, iter([]))",
+                "argument to `iter_take` must not be negative, got -1",
+            ),
+            (
+                "iter_drop(iter([]), 1)",
+                "argument to `iter_drop` must be INTEGER, got FUNCTION",
+            ),
+            (
+                "iter_collect(1)",
+                "argument to `iter_collect` must be FUNCTION, got INTEGER",
+            ),
+            ("iter([1])(1)", "wrong number of arguments. got=1, want=0"),
+            (
+                "iter_collect(iter_map(iter([1]), fn(x) { x + true }))",
+                "type mismatch: INTEGER + BOOLEAN",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, Object::error(expect.to_string()), "{}", input);
+        }
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_random() {
+        let input = "random_seed(42); [random(), random_int(1, 6), shuffle([1, 2, 3, 4]), choice([1, 2, 3])]";
+        let first = test_evaluate(input);
+        assert_eq!(test_evaluate(input), first);
+
+        let tests = vec![
+            (
+                "random_seed(1); let r = random(); r < 1.0",
+                Object::Boolean(true),
+            ),
+            (
+                "random_seed(1); let r = random(); r < 0.0",
+                Object::Boolean(false),
+            ),
+            ("random_int(3, 3)", Object::Int(3)),
+            ("let r = random_int(-2, 2); r < 3", Object::Boolean(true)),
+            ("let r = random_int(-2, 2); r > -3", Object::Boolean(true)),
+            ("sum(shuffle([1, 2, 3, 4, 5]))", Object::Int(15)),
+            ("len(shuffle([1, 2, 3, 4, 5]))", Object::Int(5)),
+            ("shuffle([])", Object::Array(vec![])),
+            ("choice([])", Object::Null),
+            ("choice([7])", Object::Int(7)),
+            (
+                "random_int(2, 1)",
+                Object::error("empty range in `random_int`: 2 is greater than 1".to_string()),
+            ),
+            (
+                "random_int(1, 2.0)",
+                Object::error(
+                    "arguments to `random_int` must be INTEGER, got INTEGER, FLOAT".to_string(),
+                ),
+            ),
+            (
+                "choice(1)",
+                Object::error("argument to `choice` must be ARRAY, got INTEGER".to_string()),
+            ),
+            (
+                "random(1)",
+                Object::error("wrong number of arguments. got=1, want=0".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object, expect, "{}", input);
+        }
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_random_sandbox() {
+        let inputs = vec![
+            "random()",
+            "random_int(1, 2)",
+            "random_seed(1)",
+            "shuffle([1])",
+            "choice([1])",
+        ];
+
+        for input in inputs {
+            let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+            let mut env = Environment::with_builtins();
+            let mut evaluator = Evaluator::new(&mut env);
+            evaluator.set_sandbox(true);
+            assert_eq!(
+                evaluator.evaluate(program),
+                Object::error("random disabled".to_string()),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_env() {
+        std::env::set_var("MONKEY_TEST_ENV", "monkey");
+        std::env::remove_var("MONKEY_TEST_ENV_UNSET");
+        let tests = vec![
+            (r#"env("MONKEY_TEST_ENV")"#, Object::String("monkey".into())),
+            (r#"env("MONKEY_TEST_ENV_UNSET")"#, Object::Null),
+            (
+                "env(1)",
+                Object::error("argument to `env` must be STRING, got INTEGER".to_string()),
+            ),
+            (
+                "env()",
+                Object::error("wrong number of arguments. got=0, want=1".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), expect, "{}", input);
+        }
+
+        let program = Parser::new(Lexer::new(r#"env("MONKEY_TEST_ENV")"#))
+            .parse_program()
+            .unwrap();
+        let mut env = Environment::with_builtins();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.set_sandbox(true);
+        assert_eq!(
+            evaluator.evaluate(program),
+            Object::error("env disabled".to_string())
+        );
+    }
+
+    #[test]
+    fn test_fs_access() {
+        let dir = std::env::temp_dir().join(format!("monkey-fs-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("in.txt"), "hello").unwrap();
+        let absolute = |name: &str| dir.join(name).display().to_string();
+        let escapes =
+            |path: &str| Object::error(format!("path escapes the root directory: {}", path));
+        let string = |value: &str| Object::String(value.into());
+
+        let tests = vec![
+            (
+                FsAccess::None,
+                format!(r#"read_file("{}")"#, absolute("in.txt")),
+                Object::error("file access disabled".to_string()),
+            ),
+            (
+                FsAccess::None,
+                r#"write_file("out.txt", "")"#.to_string(),
+                Object::error("file access disabled".to_string()),
+            ),
+            (
+                FsAccess::ReadOnly,
+                format!(r#"read_file("{}")"#, absolute("in.txt")),
+                string("hello"),
+            ),
+            (
+                FsAccess::ReadOnly,
+                format!(r#"write_file("{}", "")"#, absolute("out.txt")),
+                Object::error("file writes disabled".to_string()),
+            ),
+            (
+                FsAccess::ReadWrite(dir.clone()),
+                r#"write_file("sub/out.txt", "data"); read_file("sub/out.txt")"#.to_string(),
+                string("data"),
+            ),
+            (
+                FsAccess::ReadWrite(dir.clone()),
+                r#"read_file("./sub/../in.txt")"#.to_string(),
+                string("hello"),
+            ),
+            (
+                FsAccess::ReadWrite(dir.clone()),
+                format!(r#"read_file("{}")"#, absolute("in.txt")),
+                string("hello"),
+            ),
+            (
+                FsAccess::ReadWrite(dir.clone()),
+                r#"read_file("../in.txt")"#.to_string(),
+                escapes("../in.txt"),
+            ),
+            (
+                FsAccess::ReadWrite(dir.clone()),
+                r#"write_file("sub/../../out.txt", "")"#.to_string(),
+                escapes("sub/../../out.txt"),
+            ),
+            (
+                FsAccess::ReadWrite(dir.join("sub")),
+                format!(r#"read_file("{}")"#, absolute("in.txt")),
+                escapes(&absolute("in.txt")),
+            ),
+        ];
+        for (access, input, expect) in tests {
+            let program = Parser::new(Lexer::new(&input)).parse_program().unwrap();
+            let mut env = Environment::with_builtins();
+            let mut evaluator = Evaluator::new(&mut env);
+            evaluator.set_fs_access(access.clone());
+            assert_eq!(
+                evaluator.evaluate(program),
+                expect,
+                "{} with {:?}",
+                input,
+                access
+            );
+        }
+        assert!(!dir.join("out.txt").exists());
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_fs_access(FsAccess::ReadWrite(dir.clone()));
+        assert!(interpreter
+            .eval(r#"read_file("missing.txt")"#)
+            .unwrap_err()
+            .to_string()
+            .starts_with("cannot read missing.txt: "));
+        // the default keeps files out of reach
+        assert_eq!(
+            Interpreter::new()
+                .eval(r#"read_file("in.txt")"#)
+                .unwrap_err()
+                .to_string(),
+            "file access disabled"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_argument_errors() {
+        let tests = vec![
+            (
+                "read_file(1)",
+                "argument to `read_file` must be STRING, got INTEGER",
+            ),
+            (
+                r#"write_file("a", 1)"#,
+                "arguments to `write_file` must be STRING, got STRING, INTEGER",
+            ),
+            ("read_file()", "wrong number of arguments. got=0, want=1"),
+            (
+                r#"write_file("a")"#,
+                "wrong number of arguments. got=1, want=2",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                test_evaluate(input),
+                Object::error(expect.to_string()),
+                "{}",
+                input
+            );
+        }
+    }
+
+    fn test_evaluate(input: &str) -> Object {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program().unwrap();
+        let mut env = Environment::with_builtins();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.evaluate(program)
+    }
+}
@@ -0,0 +1,330 @@
+//! The bytecode format produced by the `compiler` module.
+//!
+//! An instruction is a one-byte `OpCode` followed by its operands, each
+//! encoded big-endian in the width given by `OpCode::operand_widths`.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::errors::MonkeyError;
+
+/// A flat sequence of encoded instructions.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Instructions(pub Vec<u8>);
+
+impl Instructions {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Lists one instruction per line as its offset, opcode name and decoded
+    /// operands, e.g. `0003 OpConstant 2`.
+    pub fn fmt_instructions(&self) -> Result<String, MonkeyError> {
+        let mut listing = String::new();
+        let mut offset = 0;
+        while offset < self.len() {
+            let (op, operands, read) = read_instruction(&self.0, offset)?;
+            listing.push_str(&format_instruction(offset, op, &operands));
+            listing.push('\n');
+            offset += read;
+        }
+        Ok(listing)
+    }
+}
+
+/// Lists the instructions like `fmt_instructions`, ending with an `ERROR:`
+/// line at the first one that cannot be decoded.
+impl fmt::Display for Instructions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut offset = 0;
+        while offset < self.len() {
+            match read_instruction(&self.0, offset) {
+                Ok((op, operands, read)) => {
+                    writeln!(f, "{}", format_instruction(offset, op, &operands))?;
+                    offset += read;
+                }
+                Err(err) => return writeln!(f, "ERROR: {}", err),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn format_instruction(offset: usize, op: OpCode, operands: &[usize]) -> String {
+    let mut line = format!("{:04} Op{:?}", offset, op);
+    for operand in operands {
+        line.push_str(&format!(" {}", operand));
+    }
+    line
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    /// Pushes the constant at the operand's index in the constant pool.
+    Constant,
+    Pop,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    True,
+    False,
+    Null,
+    Equal,
+    NotEqual,
+    GreaterThan,
+    LessThan,
+    Minus,
+    Bang,
+    /// Pops the condition and jumps to the operand's offset if it is falsy.
+    JumpNotTruthy,
+    Jump,
+    GetGlobal,
+    SetGlobal,
+    /// Pushes the local at the operand's index in the current frame.
+    GetLocal,
+    SetLocal,
+    /// Calls the function below the operand's count of arguments.
+    Call,
+    ReturnValue,
+    /// Returns from a function without a value, producing `null`.
+    Return,
+    /// Wraps the compiled function at the first operand's index in the
+    /// constant pool in a closure, capturing the second operand's count of
+    /// values from the stack.
+    Closure,
+    /// Pushes the captured value at the operand's index in the current closure.
+    GetFree,
+    /// Pushes the closure being executed.
+    CurrentClosure,
+}
+
+const OPCODES: &[OpCode] = &[
+    OpCode::Constant,
+    OpCode::Pop,
+    OpCode::Add,
+    OpCode::Sub,
+    OpCode::Mul,
+    OpCode::Div,
+    OpCode::Mod,
+    OpCode::True,
+    OpCode::False,
+    OpCode::Null,
+    OpCode::Equal,
+    OpCode::NotEqual,
+    OpCode::GreaterThan,
+    OpCode::LessThan,
+    OpCode::Minus,
+    OpCode::Bang,
+    OpCode::JumpNotTruthy,
+    OpCode::Jump,
+    OpCode::GetGlobal,
+    OpCode::SetGlobal,
+    OpCode::GetLocal,
+    OpCode::SetLocal,
+    OpCode::Call,
+    OpCode::ReturnValue,
+    OpCode::Return,
+    OpCode::Closure,
+    OpCode::GetFree,
+    OpCode::CurrentClosure,
+];
+
+impl OpCode {
+    /// The width in bytes of each operand.
+    pub fn operand_widths(self) -> &'static [usize] {
+        match self {
+            OpCode::Constant
+            | OpCode::JumpNotTruthy
+            | OpCode::Jump
+            | OpCode::GetGlobal
+            | OpCode::SetGlobal => &[2],
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::GetFree | OpCode::Call => &[1],
+            OpCode::Closure => &[2, 1],
+            _ => &[],
+        }
+    }
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        OPCODES.get(byte as usize).copied().ok_or(byte)
+    }
+}
+
+/// Encodes one instruction.
+///
+/// # Panics
+///
+/// Panics if the number of operands does not match the opcode, or an operand
+/// does not fit in its width.
+pub fn make(op: OpCode, operands: &[usize]) -> Vec<u8> {
+    let widths = op.operand_widths();
+    assert_eq!(
+        operands.len(),
+        widths.len(),
+        "wrong number of operands for {:?}",
+        op
+    );
+    let mut instruction = vec![op as u8];
+    for (&operand, &width) in operands.iter().zip(widths) {
+        let bytes = (operand as u64).to_be_bytes();
+        let (high, low) = bytes.split_at(bytes.len() - width);
+        assert!(
+            high.iter().all(|&byte| byte == 0),
+            "operand {} of {:?} does not fit in {} bytes",
+            operand,
+            op,
+            width
+        );
+        instruction.extend_from_slice(low);
+    }
+    instruction
+}
+
+/// Decodes the operands of `op` from the start of `bytes`, returning them with
+/// the number of bytes read, or `None` if `bytes` is too short.
+pub fn read_operands(op: OpCode, bytes: &[u8]) -> Option<(Vec<usize>, usize)> {
+    let mut operands = vec![];
+    let mut offset = 0;
+    for &width in op.operand_widths() {
+        let operand = bytes.get(offset..offset + width)?;
+        operands.push(
+            operand
+                .iter()
+                .fold(0, |value, &byte| (value << 8) | byte as usize),
+        );
+        offset += width;
+    }
+    Some((operands, offset))
+}
+
+/// Decodes the instruction at `offset`, returning its opcode, its operands and
+/// its length in bytes.
+pub fn read_instruction(
+    bytes: &[u8],
+    offset: usize,
+) -> Result<(OpCode, Vec<usize>, usize), MonkeyError> {
+    let op = OpCode::try_from(bytes[offset]).map_err(|byte| {
+        MonkeyError::MalformedBytecode(format!("unknown opcode {} at {:04}", byte, offset))
+    })?;
+    let (operands, read) = read_operands(op, &bytes[offset + 1..]).ok_or_else(|| {
+        MonkeyError::MalformedBytecode(format!("truncated Op{:?} at {:04}", op, offset))
+    })?;
+    Ok((op, operands, 1 + read))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use crate::code::{make, read_operands, Instructions, OpCode, OPCODES};
+
+    #[test]
+    fn test_make() {
+        let tests = vec![
+            (
+                OpCode::Constant,
+                vec![65534],
+                vec![OpCode::Constant as u8, 255, 254],
+            ),
+            (OpCode::Add, vec![], vec![OpCode::Add as u8]),
+            (OpCode::Call, vec![255], vec![OpCode::Call as u8, 255]),
+            (OpCode::Jump, vec![258], vec![OpCode::Jump as u8, 1, 2]),
+            (
+                OpCode::Closure,
+                vec![65534, 255],
+                vec![OpCode::Closure as u8, 255, 254, 255],
+            ),
+        ];
+
+        for (op, operands, expect) in tests {
+            assert_eq!(make(op, &operands), expect, "{:?}", op);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit in 1 bytes")]
+    fn test_make_overflow() {
+        make(OpCode::Call, &[256]);
+    }
+
+    #[test]
+    fn test_read_operands() {
+        let tests = vec![
+            (OpCode::Constant, vec![65535], 2),
+            (OpCode::GetGlobal, vec![7], 2),
+            (OpCode::Call, vec![3], 1),
+            (OpCode::Closure, vec![65535, 255], 3),
+            (OpCode::Pop, vec![], 0),
+        ];
+
+        for (op, operands, read) in tests {
+            let instruction = make(op, &operands);
+            assert_eq!(
+                read_operands(op, &instruction[1..]),
+                Some((operands, read)),
+                "{:?}",
+                op
+            );
+        }
+        assert_eq!(read_operands(OpCode::Constant, &[1]), None);
+    }
+
+    #[test]
+    fn test_opcode_from_byte() {
+        for &op in OPCODES {
+            assert_eq!(OpCode::try_from(op as u8), Ok(op));
+        }
+        assert_eq!(
+            OpCode::try_from(OPCODES.len() as u8),
+            Err(OPCODES.len() as u8)
+        );
+    }
+
+    #[test]
+    fn test_fmt_instructions() {
+        let instructions = Instructions(
+            [
+                make(OpCode::Constant, &[2]),
+                make(OpCode::Add, &[]),
+                make(OpCode::JumpNotTruthy, &[65535]),
+                make(OpCode::Call, &[1]),
+                make(OpCode::Closure, &[65535, 255]),
+            ]
+            .concat(),
+        );
+        let expect = "0000 OpConstant 2\n0003 OpAdd\n0004 OpJumpNotTruthy 65535\n0007 OpCall 1\n0009 OpClosure 65535 255\n";
+        assert_eq!(instructions.fmt_instructions().unwrap(), expect);
+        assert_eq!(instructions.to_string(), expect);
+    }
+
+    #[test]
+    fn test_fmt_malformed_instructions() {
+        let tests = vec![
+            (
+                vec![OpCode::Pop as u8, OpCode::Constant as u8, 1],
+                "truncated OpConstant at 0001",
+            ),
+            (vec![OpCode::Pop as u8, 255], "unknown opcode 255 at 0001"),
+        ];
+
+        for (bytes, expect) in tests {
+            let instructions = Instructions(bytes);
+            let err = instructions.fmt_instructions().unwrap_err();
+            assert_eq!(err.to_string(), format!("malformed bytecode: {}", expect));
+            assert_eq!(
+                instructions.to_string(),
+                format!("0000 OpPop\nERROR: malformed bytecode: {}\n", expect)
+            );
+        }
+    }
+}
@@ -0,0 +1,1092 @@
+//! Lowers a `Program` to bytecode.
+//!
+//! Supported so far: integer and boolean arithmetic, comparisons, `if`,
+//! `let` bindings, and function literals. Anything else is a
+//! `MonkeyError::Compile`.
+//!
+//! Every function literal becomes a closure. Unlike in the evaluator, a
+//! closure captures the values of the enclosing functions' locals when it is
+//! made, so rebinding one of them later does not change what it sees.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{Arena, BlockStatement, ExprId, Expression, Program, Statement, StmtId};
+use crate::code::{make, Instructions, OpCode};
+use crate::errors::MonkeyError;
+use crate::interner;
+use crate::object::{CompiledFunction, Object};
+use crate::symbol_table::{Symbol, SymbolScope, SymbolTable};
+
+/// The output of a compilation: the top-level instructions and the constant
+/// pool they index into.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bytecode {
+    pub instructions: Instructions,
+    pub constants: Vec<Object>,
+}
+
+/// Lists the top-level instructions, then the constant pool by index with the
+/// instructions of each compiled function indented beneath it.
+impl fmt::Display for Bytecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.instructions)?;
+        if self.constants.is_empty() {
+            return Ok(());
+        }
+        writeln!(f, "\nconstants:")?;
+        for (index, constant) in self.constants.iter().enumerate() {
+            writeln!(f, "{:04} {}", index, constant)?;
+            if let Object::CompiledFunction(function) = constant {
+                for line in function.instructions.to_string().lines() {
+                    writeln!(f, "    {}", line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Emitted {
+    op: OpCode,
+    position: usize,
+}
+
+// The instructions of the function (or program) being compiled.
+#[derive(Debug, Default)]
+struct Scope {
+    instructions: Instructions,
+    last: Option<Emitted>,
+}
+
+#[derive(Debug)]
+pub struct Compiler {
+    constants: Vec<Object>,
+    symbols: SymbolTable,
+    scopes: Vec<Scope>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self {
+            constants: vec![],
+            symbols: SymbolTable::new(),
+            scopes: vec![Scope::default()],
+        }
+    }
+
+    /// Compiles `program`, appending to the instructions compiled so far. On
+    /// error the instructions of `program` are discarded.
+    pub fn compile(&mut self, program: &Program) -> Result<(), MonkeyError> {
+        let start = self.scopes[0].instructions.len();
+        let result = program
+            .statements
+            .iter()
+            .try_for_each(|&stmt| self.compile_statement(&program.arena, stmt));
+        if result.is_err() {
+            while self.scopes.len() > 1 {
+                self.leave_scope();
+            }
+            self.scopes[0].instructions.0.truncate(start);
+            self.scopes[0].last = None;
+        }
+        result
+    }
+
+    pub fn bytecode(self) -> Bytecode {
+        let mut scopes = self.scopes;
+        Bytecode {
+            instructions: scopes.swap_remove(0).instructions,
+            constants: self.constants,
+        }
+    }
+
+    /// Takes the instructions compiled so far, keeping the globals and
+    /// constants so that programs compiled later can refer to them, as the
+    /// REPL does.
+    pub fn take_bytecode(&mut self) -> Bytecode {
+        let main = std::mem::take(&mut self.scopes[0]);
+        Bytecode {
+            instructions: main.instructions,
+            constants: self.constants.clone(),
+        }
+    }
+
+    fn compile_statement(&mut self, arena: &Arena, stmt: StmtId) -> Result<(), MonkeyError> {
+        match &arena[stmt] {
+            Statement::Expression(expr) => {
+                self.compile_expression(arena, *expr)?;
+                self.emit(OpCode::Pop, &[]);
+            }
+            Statement::Let { ident, value } => {
+                let name = match &arena[*ident] {
+                    Expression::Ident(name, _) => name.as_str(),
+                    _ => return Err(unsupported("destructuring `let`")),
+                };
+                // A function body only runs once the binding exists, so it may
+                // refer to itself. Any other value sees the binding it replaces.
+                let symbol = match &arena[*value] {
+                    Expression::Function { parameters, body } => {
+                        let symbol = self.symbols.define(name);
+                        // A local is only set after the closure has captured
+                        // its free variables, so a function bound to one
+                        // refers to itself through its own name instead.
+                        let own_name = match symbol.scope {
+                            SymbolScope::Global => None,
+                            _ => Some(name),
+                        };
+                        self.compile_function(arena, parameters, body, own_name)?;
+                        symbol
+                    }
+                    _ => {
+                        self.compile_expression(arena, *value)?;
+                        self.symbols.define(name)
+                    }
+                };
+                match symbol.scope {
+                    SymbolScope::Global => self.emit(OpCode::SetGlobal, &[symbol.index]),
+                    _ => self.emit(OpCode::SetLocal, &[symbol.index]),
+                };
+            }
+            Statement::Return(expr) => {
+                self.compile_expression(arena, *expr)?;
+                self.emit(OpCode::ReturnValue, &[]);
+            }
+            Statement::Const { .. } => return Err(unsupported("`const`")),
+            Statement::Import(_) => return Err(unsupported("`import`")),
+            Statement::Break(_) => return Err(unsupported("`break`")),
+            Statement::Continue => return Err(unsupported("`continue`")),
+            Statement::Test { .. } => return Err(unsupported("`test`")),
+        }
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, arena: &Arena, expr: ExprId) -> Result<(), MonkeyError> {
+        match &arena[expr] {
+            Expression::Int(value) => {
+                let index = self.add_constant(Object::Int(*value));
+                self.emit(OpCode::Constant, &[index]);
+            }
+            Expression::Boolean(true) => {
+                self.emit(OpCode::True, &[]);
+            }
+            Expression::Boolean(false) => {
+                self.emit(OpCode::False, &[]);
+            }
+            Expression::Ident(name, _) => {
+                let symbol = self.symbols.resolve(name.as_str()).ok_or_else(|| {
+                    MonkeyError::Compile(format!("identifier not found: {}", name))
+                })?;
+                self.load_symbol(&symbol);
+            }
+            Expression::Prefix { op, right } => {
+                self.compile_expression(arena, *right)?;
+                match op.as_str() {
+                    "-" => self.emit(OpCode::Minus, &[]),
+                    "!" => self.emit(OpCode::Bang, &[]),
+                    _ => return Err(unsupported(&format!("operator `{}`", op))),
+                };
+            }
+            Expression::Infix {
+                left, op, right, ..
+            } => {
+                let op = match op.as_str() {
+                    "+" => OpCode::Add,
+                    "-" => OpCode::Sub,
+                    "*" => OpCode::Mul,
+                    "/" => OpCode::Div,
+                    "%" => OpCode::Mod,
+                    ">" => OpCode::GreaterThan,
+                    "<" => OpCode::LessThan,
+                    "==" => OpCode::Equal,
+                    "!=" => OpCode::NotEqual,
+                    _ => return Err(unsupported(&format!("operator `{}`", op))),
+                };
+                self.compile_expression(arena, *left)?;
+                self.compile_expression(arena, *right)?;
+                self.emit(op, &[]);
+            }
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.compile_expression(arena, *condition)?;
+                // Both jump targets are patched once they are known.
+                let jump_not_truthy = self.emit(OpCode::JumpNotTruthy, &[0]);
+                self.compile_block_value(arena, consequence)?;
+                let jump = self.emit(OpCode::Jump, &[0]);
+                self.change_operand(jump_not_truthy, self.current().instructions.len());
+                match alternative {
+                    Some(alternative) => self.compile_block_value(arena, alternative)?,
+                    None => {
+                        self.emit(OpCode::Null, &[]);
+                    }
+                }
+                self.change_operand(jump, self.current().instructions.len());
+            }
+            Expression::Function { parameters, body } => {
+                self.compile_function(arena, parameters, body, None)?;
+            }
+            Expression::Call {
+                function,
+                arguments,
+            } => {
+                self.compile_expression(arena, *function)?;
+                for argument in arguments {
+                    self.compile_expression(arena, *argument)?;
+                }
+                self.emit(OpCode::Call, &[arguments.len()]);
+            }
+            Expression::Float(_) => return Err(unsupported("float literals")),
+            Expression::String(_) => return Err(unsupported("string literals")),
+            Expression::Array(_) => return Err(unsupported("array literals")),
+            Expression::Index { .. } => return Err(unsupported("index expressions")),
+            Expression::Slice { .. } => return Err(unsupported("slice expressions")),
+            Expression::Spread(_) => return Err(unsupported("spread expressions")),
+            Expression::Hash(_) => return Err(unsupported("hash literals")),
+            Expression::Block(_) => return Err(unsupported("block expressions")),
+            Expression::Try { .. } => return Err(unsupported("try expressions")),
+            Expression::Loop(_) => return Err(unsupported("loop expressions")),
+            Expression::While { .. } => return Err(unsupported("while expressions")),
+            Expression::Macro { .. } => return Err(unsupported("macros")),
+            Expression::Import(..) => return Err(unsupported("`import`")),
+        }
+        Ok(())
+    }
+
+    // Compiles a function literal to an instruction that makes a closure of it,
+    // preceded by the loads of the values it captures. `name` is the local the
+    // closure is being bound to, if any.
+    fn compile_function(
+        &mut self,
+        arena: &Arena,
+        parameters: &[interner::Symbol],
+        body: &BlockStatement,
+        name: Option<&str>,
+    ) -> Result<(), MonkeyError> {
+        self.enter_scope();
+        if let Some(name) = name {
+            self.symbols.define_function_name(name);
+        }
+        for parameter in parameters {
+            self.symbols.define(parameter.as_str());
+        }
+        for &stmt in &body.statements {
+            self.compile_statement(arena, stmt)?;
+        }
+        let last = body.statements.last().map(|&stmt| &arena[stmt]);
+        match (last, self.current().last) {
+            (
+                Some(Statement::Expression(_)),
+                Some(Emitted {
+                    op: OpCode::Pop,
+                    position,
+                }),
+            ) => {
+                // The value of the last expression is returned.
+                self.current_mut().instructions.0[position] = OpCode::ReturnValue as u8;
+            }
+            // So is the value bound by a trailing `let`.
+            (Some(Statement::Let { ident, .. }), _) => {
+                self.compile_expression(arena, *ident)?;
+                self.emit(OpCode::ReturnValue, &[]);
+            }
+            (Some(Statement::Return(_)), _) => (),
+            _ => {
+                self.emit(OpCode::Return, &[]);
+            }
+        }
+        let free_symbols = self.symbols.free_symbols().to_vec();
+        let num_locals = self.symbols.num_definitions();
+        let scope = self.leave_scope();
+        for symbol in &free_symbols {
+            self.load_symbol(symbol);
+        }
+        let function = Object::CompiledFunction(Rc::new(CompiledFunction {
+            instructions: scope.instructions,
+            num_locals,
+            num_parameters: parameters.len(),
+        }));
+        let index = self.add_constant(function);
+        self.emit(OpCode::Closure, &[index, free_symbols.len()]);
+        Ok(())
+    }
+
+    fn load_symbol(&mut self, symbol: &Symbol) {
+        match symbol.scope {
+            SymbolScope::Global => self.emit(OpCode::GetGlobal, &[symbol.index]),
+            SymbolScope::Local => self.emit(OpCode::GetLocal, &[symbol.index]),
+            SymbolScope::Free => self.emit(OpCode::GetFree, &[symbol.index]),
+            SymbolScope::Function => self.emit(OpCode::CurrentClosure, &[]),
+        };
+    }
+
+    // Compiles an `if` branch so that it leaves its value on the stack: the
+    // value of a trailing expression or `let`, or `null` when there is none.
+    fn compile_block_value(
+        &mut self,
+        arena: &Arena,
+        block: &BlockStatement,
+    ) -> Result<(), MonkeyError> {
+        for &stmt in &block.statements {
+            self.compile_statement(arena, stmt)?;
+        }
+        let last = block.statements.last().map(|&stmt| &arena[stmt]);
+        match (last, self.current().last) {
+            (
+                Some(Statement::Expression(_)),
+                Some(Emitted {
+                    op: OpCode::Pop,
+                    position,
+                }),
+            ) => {
+                self.current_mut().instructions.0.truncate(position);
+                self.current_mut().last = None;
+            }
+            (Some(Statement::Let { ident, .. }), _) => {
+                self.compile_expression(arena, *ident)?;
+            }
+            (Some(Statement::Return(_)), _) => (),
+            _ => {
+                self.emit(OpCode::Null, &[]);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_constant(&mut self, obj: Object) -> usize {
+        self.constants.push(obj);
+        self.constants.len() - 1
+    }
+
+    // Appends an instruction, returning its position.
+    fn emit(&mut self, op: OpCode, operands: &[usize]) -> usize {
+        let instruction = make(op, operands);
+        let scope = self.current_mut();
+        let position = scope.instructions.len();
+        scope.instructions.0.extend(instruction);
+        scope.last = Some(Emitted { op, position });
+        position
+    }
+
+    fn change_operand(&mut self, position: usize, operand: usize) {
+        let instructions = &mut self.current_mut().instructions.0;
+        let op = OpCode::try_from(instructions[position]).unwrap();
+        let instruction = make(op, &[operand]);
+        instructions[position..position + instruction.len()].copy_from_slice(&instruction);
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(Scope::default());
+        let outer = std::mem::take(&mut self.symbols);
+        self.symbols = SymbolTable::new_enclosed(outer);
+    }
+
+    fn leave_scope(&mut self) -> Scope {
+        let symbols = std::mem::take(&mut self.symbols);
+        self.symbols = symbols.into_outer().unwrap();
+        self.scopes.pop().unwrap()
+    }
+
+    fn current(&self) -> &Scope {
+        self.scopes.last().unwrap()
+    }
+
+    fn current_mut(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn unsupported(what: &str) -> MonkeyError {
+    MonkeyError::Compile(format!("the compiler does not support {}", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        code::{make, Instructions, OpCode},
+        compiler::{Bytecode, Compiler},
+        errors::MonkeyError,
+        lexer::Lexer,
+        object::{CompiledFunction, Object},
+        parser::Parser,
+    };
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let tests = vec![
+            (
+                "1 + 2",
+                vec![Object::Int(1), Object::Int(2)],
+                vec![
+                    make(OpCode::Constant, &[0]),
+                    make(OpCode::Constant, &[1]),
+                    make(OpCode::Add, &[]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+            (
+                "1; 2",
+                vec![Object::Int(1), Object::Int(2)],
+                vec![
+                    make(OpCode::Constant, &[0]),
+                    make(OpCode::Pop, &[]),
+                    make(OpCode::Constant, &[1]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+            (
+                "2 * 3 - 4 / 5 % 6",
+                vec![2, 3, 4, 5, 6].into_iter().map(Object::Int).collect(),
+                vec![
+                    make(OpCode::Constant, &[0]),
+                    make(OpCode::Constant, &[1]),
+                    make(OpCode::Mul, &[]),
+                    make(OpCode::Constant, &[2]),
+                    make(OpCode::Constant, &[3]),
+                    make(OpCode::Div, &[]),
+                    make(OpCode::Constant, &[4]),
+                    make(OpCode::Mod, &[]),
+                    make(OpCode::Sub, &[]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+            (
+                "-1",
+                vec![Object::Int(1)],
+                vec![
+                    make(OpCode::Constant, &[0]),
+                    make(OpCode::Minus, &[]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_boolean_expressions() {
+        let tests = vec![
+            (
+                "true",
+                vec![],
+                vec![make(OpCode::True, &[]), make(OpCode::Pop, &[])],
+            ),
+            (
+                "1 > 2",
+                vec![Object::Int(1), Object::Int(2)],
+                vec![
+                    make(OpCode::Constant, &[0]),
+                    make(OpCode::Constant, &[1]),
+                    make(OpCode::GreaterThan, &[]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+            (
+                "1 < 2",
+                vec![Object::Int(1), Object::Int(2)],
+                vec![
+                    make(OpCode::Constant, &[0]),
+                    make(OpCode::Constant, &[1]),
+                    make(OpCode::LessThan, &[]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+            (
+                "true != false",
+                vec![],
+                vec![
+                    make(OpCode::True, &[]),
+                    make(OpCode::False, &[]),
+                    make(OpCode::NotEqual, &[]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+            (
+                "!(1 == 2)",
+                vec![Object::Int(1), Object::Int(2)],
+                vec![
+                    make(OpCode::Constant, &[0]),
+                    make(OpCode::Constant, &[1]),
+                    make(OpCode::Equal, &[]),
+                    make(OpCode::Bang, &[]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let tests = vec![
+            (
+                "if (true) { 10 }; 3333;",
+                vec![Object::Int(10), Object::Int(3333)],
+                vec![
+                    // 0000
+                    make(OpCode::True, &[]),
+                    // 0001
+                    make(OpCode::JumpNotTruthy, &[10]),
+                    // 0004
+                    make(OpCode::Constant, &[0]),
+                    // 0007
+                    make(OpCode::Jump, &[11]),
+                    // 0010
+                    make(OpCode::Null, &[]),
+                    // 0011
+                    make(OpCode::Pop, &[]),
+                    // 0012
+                    make(OpCode::Constant, &[1]),
+                    // 0015
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+            (
+                "if (true) { 10 } else { 20 }; 3333;",
+                vec![Object::Int(10), Object::Int(20), Object::Int(3333)],
+                vec![
+                    // 0000
+                    make(OpCode::True, &[]),
+                    // 0001
+                    make(OpCode::JumpNotTruthy, &[10]),
+                    // 0004
+                    make(OpCode::Constant, &[0]),
+                    // 0007
+                    make(OpCode::Jump, &[13]),
+                    // 0010
+                    make(OpCode::Constant, &[1]),
+                    // 0013
+                    make(OpCode::Pop, &[]),
+                    // 0014
+                    make(OpCode::Constant, &[2]),
+                    // 0017
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+            (
+                "if (false) { }",
+                vec![],
+                vec![
+                    // 0000
+                    make(OpCode::False, &[]),
+                    // 0001
+                    make(OpCode::JumpNotTruthy, &[8]),
+                    // 0004
+                    make(OpCode::Null, &[]),
+                    // 0005
+                    make(OpCode::Jump, &[9]),
+                    // 0008
+                    make(OpCode::Null, &[]),
+                    // 0009
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_global_let_statements() {
+        let tests = vec![
+            (
+                "let one = 1; let two = 2;",
+                vec![Object::Int(1), Object::Int(2)],
+                vec![
+                    make(OpCode::Constant, &[0]),
+                    make(OpCode::SetGlobal, &[0]),
+                    make(OpCode::Constant, &[1]),
+                    make(OpCode::SetGlobal, &[1]),
+                ],
+            ),
+            (
+                "let one = 1; one;",
+                vec![Object::Int(1)],
+                vec![
+                    make(OpCode::Constant, &[0]),
+                    make(OpCode::SetGlobal, &[0]),
+                    make(OpCode::GetGlobal, &[0]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+            (
+                "let one = 1; let one = one;",
+                vec![Object::Int(1)],
+                vec![
+                    make(OpCode::Constant, &[0]),
+                    make(OpCode::SetGlobal, &[0]),
+                    make(OpCode::GetGlobal, &[0]),
+                    make(OpCode::SetGlobal, &[0]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_functions() {
+        let tests = vec![
+            (
+                "fn() { return 5 + 10 }",
+                vec![
+                    Object::Int(5),
+                    Object::Int(10),
+                    compiled(vec![
+                        make(OpCode::Constant, &[0]),
+                        make(OpCode::Constant, &[1]),
+                        make(OpCode::Add, &[]),
+                        make(OpCode::ReturnValue, &[]),
+                    ]),
+                ],
+                vec![make(OpCode::Closure, &[2, 0]), make(OpCode::Pop, &[])],
+            ),
+            (
+                "fn() { 1; 2 }",
+                vec![
+                    Object::Int(1),
+                    Object::Int(2),
+                    compiled(vec![
+                        make(OpCode::Constant, &[0]),
+                        make(OpCode::Pop, &[]),
+                        make(OpCode::Constant, &[1]),
+                        make(OpCode::ReturnValue, &[]),
+                    ]),
+                ],
+                vec![make(OpCode::Closure, &[2, 0]), make(OpCode::Pop, &[])],
+            ),
+            (
+                "fn() { }",
+                vec![compiled(vec![make(OpCode::Return, &[])])],
+                vec![make(OpCode::Closure, &[0, 0]), make(OpCode::Pop, &[])],
+            ),
+            (
+                "let f = fn() { 24 }; f();",
+                vec![
+                    Object::Int(24),
+                    compiled(vec![
+                        make(OpCode::Constant, &[0]),
+                        make(OpCode::ReturnValue, &[]),
+                    ]),
+                ],
+                vec![
+                    make(OpCode::Closure, &[1, 0]),
+                    make(OpCode::SetGlobal, &[0]),
+                    make(OpCode::GetGlobal, &[0]),
+                    make(OpCode::Call, &[0]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_locals() {
+        let tests = vec![
+            (
+                "let num = 55; fn() { num }",
+                vec![
+                    Object::Int(55),
+                    compiled(vec![
+                        make(OpCode::GetGlobal, &[0]),
+                        make(OpCode::ReturnValue, &[]),
+                    ]),
+                ],
+                vec![
+                    make(OpCode::Constant, &[0]),
+                    make(OpCode::SetGlobal, &[0]),
+                    make(OpCode::Closure, &[1, 0]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+            (
+                "fn() { let num = 55; num }",
+                vec![
+                    Object::Int(55),
+                    compiled_with_locals(
+                        vec![
+                            make(OpCode::Constant, &[0]),
+                            make(OpCode::SetLocal, &[0]),
+                            make(OpCode::GetLocal, &[0]),
+                            make(OpCode::ReturnValue, &[]),
+                        ],
+                        1,
+                        0,
+                    ),
+                ],
+                vec![make(OpCode::Closure, &[1, 0]), make(OpCode::Pop, &[])],
+            ),
+            (
+                "fn(a, b) { let c = a + b; }",
+                vec![compiled_with_locals(
+                    vec![
+                        make(OpCode::GetLocal, &[0]),
+                        make(OpCode::GetLocal, &[1]),
+                        make(OpCode::Add, &[]),
+                        make(OpCode::SetLocal, &[2]),
+                        make(OpCode::GetLocal, &[2]),
+                        make(OpCode::ReturnValue, &[]),
+                    ],
+                    3,
+                    2,
+                )],
+                vec![make(OpCode::Closure, &[0, 0]), make(OpCode::Pop, &[])],
+            ),
+            (
+                "let a = 1; fn(a) { a }",
+                vec![
+                    Object::Int(1),
+                    compiled_with_locals(
+                        vec![make(OpCode::GetLocal, &[0]), make(OpCode::ReturnValue, &[])],
+                        1,
+                        1,
+                    ),
+                ],
+                vec![
+                    make(OpCode::Constant, &[0]),
+                    make(OpCode::SetGlobal, &[0]),
+                    make(OpCode::Closure, &[1, 0]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_closures() {
+        let tests = vec![
+            (
+                "fn(a) { fn(b) { a + b } }",
+                vec![
+                    compiled_with_locals(
+                        vec![
+                            make(OpCode::GetFree, &[0]),
+                            make(OpCode::GetLocal, &[0]),
+                            make(OpCode::Add, &[]),
+                            make(OpCode::ReturnValue, &[]),
+                        ],
+                        1,
+                        1,
+                    ),
+                    compiled_with_locals(
+                        vec![
+                            make(OpCode::GetLocal, &[0]),
+                            make(OpCode::Closure, &[0, 1]),
+                            make(OpCode::ReturnValue, &[]),
+                        ],
+                        1,
+                        1,
+                    ),
+                ],
+                vec![make(OpCode::Closure, &[1, 0]), make(OpCode::Pop, &[])],
+            ),
+            (
+                "fn(a) { fn(b) { fn(c) { a + b + c } } }",
+                vec![
+                    compiled_with_locals(
+                        vec![
+                            make(OpCode::GetFree, &[0]),
+                            make(OpCode::GetFree, &[1]),
+                            make(OpCode::Add, &[]),
+                            make(OpCode::GetLocal, &[0]),
+                            make(OpCode::Add, &[]),
+                            make(OpCode::ReturnValue, &[]),
+                        ],
+                        1,
+                        1,
+                    ),
+                    compiled_with_locals(
+                        vec![
+                            make(OpCode::GetFree, &[0]),
+                            make(OpCode::GetLocal, &[0]),
+                            make(OpCode::Closure, &[0, 2]),
+                            make(OpCode::ReturnValue, &[]),
+                        ],
+                        1,
+                        1,
+                    ),
+                    compiled_with_locals(
+                        vec![
+                            make(OpCode::GetLocal, &[0]),
+                            make(OpCode::Closure, &[1, 1]),
+                            make(OpCode::ReturnValue, &[]),
+                        ],
+                        1,
+                        1,
+                    ),
+                ],
+                vec![make(OpCode::Closure, &[2, 0]), make(OpCode::Pop, &[])],
+            ),
+            (
+                r#"
+                let global = 55;
+                fn() {
+                    let a = 66;
+                    fn() {
+                        let b = 77;
+                        fn() {
+                            let c = 88;
+                            global + a + b + c;
+                        }
+                    }
+                }
+                "#,
+                vec![
+                    Object::Int(55),
+                    Object::Int(66),
+                    Object::Int(77),
+                    Object::Int(88),
+                    compiled_with_locals(
+                        vec![
+                            make(OpCode::Constant, &[3]),
+                            make(OpCode::SetLocal, &[0]),
+                            make(OpCode::GetGlobal, &[0]),
+                            make(OpCode::GetFree, &[0]),
+                            make(OpCode::Add, &[]),
+                            make(OpCode::GetFree, &[1]),
+                            make(OpCode::Add, &[]),
+                            make(OpCode::GetLocal, &[0]),
+                            make(OpCode::Add, &[]),
+                            make(OpCode::ReturnValue, &[]),
+                        ],
+                        1,
+                        0,
+                    ),
+                    compiled_with_locals(
+                        vec![
+                            make(OpCode::Constant, &[2]),
+                            make(OpCode::SetLocal, &[0]),
+                            make(OpCode::GetFree, &[0]),
+                            make(OpCode::GetLocal, &[0]),
+                            make(OpCode::Closure, &[4, 2]),
+                            make(OpCode::ReturnValue, &[]),
+                        ],
+                        1,
+                        0,
+                    ),
+                    compiled_with_locals(
+                        vec![
+                            make(OpCode::Constant, &[1]),
+                            make(OpCode::SetLocal, &[0]),
+                            make(OpCode::GetLocal, &[0]),
+                            make(OpCode::Closure, &[5, 1]),
+                            make(OpCode::ReturnValue, &[]),
+                        ],
+                        1,
+                        0,
+                    ),
+                ],
+                vec![
+                    make(OpCode::Constant, &[0]),
+                    make(OpCode::SetGlobal, &[0]),
+                    make(OpCode::Closure, &[6, 0]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_recursive_functions() {
+        let tests = vec![
+            (
+                "let countDown = fn(x) { countDown(x - 1); }; countDown(1);",
+                vec![
+                    Object::Int(1),
+                    compiled_with_locals(
+                        vec![
+                            make(OpCode::GetGlobal, &[0]),
+                            make(OpCode::GetLocal, &[0]),
+                            make(OpCode::Constant, &[0]),
+                            make(OpCode::Sub, &[]),
+                            make(OpCode::Call, &[1]),
+                            make(OpCode::ReturnValue, &[]),
+                        ],
+                        1,
+                        1,
+                    ),
+                    Object::Int(1),
+                ],
+                vec![
+                    make(OpCode::Closure, &[1, 0]),
+                    make(OpCode::SetGlobal, &[0]),
+                    make(OpCode::GetGlobal, &[0]),
+                    make(OpCode::Constant, &[2]),
+                    make(OpCode::Call, &[1]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+            (
+                r#"
+                let wrapper = fn() {
+                    let countDown = fn(x) { countDown(x - 1); };
+                    countDown(1);
+                };
+                wrapper();
+                "#,
+                vec![
+                    Object::Int(1),
+                    compiled_with_locals(
+                        vec![
+                            make(OpCode::CurrentClosure, &[]),
+                            make(OpCode::GetLocal, &[0]),
+                            make(OpCode::Constant, &[0]),
+                            make(OpCode::Sub, &[]),
+                            make(OpCode::Call, &[1]),
+                            make(OpCode::ReturnValue, &[]),
+                        ],
+                        1,
+                        1,
+                    ),
+                    Object::Int(1),
+                    compiled_with_locals(
+                        vec![
+                            make(OpCode::Closure, &[1, 0]),
+                            make(OpCode::SetLocal, &[0]),
+                            make(OpCode::GetLocal, &[0]),
+                            make(OpCode::Constant, &[2]),
+                            make(OpCode::Call, &[1]),
+                            make(OpCode::ReturnValue, &[]),
+                        ],
+                        1,
+                        0,
+                    ),
+                ],
+                vec![
+                    make(OpCode::Closure, &[3, 0]),
+                    make(OpCode::SetGlobal, &[0]),
+                    make(OpCode::GetGlobal, &[0]),
+                    make(OpCode::Call, &[0]),
+                    make(OpCode::Pop, &[]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let tests = vec![
+            ("x", "identifier not found: x"),
+            (r#""a""#, "the compiler does not support string literals"),
+            ("fn(x) { y }", "identifier not found: y"),
+            ("let f = fn() { let a = 1; }; a", "identifier not found: a"),
+        ];
+
+        for (input, expect) in tests {
+            let mut compiler = Compiler::new();
+            match compiler.compile(&parse(input)) {
+                Err(MonkeyError::Compile(message)) => assert_eq!(message, expect, "{}", input),
+                result => panic!("expected a compile error. got={:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_bytecode_listing() {
+        let tests = vec![
+            (
+                "1 + 2",
+                vec![
+                    "0000 OpConstant 0",
+                    "0003 OpConstant 1",
+                    "0006 OpAdd",
+                    "0007 OpPop",
+                    "",
+                    "constants:",
+                    "0000 1",
+                    "0001 2",
+                ],
+            ),
+            (
+                "let f = fn() { if (true) { 10 } }; f()",
+                vec![
+                    "0000 OpClosure 1 0",
+                    "0004 OpSetGlobal 0",
+                    "0007 OpGetGlobal 0",
+                    "0010 OpCall 0",
+                    "0012 OpPop",
+                    "",
+                    "constants:",
+                    "0000 10",
+                    "0001 compiled function",
+                    "    0000 OpTrue",
+                    "    0001 OpJumpNotTruthy 10",
+                    "    0004 OpConstant 0",
+                    "    0007 OpJump 11",
+                    "    0010 OpNull",
+                    "    0011 OpReturnValue",
+                ],
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let mut compiler = Compiler::new();
+            compiler.compile(&parse(input)).unwrap();
+            let listing = compiler.bytecode().to_string();
+            assert_eq!(listing, expect.join("\n") + "\n", "{}", input);
+        }
+    }
+
+    // Source, expected constants and expected instructions.
+    type CompilerTest<'a> = (&'a str, Vec<Object>, Vec<Vec<u8>>);
+
+    fn run_compiler_tests(tests: Vec<CompilerTest>) {
+        for (input, constants, instructions) in tests {
+            let mut compiler = Compiler::new();
+            compiler.compile(&parse(input)).unwrap();
+            assert_eq!(
+                compiler.bytecode(),
+                Bytecode {
+                    instructions: Instructions(instructions.concat()),
+                    constants,
+                },
+                "{}",
+                input
+            );
+        }
+    }
+
+    fn compiled(instructions: Vec<Vec<u8>>) -> Object {
+        compiled_with_locals(instructions, 0, 0)
+    }
+
+    fn compiled_with_locals(
+        instructions: Vec<Vec<u8>>,
+        num_locals: usize,
+        num_parameters: usize,
+    ) -> Object {
+        Object::CompiledFunction(Rc::new(CompiledFunction {
+            instructions: Instructions(instructions.concat()),
+            num_locals,
+            num_parameters,
+        }))
+    }
+
+    fn parse(input: &str) -> crate::ast::Program {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse_program().unwrap()
+    }
+}
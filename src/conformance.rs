@@ -0,0 +1,86 @@
+//! Checks that the bytecode VM agrees with the tree-walking evaluator.
+//!
+//! The evaluator's tests pass every program they evaluate to `check`, so the
+//! whole of their corpus is run on both engines without a copy of it to keep
+//! up to date.
+
+use crate::ast::Program;
+use crate::compiler::Compiler;
+use crate::errors::MonkeyError;
+use crate::object::Object;
+use crate::vm::VM;
+
+// The compile errors of programs using what the compiler does not support
+// yet. Such programs are not compared, and any other compile error is.
+const UNSUPPORTED: &[&str] = &[
+    "the compiler does not support `break`",
+    "the compiler does not support `const`",
+    "the compiler does not support `continue`",
+    "the compiler does not support array literals",
+    "the compiler does not support block expressions",
+    "the compiler does not support destructuring `let`",
+    "the compiler does not support float literals",
+    "the compiler does not support hash literals",
+    "the compiler does not support index expressions",
+    "the compiler does not support loop expressions",
+    "the compiler does not support slice expressions",
+    "the compiler does not support spread expressions",
+    "the compiler does not support string literals",
+    "the compiler does not support try expressions",
+    "the compiler does not support while expressions",
+    // `quote` and `unquote` are evaluated specially, not called.
+    "identifier not found: quote",
+    "identifier not found: unquote",
+];
+
+// Programs the engines are known to disagree on.
+const DIFFERENT: &[&str] = &[
+    // The VM's stack has a fixed size and it does not reuse frames for tail
+    // calls, so it runs out before the evaluator's depth limit.
+    "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(100000)",
+    "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(5000)",
+    "let countdown = fn(n) {
+    if (n == 0) {
+        0
+    } else {
+        countdown(n - 1)
+    }
+};
+countdown(1000000);",
+    // Only the evaluator suggests similar names.
+    "let f = fn(count) { cuont + 1 }; f(1)",
+];
+
+/// Runs `program`, parsed from `source`, on the VM and panics unless it gives
+/// the same result as `evaluated`, what the evaluator gave.
+pub(crate) fn check(source: &str, program: &Program, evaluated: &Object) {
+    if DIFFERENT.contains(&source) {
+        return;
+    }
+    let mut compiler = Compiler::new();
+    let result = match compiler.compile(program) {
+        Ok(()) => VM::new(compiler.bytecode()).run(),
+        Err(err) if UNSUPPORTED.contains(&err.to_string().as_str()) => return,
+        Err(err) => Err(err),
+    };
+    let evaluated = match evaluated {
+        Object::Error(err) => Err(MonkeyError::Runtime(err.message.clone())),
+        obj => Ok(obj.clone()),
+    };
+    assert_eq!(
+        describe(result),
+        describe(evaluated),
+        "engines disagree on {:?}",
+        source
+    );
+}
+
+// Functions from the two engines are different objects, so only their types
+// are compared.
+fn describe(result: Result<Object, MonkeyError>) -> String {
+    match result {
+        Ok(obj) if obj.type_info() == "FUNCTION" => obj.type_info(),
+        Ok(obj) => obj.to_string(),
+        Err(err) => format!("error: {}", err),
+    }
+}
@@ -0,0 +1,159 @@
+//! Counts how many times each statement of a program runs, enabled with
+//! `Evaluator::set_coverage` or the `--coverage` flag.
+//!
+//! Only the statements of one program are counted: the first one evaluated
+//! after coverage is enabled. Code from imported files or `eval` is not.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::ast::{Arena, Program, StmtId};
+
+#[derive(Debug, Default)]
+pub struct Coverage {
+    arena: Option<Rc<Arena>>,
+    hits: HashMap<StmtId, usize>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts counting the statements of `program`, each from zero, unless a
+    /// program is already being counted.
+    pub(crate) fn track(&mut self, program: &Program) {
+        if self.arena.is_some() {
+            return;
+        }
+        self.hits = program.arena.statement_ids().map(|id| (id, 0)).collect();
+        self.arena = Some(program.arena.clone());
+    }
+
+    pub(crate) fn hit(&mut self, arena: &Rc<Arena>, stmt: StmtId) {
+        if self
+            .arena
+            .as_ref()
+            .is_some_and(|tracked| Rc::ptr_eq(tracked, arena))
+        {
+            *self.hits.entry(stmt).or_default() += 1;
+        }
+    }
+
+    /// How many times each statement of the program ran, including the ones
+    /// that never did.
+    pub fn hits(&self) -> &HashMap<StmtId, usize> {
+        &self.hits
+    }
+}
+
+/// Prefixes each line of `source`, which `program` was parsed from, with how
+/// many times the statements starting on it ran, or `-` when none start on it.
+/// When several statements start on one line, the count is the largest of
+/// theirs.
+pub fn annotate(source: &str, program: &Program, hits: &HashMap<StmtId, usize>) -> String {
+    let mut lines = HashMap::new();
+    for (&stmt, &count) in hits {
+        let (line, _) = program.arena.stmt_span(stmt).line_col(source);
+        let hits = lines.entry(line).or_insert(0);
+        *hits = count.max(*hits);
+    }
+
+    let mut out = String::new();
+    for (i, text) in source.lines().enumerate() {
+        let _ = match lines.get(&(i + 1)) {
+            Some(count) => writeln!(out, "{:>6} | {}", count, text),
+            None => writeln!(out, "{:>6} | {}", "-", text),
+        };
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coverage::annotate;
+    use crate::environment::Environment;
+    use crate::evaluator::Evaluator;
+    use crate::interpreter::parse_source;
+
+    const SOURCE: &str = "let abs = fn(x) {
+  if (x < 0) {
+    let y = 0 - x;
+    y
+  } else {
+    x
+  }
+};
+abs(1);
+abs(2);
+import \"nowhere\";
+abs(3);
+";
+
+    #[test]
+    fn test_coverage() {
+        let program = parse_source(SOURCE).unwrap();
+        let mut env = Environment::new();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.set_coverage(true);
+        evaluator.evaluate(program.clone());
+        let hits = evaluator.coverage().unwrap();
+
+        let count = |line: usize| {
+            program
+                .arena
+                .statement_ids()
+                .filter(|&id| program.arena.stmt_span(id).line_col(SOURCE).0 == line)
+                .map(|id| hits[&id])
+                .collect::<Vec<_>>()
+        };
+        // the function body is an `if` statement
+        assert_eq!(count(1), vec![1]);
+        assert_eq!(count(2), vec![2]);
+        // the branch not taken is counted, as zero
+        assert_eq!(count(3), vec![0]);
+        assert_eq!(count(4), vec![0]);
+        assert_eq!(count(6), vec![2]);
+        assert_eq!(count(9), vec![1]);
+        assert_eq!(count(10), vec![1]);
+        // the import fails, so nothing after it runs
+        assert_eq!(count(11), vec![1]);
+        assert_eq!(count(12), vec![0]);
+        assert_eq!(hits.len(), program.arena.statement_ids().count());
+    }
+
+    #[test]
+    fn test_annotate() {
+        let program = parse_source(SOURCE).unwrap();
+        let mut env = Environment::new();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.set_coverage(true);
+        evaluator.evaluate(program.clone());
+        let hits = evaluator.coverage().unwrap();
+
+        let expect = "     1 | let abs = fn(x) {
+     2 |   if (x < 0) {
+     0 |     let y = 0 - x;
+     0 |     y
+     - |   } else {
+     2 |     x
+     - |   }
+     - | };
+     1 | abs(1);
+     1 | abs(2);
+     1 | import \"nowhere\";
+     0 | abs(3);
+";
+        assert_eq!(annotate(SOURCE, &program, &hits), expect);
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        let program = parse_source("let x = 1; x").unwrap();
+        let mut env = Environment::new();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.evaluate(program);
+        assert_eq!(evaluator.coverage(), None);
+    }
+}
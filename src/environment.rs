@@ -1,40 +1,361 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
 
+use crate::builtins::builtins;
+use crate::interner::{Symbol, SymbolMap};
 use crate::object::Object;
 
-#[derive(Debug, Clone, PartialEq)]
+// The bindings of one scope. Functions capture the scopes they are defined
+// in, so a scope is shared between the environments that can see it.
+type Scope = Rc<RefCell<SymbolMap<Binding>>>;
+
+#[derive(Clone)]
+struct Binding {
+    obj: Object,
+    // Bound by `const`, so that it cannot be rebound.
+    constant: bool,
+}
+
+/// Bindings by name. Names are keyed by their interned `Symbol`; methods take
+/// either a symbol or a `&str`, which is interned first.
+///
+/// The scopes are kept in a flat stack, the outermost first, so a lookup walks
+/// a slice from the innermost scope outwards.
+#[derive(Clone)]
 pub struct Environment {
-    store: HashMap<String, Object>,
-    outer: Option<Box<Environment>>,
+    scopes: Vec<Scope>,
+    // Whether the outermost scope holds the builtins, as in `with_builtins`.
+    builtins: bool,
 }
 
 impl Environment {
     pub fn new() -> Self {
         Self {
-            store: HashMap::new(),
-            outer: None,
+            scopes: vec![Scope::default()],
+            builtins: false,
         }
     }
 
-    pub fn new_enclosed(outer: Environment) -> Environment {
+    /// An environment whose outer scope holds every builtin, so that programs
+    /// may shadow builtins without losing them.
+    pub fn with_builtins() -> Self {
+        let mut env = Environment::new();
+        for (name, builtin) in builtins() {
+            env.set(name, builtin);
+        }
+        env.builtins = true;
+        Environment::new_enclosed(&env)
+    }
+
+    /// A new empty scope inside the scopes of `outer`, which it shares.
+    pub fn new_enclosed(outer: &Environment) -> Environment {
+        let mut scopes = Vec::with_capacity(outer.scopes.len() + 1);
+        scopes.extend(outer.scopes.iter().cloned());
+        scopes.push(Scope::default());
         Environment {
-            store: HashMap::new(),
-            outer: Some(Box::new(outer)),
+            scopes,
+            builtins: outer.builtins,
+        }
+    }
+
+    pub fn get(&self, name: impl Into<Symbol>) -> Option<Object> {
+        let name = name.into();
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.borrow().get(&name).map(|binding| binding.obj.clone()))
+    }
+
+    /// Looks `name` up like `get`, but not among the builtins, so that callers
+    /// can resolve other names between the two.
+    pub(crate) fn get_defined(&self, name: Symbol) -> Option<Object> {
+        self.scopes[usize::from(self.builtins)..]
+            .iter()
+            .rev()
+            .find_map(|scope| scope.borrow().get(&name).map(|binding| binding.obj.clone()))
+    }
+
+    /// The builtin called `name`, if this environment has the builtins.
+    pub(crate) fn get_builtin(&self, name: Symbol) -> Option<Object> {
+        if !self.builtins {
+            return None;
         }
+        self.scopes[0]
+            .borrow()
+            .get(&name)
+            .map(|binding| binding.obj.clone())
+    }
+
+    /// Binds `name` in the innermost scope, shadowing any outer binding.
+    pub fn set(&mut self, name: impl Into<Symbol>, obj: Object) -> Object {
+        self.bind(name.into(), obj, false)
+    }
+
+    /// Binds `name` in the innermost scope like `set`, as a constant, which
+    /// `set_existing` does not rebind.
+    pub fn set_const(&mut self, name: impl Into<Symbol>, obj: Object) -> Object {
+        self.bind(name.into(), obj, true)
+    }
+
+    /// Whether the nearest binding of `name` is a constant.
+    pub fn is_const(&self, name: impl Into<Symbol>) -> bool {
+        let name = name.into();
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.borrow().get(&name).map(|binding| binding.constant))
+            .unwrap_or(false)
+    }
+
+    /// Rebinds `name` in the nearest scope that defines it, returning the
+    /// value it replaced, or `None` without binding anything if `name` is not
+    /// bound. A constant is not rebound, and an error is returned instead.
+    ///
+    /// The language has no assignment, so only embedders call this; scripts
+    /// can never reach the "cannot reassign const" error.
+    pub fn set_existing(&mut self, name: impl Into<Symbol>, obj: Object) -> Option<Object> {
+        let name = name.into();
+        self.scopes.iter().rev().find_map(|scope| {
+            let mut scope = scope.borrow_mut();
+            scope.get_mut(&name).map(|binding| {
+                if binding.constant {
+                    Object::error(format!("cannot reassign const: {}", name))
+                } else {
+                    std::mem::replace(&mut binding.obj, obj.clone())
+                }
+            })
+        })
     }
 
-    pub fn get(&self, name: &str) -> Option<Object> {
-        match self.store.get(name) {
-            Some(obj) => Some(obj.clone()),
-            None => match &self.outer {
-                Some(outer) => outer.get(name),
-                None => None,
-            },
+    /// Removes `name` from the innermost scope, leaving any shadowed binding
+    /// in an outer scope visible again. Names bound only in outer scopes,
+    /// including the builtins, are left alone and `None` is returned.
+    ///
+    /// Functions that captured the scope keep the binding: a shared scope is
+    /// copied before the removal, so bindings made here afterwards are not
+    /// seen by those functions either.
+    pub fn remove(&mut self, name: impl Into<Symbol>) -> Option<Object> {
+        let name = name.into();
+        let scope = self
+            .scopes
+            .last_mut()
+            .expect("an environment has at least one scope");
+        if !scope.borrow().contains_key(&name) {
+            return None;
         }
+        if Rc::strong_count(scope) > 1 {
+            let copy = scope.borrow().clone();
+            *scope = Rc::new(RefCell::new(copy));
+        }
+        scope.borrow_mut().remove(&name).map(|binding| binding.obj)
+    }
+
+    pub fn contains(&self, name: impl Into<Symbol>) -> bool {
+        let name = name.into();
+        self.scopes
+            .iter()
+            .any(|scope| scope.borrow().contains_key(&name))
+    }
+
+    /// Whether `name` is bound in this scope itself, ignoring outer scopes.
+    pub fn contains_local(&self, name: impl Into<Symbol>) -> bool {
+        self.innermost().borrow().contains_key(&name.into())
+    }
+
+    /// Number of bindings in this scope, not counting outer scopes.
+    pub fn len(&self) -> usize {
+        self.innermost().borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The bindings of this scope, not counting outer scopes.
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        self.innermost()
+            .borrow()
+            .iter()
+            .map(|(name, binding)| (name.to_string(), binding.obj.clone()))
+            .collect()
     }
 
-    pub fn set(&mut self, name: &str, obj: Object) -> Object {
-        self.store.insert(name.to_string(), obj.clone());
+    /// Every name bound in any scope, each once.
+    pub fn names(&self) -> Vec<Symbol> {
+        let mut names = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.borrow().keys().copied().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        names.sort_unstable_by_key(|name| name.as_str());
+        names.dedup();
+        names
+    }
+
+    fn bind(&mut self, name: Symbol, obj: Object, constant: bool) -> Object {
+        let binding = Binding {
+            obj: obj.clone(),
+            constant,
+        };
+        self.innermost().borrow_mut().insert(name, binding);
         obj
     }
+
+    fn innermost(&self) -> &Scope {
+        self.scopes
+            .last()
+            .expect("an environment has at least one scope")
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Functions capture the environment they are defined in, so a scope may end up
+// containing itself. Equality is therefore identity, and Debug only lists names.
+impl PartialEq for Environment {
+    fn eq(&self, other: &Self) -> bool {
+        self.scopes.len() == other.scopes.len()
+            && self
+                .scopes
+                .iter()
+                .zip(&other.scopes)
+                .all(|(a, b)| Rc::ptr_eq(a, b))
+    }
+}
+
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scopes = self
+            .scopes
+            .iter()
+            .map(|scope| {
+                let mut names = scope
+                    .borrow()
+                    .keys()
+                    .map(|name| name.as_str())
+                    .collect::<Vec<_>>();
+                names.sort_unstable();
+                names
+            })
+            .collect::<Vec<_>>();
+        f.debug_struct("Environment")
+            .field("scopes", &scopes)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{environment::Environment, object::Object};
+
+    #[test]
+    fn test_remove() {
+        let mut env = Environment::new();
+        env.set("x", Object::Int(1));
+        assert!(env.contains("x"));
+        assert_eq!(env.len(), 1);
+
+        assert_eq!(env.remove("x"), Some(Object::Int(1)));
+        assert!(!env.contains("x"));
+        assert_eq!(env.len(), 0);
+        assert_eq!(env.remove("x"), None);
+    }
+
+    #[test]
+    fn test_with_builtins() {
+        let env = Environment::with_builtins();
+        assert!(matches!(env.get("len"), Some(Object::Builtin(_))));
+        assert!(env.is_empty());
+
+        let env = Environment::new();
+        assert_eq!(env.get("len"), None);
+    }
+
+    #[test]
+    fn test_remove_enclosed() {
+        let mut outer = Environment::new();
+        outer.set("x", Object::Int(1));
+        outer.set("y", Object::Int(2));
+        let mut inner = Environment::new_enclosed(&outer);
+        inner.set("x", Object::Int(10));
+        assert_eq!(inner.len(), 1);
+
+        // the shadowing binding goes first and the outer one becomes visible
+        assert_eq!(inner.remove("x"), Some(Object::Int(10)));
+        assert_eq!(inner.get("x"), Some(Object::Int(1)));
+
+        // names only bound outside are left alone
+        assert_eq!(inner.remove("y"), None);
+        assert_eq!(inner.get("y"), Some(Object::Int(2)));
+        assert_eq!(outer.len(), 2);
+    }
+
+    #[test]
+    fn test_remove_builtin() {
+        let mut env = Environment::with_builtins();
+        assert_eq!(env.remove("len"), None);
+        assert!(matches!(env.get("len"), Some(Object::Builtin(_))));
+
+        env.set("len", Object::Int(1));
+        assert_eq!(env.remove("len"), Some(Object::Int(1)));
+        assert!(matches!(env.get("len"), Some(Object::Builtin(_))));
+    }
+
+    #[test]
+    fn test_remove_captured() {
+        let mut env = Environment::new();
+        env.set("x", Object::Int(1));
+        let captured = env.clone();
+
+        assert_eq!(env.remove("x"), Some(Object::Int(1)));
+        assert!(!env.contains("x"));
+        assert_eq!(captured.get("x"), Some(Object::Int(1)));
+    }
+
+    #[test]
+    fn test_set_existing() {
+        let mut outer = Environment::new();
+        outer.set("x", Object::Int(1));
+        let mut inner = Environment::new_enclosed(&outer);
+
+        // the binding is replaced where it is, not shadowed
+        assert_eq!(
+            inner.set_existing("x", Object::Int(2)),
+            Some(Object::Int(1))
+        );
+        assert!(!inner.contains_local("x"));
+        assert_eq!(outer.get("x"), Some(Object::Int(2)));
+
+        assert_eq!(inner.set_existing("y", Object::Int(3)), None);
+        assert!(!inner.contains("y"));
+    }
+
+    #[test]
+    fn test_set_const() {
+        let mut outer = Environment::new();
+        outer.set_const("PI", Object::Int(3));
+        let mut inner = Environment::new_enclosed(&outer);
+        assert!(inner.is_const("PI"));
+
+        // only reachable through this method, as scripts cannot assign
+        assert_eq!(
+            inner.set_existing("PI", Object::Int(4)),
+            Some(Object::error("cannot reassign const: PI".to_string()))
+        );
+        assert_eq!(inner.get("PI"), Some(Object::Int(3)));
+
+        // a plain binding shadowing the constant can be rebound
+        inner.set("PI", Object::Int(4));
+        assert!(!inner.is_const("PI"));
+        assert_eq!(
+            inner.set_existing("PI", Object::Int(5)),
+            Some(Object::Int(4))
+        );
+        assert!(!inner.is_const("E"));
+    }
 }
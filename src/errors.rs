@@ -1,10 +1,149 @@
+use crate::environment::Environment;
 use crate::token::{Token, TokenKind};
 use thiserror::Error;
 
 #[derive(Clone, Debug, Error)]
 pub enum MonkeyError {
+    /// The expected kind, and the token found at the byte offset in the input.
     #[error("expected next token to be \"{:?}\", got \"{:?}\" instead", .0, .1)]
-    UnexpectedToken(TokenKind, Token),
+    UnexpectedToken(TokenKind, Token, usize),
+    /// The token, and its byte offset in the input.
     #[error("invalid token \"{:?}\"", .0)]
-    InvalidToken(Token),
+    InvalidToken(Token, usize),
+    #[error("could not parse \"{}\" as integer", .0)]
+    InvalidInteger(String),
+    #[error("could not parse \"{}\" as float", .0)]
+    InvalidFloat(String),
+    #[error("expressions nested more than {} levels deep", .0)]
+    NestingTooDeep(usize),
+    #[error("{}", .0)]
+    Compile(String),
+    #[error("malformed bytecode: {}", .0)]
+    MalformedBytecode(String),
+    #[error("{}", .0)]
+    Runtime(String),
+    #[error("{}", .0)]
+    Conversion(String),
+}
+
+impl MonkeyError {
+    /// The byte offset in the source of the token the error is about, if known.
+    /// Runtime errors have no position, since the AST does not record them.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            MonkeyError::UnexpectedToken(_, _, position)
+            | MonkeyError::InvalidToken(_, position) => Some(*position),
+            _ => None,
+        }
+    }
+}
+
+/// The name bound in `env`, or among `hosts`, the names of the host functions
+/// that resolve like builtins, that `name` is most likely a misspelling of: the
+/// closest by edit distance, if it is at most 2 and at most a third of the
+/// length of `name`, so that short names are not matched with anything of
+/// the same length. Ties go to the name first in alphabetical order.
+pub fn suggest_similar<'a>(
+    name: &str,
+    env: &Environment,
+    hosts: impl IntoIterator<Item = &'a str>,
+) -> Option<String> {
+    let limit = (name.chars().count() / 3).min(2);
+    let names = env.names();
+    let mut candidates = names
+        .iter()
+        .map(|name| name.as_str())
+        .chain(hosts)
+        .collect::<Vec<_>>();
+    candidates.sort_unstable();
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance > 0 && distance <= limit)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+// The number of characters to insert, delete or replace, or pairs of
+// neighbouring characters to swap, to turn `a` into `b`. Only the last two
+// rows of the table are kept.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    let mut before = vec![0; b.len() + 1];
+    let mut last = (0..=b.len()).collect::<Vec<_>>();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let substitution = last[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            row[j] = substitution.min(last[j] + 1).min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut last, row);
+    }
+    last[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::environment::Environment;
+    use crate::errors::{edit_distance, suggest_similar};
+    use crate::object::Object;
+
+    #[test]
+    fn test_edit_distance() {
+        let tests = vec![
+            ("", "", 0),
+            ("abc", "", 3),
+            ("", "abc", 3),
+            ("len", "len", 0),
+            ("lne", "len", 1),
+            ("kitten", "sitting", 3),
+            ("stirng", "string", 1),
+            ("ab", "ba", 1),
+            ("abc", "ca", 3),
+            ("héllo", "hello", 1),
+        ];
+        for (a, b, expect) in tests {
+            assert_eq!(edit_distance(a, b), expect, "{} {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_suggest_similar() {
+        let mut env = Environment::with_builtins();
+        env.set("string", Object::Null);
+        env.set("counter", Object::Null);
+        let tests = vec![
+            ("stirng", Some("string")),
+            ("lenn", Some("len")),
+            ("countr", Some("counter")),
+            ("pust", Some("puts")),
+            // too far from anything
+            ("foobar", None),
+            ("strung_out", None),
+            // too short for a guess to be likely
+            ("s", None),
+            ("ln", None),
+        ];
+        for (name, expect) in tests {
+            assert_eq!(
+                suggest_similar(name, &env, []).as_deref(),
+                expect,
+                "{}",
+                name
+            );
+        }
+
+        // host functions are suggested like bindings
+        assert_eq!(
+            suggest_similar("fetch_usr", &env, ["fetch_user", "log"]).as_deref(),
+            Some("fetch_user")
+        );
+        assert_eq!(
+            suggest_similar("lo", &env, ["fetch_user", "log"]).as_deref(),
+            None
+        );
+    }
 }
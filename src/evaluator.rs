@@ -1,22 +1,332 @@
-use crate::ast::{BlockStatement, Expression, Program, Statement};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::thread::LocalKey;
+
+use crate::ast::{
+    Arena, BlockStatement, ExprId, Expression, Node, Program, Span, Statement, StmtId,
+};
+#[cfg(feature = "bigint")]
+use crate::bigint;
+use crate::builtins::{apply_memoized, next_element};
+use crate::coverage::Coverage;
 use crate::environment::Environment;
-use crate::object::Object;
+use crate::errors::suggest_similar;
+use crate::interner::Symbol;
+use crate::interpreter::evaluate_source;
+use crate::object::{next_function_id, Builtin, HashPairs, Location, Object};
+use crate::profiler::Profiler;
+
+thread_local! {
+    // Called like functions, but evaluated specially.
+    static QUOTE: Symbol = Symbol::intern("quote");
+    static UNQUOTE: Symbol = Symbol::intern("unquote");
+}
+
+/// How deeply function calls may nest unless `set_max_depth` says otherwise,
+/// so that runaway recursion ends in an error rather than exhausting memory.
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+// When less native stack than `RED_ZONE` is left before a call, the call runs
+// on a new segment of `STACK_SEGMENT` bytes, so that the depth limit rather
+// than the thread's stack size decides how deep calls may nest.
+#[cfg(not(target_arch = "wasm32"))]
+const RED_ZONE: usize = 256 * 1024;
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+/// Where `puts` and other printing builtins write to.
+pub type Output = Rc<RefCell<dyn Write>>;
+
+/// Called before each statement and expression is evaluated, with the node and
+/// the environment it is evaluated in. It runs synchronously, so a debugger can
+/// pause evaluation by not returning until it is told to continue.
+pub type Hook = Rc<RefCell<dyn FnMut(Node<'_>, &Environment)>>;
+
+/// What the `read_file` and `write_file` builtins may do.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum FsAccess {
+    /// Neither builtin runs.
+    #[default]
+    None,
+    /// `read_file` may read any file, and `write_file` does not run.
+    ReadOnly,
+    /// Both run, on paths relative to the root directory, which they may not
+    /// lead out of.
+    ReadWrite(PathBuf),
+}
+
+impl FsAccess {
+    /// The file `path` names, checked against this mode without touching the
+    /// file system.
+    pub(crate) fn resolve(&self, path: &str, write: bool) -> Result<PathBuf, String> {
+        let root = match self {
+            FsAccess::None => return Err("file access disabled".to_string()),
+            FsAccess::ReadOnly if write => return Err("file writes disabled".to_string()),
+            FsAccess::ReadOnly => return Ok(PathBuf::from(path)),
+            FsAccess::ReadWrite(root) => root,
+        };
+        let escapes = || format!("path escapes the root directory: {}", path);
+        let path = Path::new(path);
+        let relative = match path.strip_prefix(root) {
+            Ok(relative) => relative,
+            Err(_) if path.is_absolute() => return Err(escapes()),
+            Err(_) => path,
+        };
+        let mut resolved = root.clone();
+        let mut depth = 0;
+        for component in relative.components() {
+            match component {
+                Component::Normal(name) => {
+                    resolved.push(name);
+                    depth += 1;
+                }
+                Component::CurDir => (),
+                Component::ParentDir if depth > 0 => {
+                    resolved.pop();
+                    depth -= 1;
+                }
+                Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                    return Err(escapes())
+                }
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+enum TailCall {
+    Value(Object),
+    Call(Vec<Object>),
+}
+
+// Files imported so far, shared by every evaluator of one run. `loading` is
+// the chain of imports currently being evaluated, used to detect cycles.
+#[derive(Default)]
+struct Modules {
+    cache: HashMap<PathBuf, Environment>,
+    loading: Vec<PathBuf>,
+}
 
 pub struct Evaluator<'a> {
     pub env: &'a mut Environment,
+    output: Output,
+    warnings: Output,
+    hook: Option<Hook>,
+    strict: bool,
+    sandbox: bool,
+    fs_access: Rc<FsAccess>,
+    max_depth: Option<usize>,
+    max_iterations: Option<usize>,
+    depth: usize,
+    hosts: Rc<HashMap<String, Object>>,
+    file: Option<PathBuf>,
+    modules: Rc<RefCell<Modules>>,
+    profiler: Rc<RefCell<Profiler>>,
+    coverage: Option<Rc<RefCell<Coverage>>>,
+    // The values of the string literals evaluated so far, so that each
+    // literal with the same value shares one allocation.
+    strings: Rc<RefCell<HashSet<Rc<str>>>>,
 }
 
 impl<'a> Evaluator<'a> {
-    pub fn new(env: &'a mut Environment) -> Evaluator {
-        Self { env }
+    pub fn new(env: &'a mut Environment) -> Evaluator<'a> {
+        Self {
+            env,
+            output: Rc::new(RefCell::new(io::stdout())),
+            warnings: Rc::new(RefCell::new(io::stderr())),
+            hook: None,
+            strict: false,
+            sandbox: false,
+            fs_access: Rc::new(FsAccess::None),
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_iterations: None,
+            depth: 0,
+            hosts: Rc::new(HashMap::new()),
+            file: None,
+            modules: Rc::new(RefCell::new(Modules::default())),
+            profiler: Rc::new(RefCell::new(Profiler::new())),
+            coverage: None,
+            strings: Rc::new(RefCell::new(HashSet::new())),
+        }
+    }
+
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    /// Where builtins report problems that do not stop evaluation. Defaults to
+    /// stderr.
+    pub fn set_warnings(&mut self, warnings: Output) {
+        self.warnings = warnings;
+    }
+
+    pub fn set_hook(&mut self, hook: Option<Hook>) {
+        self.hook = hook;
+    }
+
+    /// In strict mode a `let` may not redeclare a name already bound in the
+    /// same scope.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// In sandbox mode builtins with nondeterministic results, like `random`,
+    /// or that read the state of the process, like `env`, fail instead of
+    /// running.
+    pub fn set_sandbox(&mut self, sandbox: bool) {
+        self.sandbox = sandbox;
+    }
+
+    /// What `read_file` and `write_file` may do. Defaults to `FsAccess::None`.
+    pub fn set_fs_access(&mut self, fs_access: FsAccess) {
+        self.fs_access = Rc::new(fs_access);
+    }
+
+    /// Limits how deeply function calls may nest, to `DEFAULT_MAX_DEPTH`
+    /// unless set. Tail calls do not count, and `None` removes the limit.
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.max_depth = max_depth;
+    }
+
+    /// Limits how many times a single `loop` may run its body.
+    pub fn set_max_iterations(&mut self, max_iterations: Option<usize>) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Host functions are resolved like builtins: after the environment's own
+    /// bindings, but before the builtins they may override.
+    pub fn set_host_functions(&mut self, hosts: Rc<HashMap<String, Object>>) {
+        self.hosts = hosts;
+    }
+
+    /// The file being evaluated. `import` paths are resolved relative to it,
+    /// or to the working directory when there is none.
+    pub fn set_file(&mut self, file: Option<PathBuf>) {
+        self.file = file;
+    }
+
+    /// Measures function calls once started by `profile_start` or
+    /// `Profiler::start`. Evaluators sharing a profiler add to the same report.
+    pub fn set_profiler(&mut self, profiler: Rc<RefCell<Profiler>>) {
+        self.profiler = profiler;
+    }
+
+    pub fn profiler(&self) -> Rc<RefCell<Profiler>> {
+        self.profiler.clone()
+    }
+
+    /// Counts how many times each statement of the next program evaluated
+    /// runs; see the `coverage` module.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage = enabled.then(|| Rc::new(RefCell::new(Coverage::new())));
+    }
+
+    /// The count for each statement, if coverage is enabled.
+    pub fn coverage(&self) -> Option<HashMap<StmtId, usize>> {
+        self.coverage
+            .as_ref()
+            .map(|coverage| coverage.borrow().hits().clone())
+    }
+
+    pub(crate) fn output(&self) -> Output {
+        self.output.clone()
+    }
+
+    // A warning that cannot be written is dropped rather than failing the
+    // program that raised it.
+    pub(crate) fn warn(&self, message: &str) {
+        let _ = writeln!(self.warnings.borrow_mut(), "warning: {}", message);
+    }
+
+    pub(crate) fn sandboxed(&self) -> bool {
+        self.sandbox
+    }
+
+    pub(crate) fn fs_access(&self) -> &FsAccess {
+        &self.fs_access
+    }
+
+    fn trace(&self, node: Node) {
+        if let Some(hook) = &self.hook {
+            (hook.borrow_mut())(node, self.env);
+        }
+    }
+
+    fn enter_statement(&self, arena: &Rc<Arena>, stmt: StmtId) {
+        if let Some(coverage) = &self.coverage {
+            coverage.borrow_mut().hit(arena, stmt);
+        }
+        self.trace(Node::Statement(arena, stmt));
+    }
+
+    // The shared string with the value `value`.
+    fn intern(&self, value: &str) -> Rc<str> {
+        let mut strings = self.strings.borrow_mut();
+        if let Some(string) = strings.get(value) {
+            return string.clone();
+        }
+        let string: Rc<str> = value.into();
+        strings.insert(string.clone());
+        string
+    }
+
+    fn enclosed<'b>(&self, env: &'b mut Environment) -> Evaluator<'b>
+    where
+        'a: 'b,
+    {
+        Evaluator {
+            env,
+            output: self.output.clone(),
+            warnings: self.warnings.clone(),
+            hook: self.hook.clone(),
+            strict: self.strict,
+            sandbox: self.sandbox,
+            fs_access: self.fs_access.clone(),
+            max_depth: self.max_depth,
+            max_iterations: self.max_iterations,
+            depth: self.depth + 1,
+            hosts: self.hosts.clone(),
+            file: self.file.clone(),
+            modules: self.modules.clone(),
+            profiler: self.profiler.clone(),
+            coverage: self.coverage.clone(),
+            strings: self.strings.clone(),
+        }
+    }
+
+    /// Evaluates `program` in the current environment as if it were the body
+    /// of a function call, so that it counts towards the depth limit. This is
+    /// how the `eval` builtin keeps `eval` calling itself from recursing
+    /// without bound.
+    pub(crate) fn evaluate_nested(&mut self, program: Program) -> Object {
+        if self.depth_exceeded() {
+            return Object::error("maximum recursion depth exceeded".to_string());
+        }
+        self.depth += 1;
+        let obj = grow_stack(|| self.evaluate(program));
+        self.depth -= 1;
+        obj
+    }
+
+    fn depth_exceeded(&self) -> bool {
+        self.max_depth
+            .is_some_and(|max_depth| self.depth >= max_depth)
     }
 
     pub fn evaluate(&mut self, program: Program) -> Object {
+        if let Some(coverage) = &self.coverage {
+            coverage.borrow_mut().track(&program);
+        }
         let mut obj = Object::Null;
         for stmt in program.statements {
-            obj = self.evaluate_statement(stmt);
+            obj = self.evaluate_statement(&program.arena, stmt);
             match obj {
                 Object::Return(value) => return *value,
+                Object::Break(_) | Object::Continue => return outside_loop(&obj),
                 Object::Error(_) => return obj,
                 _ => (),
             }
@@ -24,120 +334,767 @@ impl<'a> Evaluator<'a> {
         obj
     }
 
-    fn evaluate_block_statement(&mut self, block: BlockStatement) -> Object {
+    pub(crate) fn evaluate_block_statement(
+        &mut self,
+        arena: &Rc<Arena>,
+        block: &BlockStatement,
+    ) -> Object {
         let mut obj = Object::Null;
-        for stmt in block.statements {
-            obj = self.evaluate_statement(stmt);
-            match obj {
-                Object::Return(value) => return *value,
-                Object::Error(_) => return obj,
-                _ => (),
+        for &stmt in &block.statements {
+            obj = self.evaluate_statement(arena, stmt);
+            if let Object::Return(_) | Object::Break(_) | Object::Continue | Object::Error(_) = obj
+            {
+                return obj;
             }
         }
         obj
     }
 
-    pub fn evaluate_statement(&mut self, stmt: Statement) -> Object {
-        match stmt {
-            Statement::Expression(expr) => self.evaluate_expression(expr),
-            Statement::Let { ident, value } => self.evaluate_let_statement(ident, value),
-            Statement::Return(expr) => self.evaluate_return_statement(expr),
+    // Evaluates a function body of `func`, handing a direct self-call in tail
+    // position back to `apply_function` instead of recursing into it.
+    fn evaluate_tail_block(
+        &mut self,
+        arena: &Rc<Arena>,
+        block: &BlockStatement,
+        func: &Object,
+    ) -> TailCall {
+        let (&last, statements) = match block.statements.split_last() {
+            Some(split) => split,
+            None => return TailCall::Value(Object::Null),
+        };
+        for &stmt in statements {
+            let obj = self.evaluate_statement(arena, stmt);
+            if let Object::Return(_) | Object::Break(_) | Object::Continue | Object::Error(_) = obj
+            {
+                return TailCall::Value(obj);
+            }
+        }
+        self.enter_statement(arena, last);
+        match &arena[last] {
+            Statement::Expression(expr) => self.evaluate_tail_expression(arena, *expr, func),
+            Statement::Return(expr) => match self.evaluate_tail_expression(arena, *expr, func) {
+                TailCall::Value(Object::Error(err)) => TailCall::Value(Object::Error(err)),
+                TailCall::Value(obj) => TailCall::Value(Object::Return(Box::new(obj))),
+                call => call,
+            },
+            Statement::Let { ident, value } => {
+                TailCall::Value(self.evaluate_let_statement(arena, *ident, *value, false))
+            }
+            Statement::Const { ident, value } => {
+                TailCall::Value(self.evaluate_let_statement(arena, *ident, *value, true))
+            }
+            Statement::Break(value) => {
+                TailCall::Value(self.evaluate_break_statement(arena, *value))
+            }
+            Statement::Continue => TailCall::Value(Object::Continue),
+            Statement::Import(path) => {
+                TailCall::Value(self.evaluate_import_statement(arena, last, path))
+            }
+            Statement::Test { .. } => TailCall::Value(Object::Null),
+        }
+    }
+
+    fn evaluate_tail_expression(
+        &mut self,
+        arena: &Rc<Arena>,
+        expr: ExprId,
+        func: &Object,
+    ) -> TailCall {
+        self.trace(Node::Expression(arena, expr));
+        match &arena[expr] {
+            Expression::Call {
+                function,
+                arguments,
+            } => {
+                if is_call_to(arena, *function, &QUOTE) {
+                    return TailCall::Value(self.evaluate_quote(arena, arguments));
+                }
+                let callee = self.evaluate_expression(arena, *function);
+                if let Object::Error(_) = callee {
+                    return TailCall::Value(callee);
+                }
+                let args = match self.evaluate_arguments(arena, &callee, arguments) {
+                    Ok(args) => args,
+                    Err(err) => return TailCall::Value(err),
+                };
+                if same_function(&callee, func) {
+                    return TailCall::Call(args);
+                }
+                TailCall::Value(self.apply_function(callee, args))
+            }
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let condition = self.evaluate_expression(arena, *condition);
+                if let Object::Error(_) = condition {
+                    return TailCall::Value(condition);
+                }
+                if Self::is_truthy(condition) {
+                    return self.evaluate_tail_block(arena, consequence, func);
+                }
+                match alternative {
+                    Some(alternative) => self.evaluate_tail_block(arena, alternative, func),
+                    None => TailCall::Value(Object::Null),
+                }
+            }
+            Expression::Block(block) => {
+                let mut env = Environment::new_enclosed(self.env);
+                let mut evaluator = self.enclosed(&mut env);
+                evaluator.depth = self.depth;
+                evaluator.evaluate_tail_block(arena, block, func)
+            }
+            _ => TailCall::Value(self.evaluate_untraced_expression(arena, expr)),
+        }
+    }
+
+    pub fn evaluate_statement(&mut self, arena: &Rc<Arena>, stmt: StmtId) -> Object {
+        self.enter_statement(arena, stmt);
+        match &arena[stmt] {
+            Statement::Expression(expr) => self.evaluate_expression(arena, *expr),
+            Statement::Let { ident, value } => {
+                self.evaluate_let_statement(arena, *ident, *value, false)
+            }
+            Statement::Const { ident, value } => {
+                self.evaluate_let_statement(arena, *ident, *value, true)
+            }
+            Statement::Return(expr) => self.evaluate_return_statement(arena, *expr),
+            Statement::Break(value) => self.evaluate_break_statement(arena, *value),
+            Statement::Continue => Object::Continue,
+            Statement::Import(path) => self.evaluate_import_statement(arena, stmt, path),
+            // Tests are run by the `runner`, not when the program is.
+            Statement::Test { .. } => Object::Null,
         }
     }
 
-    fn evaluate_let_statement(&mut self, ident: Expression, expr: Expression) -> Object {
-        let name = match ident {
-            Expression::Ident(name) => name,
-            _ => unreachable!(),
+    // Constants cannot be shadowed, whether by a `let` or another `const`.
+    fn evaluate_let_statement(
+        &mut self,
+        arena: &Rc<Arena>,
+        ident: ExprId,
+        expr: ExprId,
+        constant: bool,
+    ) -> Object {
+        let name = match &arena[ident] {
+            Expression::Ident(name, _) => *name,
+            _ => return self.evaluate_destructuring_let(arena, ident, expr),
         };
-        let obj = self.evaluate_expression(expr);
+        if self.env.is_const(name) {
+            return Object::error(format!("cannot shadow const: {}", name));
+        }
+        if self.strict && self.env.contains_local(name) {
+            return Object::error(format!("identifier already declared: {}", name));
+        }
+        let mut obj = self.evaluate_expression(arena, expr);
+        match &mut obj {
+            Object::Error(_) => return obj,
+            Object::Function {
+                name: function_name @ None,
+                ..
+            } => *function_name = Some(name),
+            _ => {}
+        }
+        if constant {
+            self.env.set_const(name, obj)
+        } else {
+            self.env.set(name, obj)
+        }
+    }
+
+    // Nothing is bound unless the whole value matches the pattern.
+    fn evaluate_destructuring_let(
+        &mut self,
+        arena: &Rc<Arena>,
+        pattern: ExprId,
+        expr: ExprId,
+    ) -> Object {
+        let obj = self.evaluate_expression(arena, expr);
         if let Object::Error(_) = obj {
             return obj;
         }
-        self.env.set(&name, obj)
+        let mut bindings = vec![];
+        if let Err(err) = destructure(arena, pattern, obj.clone(), &mut bindings) {
+            return err;
+        }
+        if let Some((name, _)) = bindings.iter().find(|(name, _)| self.env.is_const(*name)) {
+            return Object::error(format!("cannot shadow const: {}", name));
+        }
+        if self.strict {
+            if let Some((name, _)) = bindings
+                .iter()
+                .find(|(name, _)| self.env.contains_local(*name))
+            {
+                return Object::error(format!("identifier already declared: {}", name));
+            }
+        }
+        for (name, value) in bindings {
+            self.env.set(name, value);
+        }
+        obj
+    }
+
+    // Merges the top-level bindings of the imported file into the current
+    // scope, keeping its constants constant. Like a `let`, an import cannot
+    // shadow a constant, unless importing the same constant again, and binds
+    // nothing if it would.
+    fn evaluate_import_statement(&mut self, arena: &Arena, stmt: StmtId, path: &str) -> Object {
+        let module = match self.import(path) {
+            Ok(module) => module,
+            Err(err) => return self.locate(arena, arena.stmt_span(stmt), err),
+        };
+        let bindings = module.bindings();
+        if let Some((name, _)) = bindings.iter().find(|(name, obj)| {
+            self.env.is_const(name)
+                && !(module.is_const(name) && self.env.get(name).as_ref() == Some(obj))
+        }) {
+            let err = Object::error(format!("cannot shadow const: {}", name));
+            return self.locate(arena, arena.stmt_span(stmt), err);
+        }
+        for (name, obj) in bindings {
+            if module.is_const(&name) {
+                self.env.set_const(&name, obj);
+            } else {
+                self.env.set(&name, obj);
+            }
+        }
+        Object::Null
+    }
+
+    // A hash from the name of each top-level binding of the imported file to
+    // its value.
+    fn evaluate_import_expression(&mut self, arena: &Arena, path: &str, span: Span) -> Object {
+        match self.import(path) {
+            Ok(module) => {
+                let mut bindings = module.bindings();
+                bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+                Object::Hash(
+                    bindings
+                        .into_iter()
+                        .map(|(name, obj)| (Object::String(name.into()), obj))
+                        .collect(),
+                )
+            }
+            Err(err) => self.locate(arena, span, err),
+        }
+    }
+
+    // The environment the imported file was evaluated in. Each file is only
+    // evaluated the first time it is imported, in a fresh environment.
+    fn import(&mut self, path: &str) -> Result<Environment, Object> {
+        let base = match self.file.as_ref().and_then(|file| file.parent()) {
+            Some(dir) => dir.to_path_buf(),
+            None => PathBuf::from("."),
+        };
+        let file = base
+            .join(path)
+            .canonicalize()
+            .map_err(|err| Object::error(format!("cannot import {}: {}", path, err)))?;
+
+        let cached = self.modules.borrow().cache.get(&file).cloned();
+        match cached {
+            Some(module) => Ok(module),
+            None => self.load_module(&file),
+        }
+    }
+
+    fn load_module(&mut self, file: &Path) -> Result<Environment, Object> {
+        {
+            let loading = &self.modules.borrow().loading;
+            if loading.iter().any(|f| f == file) {
+                let mut chain = loading
+                    .iter()
+                    .map(|f| f.display().to_string())
+                    .collect::<Vec<_>>();
+                chain.push(file.display().to_string());
+                return Err(Object::error(format!(
+                    "circular import: {}",
+                    chain.join(" -> ")
+                )));
+            }
+        }
+        let source = fs::read_to_string(file)
+            .map_err(|err| Object::error(format!("cannot import {}: {}", file.display(), err)))?;
+
+        let mut env = Environment::with_builtins();
+        let mut evaluator = Evaluator {
+            env: &mut env,
+            output: self.output.clone(),
+            warnings: self.warnings.clone(),
+            hook: self.hook.clone(),
+            strict: self.strict,
+            sandbox: self.sandbox,
+            fs_access: self.fs_access.clone(),
+            max_depth: self.max_depth,
+            max_iterations: self.max_iterations,
+            depth: self.depth,
+            hosts: self.hosts.clone(),
+            file: Some(file.to_path_buf()),
+            modules: self.modules.clone(),
+            profiler: self.profiler.clone(),
+            coverage: self.coverage.clone(),
+            strings: self.strings.clone(),
+        };
+        self.modules.borrow_mut().loading.push(file.to_path_buf());
+        let result = evaluate_source(&mut evaluator, &source, Some(file.display().to_string()));
+        self.modules.borrow_mut().loading.pop();
+        // The error is named after the imported file here, and after the
+        // importing one by whoever evaluates that, so each appears once.
+        if let Err(err) = result {
+            let message = match err.position() {
+                Some(position) => {
+                    let (line, col) = Span::new(position).line_col(&source);
+                    format!("line {}, col {}: {}", line, col, err)
+                }
+                None => err.to_string(),
+            };
+            return Err(Object::error(format!("in {}: {}", file.display(), message)));
+        }
+
+        self.modules
+            .borrow_mut()
+            .cache
+            .insert(file.to_path_buf(), env.clone());
+        Ok(env)
     }
 
-    fn evaluate_return_statement(&mut self, expr: Expression) -> Object {
-        let obj = self.evaluate_expression(expr);
+    fn evaluate_return_statement(&mut self, arena: &Rc<Arena>, expr: ExprId) -> Object {
+        let obj = self.evaluate_expression(arena, expr);
         if let Object::Error(_) = obj {
             return obj;
         }
         Object::Return(Box::new(obj))
     }
 
-    pub fn evaluate_expression(&mut self, expr: Expression) -> Object {
-        match expr {
-            Expression::Int(value) => Object::Int(value),
-            Expression::Ident(name) => self.evaluate_identifier(name),
-            Expression::Boolean(value) => Object::Boolean(value),
+    fn evaluate_break_statement(&mut self, arena: &Rc<Arena>, value: Option<ExprId>) -> Object {
+        let obj = match value {
+            Some(value) => self.evaluate_expression(arena, value),
+            None => Object::Null,
+        };
+        if let Object::Error(_) = obj {
+            return obj;
+        }
+        Object::Break(Box::new(obj))
+    }
+
+    pub fn evaluate_expression(&mut self, arena: &Rc<Arena>, expr: ExprId) -> Object {
+        self.trace(Node::Expression(arena, expr));
+        self.evaluate_untraced_expression(arena, expr)
+    }
+
+    fn evaluate_untraced_expression(&mut self, arena: &Rc<Arena>, expr: ExprId) -> Object {
+        match &arena[expr] {
+            Expression::Int(value) => Object::Int(*value),
+            Expression::Float(value) => Object::Float(*value),
+            Expression::String(value) => Object::String(self.intern(value)),
+            Expression::Ident(name, span) => {
+                let obj = self.evaluate_identifier(*name);
+                self.locate(arena, *span, obj)
+            }
+            Expression::Boolean(value) => Object::Boolean(*value),
             Expression::Prefix { op, right } => {
-                let right = self.evaluate_expression(*right);
+                let right = self.evaluate_expression(arena, *right);
                 if let Object::Error(_) = right {
                     return right;
                 }
-                self.evaluate_prefix_expression(op, right)
+                Self::evaluate_prefix_expression(op.clone(), right)
             }
-            Expression::Infix { left, op, right } => {
-                let left = self.evaluate_expression(*left);
+            Expression::Infix {
+                left,
+                op,
+                right,
+                span,
+            } => {
+                let left = self.evaluate_expression(arena, *left);
                 if let Object::Error(_) = left {
                     return left;
                 }
-                let right = self.evaluate_expression(*right);
+                let right = self.evaluate_expression(arena, *right);
                 if let Object::Error(_) = right {
                     return right;
                 }
-                self.evaluate_infix_expression(op, left, right)
+                let obj = Self::evaluate_infix_expression(op.clone(), left, right);
+                self.locate(arena, *span, obj)
             }
             Expression::If {
                 condition,
                 consequence,
                 alternative,
-            } => self.evaluate_if_expression(*condition, consequence, alternative),
-            Expression::Function { parameters, body } => Object::Function {
-                parameters,
+            } => self.evaluate_if_expression(arena, *condition, consequence, alternative),
+            Expression::Block(block) => {
+                let mut env = Environment::new_enclosed(self.env);
+                let mut evaluator = self.enclosed(&mut env);
+                evaluator.depth = self.depth;
+                evaluator.evaluate_block_statement(arena, block)
+            }
+            Expression::Try {
                 body,
+                error,
+                handler,
+            } => self.evaluate_try_expression(arena, body, *error, handler),
+            Expression::Loop(body) => self.evaluate_loop_expression(arena, body),
+            Expression::While { condition, body } => {
+                self.evaluate_while_expression(arena, *condition, body)
+            }
+            Expression::Import(path, span) => self.evaluate_import_expression(arena, path, *span),
+            // Spreads are evaluated by the array literal or call they are in.
+            Expression::Spread(_) => {
+                Object::error("`...` is only allowed in arrays and call arguments".to_string())
+            }
+            Expression::Function { parameters, body } => Object::Function {
+                id: next_function_id(),
+                name: None,
+                parameters: parameters.as_slice().into(),
+                body: Rc::new(body.clone()),
+                arena: arena.clone(),
+                environment: Rc::new(self.env.clone()),
+            },
+            Expression::Macro { parameters, body } => Object::Macro {
+                parameters: parameters.clone(),
+                body: body.clone(),
+                arena: arena.clone(),
                 environment: self.env.clone(),
             },
             Expression::Call {
                 function,
                 arguments,
             } => {
-                let func = self.evaluate_expression(*function);
+                if is_call_to(arena, *function, &QUOTE) {
+                    return self.evaluate_quote(arena, arguments);
+                }
+                let func = self.evaluate_expression(arena, *function);
                 if let Object::Error(_) = func {
                     return func;
                 }
-                let args = self.evaluate_expressions(arguments);
+                let args = match self.evaluate_arguments(arena, &func, arguments) {
+                    Ok(args) => args,
+                    Err(err) => return err,
+                };
                 self.apply_function(func, args)
             }
+            Expression::Array(elements) => {
+                let elements = self.evaluate_expressions(arena, elements);
+                if let [Object::Error(_)] = elements.as_slice() {
+                    return elements[0].clone();
+                }
+                Object::Array(elements)
+            }
+            Expression::Index { left, index } => {
+                let left = self.evaluate_expression(arena, *left);
+                if let Object::Error(_) = left {
+                    return left;
+                }
+                let index = self.evaluate_expression(arena, *index);
+                if let Object::Error(_) = index {
+                    return index;
+                }
+                Self::evaluate_index_expression(left, index)
+            }
+            Expression::Slice { target, start, end } => {
+                let target = self.evaluate_expression(arena, *target);
+                if let Object::Error(_) = target {
+                    return target;
+                }
+                let mut bounds = [None, None];
+                for (bound, expr) in bounds.iter_mut().zip([start, end]) {
+                    if let Some(expr) = expr {
+                        match self.evaluate_expression(arena, *expr) {
+                            Object::Int(value) => *bound = Some(value),
+                            Object::Error(err) => return Object::Error(err),
+                            obj => {
+                                return Object::error(format!(
+                                    "slice bound must be INTEGER, got {}",
+                                    obj.type_info()
+                                ))
+                            }
+                        }
+                    }
+                }
+                Self::evaluate_slice_expression(target, bounds[0], bounds[1])
+            }
+            Expression::Hash(pairs) => self.evaluate_hash_literal(arena, pairs),
+        }
+    }
+
+    /// Indexes an array by position, a string by character or a hash by key.
+    /// A negative position counts back from the end, so `a[-1]` is the last
+    /// element. A position outside the array or string in either direction,
+    /// or a missing key, gives `null`, but a big integer position is an error
+    /// since no array or string could be that long.
+    fn evaluate_index_expression(left: Object, index: Object) -> Object {
+        match (left, index) {
+            (Object::String(value), Object::Int(index)) => {
+                let len = value.chars().count() as i64;
+                let index = if index < 0 { len + index } else { index };
+                if (0..len).contains(&index) {
+                    Object::String(
+                        value
+                            .chars()
+                            .nth(index as usize)
+                            .unwrap()
+                            .to_string()
+                            .into(),
+                    )
+                } else {
+                    Object::Null
+                }
+            }
+            (Object::Array(elements), Object::Int(index)) => {
+                let len = elements.len() as i64;
+                let index = if index < 0 { len + index } else { index };
+                if (0..len).contains(&index) {
+                    elements[index as usize].clone()
+                } else {
+                    Object::Null
+                }
+            }
+            #[cfg(feature = "bigint")]
+            (Object::Array(_) | Object::String(_), Object::BigInt(index)) => {
+                Object::error(format!("index out of range: {}", index))
+            }
+            (Object::Hash(pairs), key) => {
+                if !key.is_hashable() {
+                    return Object::error(format!("unusable as hash key: {}", key.type_info()));
+                }
+                pairs
+                    .into_iter()
+                    .find(|(k, _)| *k == key)
+                    .map_or(Object::Null, |(_, value)| value)
+            }
+            (left, _) => Object::error(format!(
+                "index operator not supported: {}",
+                left.type_info()
+            )),
+        }
+    }
+
+    /// `quote(expr)` evaluates to `expr` itself, except that each
+    /// `unquote(arg)` inside it is replaced by the value of `arg`, turned back
+    /// into an expression.
+    fn evaluate_quote(&mut self, arena: &Rc<Arena>, arguments: &[ExprId]) -> Object {
+        let expr = match arguments {
+            [expr] => *expr,
+            _ => {
+                return Object::error(format!(
+                    "wrong number of arguments. got={}, want=1",
+                    arguments.len()
+                ))
+            }
+        };
+
+        let mut quoted = Arena::new();
+        let mut error = None;
+        let expr = quoted.copy_expr_with(
+            arena,
+            &mut |quoted, id| {
+                let arg = match &arena[id] {
+                    Expression::Call {
+                        function,
+                        arguments,
+                    } if arguments.len() == 1 && is_call_to(arena, *function, &UNQUOTE) => {
+                        arguments[0]
+                    }
+                    _ => return None,
+                };
+                match unquote(quoted, self.evaluate_expression(arena, arg)) {
+                    Ok(expr) => Some(expr),
+                    Err(err) => {
+                        error.get_or_insert(err);
+                        Some(quoted.alloc_expr(Expression::Boolean(false)))
+                    }
+                }
+            },
+            expr,
+        );
+        match error {
+            Some(err) => err,
+            None => Object::Quote {
+                expr,
+                arena: Rc::new(quoted),
+            },
+        }
+    }
+
+    /// Slices a string by character or an array by element, from `start` up
+    /// to but not including `end`. A missing bound is the start or the end,
+    /// a negative one counts back from the end, and one outside the string or
+    /// array is moved to its nearest end, so slicing never fails.
+    fn evaluate_slice_expression(target: Object, start: Option<i64>, end: Option<i64>) -> Object {
+        let range = |len: usize| {
+            let len = len as i64;
+            let clamp = |bound: i64| if bound < 0 { len + bound } else { bound }.clamp(0, len);
+            let start = start.map_or(0, clamp);
+            let end = end.map_or(len, clamp).max(start);
+            start as usize..end as usize
+        };
+        match target {
+            Object::String(value) => {
+                let range = range(value.chars().count());
+                Object::String(
+                    value
+                        .chars()
+                        .skip(range.start)
+                        .take(range.len())
+                        .collect::<String>()
+                        .into(),
+                )
+            }
+            Object::Array(elements) => {
+                let range = range(elements.len());
+                Object::Array(elements[range].to_vec())
+            }
+            target => Object::error(format!(
+                "slice operator not supported: {}",
+                target.type_info()
+            )),
+        }
+    }
+
+    fn evaluate_hash_literal(&mut self, arena: &Rc<Arena>, pairs: &[(ExprId, ExprId)]) -> Object {
+        let mut hash = HashPairs::default();
+        for &(key, value) in pairs {
+            let key = self.evaluate_expression(arena, key);
+            if let Object::Error(_) = key {
+                return key;
+            }
+            if !key.is_hashable() {
+                return Object::error(format!("unusable as hash key: {}", key.type_info()));
+            }
+            let value = self.evaluate_expression(arena, value);
+            if let Object::Error(_) = value {
+                return value;
+            }
+            hash.insert(key, value);
+        }
+        Object::Hash(hash)
+    }
+
+    pub(crate) fn apply_function(&mut self, func: Object, args: Vec<Object>) -> Object {
+        let profiled = self.profiler.borrow_mut().enter(&func);
+        let obj = self.call(func, args, profiled);
+        if profiled {
+            self.profiler.borrow_mut().exit();
         }
+        obj
     }
 
-    fn apply_function(&mut self, func: Object, args: Vec<Object>) -> Object {
-        match func {
+    fn call(&mut self, func: Object, args: Vec<Object>, profiled: bool) -> Object {
+        match &func {
             Object::Function {
                 parameters,
                 body,
+                arena,
                 environment,
+                ..
             } => {
-                let mut env = Environment::new_enclosed(environment);
-                for (i, param) in parameters.iter().enumerate() {
-                    env.set(param, args[i].clone());
+                if self.depth_exceeded() {
+                    return Object::error("maximum recursion depth exceeded".to_string());
+                }
+                let mut args = args;
+                loop {
+                    if args.len() != parameters.len() {
+                        return Object::error(format!(
+                            "wrong number of arguments. got={}, want={}",
+                            args.len(),
+                            parameters.len()
+                        ));
+                    }
+                    // Parameters are bound like `let`, so they cannot shadow a
+                    // constant either.
+                    if let Some(param) = parameters
+                        .iter()
+                        .find(|&&param| environment.is_const(param))
+                    {
+                        return Object::error(format!("cannot shadow const: {}", param));
+                    }
+                    let mut env = Environment::new_enclosed(environment);
+                    for (&param, arg) in parameters.iter().zip(args) {
+                        env.set(param, arg);
+                    }
+                    let mut evaluator = self.enclosed(&mut env);
+                    match grow_stack(|| evaluator.evaluate_tail_block(arena, body, &func)) {
+                        TailCall::Call(next) => {
+                            if profiled {
+                                self.profiler.borrow_mut().tail_call();
+                            }
+                            args = next
+                        }
+                        TailCall::Value(Object::Return(obj)) => return *obj,
+                        TailCall::Value(obj @ (Object::Break(_) | Object::Continue)) => {
+                            return outside_loop(&obj)
+                        }
+                        TailCall::Value(obj) => return obj,
+                    }
                 }
-                let mut evaluator = Evaluator::new(&mut env);
-                let obj = evaluator.evaluate_block_statement(body);
-                match obj {
-                    Object::Return(obj) => *obj,
-                    _ => obj,
+            }
+            Object::Builtin(builtin) => (builtin.func)(self, args),
+            Object::Host(host) => match (host.func)(&args) {
+                Ok(obj) => obj,
+                Err(err) => Object::error(err.to_string()),
+            },
+            Object::Memoized(memoized) => apply_memoized(self, memoized, args),
+            Object::Partial(func, bound) => {
+                let args = bound.iter().cloned().chain(args).collect();
+                self.apply_function(*func.clone(), args)
+            }
+            Object::Composed(funcs) => {
+                let (last, rest) = funcs.split_last().expect("`compose` takes a function");
+                let mut obj = self.apply_function(last.clone(), args);
+                for func in rest.iter().rev() {
+                    if let Object::Error(_) = obj {
+                        break;
+                    }
+                    obj = self.apply_function(func.clone(), vec![obj]);
                 }
+                obj
             }
-            _ => Object::Error(format!("not a function: {}", func.type_info())),
+            Object::Iterator(iterator) => next_element(self, iterator, args),
+            _ => Object::error(format!("not a function: {}", func.type_info())),
+        }
+    }
+
+    // `is_error` has to see error objects, so its arguments do not
+    // short-circuit like those of every other call.
+    fn evaluate_arguments(
+        &mut self,
+        arena: &Rc<Arena>,
+        func: &Object,
+        arguments: &[ExprId],
+    ) -> Result<Vec<Object>, Object> {
+        if let Object::Builtin(Builtin {
+            name: "is_error", ..
+        }) = func
+        {
+            return Ok(arguments
+                .iter()
+                .map(|&arg| self.evaluate_expression(arena, arg))
+                .collect());
+        }
+        let args = self.evaluate_expressions(arena, arguments);
+        match args.as_slice() {
+            [Object::Error(_)] => Err(args[0].clone()),
+            _ => Ok(args),
         }
     }
 
-    fn evaluate_expressions(&mut self, exprs: Vec<Expression>) -> Vec<Object> {
+    // The elements of a spread array are evaluated in its place.
+    fn evaluate_expressions(&mut self, arena: &Rc<Arena>, exprs: &[ExprId]) -> Vec<Object> {
         let mut result = vec![];
-        for expr in exprs {
-            let obj = self.evaluate_expression(expr);
+        for &expr in exprs {
+            if let Expression::Spread(array) = &arena[expr] {
+                match self.evaluate_expression(arena, *array) {
+                    Object::Array(elements) => result.extend(elements),
+                    obj @ Object::Error(_) => return vec![obj],
+                    obj => {
+                        return vec![Object::error(format!(
+                            "value to spread must be ARRAY, got {}",
+                            obj.type_info()
+                        ))]
+                    }
+                }
+                continue;
+            }
+            let obj = self.evaluate_expression(arena, expr);
             if let Object::Error(_) = obj {
                 return vec![obj];
             }
@@ -146,49 +1103,184 @@ impl<'a> Evaluator<'a> {
         result
     }
 
-    fn evaluate_if_expression(
-        &mut self,
-        condition: Expression,
-        consequence: BlockStatement,
-        alternative: Option<BlockStatement>,
-    ) -> Object {
-        let condition = self.evaluate_expression(condition);
-        if let Object::Error(_) = condition {
-            return condition;
-        }
-        if Self::is_truthy(condition) {
-            return self.evaluate_block_statement(consequence);
-        }
+    // Each iteration runs the body in a fresh scope.
+    fn evaluate_loop_expression(&mut self, arena: &Rc<Arena>, body: &BlockStatement) -> Object {
+        let mut iterations = 0;
+        loop {
+            if self
+                .max_iterations
+                .is_some_and(|max_iterations| iterations >= max_iterations)
+            {
+                return Object::error("maximum loop iterations exceeded".to_string());
+            }
+            iterations += 1;
 
-        if let Some(alternative) = alternative {
-            return self.evaluate_block_statement(alternative);
+            let mut env = Environment::new_enclosed(self.env);
+            let mut evaluator = self.enclosed(&mut env);
+            evaluator.depth = self.depth;
+            match evaluator.evaluate_block_statement(arena, body) {
+                Object::Break(value) => return *value,
+                obj @ (Object::Return(_) | Object::Error(_)) => return obj,
+                _ => (),
+            }
+        }
+    }
+
+    // Like a `loop`, but stops before an iteration once `condition` is falsy.
+    // Without a `break`, the value is the last one other than null that the
+    // body evaluated to.
+    fn evaluate_while_expression(
+        &mut self,
+        arena: &Rc<Arena>,
+        condition: ExprId,
+        body: &BlockStatement,
+    ) -> Object {
+        let mut iterations = 0;
+        let mut last = Object::Null;
+        loop {
+            match self.evaluate_expression(arena, condition) {
+                obj @ Object::Error(_) => return obj,
+                obj => {
+                    if !Self::is_truthy(obj) {
+                        return last;
+                    }
+                }
+            }
+            if self
+                .max_iterations
+                .is_some_and(|max_iterations| iterations >= max_iterations)
+            {
+                return Object::error("maximum loop iterations exceeded".to_string());
+            }
+            iterations += 1;
+
+            let mut env = Environment::new_enclosed(self.env);
+            let mut evaluator = self.enclosed(&mut env);
+            evaluator.depth = self.depth;
+            match evaluator.evaluate_block_statement(arena, body) {
+                Object::Break(value) => return *value,
+                obj @ (Object::Return(_) | Object::Error(_)) => return obj,
+                Object::Null | Object::Continue => (),
+                obj => last = obj,
+            }
+        }
+    }
+
+    // An error object from `body` is caught and its message bound to `error`
+    // while `handler` runs. A `return` from `body` is not an error and passes
+    // through, as do errors raised by `handler` itself.
+    fn evaluate_try_expression(
+        &mut self,
+        arena: &Rc<Arena>,
+        body: &BlockStatement,
+        error: Symbol,
+        handler: &BlockStatement,
+    ) -> Object {
+        let mut env = Environment::new_enclosed(self.env);
+        let mut evaluator = self.enclosed(&mut env);
+        evaluator.depth = self.depth;
+        let message = match evaluator.evaluate_block_statement(arena, body) {
+            Object::Error(err) => err.message,
+            obj => return obj,
+        };
+
+        let mut env = Environment::new_enclosed(self.env);
+        env.set(error, Object::String(message.into()));
+        let mut evaluator = self.enclosed(&mut env);
+        evaluator.depth = self.depth;
+        evaluator.evaluate_block_statement(arena, handler)
+    }
+
+    fn evaluate_if_expression(
+        &mut self,
+        arena: &Rc<Arena>,
+        condition: ExprId,
+        consequence: &BlockStatement,
+        alternative: &Option<BlockStatement>,
+    ) -> Object {
+        let condition = self.evaluate_expression(arena, condition);
+        if let Object::Error(_) = condition {
+            return condition;
+        }
+        if Self::is_truthy(condition) {
+            return self.evaluate_block_statement(arena, consequence);
+        }
+
+        if let Some(alternative) = alternative {
+            return self.evaluate_block_statement(arena, alternative);
         }
 
         Object::Null
     }
 
-    fn evaluate_prefix_expression(&mut self, op: String, right: Object) -> Object {
+    pub(crate) fn evaluate_prefix_expression(op: String, right: Object) -> Object {
         match op.as_str() {
-            "!" => self.evaluate_bang_operator_expression(right),
-            "-" => self.evaluate_minus_prefix_operator_expression(right),
-            _ => Object::Error(format!("unknown operator: {}{}", op, &right.type_info())),
+            "!" => Self::evaluate_bang_operator_expression(right),
+            "-" => Self::evaluate_minus_prefix_operator_expression(right),
+            _ => Object::error(format!("unknown operator: {}{}", op, &right.type_info())),
         }
     }
 
-    fn evaluate_infix_expression(&mut self, op: String, left: Object, right: Object) -> Object {
+    pub(crate) fn evaluate_infix_expression(op: String, left: Object, right: Object) -> Object {
         match (op.as_str(), left, right) {
-            (_, Object::Int(l), Object::Int(r)) => self.evaluate_int_infix_expression(op, l, r),
+            (_, Object::Float(l), Object::Float(r)) => {
+                Self::evaluate_float_infix_expression(op, l, r)
+            }
+            (_, Object::Int(l), Object::Float(r)) => {
+                Self::evaluate_float_infix_expression(op, l as f64, r)
+            }
+            (_, Object::Float(l), Object::Int(r)) => {
+                Self::evaluate_float_infix_expression(op, l, r as f64)
+            }
+            #[cfg(feature = "bigint")]
+            (_, Object::BigInt(l), Object::Float(r)) => {
+                Self::evaluate_float_infix_expression(op, bigint::to_f64(&l), r)
+            }
+            #[cfg(feature = "bigint")]
+            (_, Object::Float(l), Object::BigInt(r)) => {
+                Self::evaluate_float_infix_expression(op, l, bigint::to_f64(&r))
+            }
+            #[cfg(feature = "bigint")]
+            (_, Object::BigInt(l), Object::BigInt(r)) => {
+                bigint::evaluate_infix_expression(&op, l, r)
+            }
+            #[cfg(feature = "bigint")]
+            (_, Object::BigInt(l), Object::Int(r)) => {
+                bigint::evaluate_infix_expression(&op, l, r.into())
+            }
+            #[cfg(feature = "bigint")]
+            (_, Object::Int(l), Object::BigInt(r)) => {
+                bigint::evaluate_infix_expression(&op, l.into(), r)
+            }
+            ("<" | ">", left, right) if left.type_info() == right.type_info() => {
+                match left.partial_cmp(&right) {
+                    Some(ordering) if op == "<" => Object::Boolean(ordering.is_lt()),
+                    Some(ordering) => Object::Boolean(ordering.is_gt()),
+                    None => Object::error(format!(
+                        "unknown operator: {} {} {}",
+                        left.type_info(),
+                        op,
+                        right.type_info()
+                    )),
+                }
+            }
+            (_, Object::Int(l), Object::Int(r)) => Self::evaluate_int_infix_expression(op, l, r),
+            (_, Object::String(l), Object::String(r)) => {
+                Self::evaluate_string_infix_expression(op, l, r)
+            }
             ("==", Object::Boolean(l), Object::Boolean(r)) => Object::Boolean(l == r),
             ("!=", Object::Boolean(l), Object::Boolean(r)) => Object::Boolean(l != r),
+            ("==", Object::Hash(l), Object::Hash(r)) => Object::Boolean(l == r),
+            ("!=", Object::Hash(l), Object::Hash(r)) => Object::Boolean(l != r),
             (_, _left, _right) if _left.type_info() != _right.type_info() => {
-                Object::Error(format!(
+                Object::error(format!(
                     "type mismatch: {} {} {}",
                     _left.type_info(),
                     op,
                     _right.type_info()
                 ))
             }
-            (_, _left, _right) => Object::Error(format!(
+            (_, _left, _right) => Object::error(format!(
                 "unknown operator: {} {} {}",
                 _left.type_info(),
                 op,
@@ -197,28 +1289,60 @@ impl<'a> Evaluator<'a> {
         }
     }
 
-    fn evaluate_int_infix_expression(&mut self, op: String, left: i64, right: i64) -> Object {
+    fn evaluate_int_infix_expression(op: String, left: i64, right: i64) -> Object {
+        let result = match op.as_str() {
+            "+" => left.checked_add(right),
+            "-" => left.checked_sub(right),
+            "*" => left.checked_mul(right),
+            "/" | "%" if right == 0 => return Object::error("division by zero".to_string()),
+            "/" => left.checked_div(right),
+            "%" => left.checked_rem(right),
+            "==" => return Object::Boolean(left == right),
+            "!=" => return Object::Boolean(left != right),
+            _ => return Object::error(format!("unknown operator: INTEGER {} INTEGER", op)),
+        };
+        result.map_or_else(|| integer_overflow(&op, left, right), Object::Int)
+    }
+
+    fn evaluate_float_infix_expression(op: String, left: f64, right: f64) -> Object {
         match op.as_str() {
-            "+" => Object::Int(left + right),
-            "-" => Object::Int(left - right),
-            "*" => Object::Int(left * right),
-            "/" => Object::Int(left / right),
+            "+" => Object::Float(left + right),
+            "-" => Object::Float(left - right),
+            "*" => Object::Float(left * right),
+            "/" | "%" if right == 0.0 => Object::error("division by zero".to_string()),
+            "/" => Object::Float(left / right),
+            "%" => Object::Float(left % right),
             "<" => Object::Boolean(left < right),
             ">" => Object::Boolean(left > right),
             "==" => Object::Boolean(left == right),
             "!=" => Object::Boolean(left != right),
-            _ => Object::Error(format!("unknown operator: INTEGER {} INTEGER", op)),
+            _ => Object::error(format!("unknown operator: FLOAT {} FLOAT", op)),
         }
     }
 
-    fn evaluate_minus_prefix_operator_expression(&mut self, right: Object) -> Object {
+    // Interned strings are compared by pointer before their contents.
+    fn evaluate_string_infix_expression(op: String, left: Rc<str>, right: Rc<str>) -> Object {
+        match op.as_str() {
+            "+" => Object::String((left.to_string() + &right).into()),
+            "==" => Object::Boolean(Rc::ptr_eq(&left, &right) || left == right),
+            "!=" => Object::Boolean(!Rc::ptr_eq(&left, &right) && left != right),
+            _ => Object::error(format!("unknown operator: STRING {} STRING", op)),
+        }
+    }
+
+    fn evaluate_minus_prefix_operator_expression(right: Object) -> Object {
         match right {
-            Object::Int(value) => Object::Int(-value),
-            _ => Object::Error(format!("unknown operator: -{}", right.type_info())),
+            Object::Int(value) => value
+                .checked_neg()
+                .map_or_else(|| integer_overflow("-", 0, value), Object::Int),
+            #[cfg(feature = "bigint")]
+            Object::BigInt(value) => bigint::normalize(-value),
+            Object::Float(value) => Object::Float(-value),
+            _ => Object::error(format!("unknown operator: -{}", right.type_info())),
         }
     }
 
-    fn evaluate_bang_operator_expression(&mut self, right: Object) -> Object {
+    fn evaluate_bang_operator_expression(right: Object) -> Object {
         match right {
             Object::Boolean(true) => Object::Boolean(false),
             Object::Boolean(false) => Object::Boolean(true),
@@ -227,14 +1351,44 @@ impl<'a> Evaluator<'a> {
         }
     }
 
-    fn evaluate_identifier(&mut self, name: String) -> Object {
-        match self.env.get(&name) {
+    // Records that an error not located yet was raised at `span` in `arena`,
+    // when the source of the arena is known.
+    fn locate(&self, arena: &Arena, span: Span, obj: Object) -> Object {
+        match (obj, arena.source()) {
+            (Object::Error(mut err), Some(source)) if err.location.is_none() => {
+                err.location = Some(Location {
+                    source: source.clone(),
+                    span,
+                });
+                Object::Error(err)
+            }
+            (obj, _) => obj,
+        }
+    }
+
+    fn evaluate_identifier(&mut self, name: Symbol) -> Object {
+        let found = self
+            .env
+            .get_defined(name)
+            .or_else(|| self.hosts.get(name.as_str()).cloned())
+            .or_else(|| self.env.get_builtin(name));
+        match found {
             Some(obj) => obj,
-            None => Object::Error(format!("identifier not found: {}", name)),
+            None => match suggest_similar(
+                name.as_str(),
+                self.env,
+                self.hosts.keys().map(String::as_str),
+            ) {
+                Some(similar) => Object::error(format!(
+                    "identifier not found: {} (did you mean '{}'?)",
+                    name, similar
+                )),
+                None => Object::error(format!("identifier not found: {}", name)),
+            },
         }
     }
 
-    fn is_truthy(obj: Object) -> bool {
+    pub(crate) fn is_truthy(obj: Object) -> bool {
         match obj {
             Object::Null => false,
             Object::Boolean(value) => value,
@@ -243,10 +1397,160 @@ impl<'a> Evaluator<'a> {
     }
 }
 
+// Whether `function`, the callee of a call, is the identifier `name`.
+fn is_call_to(arena: &Arena, function: ExprId, name: &'static LocalKey<Symbol>) -> bool {
+    match &arena[function] {
+        Expression::Ident(ident, _) => name.with(|name| ident == name),
+        _ => false,
+    }
+}
+
+// Turns the value of an `unquote` back into an expression of `arena`.
+fn unquote(arena: &mut Arena, obj: Object) -> Result<ExprId, Object> {
+    let expr = match obj {
+        Object::Int(value) => Expression::Int(value),
+        Object::Float(value) => Expression::Float(value),
+        Object::Boolean(value) => Expression::Boolean(value),
+        Object::String(value) => Expression::String(value.to_string()),
+        Object::Quote { expr, arena: from } => return Ok(arena.copy_expr(&from, expr)),
+        Object::Error(_) => return Err(obj),
+        obj => return Err(Object::error(format!("cannot unquote {}", obj.type_info()))),
+    };
+    Ok(arena.alloc_expr(expr))
+}
+
+// A `break` or `continue` that reached a function or the program without
+// meeting a loop.
+fn outside_loop(obj: &Object) -> Object {
+    let keyword = match obj {
+        Object::Continue => "continue",
+        _ => "break",
+    };
+    Object::error(format!("`{}` outside of a loop", keyword))
+}
+
+// Matches `obj` against `pattern`, collecting the value each name in it is
+// bound to.
+fn destructure(
+    arena: &Arena,
+    pattern: ExprId,
+    obj: Object,
+    bindings: &mut Vec<(Symbol, Object)>,
+) -> Result<(), Object> {
+    let patterns = match &arena[pattern] {
+        Expression::Ident(name, _) => {
+            bindings.push((*name, obj));
+            return Ok(());
+        }
+        Expression::Array(patterns) => patterns,
+        Expression::Hash(pairs) => return destructure_hash(arena, pairs, obj, bindings),
+        _ => unreachable!("the parser only makes identifier, array and hash patterns"),
+    };
+    let elements = match obj {
+        Object::Array(elements) => elements,
+        obj => {
+            return Err(Object::error(format!(
+                "value to destructure must be ARRAY, got {}",
+                obj.type_info()
+            )))
+        }
+    };
+    if elements.len() != patterns.len() {
+        return Err(Object::error(format!(
+            "wrong number of elements to destructure. got={}, want={}",
+            elements.len(),
+            patterns.len()
+        )));
+    }
+    for (&pattern, element) in patterns.iter().zip(elements) {
+        destructure(arena, pattern, element, bindings)?;
+    }
+    Ok(())
+}
+
+// The keys of a hash pattern are always string literals.
+fn destructure_hash(
+    arena: &Arena,
+    patterns: &[(ExprId, ExprId)],
+    obj: Object,
+    bindings: &mut Vec<(Symbol, Object)>,
+) -> Result<(), Object> {
+    let pairs = match obj {
+        Object::Hash(pairs) => pairs,
+        obj => {
+            return Err(Object::error(format!(
+                "value to destructure must be HASH, got {}",
+                obj.type_info()
+            )))
+        }
+    };
+    for &(key, pattern) in patterns {
+        let key = match &arena[key] {
+            Expression::String(key) => key,
+            _ => unreachable!("the parser only makes string keys in hash patterns"),
+        };
+        let value = pairs
+            .iter()
+            .find(|(k, _)| matches!(k, Object::String(k) if **k == **key))
+            .map(|(_, value)| value.clone());
+        match value {
+            Some(value) => destructure(arena, pattern, value, bindings)?,
+            None => {
+                return Err(Object::error(format!(
+                    "key to destructure not found: {:?}",
+                    key
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+// Whether `a` and `b` are the same function value. Two values made by
+// evaluating the same literal twice are different functions.
+fn same_function(a: &Object, b: &Object) -> bool {
+    match (a, b) {
+        (Object::Function { id: a, .. }, Object::Function { id: b, .. }) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn grow_stack<T>(f: impl FnOnce() -> T) -> T {
+    stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, f)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn grow_stack<T>(f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+// Called when `left op right` does not fit in an `i64`.
+#[cfg(not(feature = "bigint"))]
+fn integer_overflow(_: &str, _: i64, _: i64) -> Object {
+    Object::error("integer overflow".to_string())
+}
+
+#[cfg(feature = "bigint")]
+fn integer_overflow(op: &str, left: i64, right: i64) -> Object {
+    bigint::evaluate_infix_expression(op, left.into(), right.into())
+}
+
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+
     use crate::{
-        environment::Environment, evaluator::Evaluator, lexer::Lexer, object::Object,
+        ast::Node,
+        conformance,
+        environment::Environment,
+        evaluator::{Evaluator, Hook},
+        interpreter::{evaluate_source, parse_source},
+        lexer::Lexer,
+        object::Object,
         parser::Parser,
     };
 
@@ -267,6 +1571,8 @@ mod tests {
             ("3 * 3 * 3 + 10", 37),
             ("3 * (3 * 3) + 10", 37),
             ("(5 + 10 * 2 + 15 / 3) * 2 + -10", 50),
+            ("10 % 3", 1),
+            ("2 + 7 % 4 * 2", 8),
         ];
 
         for test in tests {
@@ -275,6 +1581,84 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_evaluate_float_expression() {
+        let tests = vec![
+            ("2.5", Object::Float(2.5)),
+            ("-2.5", Object::Float(-2.5)),
+            ("1.5 + 1.25", Object::Float(2.75)),
+            ("1 + 0.5", Object::Float(1.5)),
+            ("7.5 / 2", Object::Float(3.75)),
+            ("5.5 % 2", Object::Float(1.5)),
+            ("2 * 1.5 == 3", Object::Boolean(true)),
+            ("0.1 < 0.2", Object::Boolean(true)),
+            ("1 > 1.5", Object::Boolean(false)),
+            ("1.0 / 0", Object::error("division by zero".to_string())),
+            (
+                r#"1.5 + "a""#,
+                Object::error("type mismatch: FLOAT + STRING".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), expect, "{}", input);
+        }
+        assert_eq!(test_evaluate("3.0").to_string(), "3.0");
+    }
+
+    #[cfg(not(feature = "bigint"))]
+    #[test]
+    fn test_integer_overflow() {
+        let fact = "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(50)";
+        let tests = vec![
+            fact,
+            "9223372036854775807 + 1",
+            "-9223372036854775807 - 2",
+            "-(-9223372036854775807 - 1)",
+            "(-9223372036854775807 - 1) / -1",
+        ];
+
+        for input in tests {
+            let object = test_evaluate(input);
+            assert_eq!(
+                object,
+                Object::error("integer overflow".to_string()),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_bigint() {
+        let tests = vec![
+            (
+                "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(50)",
+                "30414093201713378043612608166064768844377641568960512000000000000",
+            ),
+            ("9223372036854775807 + 1", "9223372036854775808"),
+            ("-(-9223372036854775807 - 1)", "9223372036854775808"),
+            ("9223372036854775807 * 2 / 2", "9223372036854775807"),
+            ("(9223372036854775807 + 1) % 10", "8"),
+            ("9223372036854775807 + 1 > 9223372036854775807", "true"),
+            ("9223372036854775807 * 3 == 9223372036854775807 * 3", "true"),
+            (
+                "(9223372036854775807 + 1) * 0.5 == 4611686018427387904.0",
+                "true",
+            ),
+            ("(9223372036854775807 + 1) / 0", "Error: division by zero"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input).to_string(), expect, "{}", input);
+        }
+        assert_eq!(
+            test_evaluate("9223372036854775807 + 1 - 1"),
+            Object::Int(i64::MAX)
+        );
+    }
+
     #[test]
     fn test_evaluate_boolean_expression() {
         let tests = vec![
@@ -301,6 +1685,11 @@ mod tests {
             ("(1 < 2) == false", false),
             ("(1 > 2) == true", false),
             ("(1 > 2) == false", true),
+            (r#""abc" < "abd""#, true),
+            (r#""b" > "abc""#, true),
+            ("false < true", true),
+            ("[1, 2] < [1, 3]", true),
+            ("[2] > [1, 5]", true),
         ];
 
         for test in tests {
@@ -371,6 +1760,21 @@ if (10 > 1) {
                 "unknown operator: BOOLEAN + BOOLEAN",
             ),
             ("foobar", "identifier not found: foobar"),
+            (
+                "let string = \"a\"; stirng",
+                "identifier not found: stirng (did you mean 'string'?)",
+            ),
+            (
+                "let f = fn(count) { cuont + 1 }; f(1)",
+                "identifier not found: cuont (did you mean 'count'?)",
+            ),
+            (r#""Hello" - "World""#, "unknown operator: STRING - STRING"),
+            ("{fn(x) { x }: 1}", "unusable as hash key: FUNCTION"),
+            ("5 / 0", "division by zero"),
+            ("5 % (2 - 2)", "division by zero"),
+            (r#"1 < "2""#, "type mismatch: INTEGER < STRING"),
+            ("[1] < [true]", "unknown operator: ARRAY < ARRAY"),
+            (r#"{"a": 1} > {}"#, "unknown operator: HASH > HASH"),
         ];
 
         for test in tests {
@@ -379,6 +1783,44 @@ if (10 > 1) {
         }
     }
 
+    #[test]
+    fn test_error_location() {
+        let tests = vec![
+            (
+                "let a = 1;\nlet b = 2;\n\nlet c = a + d;",
+                "line 4, col 13: identifier not found: d",
+            ),
+            (
+                "let f = fn(x) {\n  x * true\n};\nf(2)",
+                "line 2, col 5: type mismatch: INTEGER * BOOLEAN",
+            ),
+            (
+                "\"é\" + 1",
+                "line 1, col 5: type mismatch: STRING + INTEGER",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let program = parse_source(input).unwrap();
+            let source = program.arena.source().cloned();
+            let mut env = Environment::new();
+            match Evaluator::new(&mut env).evaluate(program) {
+                Object::Error(err) => {
+                    assert_eq!(err.report(source.as_ref()), expect, "{}", input);
+                    // the location is not part of the message
+                    assert!(expect.ends_with(&format!(": {}", err.message)));
+                }
+                obj => panic!("{}: expected an error, got {}", input, obj),
+            }
+        }
+
+        // Without the source, messages are unchanged.
+        assert_eq!(
+            test_evaluate("let a = 1;\nlet c = a + d;").to_string(),
+            "Error: identifier not found: d"
+        );
+    }
+
     #[test]
     fn test_let_statement() {
         let tests = vec![
@@ -394,6 +1836,368 @@ if (10 > 1) {
         }
     }
 
+    #[test]
+    fn test_const_statement() {
+        let tests = vec![
+            ("const X = 5; X * 2", Object::Int(10)),
+            (
+                "const X = 5; let X = 10",
+                Object::error("cannot shadow const: X".to_string()),
+            ),
+            (
+                "const X = 5; const X = 5",
+                Object::error("cannot shadow const: X".to_string()),
+            ),
+            (
+                "const X = 5; let f = fn() { let X = 1; X }; f()",
+                Object::error("cannot shadow const: X".to_string()),
+            ),
+            (
+                "const X = 5; let [a, X] = [1, 2]",
+                Object::error("cannot shadow const: X".to_string()),
+            ),
+            // a constant in a function is gone once the call returns
+            (
+                "let f = fn() { const Y = 2; Y }; let Y = f() + 1; Y",
+                Object::Int(3),
+            ),
+            // parameters are checked like `let`
+            (
+                "const X = 5; fn(X) { X }(1)",
+                Object::error("cannot shadow const: X".to_string()),
+            ),
+            (
+                "const X = 1; let f = fn(X) { X }; f(2)",
+                Object::error("cannot shadow const: X".to_string()),
+            ),
+            (
+                "let f = fn(X) { X }; const X = 1; f(2)",
+                Object::error("cannot shadow const: X".to_string()),
+            ),
+            ("let f = fn(X) { X }; f(2)", Object::Int(2)),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_destructuring_let() {
+        let tests = vec![
+            (
+                "let [a, b, c] = [1, 2, 3]; a * 100 + b * 10 + c",
+                Object::Int(123),
+            ),
+            ("let [a, [b, c]] = [1, [2, 3]]; a + b + c", Object::Int(6)),
+            (
+                "let [x, y] = [[1], 2]; x",
+                Object::Array(vec![Object::Int(1)]),
+            ),
+            ("let [] = []; 1", Object::Int(1)),
+            (
+                "let f = fn() { let [a, b] = [1, 2]; b }; f()",
+                Object::Int(2),
+            ),
+            (
+                "let [a, b] = [1, 2, 3]",
+                Object::error("wrong number of elements to destructure. got=3, want=2".to_string()),
+            ),
+            (
+                "let [a, [b, c]] = [1, [2]]",
+                Object::error("wrong number of elements to destructure. got=1, want=2".to_string()),
+            ),
+            (
+                "let [a, b] = 1",
+                Object::error("value to destructure must be ARRAY, got INTEGER".to_string()),
+            ),
+            (
+                r#"let person = {"name": "Ann", "age": 30}; let {name, age} = person; [name, age]"#,
+                Object::Array(vec![Object::String("Ann".into()), Object::Int(30)]),
+            ),
+            (
+                r#"let {"point": [x, y], z} = {"z": 3, "point": [1, 2], "w": 4}; x + y + z"#,
+                Object::Int(6),
+            ),
+            ("let {} = {}; 1", Object::Int(1)),
+            (
+                r#"let {name, age} = {"name": "Ann"}"#,
+                Object::error(r#"key to destructure not found: "age""#.to_string()),
+            ),
+            (
+                "let {a} = [1]",
+                Object::error("value to destructure must be HASH, got ARRAY".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), expect, "{}", input);
+        }
+
+        // nothing is bound when the value does not match
+        let mut env = Environment::new();
+        let program = Parser::new(Lexer::new("let [a, [b]] = [1, 2]"))
+            .parse_program()
+            .unwrap();
+        Evaluator::new(&mut env).evaluate(program);
+        assert!(!env.contains("a"));
+
+        let program = Parser::new(Lexer::new("let a = 1; let [b, a] = [2, 3]"))
+            .parse_program()
+            .unwrap();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.set_strict(true);
+        assert_eq!(
+            evaluator.evaluate(program),
+            Object::error("identifier already declared: a".to_string())
+        );
+        assert!(!env.contains("b"));
+    }
+
+    #[test]
+    fn test_evaluate_block_expression() {
+        let tests = vec![
+            ("let z = { let a = 3; let b = 4; a * b }; z == 12", true),
+            ("1 + { 2 + 3 } == 6", true),
+            ("{ let a = 1; { let b = a + 1; b * 10 } } == 20", true),
+            ("let a = 1; { let a = 2; a }; a == 1", true),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), Object::Boolean(expect), "{}", input);
+        }
+        assert_eq!(
+            test_evaluate("{ let a = 1; }; a"),
+            Object::error("identifier not found: a".to_string())
+        );
+    }
+
+    #[test]
+    fn test_try_expression() {
+        let tests = vec![
+            (
+                "try { 1 / 0 } catch (e) { e }",
+                Object::String("division by zero".into()),
+            ),
+            ("try { 1 + 1 } catch (e) { 0 }", Object::Int(2)),
+            (
+                "let f = fn(x) { x / 0 }; try { f(1); 2 } catch (e) { e == \"division by zero\" }",
+                Object::Boolean(true),
+            ),
+            (
+                "try { try { 1 / 0 } catch (e) { e + 1 } } catch (e) { e }",
+                Object::String("type mismatch: STRING + INTEGER".into()),
+            ),
+            (
+                "let f = fn() { try { return 1; } catch (e) { 2 }; 3 }; f()",
+                Object::Int(1),
+            ),
+            (
+                "try { 1 / 0 } catch (e) { 1 }; e",
+                Object::error("identifier not found: e".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_loop_expression() {
+        let tests = vec![
+            (
+                "let x = loop { if (true) { break 42 } }; x == 42",
+                Object::Boolean(true),
+            ),
+            ("loop { break }", Object::Null),
+            ("loop { let a = 1; break a + 1; 3 }", Object::Int(2)),
+            ("loop { loop { break 1 }; break 2 }", Object::Int(2)),
+            (
+                "let f = fn() { loop { return 1; }; 2 }; f()",
+                Object::Int(1),
+            ),
+            (
+                "let f = fn() { loop { if (true) { return loop { break 3 } } } }; f()",
+                Object::Int(3),
+            ),
+            (
+                "loop { break 1 / 0 }",
+                Object::error("division by zero".to_string()),
+            ),
+            (
+                "loop { 1 / 0 }",
+                Object::error("division by zero".to_string()),
+            ),
+            (
+                "break 1; 2",
+                Object::error("`break` outside of a loop".to_string()),
+            ),
+            (
+                "let f = fn() { continue }; loop { f() }",
+                Object::error("`continue` outside of a loop".to_string()),
+            ),
+            (
+                "loop { break }; a",
+                Object::error("identifier not found: a".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_while_expression() {
+        let tests = vec![
+            ("while (false) { 1 }", Object::Null),
+            (
+                "let x = while (true) { break 42 }; x == 42",
+                Object::Boolean(true),
+            ),
+            ("while (true) { break }", Object::Null),
+            (
+                "while (1 / 0) { 1 }",
+                Object::error("division by zero".to_string()),
+            ),
+            (
+                "let f = fn() { while (true) { return 1; }; 2 }; f()",
+                Object::Int(1),
+            ),
+            (
+                "while (true) { 1 / 0 }",
+                Object::error("division by zero".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_max_iterations() {
+        let tests = vec![
+            // `continue` skips the rest of the body
+            (
+                "loop { continue; 1 / 0 }",
+                "maximum loop iterations exceeded",
+            ),
+            (
+                "loop { loop { break }; }",
+                "maximum loop iterations exceeded",
+            ),
+            ("while (true) { 1 }", "maximum loop iterations exceeded"),
+        ];
+
+        for (input, expect) in tests {
+            let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+            let mut env = Environment::new();
+            let mut evaluator = Evaluator::new(&mut env);
+            evaluator.set_max_iterations(Some(10));
+            assert_eq!(
+                evaluator.evaluate(program),
+                Object::error(expect.to_string()),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_quote() {
+        let tests = vec![
+            ("quote(5)", "5"),
+            ("quote(5 + 8)", "(5 + 8)"),
+            ("quote(foobar)", "foobar"),
+            ("quote(foobar + barfoo)", "(foobar + barfoo)"),
+            ("quote(fn(x) { x })", "fn (x) { x }"),
+            ("quote(quote(1))", "quote(1)"),
+        ];
+
+        for (input, expect) in tests {
+            assert_quotes(input, expect);
+        }
+        assert_eq!(test_evaluate("quote(1 + 2)").to_string(), "QUOTE((1 + 2))");
+    }
+
+    #[test]
+    fn test_quote_unquote() {
+        let tests = vec![
+            ("quote(unquote(4))", "4"),
+            ("quote(unquote(4 + 4))", "8"),
+            ("quote(8 + unquote(4 + 4))", "(8 + 8)"),
+            ("quote(unquote(4 + 4) + 8)", "(8 + 8)"),
+            ("quote(5 + unquote(4 + 4))", "(5 + 8)"),
+            ("let foobar = 8; quote(foobar)", "foobar"),
+            ("let foobar = 8; quote(unquote(foobar))", "8"),
+            ("quote(unquote(true))", "true"),
+            ("quote(unquote(true == false))", "false"),
+            (r#"quote(unquote("a") + unquote(1.5))"#, r#"("a" + 1.5)"#),
+            ("quote(unquote(quote(4 + 4)))", "(4 + 4)"),
+            (
+                "let quotedInfixExpression = quote(4 + 4); \
+                 quote(unquote(4 + 4) + unquote(quotedInfixExpression))",
+                "(8 + (4 + 4))",
+            ),
+            // an `unquote` in a nested `quote` is evaluated by the outer one
+            ("quote(quote(unquote(1 + 1)))", "quote(2)"),
+            ("quote(fn(x) { unquote(2 * 3) })", "fn (x) { 6 }"),
+        ];
+
+        for (input, expect) in tests {
+            assert_quotes(input, expect);
+        }
+
+        let errors = vec![
+            ("quote(unquote(1 / 0))", "division by zero"),
+            ("quote(unquote(fn(x) { x }))", "cannot unquote FUNCTION"),
+            ("quote(1, 2)", "wrong number of arguments. got=2, want=1"),
+            ("unquote(1)", "identifier not found: unquote"),
+        ];
+
+        for (input, expect) in errors {
+            assert_eq!(
+                test_evaluate(input),
+                Object::error(expect.to_string()),
+                "{}",
+                input
+            );
+        }
+    }
+
+    fn assert_quotes(input: &str, expect: &str) {
+        match test_evaluate(input) {
+            Object::Quote { expr, arena } => {
+                assert_eq!(arena.display(expr).to_string(), expect, "{}", input)
+            }
+            obj => panic!("{}: object is not Quote. got={}", input, obj),
+        }
+    }
+
+    #[test]
+    fn test_spread() {
+        let tests = vec![
+            ("let rest = [2, 3]; [1, ...rest, 4]", "[1, 2, 3, 4]"),
+            ("[...[], ...[1], ...[[2]]]", "[1, [2]]"),
+            (
+                "let f = fn(a, b, c) { a * 100 + b * 10 + c }; f(...[1, 2], 3)",
+                "123",
+            ),
+            ("let f = fn(a, b) { b }; let args = [1, 2]; f(...args)", "2"),
+            (
+                "[1, ...2]",
+                "Error: value to spread must be ARRAY, got INTEGER",
+            ),
+            ("[...[1 / 0]]", "Error: division by zero"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input).to_string(), expect, "{}", input);
+        }
+    }
+
     #[test]
     fn test_evaluate_function() {
         let tests = vec![
@@ -411,6 +2215,35 @@ if (10 > 1) {
         }
     }
 
+    #[test]
+    fn test_wrong_number_of_arguments() {
+        let tests = vec![
+            (
+                "let add = fn(x, y) { x + y }; add(5)",
+                "wrong number of arguments. got=1, want=2",
+            ),
+            (
+                "let add = fn(x, y) { x + y }; add(1, 2, 3)",
+                "wrong number of arguments. got=3, want=2",
+            ),
+            ("fn() { 1 }(1)", "wrong number of arguments. got=1, want=0"),
+            // a call in tail position is checked too
+            (
+                "let f = fn(n) { if (n == 0) { 0 } else { f() } }; f(3)",
+                "wrong number of arguments. got=0, want=1",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                test_evaluate(input),
+                Object::error(expect.to_string()),
+                "{}",
+                input
+            );
+        }
+    }
+
     #[test]
     fn test_function_object() {
         let input = "fn(x) { x + 2 };";
@@ -418,12 +2251,532 @@ if (10 > 1) {
         if let Object::Function {
             parameters,
             body,
-            environment: _,
+            arena,
+            ..
         } = evaluated
         {
             assert_eq!(parameters[0], "x");
-            assert_eq!(body.to_string(), "(x + 2)")
+            assert_eq!(arena.display(&*body).to_string(), "{ (x + 2) }")
+        }
+    }
+
+    #[test]
+    fn test_array_literal() {
+        let tests = vec![
+            ("[1, 2 * 2, 3 + 3]", "[1, 4, 6]"),
+            ("[]", "[]"),
+            ("[[1], true]", "[[1], true]"),
+        ];
+
+        for (input, expect) in tests {
+            let object = test_evaluate(input);
+            assert_eq!(object.to_string(), expect);
+        }
+
+        let object = test_evaluate("[1, -true]");
+        assert_eq!(object.to_string(), "Error: unknown operator: -BOOLEAN");
+    }
+
+    #[test]
+    fn test_index_expression() {
+        let tests = vec![
+            ("[1, 2, 3][0]", "1"),
+            ("[1, 2, 3][1 + 1]", "3"),
+            ("let a = [1, 2, 3]; a[0] + a[1] + a[2]", "6"),
+            ("[[1, 2], [3]][0][1]", "2"),
+            // Positions past the end are `null`.
+            ("[1, 2, 3][3]", "null"),
+            ("[][0]", "null"),
+            // Negative positions count back from the end, down to `-len`.
+            ("[1, 2, 3][-1]", "3"),
+            ("[1, 2, 3][-2]", "2"),
+            ("[1, 2, 3][-3]", "1"),
+            ("[1, 2, 3][-4]", "null"),
+            ("[][-1]", "null"),
+            (r#"{"a": 1, "b": 2}["b"]"#, "2"),
+            (r#"{"a": 1}["c"]"#, "null"),
+            ("{1: true}[1]", "true"),
+            (r#""hello"[1]"#, "e"),
+            (r#""hello"[-1]"#, "o"),
+            (r#""hello"[5]"#, "null"),
+            (r#""héllo"[1]"#, "é"),
+            ("1[0]", "Error: index operator not supported: INTEGER"),
+            (r#"[1]["0"]"#, "Error: index operator not supported: ARRAY"),
+            (
+                "{1: true}[fn(x) { x }]",
+                "Error: unusable as hash key: FUNCTION",
+            ),
+            ("[1][-true]", "Error: unknown operator: -BOOLEAN"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input).to_string(), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_slice_expression() {
+        let tests = vec![
+            (r#""hello"[1..3] == "el""#, "true"),
+            (r#""hello"[..2] == "he""#, "true"),
+            (r#""hello"[2..]"#, "llo"),
+            (r#""hello"[-2..] == "lo""#, "true"),
+            (r#""hello"[..-1]"#, "hell"),
+            (r#""hello"[..]"#, "hello"),
+            (r#""héllo"[1..3]"#, "él"),
+            ("[1, 2, 3, 4][1..3]", "[2, 3]"),
+            ("let a = [1, 2, 3]; a[1 - 1..a[1]]", "[1, 2]"),
+            // Bounds outside are moved to the nearest end.
+            ("[1, 2, 3][1..10]", "[2, 3]"),
+            ("[1, 2, 3][-10..1]", "[1]"),
+            ("[1, 2, 3][2..1]", "[]"),
+            (r#""abc"[5..]"#, ""),
+            ("1[0..1]", "Error: slice operator not supported: INTEGER"),
+            (
+                r#"[1][true..]"#,
+                "Error: slice bound must be INTEGER, got BOOLEAN",
+            ),
+            ("[1][..1 / 0]", "Error: division by zero"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input).to_string(), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_string_expression() {
+        let tests = vec![
+            (r#""Hello World!""#, Object::String("Hello World!".into())),
+            (
+                r#""Hello" + " " + "World!""#,
+                Object::String("Hello World!".into()),
+            ),
+            (r#""a" == "a""#, Object::Boolean(true)),
+            (r#""a" != "a""#, Object::Boolean(false)),
+        ];
+
+        for test in tests {
+            let object = test_evaluate(test.0);
+            assert_eq!(object, test.1);
+        }
+    }
+
+    #[test]
+    fn test_string_literals_interned() {
+        let object = test_evaluate(r#"let f = fn() { "abc" }; [f(), "abc", "ab" + "c"]"#);
+        let strings = match object {
+            Object::Array(elements) => elements
+                .into_iter()
+                .map(|obj| match obj {
+                    Object::String(value) => value,
+                    obj => panic!("object is not String. got={:?}", obj),
+                })
+                .collect::<Vec<_>>(),
+            obj => panic!("object is not Array. got={:?}", obj),
+        };
+        assert!(Rc::ptr_eq(&strings[0], &strings[1]));
+        // strings computed at runtime are not interned, but still compare equal
+        assert!(!Rc::ptr_eq(&strings[0], &strings[2]));
+        assert_eq!(strings[0], strings[2]);
+    }
+
+    #[test]
+    fn test_hash_literal() {
+        let input = r#"let two = "two";
+{
+    "one": 10 - 9,
+    two: 1 + 1,
+    "thr" + "ee": 6 / 2,
+    4: 4,
+    true: 5,
+    false: 6,
+    "one": 1
+}"#;
+        let object = test_evaluate(input);
+        assert_eq!(
+            object,
+            Object::Hash(
+                vec![
+                    (Object::String("one".into()), Object::Int(1)),
+                    (Object::String("two".into()), Object::Int(2)),
+                    (Object::String("three".into()), Object::Int(3)),
+                    (Object::Int(4), Object::Int(4)),
+                    (Object::Boolean(true), Object::Int(5)),
+                    (Object::Boolean(false), Object::Int(6)),
+                ]
+                .into()
+            )
+        );
+        // equality ignores the order of the pairs, which is kept for display
+        assert_eq!(
+            object.to_string(),
+            "{one: 1, two: 2, three: 3, 4: 4, true: 5, false: 6}"
+        );
+    }
+
+    #[test]
+    fn test_hash_equality() {
+        let tests = vec![
+            (r#"{"a": 1, "b": 2} == {"b": 2, "a": 1}"#, true),
+            (r#"{"a": 1, "b": 2} != {"b": 2, "a": 1}"#, false),
+            (r#"{"a": 1, "b": 2} == {"a": 1, "b": 3}"#, false),
+            (r#"{"a": 1, "b": 2} == {"a": 1}"#, false),
+            (r#"{"a": 1} == {"a": 1, "a": 1}"#, true),
+            (r#"{} == {}"#, true),
+            (r#"{1: {"x": 1, "y": 2}} == {1: {"y": 2, "x": 1}}"#, true),
+            (r#"{true: 1} == {"true": 1}"#, false),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(test_evaluate(input), Object::Boolean(expect), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_recursive_function() {
+        let tests = vec![
+            (
+                "let fact = fn(n) { if (n == 0) { 1 } else { n * fact(n - 1) } }; fact(10);",
+                3628800,
+            ),
+            (
+                "let f = fn() { if (true) { return 1; } 2 }; f();",
+                1,
+            ),
+            (
+                "let sum = fn(n, acc) { if (n == 0) { return acc; } return sum(n - 1, acc + n); }; sum(100, 0);",
+                5050,
+            ),
+        ];
+
+        for test in tests {
+            let object = test_evaluate(test.0);
+            assert_eq!(object, Object::Int(test.1));
+        }
+    }
+
+    #[test]
+    fn test_tail_call() {
+        let input = r#"let countdown = fn(n) {
+    if (n == 0) {
+        0
+    } else {
+        countdown(n - 1)
+    }
+};
+countdown(1000000);"#;
+        let object = test_evaluate(input);
+        assert_eq!(object, Object::Int(0));
+    }
+
+    #[test]
+    fn test_deep_recursion() {
+        // a call that is not in tail position nests, so it is stopped by the
+        // default depth limit rather than by a stack overflow
+        let input = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(100000)";
+        assert_eq!(
+            test_evaluate(input),
+            Object::error("maximum recursion depth exceeded".to_string())
+        );
+        let input = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(5000)";
+        assert_eq!(test_evaluate(input), Object::Int(5000));
+    }
+
+    #[test]
+    fn test_hook() {
+        let visited = Rc::new(RefCell::new(vec![]));
+        let recorder = visited.clone();
+        let hook: Hook = Rc::new(RefCell::new(move |node: Node, env: &Environment| {
+            let a = env.get("a").map(|obj| obj.to_string());
+            recorder.borrow_mut().push((node.to_string(), a));
+        }));
+
+        let program = Parser::new(Lexer::new("let a = 1; a + 2"))
+            .parse_program()
+            .unwrap();
+        let mut env = Environment::new();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.set_hook(Some(hook));
+        assert_eq!(evaluator.evaluate(program), Object::Int(3));
+
+        let one = Some("1".to_string());
+        assert_eq!(
+            *visited.borrow(),
+            vec![
+                ("let a = 1;".to_string(), None),
+                ("1".to_string(), None),
+                ("(a + 2)".to_string(), one.clone()),
+                ("(a + 2)".to_string(), one.clone()),
+                ("a".to_string(), one.clone()),
+                ("2".to_string(), one),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hook_in_tail_position() {
+        let visited = Rc::new(RefCell::new(vec![]));
+        let recorder = visited.clone();
+        let hook: Hook = Rc::new(RefCell::new(move |node: Node, _: &Environment| {
+            recorder.borrow_mut().push(node.to_string());
+        }));
+
+        let input = "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(2)";
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        let mut env = Environment::with_builtins();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.set_hook(Some(hook));
+        assert_eq!(evaluator.evaluate(program), Object::Int(0));
+        // Tail calls reuse the caller's frame but are still visited, once as
+        // the statement of the `else` block and once as an expression.
+        let tail_calls = visited
+            .borrow()
+            .iter()
+            .filter(|node| *node == "f((n - 1))")
+            .count();
+        assert_eq!(tail_calls, 4);
+    }
+
+    #[test]
+    fn test_import() {
+        let dir = temp_dir("import");
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("lib").join("math.monkey"),
+            "let square = fn(x) { x * x }; let two = 2;",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("main.monkey"),
+            r#"import "lib/math.monkey"; square(two) + 1"#,
+        )
+        .unwrap();
+
+        let mut env = Environment::with_builtins();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.set_file(Some(dir.join("main.monkey")));
+        let source = fs::read_to_string(dir.join("main.monkey")).unwrap();
+        assert_eq!(
+            evaluate_source(&mut evaluator, &source, None).unwrap(),
+            Object::Int(5)
+        );
+        assert!(env.contains("square"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_circular_import() {
+        let dir = temp_dir("circular");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.monkey"), r#"import "b.monkey";"#).unwrap();
+        fs::write(dir.join("b.monkey"), r#"import "a.monkey";"#).unwrap();
+
+        let mut env = Environment::with_builtins();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.set_file(Some(dir.join("main.monkey")));
+        let err = evaluate_source(&mut evaluator, r#"import "a.monkey";"#, None).unwrap_err();
+        let dir = dir.canonicalize().unwrap();
+        let (a, b) = (dir.join("a.monkey"), dir.join("b.monkey"));
+        assert!(
+            err.to_string().ends_with(&format!(
+                "circular import: {} -> {} -> {}",
+                a.display(),
+                b.display(),
+                a.display()
+            )),
+            "{}",
+            err
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_const() {
+        let dir = temp_dir("import-const");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("m.monkey"), "let X = 99; const Y = 2;").unwrap();
+
+        let tests = vec![
+            (
+                "const X = 1;\nimport \"m.monkey\";",
+                "line 2, col 1: cannot shadow const: X",
+            ),
+            // the module's constants stay constant
+            ("import \"m.monkey\"; let Y = 3;", "cannot shadow const: Y"),
+            ("import \"m.monkey\"; import \"m.monkey\"; X + Y", "101"),
+        ];
+        for (source, expect) in tests {
+            let mut env = Environment::with_builtins();
+            let mut evaluator = Evaluator::new(&mut env);
+            evaluator.set_file(Some(dir.join("main.monkey")));
+            let result = match evaluate_source(&mut evaluator, source, None) {
+                Ok(obj) => obj.to_string(),
+                Err(err) => err.to_string(),
+            };
+            assert_eq!(result, expect, "{}", source);
+        }
+
+        // nothing is bound when the import fails
+        let mut env = Environment::with_builtins();
+        env.set_const("X", Object::Int(1));
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.set_file(Some(dir.join("main.monkey")));
+        assert!(evaluate_source(&mut evaluator, r#"import "m.monkey";"#, None).is_err());
+        assert_eq!(env.get("X"), Some(Object::Int(1)));
+        assert!(env.is_const("X"));
+        assert!(!env.contains("Y"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_expression() {
+        let dir = temp_dir("import-expression");
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("lib").join("math.monkey"),
+            "let square = fn(x) { x * x }; let two = 2;",
+        )
+        .unwrap();
+
+        let mut env = Environment::with_builtins();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.set_file(Some(dir.join("main.monkey")));
+        let source = r#"let math = import("lib/math.monkey"); math["square"](4) + math["two"]"#;
+        assert_eq!(
+            evaluate_source(&mut evaluator, source, None).unwrap(),
+            Object::Int(18)
+        );
+        // the bindings are only in the hash
+        assert!(!env.contains("square"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_chain() {
+        let dir = temp_dir("chain");
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("a.monkey"),
+            r#"let b = import("lib/b.monkey"); let a = b["b"] + 1;"#,
+        )
+        .unwrap();
+        // relative to the file importing it
+        fs::write(
+            dir.join("lib").join("b.monkey"),
+            r#"puts("loading b"); let b = 1;"#,
+        )
+        .unwrap();
+
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut env = Environment::with_builtins();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.set_file(Some(dir.join("main.monkey")));
+        evaluator.set_output(output.clone());
+        let source = r#"import "a.monkey"; import "a.monkey"; import "lib/b.monkey"; [a, b]"#;
+        assert_eq!(
+            evaluate_source(&mut evaluator, source, None)
+                .unwrap()
+                .to_string(),
+            "[2, 1]"
+        );
+        // each file runs once, however many times it is imported
+        assert_eq!(output.borrow().as_slice(), b"loading b\n");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_imported_function_error_location() {
+        let dir = temp_dir("imported-function-error");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("lib.monkey"), "let f = fn(x) {\n  x * true\n};").unwrap();
+        let lib = dir.canonicalize().unwrap().join("lib.monkey");
+
+        let tests = vec![
+            (
+                "import \"lib.monkey\";\nlet y = 1;\nf(2)",
+                format!(
+                    "in {}: line 2, col 5: type mismatch: INTEGER * BOOLEAN",
+                    lib.display()
+                ),
+            ),
+            (
+                "let g = eval(\"fn() {\n  1 - true }\");\ng()",
+                "in eval: line 2, col 5: type mismatch: INTEGER - BOOLEAN".to_string(),
+            ),
+            (
+                "let h = eval(\"fn() { 1 +\n  true }\");\nh()",
+                "in eval: line 1, col 10: type mismatch: INTEGER + BOOLEAN".to_string(),
+            ),
+        ];
+        for (source, expect) in tests {
+            let mut env = Environment::with_builtins();
+            let mut evaluator = Evaluator::new(&mut env);
+            evaluator.set_file(Some(dir.join("main.monkey")));
+            let err = evaluate_source(&mut evaluator, source, None).unwrap_err();
+            assert_eq!(err.to_string(), expect, "{}", source);
         }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = temp_dir("import-errors");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("bad.monkey"), "let x = 1;\nlet = 2;").unwrap();
+        fs::write(dir.join("undefined.monkey"), "let x = y;").unwrap();
+        let canonical = dir.canonicalize().unwrap();
+        let (bad, undefined) = (
+            canonical.join("bad.monkey"),
+            canonical.join("undefined.monkey"),
+        );
+
+        // the importing file is named by whoever runs it, as `run_scripts` does
+        let tests = vec![
+            (
+                "let x = 1;\nimport \"missing.monkey\";",
+                "line 2, col 1: cannot import missing.monkey: ".to_string(),
+            ),
+            (
+                "import(\"bad.monkey\")",
+                format!(
+                    "line 1, col 1: in {}: line 2, col 5: expected next token",
+                    bad.display()
+                ),
+            ),
+            (
+                "\n  let u = import(\"undefined.monkey\")",
+                format!(
+                    "line 2, col 11: in {}: line 1, col 9: identifier not found: y",
+                    undefined.display()
+                ),
+            ),
+        ];
+        for (source, expect) in tests {
+            let mut env = Environment::with_builtins();
+            let mut evaluator = Evaluator::new(&mut env);
+            evaluator.set_file(Some(dir.join("main.monkey")));
+            let err = evaluate_source(&mut evaluator, source, None).unwrap_err();
+            assert!(
+                err.to_string().starts_with(&expect),
+                "{}: {} does not start with {}",
+                source,
+                err,
+                expect
+            );
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("monkey-{}-{}", name, std::process::id()))
     }
 
     fn test_evaluate(input: &str) -> Object {
@@ -432,6 +2785,9 @@ if (10 > 1) {
         let program = parser.parse_program().unwrap();
         let mut env = Environment::new();
         let mut evaluator = Evaluator::new(&mut env);
-        evaluator.evaluate(program)
+        let obj = evaluator.evaluate(program.clone());
+        // every program the tests evaluate must give the same on the VM
+        conformance::check(input, &program, &obj);
+        obj
     }
 }
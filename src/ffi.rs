@@ -0,0 +1,223 @@
+//! A C API for embedding Monkey in programs written in other languages,
+//! enabled by the `ffi` feature. Generate the header with
+//! `cbindgen --config cbindgen.toml --output monkey.h`; `examples/ffi.c`
+//! shows how to use it.
+//!
+//! Every string returned by `monkey_eval` is owned by the caller, who must
+//! release it with `monkey_string_free`. An interpreter is released with
+//! `monkey_interp_free`. A panic never unwinds into the host: it is reported
+//! as the result of the call instead.
+
+use std::any::Any;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::interpreter::Interpreter;
+
+/// An interpreter whose bindings persist across calls to `monkey_eval`.
+/// Opaque to C.
+pub struct MonkeyInterp {
+    interpreter: Interpreter,
+}
+
+/// Creates an interpreter, or returns null if that fails.
+#[no_mangle]
+pub extern "C" fn monkey_interp_new() -> *mut MonkeyInterp {
+    panic::catch_unwind(|| {
+        Box::into_raw(Box::new(MonkeyInterp {
+            interpreter: Interpreter::new(),
+        }))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Releases an interpreter created by `monkey_interp_new`. Does nothing if
+/// `interp` is null.
+///
+/// # Safety
+///
+/// `interp` must be null or a pointer returned by `monkey_interp_new` that has
+/// not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_interp_free(interp: *mut MonkeyInterp) {
+    if !interp.is_null() {
+        // Dropping can run arbitrary destructors; a panic in one must not
+        // cross into C.
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(interp))));
+    }
+}
+
+/// Evaluates the NUL-terminated UTF-8 `source` and returns the value of its
+/// last statement, or the text of the error it failed with, as a new string
+/// to be released with `monkey_string_free`.
+///
+/// # Safety
+///
+/// `interp` must be null or a live pointer returned by `monkey_interp_new`,
+/// and `source` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_eval(
+    interp: *mut MonkeyInterp,
+    source: *const c_char,
+) -> *mut c_char {
+    if interp.is_null() {
+        return into_c_string("interpreter is null".to_string());
+    }
+    if source.is_null() {
+        return into_c_string("source is null".to_string());
+    }
+    let interp = &mut *interp;
+    let source = CStr::from_ptr(source);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| match source.to_str() {
+        Ok(source) => match interp.interpreter.eval(source) {
+            Ok(obj) => obj.to_string(),
+            Err(err) => err.to_string(),
+        },
+        Err(_) => "source is not valid UTF-8".to_string(),
+    }));
+    into_c_string(result.unwrap_or_else(|payload| panic_message(&*payload)))
+}
+
+/// Releases a string returned by `monkey_eval`. Does nothing if `s` is null.
+///
+/// # Safety
+///
+/// `s` must be null or a pointer returned by `monkey_eval` that has not been
+/// freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+// C strings end at the first NUL, so any inside `s` are escaped.
+fn into_c_string(s: String) -> *mut c_char {
+    CString::new(s.replace('\0', "\\0"))
+        .expect("NUL bytes were escaped")
+        .into_raw()
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown error");
+    format!("panic: {}", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+    use std::os::raw::c_char;
+    use std::ptr;
+
+    use crate::errors::MonkeyError;
+    use crate::ffi::{
+        monkey_eval, monkey_interp_free, monkey_interp_new, monkey_string_free, MonkeyInterp,
+    };
+    use crate::object::Object;
+
+    // Evaluates `source` and takes ownership of the result back from C.
+    fn eval(interp: *mut MonkeyInterp, source: &str) -> String {
+        let source = CString::new(source).unwrap();
+        unsafe { take(monkey_eval(interp, source.as_ptr())) }
+    }
+
+    unsafe fn take(result: *mut c_char) -> String {
+        assert!(!result.is_null());
+        let s = CStr::from_ptr(result).to_str().unwrap().to_string();
+        monkey_string_free(result);
+        s
+    }
+
+    #[test]
+    fn test_eval() {
+        let interp = monkey_interp_new();
+        assert!(!interp.is_null());
+        let tests = vec![
+            ("let add = fn(a, b) { a + b };", "fn (a, b) { (a + b) }"),
+            ("add(1, 2)", "3"),
+            (r#""a" + "b""#, "ab"),
+            ("1 / 0", "line 1, col 3: division by zero"),
+            ("missing", "line 1, col 1: identifier not found: missing"),
+        ];
+        for (input, expect) in tests {
+            assert_eq!(eval(interp, input), expect, "{}", input);
+        }
+        unsafe { monkey_interp_free(interp) };
+    }
+
+    #[test]
+    fn test_interpreters_are_independent() {
+        let first = monkey_interp_new();
+        let second = monkey_interp_new();
+        eval(first, "let a = 1;");
+        assert_eq!(eval(first, "a"), "1");
+        assert_eq!(eval(second, "a"), "line 1, col 1: identifier not found: a");
+        unsafe {
+            monkey_interp_free(first);
+            monkey_interp_free(second);
+        }
+    }
+
+    #[test]
+    fn test_null_arguments() {
+        let interp = monkey_interp_new();
+        unsafe {
+            assert_eq!(take(monkey_eval(interp, ptr::null())), "source is null");
+            let source = CString::new("1").unwrap();
+            assert_eq!(
+                take(monkey_eval(ptr::null_mut(), source.as_ptr())),
+                "interpreter is null"
+            );
+            monkey_string_free(ptr::null_mut());
+            monkey_interp_free(ptr::null_mut());
+            monkey_interp_free(interp);
+        }
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let interp = monkey_interp_new();
+        let source = CString::new(vec![b'"', 0xff, b'"']).unwrap();
+        unsafe {
+            assert_eq!(
+                take(monkey_eval(interp, source.as_ptr())),
+                "source is not valid UTF-8"
+            );
+            monkey_interp_free(interp);
+        }
+    }
+
+    #[test]
+    fn test_nul_in_result() {
+        let interp = monkey_interp_new();
+        unsafe {
+            (*interp)
+                .interpreter
+                .register("nul", |_| Ok(Object::String("a\0b".into())));
+        }
+        assert_eq!(eval(interp, "nul()"), "a\\0b");
+        unsafe { monkey_interp_free(interp) };
+    }
+
+    #[test]
+    fn test_panic_is_caught() {
+        let interp = monkey_interp_new();
+        unsafe {
+            (*interp)
+                .interpreter
+                .register("boom", |_| -> Result<Object, MonkeyError> {
+                    panic!("boom")
+                });
+        }
+        assert_eq!(eval(interp, "boom()"), "panic: boom");
+        // The interpreter is still usable afterwards.
+        assert_eq!(eval(interp, "1 + 1"), "2");
+        unsafe { monkey_interp_free(interp) };
+    }
+}
@@ -0,0 +1,134 @@
+//! Interns identifier names, so that the AST and environments refer to a name
+//! by a small integer that is cheap to copy, compare and hash, and only look
+//! the text up again to print it.
+//!
+//! The interner is shared by the whole process and names are never freed;
+//! there are only ever as many as there are distinct identifiers in the
+//! programs that were parsed.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+/// An interned name. Two symbols are equal exactly when their names are.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// A map keyed by symbols. Symbols are already small distinct integers, so
+/// they are hashed with a multiply rather than the default SipHash.
+pub type SymbolMap<V> = HashMap<Symbol, V, BuildHasherDefault<SymbolHasher>>;
+
+#[derive(Debug, Default)]
+pub struct SymbolHasher(u64);
+
+impl Hasher for SymbolHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(u64::from(byte));
+        }
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write_u64(u64::from(n));
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        // The multiplier spreads consecutive symbols over the high bits, which
+        // the map uses to pick a bucket group.
+        self.0 = (self.0.rotate_left(5) ^ n).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+}
+
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+fn interner() -> MutexGuard<'static, Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    // The interner is consistent between calls, so a panic elsewhere while
+    // it was locked does not matter.
+    INTERNER
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Symbol {
+    /// The symbol for `name`, interning it if it is new.
+    pub fn intern(name: &str) -> Symbol {
+        let mut interner = interner();
+        if let Some(&symbol) = interner.symbols.get(name) {
+            return symbol;
+        }
+        let index = u32::try_from(interner.names.len()).expect("too many distinct names");
+        let name: &'static str = Box::leak(name.into());
+        let symbol = Symbol(index);
+        interner.names.push(name);
+        interner.symbols.insert(name, symbol);
+        symbol
+    }
+
+    pub fn as_str(self) -> &'static str {
+        interner().names[self.0 as usize]
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interner::Symbol;
+
+    #[test]
+    fn test_intern() {
+        let a = Symbol::intern("interned_a");
+        assert_eq!(Symbol::intern("interned_a"), a);
+        assert_ne!(Symbol::intern("interned_b"), a);
+        assert_eq!(a.as_str(), "interned_a");
+        assert_eq!(a.to_string(), "interned_a");
+        assert_eq!(format!("{:?}", a), r#""interned_a""#);
+        assert_eq!(a, "interned_a");
+    }
+}
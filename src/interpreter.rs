@@ -0,0 +1,442 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::ast::{Program, Source};
+use crate::environment::Environment;
+use crate::errors::MonkeyError;
+use crate::evaluator::{Evaluator, FsAccess, Hook, Output, DEFAULT_MAX_DEPTH};
+use crate::lexer::Lexer;
+use crate::lint::non_tail_recursion;
+use crate::macros::{define_macros, expand_macros};
+use crate::object::{Host, Object};
+use crate::optimizer::fold_constants;
+use crate::parser::Parser;
+use crate::profiler::Profiler;
+
+/// Bundles an environment with evaluator settings so that state persists
+/// across calls to `eval`, the way the REPL uses it.
+pub struct Interpreter {
+    env: Environment,
+    output: Option<Output>,
+    warnings: Option<Output>,
+    hook: Option<Hook>,
+    strict: bool,
+    sandbox: bool,
+    fs_access: FsAccess,
+    max_depth: Option<usize>,
+    max_iterations: Option<usize>,
+    hosts: Rc<HashMap<String, Object>>,
+    file: Option<PathBuf>,
+    optimize: bool,
+    profiler: Rc<RefCell<Profiler>>,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self {
+            env: Environment::with_builtins(),
+            output: None,
+            warnings: None,
+            hook: None,
+            strict: false,
+            sandbox: false,
+            fs_access: FsAccess::None,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_iterations: None,
+            hosts: Rc::new(HashMap::new()),
+            file: None,
+            optimize: false,
+            profiler: Rc::new(RefCell::new(Profiler::new())),
+        }
+    }
+
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
+
+    pub fn env_mut(&mut self) -> &mut Environment {
+        &mut self.env
+    }
+
+    pub fn set_hook(&mut self, hook: Option<Hook>) {
+        self.hook = hook;
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn set_sandbox(&mut self, sandbox: bool) {
+        self.sandbox = sandbox;
+    }
+
+    pub fn set_fs_access(&mut self, fs_access: FsAccess) {
+        self.fs_access = fs_access;
+    }
+
+    /// Limits how deeply function calls may nest. Defaults to
+    /// `DEFAULT_MAX_DEPTH`; `None` removes the limit.
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn set_max_iterations(&mut self, max_iterations: Option<usize>) {
+        self.max_iterations = max_iterations;
+    }
+
+    pub fn set_output(&mut self, output: Output) {
+        self.output = Some(output);
+    }
+
+    pub fn set_warnings(&mut self, warnings: Output) {
+        self.warnings = Some(warnings);
+    }
+
+    /// Runs `fold_constants` over each program before evaluating it.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// `import` paths are resolved relative to this file.
+    pub fn set_file(&mut self, file: Option<PathBuf>) {
+        self.file = file;
+    }
+
+    /// The profiler shared by every call to `eval`, so that profiling started
+    /// by one program covers the next ones too.
+    pub fn profiler(&self) -> Rc<RefCell<Profiler>> {
+        self.profiler.clone()
+    }
+
+    /// Exposes a Rust function to scripts under `name`. It resolves like a
+    /// builtin, so a `let` binding of the same name shadows it, and it
+    /// overrides a builtin of the same name.
+    pub fn register<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(&[Object]) -> Result<Object, MonkeyError> + 'static,
+    {
+        let host = Object::Host(Host {
+            name: name.to_string(),
+            func: Rc::new(func),
+        });
+        Rc::make_mut(&mut self.hosts).insert(name.to_string(), host);
+    }
+
+    pub fn eval(&mut self, source: &str) -> Result<Object, MonkeyError> {
+        let name = self.file.as_ref().map(|file| file.display().to_string());
+        let mut program = expand_source(source, name, &mut self.env)?;
+        if self.optimize {
+            program = fold_constants(program);
+        }
+        let mut evaluator = Evaluator::new(&mut self.env);
+        if let Some(output) = &self.output {
+            evaluator.set_output(output.clone());
+        }
+        if let Some(warnings) = &self.warnings {
+            evaluator.set_warnings(warnings.clone());
+        }
+        evaluator.set_hook(self.hook.clone());
+        evaluator.set_strict(self.strict);
+        evaluator.set_sandbox(self.sandbox);
+        evaluator.set_fs_access(self.fs_access.clone());
+        evaluator.set_max_depth(self.max_depth);
+        evaluator.set_max_iterations(self.max_iterations);
+        evaluator.set_host_functions(self.hosts.clone());
+        evaluator.set_file(self.file.clone());
+        evaluator.set_profiler(self.profiler.clone());
+        for (name, span) in non_tail_recursion(&program) {
+            let (line, col) = span.line_col(source);
+            evaluator.warn(&format!(
+                "line {}, col {}: this recursive call to `{}` is not in tail position and may cause stack overflow",
+                line, col, name
+            ));
+        }
+        evaluate_program(&mut evaluator, program)
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Evaluates `source`, called `name` in errors raised in it while evaluating
+/// another program; see `Source`.
+pub(crate) fn evaluate_source(
+    evaluator: &mut Evaluator,
+    source: &str,
+    name: Option<String>,
+) -> Result<Object, MonkeyError> {
+    let program = expand_source(source, name, evaluator.env)?;
+    evaluate_program(evaluator, program)
+}
+
+// Parses `source`, binding the macros it defines in `env` and expanding the
+// calls of macros in it.
+fn expand_source(
+    source: &str,
+    name: Option<String>,
+    env: &mut Environment,
+) -> Result<Program, MonkeyError> {
+    let mut program = parse_named_source(source, name)?;
+    define_macros(&mut program, env);
+    expand_macros(program, env)
+}
+
+pub(crate) fn parse_source(source: &str) -> Result<Program, MonkeyError> {
+    parse_named_source(source, None)
+}
+
+// Parses `source`, keeping it in the arena so that errors raised in the
+// program can be located.
+fn parse_named_source(source: &str, name: Option<String>) -> Result<Program, MonkeyError> {
+    let lexer = Lexer::new(source);
+    let mut parser = Parser::new(lexer);
+    let mut program = parser.parse_program().map_err(|err| {
+        err.downcast::<MonkeyError>()
+            .expect("parser only fails with MonkeyError")
+    })?;
+    program.arena_mut().set_source(Source {
+        text: source.to_string(),
+        name,
+    });
+    Ok(program)
+}
+
+// Evaluates `program`, reporting an error with where it was raised.
+fn evaluate_program(evaluator: &mut Evaluator, program: Program) -> Result<Object, MonkeyError> {
+    let source = program.arena.source().cloned();
+    match evaluator.evaluate(program) {
+        Object::Error(err) => Err(MonkeyError::Runtime(err.report(source.as_ref()))),
+        obj => Ok(obj),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    use crate::{errors::MonkeyError, interpreter::Interpreter, object::Object};
+
+    #[test]
+    fn test_state_persists() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("let x = 2;").unwrap();
+        assert_eq!(interpreter.eval("x * 21").unwrap(), Object::Int(42));
+        assert!(interpreter.env().contains("x"));
+
+        interpreter.env_mut().remove("x");
+        assert!(interpreter.eval("x").is_err());
+    }
+
+    #[test]
+    fn test_output() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(output.clone());
+        interpreter.eval(r#"puts("hello", 1 + 2)"#).unwrap();
+        interpreter
+            .eval("let f = fn() { puts([true]) }; f();")
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.borrow().clone()).unwrap(),
+            "hello\n3\n[true]\n"
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("let x = 1; let x = 2;").unwrap();
+
+        interpreter.set_strict(true);
+        match interpreter.eval("let x = 3;") {
+            Err(MonkeyError::Runtime(message)) => {
+                assert_eq!(message, "identifier already declared: x")
+            }
+            result => panic!("expected a runtime error. got={:?}", result),
+        }
+        assert_eq!(
+            interpreter
+                .eval("let f = fn(x) { let y = x; y }; f(1)")
+                .unwrap(),
+            Object::Int(1)
+        );
+    }
+
+    #[test]
+    fn test_register() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register("add_all", |args| {
+            let mut sum = 0;
+            for arg in args {
+                match arg {
+                    Object::Int(value) => sum += value,
+                    obj => {
+                        return Err(MonkeyError::Runtime(format!(
+                            "argument to `add_all` must be INTEGER, got {}",
+                            obj.type_info()
+                        )))
+                    }
+                }
+            }
+            Ok(Object::Int(sum))
+        });
+        interpreter.register("fail", |_| {
+            Err(MonkeyError::Runtime("host failure".to_string()))
+        });
+
+        assert_eq!(
+            interpreter.eval("add_all(1, 2, 3)").unwrap(),
+            Object::Int(6)
+        );
+        assert_eq!(
+            interpreter
+                .eval("let f = fn(x) { add_all(x, x) }; f(4)")
+                .unwrap(),
+            Object::Int(8)
+        );
+        match interpreter.eval("add_all(1, true)") {
+            Err(MonkeyError::Runtime(message)) => {
+                assert_eq!(
+                    message,
+                    "argument to `add_all` must be INTEGER, got BOOLEAN"
+                )
+            }
+            result => panic!("expected a runtime error. got={:?}", result),
+        }
+        match interpreter.eval("fail()") {
+            Err(MonkeyError::Runtime(message)) => assert_eq!(message, "host failure"),
+            result => panic!("expected a runtime error. got={:?}", result),
+        }
+
+        interpreter.eval("let add_all = fn(a, b) { 0 };").unwrap();
+        assert_eq!(interpreter.eval("add_all(1, 2)").unwrap(), Object::Int(0));
+    }
+
+    #[test]
+    fn test_register_suggested() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register("fetch_user", |_| Ok(Object::Null));
+        match interpreter.eval("fetch_usr(1)") {
+            Err(MonkeyError::Runtime(message)) => assert_eq!(
+                message,
+                "line 1, col 1: identifier not found: fetch_usr (did you mean 'fetch_user'?)"
+            ),
+            result => panic!("expected a runtime error. got={:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_register_overrides_builtin() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register("len", |_| Ok(Object::Int(-1)));
+        assert_eq!(interpreter.eval("len([1, 2])").unwrap(), Object::Int(-1));
+
+        interpreter.eval("let len = fn(x) { 0 };").unwrap();
+        assert_eq!(interpreter.eval("len([1, 2])").unwrap(), Object::Int(0));
+    }
+
+    #[test]
+    fn test_register_captures_state() {
+        let calls = Rc::new(Cell::new(0));
+        let mut interpreter = Interpreter::new();
+        let counter = calls.clone();
+        interpreter.register("count", move |_| {
+            counter.set(counter.get() + 1);
+            Ok(Object::Int(counter.get()))
+        });
+        interpreter.eval("count(); count();").unwrap();
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_while_value() {
+        let mut interpreter = Interpreter::new();
+        let ticks = Rc::new(Cell::new(0));
+        let counter = ticks.clone();
+        interpreter.register("tick", move |_| {
+            counter.set(counter.get() + 1);
+            Ok(Object::Int(counter.get()))
+        });
+        // the value is that of the last iteration whose body was not null
+        let source = "let last = while (tick() < 6) {
+  let i = tick();
+  if (i < 4) { i }
+};
+last == 2";
+        assert_eq!(interpreter.eval(source).unwrap(), Object::Boolean(true));
+        assert_eq!(ticks.get(), 7);
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_depth(Some(10));
+        interpreter
+            .eval("let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };")
+            .unwrap();
+        assert_eq!(interpreter.eval("f(5)").unwrap(), Object::Int(5));
+        match interpreter.eval("f(20)") {
+            Err(MonkeyError::Runtime(message)) => {
+                assert_eq!(message, "maximum recursion depth exceeded")
+            }
+            result => panic!("expected a runtime error. got={:?}", result),
+        }
+
+        // `eval` counts as a call, so recursing through it is limited too.
+        match interpreter.eval(r#"let g = fn() { eval("g()") }; g()"#) {
+            Err(MonkeyError::Runtime(message)) => {
+                assert_eq!(message, "maximum recursion depth exceeded")
+            }
+            result => panic!("expected a runtime error. got={:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_try_binds_message() {
+        // the error is located when reported, not in the message caught
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            interpreter
+                .eval(r#"try { 1 / 0 } catch (e) { e == "division by zero" }"#)
+                .unwrap(),
+            Object::Boolean(true)
+        );
+        match interpreter.eval("let x = 1;\nx / 0") {
+            Err(MonkeyError::Runtime(message)) => {
+                assert_eq!(message, "line 2, col 3: division by zero")
+            }
+            result => panic!("expected a runtime error. got={:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_default_max_depth() {
+        // without a limit set, `eval` calling itself stops at the default one
+        // instead of overflowing the stack
+        let mut interpreter = Interpreter::new();
+        match interpreter.eval(r#"let s = "eval(s)"; eval(s)"#) {
+            Err(MonkeyError::Runtime(message)) => {
+                assert_eq!(message, "maximum recursion depth exceeded")
+            }
+            result => panic!("expected a runtime error. got={:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_optimize() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_optimize(true);
+        interpreter.eval("let x = 2 * 3;").unwrap();
+        assert_eq!(
+            interpreter.eval("if (1 < 2) { x + 4 * 1 }").unwrap(),
+            Object::Int(10)
+        );
+        assert!(interpreter.eval("x / (1 - 1)").is_err());
+    }
+}
@@ -0,0 +1,281 @@
+//! Conversions between `Object` and `serde_json::Value`.
+//!
+//! Only data survives the trip: numbers, booleans, null, strings, arrays and
+//! hashes with string keys. Functions, builtins, pairs, errors and non-finite
+//! floats have no counterpart and fail with `MonkeyError::Conversion` rather
+//! than being silently replaced by a placeholder. `to_value_lenient` also
+//! accepts integer and boolean hash keys, which it stringifies, but fails if
+//! two keys of a hash stringify alike.
+//!
+//! Every JSON value has a counterpart, so the conversion back is a `From`.
+//! With the `bigint` feature, integers beyond `INTEGER` up to `u64::MAX`
+//! become big integers, and convert back unchanged. Other numbers that do not
+//! fit an `INTEGER` become a `FLOAT`, losing precision.
+
+use std::convert::TryFrom;
+
+use serde_json::{Map, Number, Value};
+
+use crate::errors::MonkeyError;
+use crate::object::{HashPairs, Object};
+
+impl TryFrom<&Object> for Value {
+    type Error = MonkeyError;
+
+    fn try_from(obj: &Object) -> Result<Self, Self::Error> {
+        convert(obj, None)
+    }
+}
+
+/// Like `Value::try_from`, but integer and boolean hash keys are converted to
+/// strings. The keys that were converted are returned alongside the value.
+pub fn to_value_lenient(obj: &Object) -> Result<(Value, Vec<Object>), MonkeyError> {
+    let mut stringified = vec![];
+    let value = convert(obj, Some(&mut stringified))?;
+    Ok((value, stringified))
+}
+
+// Non-string hash keys are stringified and collected into `stringified` if it
+// is given, and rejected otherwise.
+fn convert(obj: &Object, mut stringified: Option<&mut Vec<Object>>) -> Result<Value, MonkeyError> {
+    match obj {
+        Object::Int(value) => Ok(Value::Number(Number::from(*value))),
+        #[cfg(feature = "bigint")]
+        Object::BigInt(value) => u64::try_from(value)
+            .map(|value| Value::Number(Number::from(value)))
+            .map_err(|_| {
+                MonkeyError::Conversion(format!("cannot convert {} to JSON: out of range", value))
+            }),
+        Object::Float(value) => Number::from_f64(*value)
+            .map(Value::Number)
+            .ok_or_else(|| MonkeyError::Conversion(format!("cannot convert {} to JSON", value))),
+        Object::Boolean(value) => Ok(Value::Bool(*value)),
+        Object::Null => Ok(Value::Null),
+        Object::String(value) => Ok(Value::String(value.to_string())),
+        Object::Array(elements) => elements
+            .iter()
+            .map(|element| convert(element, stringified.as_deref_mut()))
+            .collect::<Result<_, _>>()
+            .map(Value::Array),
+        Object::Hash(pairs) => {
+            let mut map = Map::new();
+            for (key, value) in pairs {
+                let name = match (key, stringified.as_deref_mut()) {
+                    (Object::String(name), _) => name.to_string(),
+                    (Object::Int(_) | Object::Boolean(_), Some(stringified)) => {
+                        stringified.push(key.clone());
+                        key.to_string()
+                    }
+                    (key, _) => {
+                        return Err(MonkeyError::Conversion(format!(
+                            "cannot convert hash key of type {} to JSON",
+                            key.type_info()
+                        )))
+                    }
+                };
+                if map.contains_key(&name) {
+                    return Err(MonkeyError::Conversion(format!(
+                        "cannot convert hash to JSON: more than one key is \"{}\"",
+                        name
+                    )));
+                }
+                map.insert(name, convert(value, stringified.as_deref_mut())?);
+            }
+            Ok(Value::Object(map))
+        }
+        obj if obj.is_callable()
+            || matches!(obj, Object::CompiledFunction(_) | Object::Closure(_)) =>
+        {
+            Err(MonkeyError::Conversion(
+                "cannot convert function to JSON value".to_string(),
+            ))
+        }
+        obj => Err(MonkeyError::Conversion(format!(
+            "cannot convert {} to JSON",
+            obj.type_info()
+        ))),
+    }
+}
+
+impl TryFrom<Object> for Value {
+    type Error = MonkeyError;
+
+    fn try_from(obj: Object) -> Result<Self, Self::Error> {
+        Value::try_from(&obj)
+    }
+}
+
+impl From<Value> for Object {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Object::Null,
+            Value::Bool(value) => Object::Boolean(value),
+            Value::Number(number) => number_to_object(&number),
+            Value::String(value) => Object::String(value.into()),
+            Value::Array(elements) => {
+                Object::Array(elements.into_iter().map(Object::from).collect())
+            }
+            Value::Object(map) => {
+                let mut pairs = HashPairs::default();
+                for (key, value) in map {
+                    pairs.insert(Object::String(key.into()), Object::from(value));
+                }
+                Object::Hash(pairs)
+            }
+        }
+    }
+}
+
+fn number_to_object(number: &Number) -> Object {
+    if let Some(value) = number.as_i64() {
+        return Object::Int(value);
+    }
+    #[cfg(feature = "bigint")]
+    if let Some(value) = number.as_u64() {
+        return Object::BigInt(value.into());
+    }
+    // `as_f64` only fails on numbers beyond the range of a float, which parse
+    // to an infinity like a float literal would.
+    Object::Float(
+        number
+            .as_f64()
+            .unwrap_or_else(|| number.to_string().parse().unwrap_or(f64::NAN)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use serde_json::{json, Value};
+
+    use crate::{errors::MonkeyError, object::Object};
+
+    #[test]
+    fn test_object_to_value() {
+        let obj = Object::Hash(
+            vec![
+                (
+                    Object::String("list".into()),
+                    Object::Array(vec![
+                        Object::Int(1),
+                        Object::Boolean(true),
+                        Object::Null,
+                        Object::Hash(
+                            vec![(
+                                Object::String("nested".into()),
+                                Object::String("yes".into()),
+                            )]
+                            .into(),
+                        ),
+                    ]),
+                ),
+                (Object::String("n".into()), Object::Int(-3)),
+                (Object::String("x".into()), Object::Float(1.5)),
+            ]
+            .into(),
+        );
+        let value = Value::try_from(&obj).unwrap();
+        assert_eq!(
+            value,
+            json!({"list": [1, true, null, {"nested": "yes"}], "n": -3, "x": 1.5})
+        );
+        assert_eq!(Object::from(value), obj);
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        let value = json!({
+            "users": [
+                {"name": "ann", "age": 31, "tags": ["admin"], "manager": null},
+                {"name": "bob", "age": 27, "tags": [], "score": -0.5},
+            ],
+            "total": 2,
+            "big": 18446744073709551615u64,
+        });
+        let obj = Object::from(value.clone());
+        match &obj {
+            Object::Hash(pairs) => assert_eq!(pairs.len(), 3),
+            obj => panic!("object is not Hash. got={:?}", obj),
+        }
+        // too large for an INTEGER, so it comes back as a float unless it is
+        // kept as a big integer
+        #[cfg(not(feature = "bigint"))]
+        let big = json!(18446744073709551615.0);
+        #[cfg(feature = "bigint")]
+        let big = json!(18446744073709551615u64);
+        assert_eq!(
+            Value::try_from(&obj).unwrap(),
+            json!({
+                "users": value["users"],
+                "total": 2,
+                "big": big,
+            })
+        );
+
+        let obj = Object::from(value["users"].clone());
+        assert_eq!(Value::try_from(obj).unwrap(), value["users"]);
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_bigint_round_trip() {
+        use num_bigint::BigInt;
+
+        let value = json!([9223372036854775808u64, 18446744073709551615u64]);
+        let obj = Object::from(value.clone());
+        assert_eq!(
+            obj,
+            Object::Array(vec![
+                Object::BigInt(BigInt::from(9223372036854775808u64)),
+                Object::BigInt(BigInt::from(u64::MAX)),
+            ])
+        );
+        assert_eq!(Value::try_from(&obj).unwrap(), value);
+
+        // beyond what a JSON number holds exactly
+        let big = Object::BigInt(BigInt::from(u64::MAX) + 1);
+        match Value::try_from(big) {
+            Err(MonkeyError::Conversion(message)) => assert_eq!(
+                message,
+                "cannot convert 18446744073709551616 to JSON: out of range"
+            ),
+            result => panic!("expected a conversion error. got={:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let tests = vec![
+            (
+                Object::error("boom".to_string()),
+                "cannot convert ERROR to JSON",
+            ),
+            (
+                Object::Array(vec![Object::Pair(
+                    Box::new(Object::Int(1)),
+                    Box::new(Object::Int(2)),
+                )]),
+                "cannot convert PAIR to JSON",
+            ),
+            (
+                Object::Hash(vec![(Object::Int(1), Object::Int(2))].into()),
+                "cannot convert hash key of type INTEGER to JSON",
+            ),
+        ];
+
+        for (obj, expect) in tests {
+            match Value::try_from(obj) {
+                Err(MonkeyError::Conversion(message)) => assert_eq!(message, expect),
+                result => panic!("expected a conversion error. got={:?}", result),
+            }
+        }
+
+        match Value::try_from(Object::Float(f64::NAN)) {
+            Err(MonkeyError::Conversion(message)) => {
+                assert_eq!(message, "cannot convert NaN to JSON")
+            }
+            result => panic!("expected a conversion error. got={:?}", result),
+        }
+    }
+}
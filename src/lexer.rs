@@ -1,29 +1,54 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::BufRead;
+
+use crate::interner::Symbol;
 use crate::token::{Token, TokenKind};
 
-#[derive(Debug, Clone)]
 pub struct Lexer<'a> {
-    input: &'a str,
+    input: Cow<'a, str>,
+    // When lexing from a reader, `input` only holds the unconsumed part of the
+    // current line(s) and `offset` is the number of bytes dropped before it.
+    reader: Option<Box<dyn BufRead + 'a>>,
+    offset: usize,
+    start: usize,
     position: usize,
     read_position: usize,
     ch: u8,
+    // The interned name of the last token, if it is an identifier.
+    symbol: Option<Symbol>,
 }
 
 macro_rules! token {
     ($kind:expr, $literal:expr) => {
         Token {
             kind: $kind,
-            literal: String::from($literal),
+            literal: std::string::String::from($literal),
         }
     };
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
+        Self::with_input(Cow::Borrowed(input), None)
+    }
+
+    /// Lexes `reader` line by line instead of holding the whole input in
+    /// memory. Read errors and invalid UTF-8 end the input like EOF does.
+    pub fn from_reader<R: BufRead + 'a>(reader: R) -> Self {
+        Self::with_input(Cow::Owned(String::new()), Some(Box::new(reader)))
+    }
+
+    fn with_input(input: Cow<'a, str>, reader: Option<Box<dyn BufRead + 'a>>) -> Self {
         let mut lexer = Lexer {
             input,
+            reader,
+            offset: 0,
+            start: 0,
             position: 0,
             read_position: 0,
             ch: 0,
+            symbol: None,
         };
 
         lexer.read_char();
@@ -31,7 +56,44 @@ impl<'a> Lexer<'a> {
         lexer
     }
 
+    /// Byte offset in the whole input of the token last returned by
+    /// `next_token`.
+    pub fn token_position(&self) -> usize {
+        self.start
+    }
+
+    /// The interned name of the token last returned by `next_token`, if it is
+    /// an identifier.
+    pub fn token_symbol(&self) -> Option<Symbol> {
+        self.symbol
+    }
+
+    // Appends the next line from the reader, if any, to `input`.
+    fn fill(&mut self) {
+        if let Some(reader) = &mut self.reader {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(n) if n > 0 => self.input.to_mut().push_str(&line),
+                _ => self.reader = None,
+            }
+        }
+    }
+
+    // Drops the input before the current character, which no token needs any
+    // more.
+    fn discard_consumed(&mut self) {
+        if self.reader.is_some() && self.position > 0 {
+            self.input.to_mut().drain(..self.position);
+            self.offset += self.position;
+            self.read_position -= self.position;
+            self.position = 0;
+        }
+    }
+
     fn read_char(&mut self) {
+        if self.read_position >= self.input.len() {
+            self.fill();
+        }
         if self.read_position < self.input.len() {
             self.ch = self.input.as_bytes()[self.read_position];
         } else {
@@ -43,6 +105,9 @@ impl<'a> Lexer<'a> {
 
     pub fn next_token(&mut self) -> Token {
         self.skip_whitespace();
+        self.discard_consumed();
+        self.start = self.offset + self.position;
+        self.symbol = None;
 
         let tok = match self.ch {
             b'=' => {
@@ -57,6 +122,7 @@ impl<'a> Lexer<'a> {
             b'-' => token!(TokenKind::Minus, "-"),
             b'/' => token!(TokenKind::Slash, "/"),
             b'*' => token!(TokenKind::Aster, "*"),
+            b'%' => token!(TokenKind::Percent, "%"),
             b'!' => {
                 if self.peek_char() == b'=' {
                     self.read_char();
@@ -69,22 +135,41 @@ impl<'a> Lexer<'a> {
             b'(' => token!(TokenKind::Lparen, "("),
             b')' => token!(TokenKind::Rparen, ")"),
             b',' => token!(TokenKind::Comma, ","),
+            b':' => token!(TokenKind::Colon, ":"),
+            b'.' => {
+                if self.peek_char() == b'.' {
+                    self.read_char();
+                    if self.peek_char() == b'.' {
+                        self.read_char();
+                        token!(TokenKind::Ellipsis, "...")
+                    } else {
+                        token!(TokenKind::DotDot, "..")
+                    }
+                } else {
+                    token!(TokenKind::Illegal, ".")
+                }
+            }
+            b'"' => Token {
+                kind: TokenKind::String,
+                literal: self.read_string(),
+            },
             b'{' => token!(TokenKind::Lbrace, "{"),
             b'}' => token!(TokenKind::Rbrace, "}"),
+            b'[' => token!(TokenKind::Lbracket, "["),
+            b']' => token!(TokenKind::Rbracket, "]"),
             b'>' => token!(TokenKind::GreaterThan, ">"),
             b'<' => token!(TokenKind::LessThan, "<"),
-            b'a'..=b'z' | b'A'..=b'Z' => {
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
                 let literal = self.read_identifier();
                 let kind = crate::token::look_up_ident(&literal);
-                return Token { kind, literal };
-            }
-            b'0'..=b'9' => {
-                return Token {
-                    kind: TokenKind::Int,
-                    literal: self.read_number(),
+                if kind == TokenKind::Ident {
+                    self.symbol = Some(Symbol::intern(&literal));
                 }
+                return Token { kind, literal };
             }
-            _ => token!(TokenKind::Eof, ""),
+            b'0'..=b'9' => return self.read_number(),
+            0 => token!(TokenKind::Eof, ""),
+            ch => token!(TokenKind::Illegal, (ch as char).to_string()),
         };
 
         self.read_char();
@@ -93,27 +178,56 @@ impl<'a> Lexer<'a> {
 
     fn read_identifier(&mut self) -> String {
         let position = self.position;
-        while let b'a'..=b'z' | b'A'..=b'Z' = self.ch {
+        while let b'a'..=b'z' | b'A'..=b'Z' | b'_' = self.ch {
+            self.read_char();
+        }
+        self.input.get(position..self.position).unwrap().to_string()
+    }
+
+    fn read_string(&mut self) -> String {
+        let position = self.position + 1;
+        loop {
             self.read_char();
+            if self.ch == b'"' || self.ch == 0 {
+                break;
+            }
         }
         self.input.get(position..self.position).unwrap().to_string()
     }
 
-    fn read_number(&mut self) -> String {
+    // A number with a `.` followed by a digit is a float, so `1.` stays an
+    // integer followed by a dot.
+    fn read_number(&mut self) -> Token {
         let position = self.position;
+        let mut kind = TokenKind::Int;
         while let b'0'..=b'9' = self.ch {
             self.read_char();
+            if self.ch == b'.' && kind == TokenKind::Int && self.peek_char().is_ascii_digit() {
+                kind = TokenKind::Float;
+                self.read_char();
+            }
         }
-        self.input.get(position..self.position).unwrap().to_string()
+        let literal = self.input.get(position..self.position).unwrap().to_string();
+        Token { kind, literal }
     }
 
+    // A `\` right before a line break continues the line, so it is skipped
+    // along with the break.
     fn skip_whitespace(&mut self) {
-        while let b' ' | b'\t' | b'\n' | b'\r' = self.ch {
-            self.read_char();
+        loop {
+            let continues = self.ch == b'\\' && matches!(self.peek_char(), b'\n' | b'\r');
+            match self.ch {
+                b' ' | b'\t' | b'\n' | b'\r' => self.read_char(),
+                _ if continues => self.read_char(),
+                _ => break,
+            }
         }
     }
 
-    fn peek_char(&self) -> u8 {
+    fn peek_char(&mut self) -> u8 {
+        if self.read_position >= self.input.len() {
+            self.fill();
+        }
         if self.read_position < self.input.len() {
             self.input.as_bytes()[self.read_position]
         } else {
@@ -122,8 +236,22 @@ impl<'a> Lexer<'a> {
     }
 }
 
+impl fmt::Debug for Lexer<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lexer")
+            .field("input", &self.input)
+            .field("offset", &self.offset)
+            .field("position", &self.position)
+            .field("ch", &self.ch)
+            .finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::io::Cursor;
+
+    use crate::interner::Symbol;
     use crate::lexer::Lexer;
     use crate::token::{Token, TokenKind::*};
 
@@ -147,7 +275,14 @@ mod tests {
         }
 
         10 == 10;
-        10 != 9;"#;
+        10 != 9;
+        [1, 2];
+        flatten_deep;
+        "foo bar";
+        3.25 + 10;
+        {"foo": 10 % 3};
+        a[1..-2];
+        f(...a);"#;
 
         let tests: Vec<Token> = vec![
             token!(Let, "let"),
@@ -223,6 +358,42 @@ mod tests {
             token!(NotEqual, "!="),
             token!(Int, "9"),
             token!(Semicolon, ";"),
+            token!(Lbracket, "["),
+            token!(Int, "1"),
+            token!(Comma, ","),
+            token!(Int, "2"),
+            token!(Rbracket, "]"),
+            token!(Semicolon, ";"),
+            token!(Ident, "flatten_deep"),
+            token!(Semicolon, ";"),
+            token!(String, "foo bar"),
+            token!(Semicolon, ";"),
+            token!(Float, "3.25"),
+            token!(Plus, "+"),
+            token!(Int, "10"),
+            token!(Semicolon, ";"),
+            token!(Lbrace, "{"),
+            token!(String, "foo"),
+            token!(Colon, ":"),
+            token!(Int, "10"),
+            token!(Percent, "%"),
+            token!(Int, "3"),
+            token!(Rbrace, "}"),
+            token!(Semicolon, ";"),
+            token!(Ident, "a"),
+            token!(Lbracket, "["),
+            token!(Int, "1"),
+            token!(DotDot, ".."),
+            token!(Minus, "-"),
+            token!(Int, "2"),
+            token!(Rbracket, "]"),
+            token!(Semicolon, ";"),
+            token!(Ident, "f"),
+            token!(Lparen, "("),
+            token!(Ellipsis, "..."),
+            token!(Ident, "a"),
+            token!(Rparen, ")"),
+            token!(Semicolon, ";"),
         ];
 
         let mut lexer = Lexer::new(input);
@@ -230,4 +401,53 @@ mod tests {
             assert_eq!(lexer.next_token(), t);
         }
     }
+
+    #[test]
+    fn test_from_reader() {
+        let input =
+            "let add = fn(x, y) {\n  x + y;\n};\r\n\nadd(1,\n 22) == 23;\n\"a\nb\" !=\n\"c\"";
+        let mut expected = Lexer::new(input);
+        let mut lexer = Lexer::from_reader(Cursor::new(input));
+        loop {
+            let token = expected.next_token();
+            assert_eq!(lexer.next_token(), token);
+            assert_eq!(lexer.token_position(), expected.token_position());
+            if token.kind == Eof {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_continuation() {
+        let input = "let total = 1 +\\\n    2;\\\r\nx \\ y";
+        let tests = vec![
+            (token!(Let, "let"), 0),
+            (token!(Ident, "total"), 4),
+            (token!(Assign, "="), 10),
+            (token!(Int, "1"), 12),
+            (token!(Plus, "+"), 14),
+            (token!(Int, "2"), 21),
+            (token!(Semicolon, ";"), 22),
+            (token!(Ident, "x"), 26),
+            (token!(Illegal, "\\"), 28),
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for (token, position) in tests {
+            assert_eq!(lexer.next_token(), token);
+            assert_eq!(lexer.token_position(), position);
+        }
+    }
+
+    #[test]
+    fn test_token_symbol() {
+        let mut lexer = Lexer::new(r#"let x = "x"; x"#);
+        let mut symbols = vec![];
+        while lexer.next_token().kind != Eof {
+            symbols.push(lexer.token_symbol());
+        }
+        let x = Some(Symbol::intern("x"));
+        assert_eq!(symbols, vec![None, x, None, None, None, x]);
+    }
 }
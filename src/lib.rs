@@ -0,0 +1,77 @@
+//! A tree-walking interpreter for the Monkey programming language.
+//!
+//! ```
+//! use monkey_rust::{eval, Environment, Object};
+//!
+//! let mut env = Environment::with_builtins();
+//! eval("let add = fn(x, y) { x + y };", &mut env).unwrap();
+//! assert_eq!(eval("add(1, 2)", &mut env).unwrap(), Object::Int(3));
+//! ```
+
+pub mod ast;
+#[cfg(feature = "bigint")]
+pub mod bigint;
+pub mod builtins;
+pub mod code;
+pub mod compiler;
+#[cfg(test)]
+mod conformance;
+pub mod coverage;
+pub mod environment;
+pub mod errors;
+pub mod evaluator;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod interner;
+pub mod interpreter;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod lexer;
+pub mod line_profile;
+pub mod lint;
+pub mod macros;
+pub mod object;
+pub mod optimizer;
+pub mod parser;
+pub mod profiler;
+pub mod repl;
+pub mod runner;
+pub mod symbol_table;
+pub mod token;
+pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use crate::environment::Environment;
+pub use crate::errors::MonkeyError;
+pub use crate::evaluator::{Evaluator, FsAccess, DEFAULT_MAX_DEPTH};
+pub use crate::interpreter::Interpreter;
+pub use crate::lexer::Lexer;
+pub use crate::object::Object;
+pub use crate::parser::Parser;
+
+/// Evaluates `source` in `env`, keeping any bindings it creates.
+///
+/// Parse errors and runtime error objects are both returned as `Err`.
+///
+/// ```
+/// use monkey_rust::{eval, Environment, MonkeyError};
+///
+/// let mut env = Environment::with_builtins();
+/// let err = eval("-true", &mut env).unwrap_err();
+/// assert_eq!(err.to_string(), "unknown operator: -BOOLEAN");
+/// ```
+pub fn eval(source: &str, env: &mut Environment) -> Result<Object, MonkeyError> {
+    interpreter::evaluate_source(&mut Evaluator::new(env), source, None)
+}
+
+/// Evaluates `source` in a fresh environment.
+///
+/// ```
+/// use monkey_rust::{eval_str, Object};
+///
+/// assert_eq!(eval_str("2 * (3 + 4)").unwrap(), Object::Int(14));
+/// ```
+pub fn eval_str(source: &str) -> Result<Object, MonkeyError> {
+    eval(source, &mut Environment::with_builtins())
+}
@@ -0,0 +1,266 @@
+//! Counts how many statements and expressions are evaluated on each line of a
+//! program, through an evaluation hook. Scripts run with the `--profile` flag
+//! print the counts when they end.
+//!
+//! Like coverage, only the nodes of one program are counted: the first one
+//! evaluated after the hook is set. An expression is counted on the line it
+//! starts on when it knows where that is (identifiers, infix operations and
+//! imports), and otherwise on the line of the expression or statement it is
+//! part of.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{Arena, ExprId, Expression, Node, Statement, StmtId};
+use crate::evaluator::Hook;
+
+/// How many nodes were evaluated on one line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineCount {
+    pub statements: usize,
+    pub expressions: usize,
+}
+
+#[derive(Debug)]
+pub struct LineProfile {
+    source: String,
+    // The arena of the program profiled, only compared by address, while the
+    // program is being evaluated and so cannot be freed.
+    arena: Option<*const Arena>,
+    statement_lines: HashMap<StmtId, usize>,
+    expression_lines: HashMap<ExprId, usize>,
+    counts: BTreeMap<usize, LineCount>,
+}
+
+impl LineProfile {
+    /// A profile of the program parsed from `source`.
+    pub fn new(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            arena: None,
+            statement_lines: HashMap::new(),
+            expression_lines: HashMap::new(),
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// A hook counting each node `profile` is evaluated on.
+    pub fn hook(profile: &Rc<RefCell<LineProfile>>) -> Hook {
+        let profile = profile.clone();
+        Rc::new(RefCell::new(move |node: Node<'_>, _: &_| {
+            profile.borrow_mut().count(node)
+        }))
+    }
+
+    /// Counts `node`. The first node counted decides the program profiled.
+    pub fn count(&mut self, node: Node) {
+        let arena = match node {
+            Node::Statement(arena, _) | Node::Expression(arena, _) => arena,
+        };
+        match self.arena {
+            Some(tracked) if std::ptr::eq(tracked, arena) => (),
+            Some(_) => return,
+            None => self.track(arena),
+        }
+        let line = match node {
+            Node::Statement(_, stmt) => self.statement_lines.get(&stmt),
+            Node::Expression(_, expr) => self.expression_lines.get(&expr),
+        };
+        let count = match line {
+            Some(line) => self.counts.entry(*line).or_default(),
+            None => return,
+        };
+        match node {
+            Node::Statement(..) => count.statements += 1,
+            Node::Expression(..) => count.expressions += 1,
+        }
+    }
+
+    /// The counts of each line something was evaluated on, by line number.
+    pub fn counts(&self) -> &BTreeMap<usize, LineCount> {
+        &self.counts
+    }
+
+    // Works out the line of each node of `arena`.
+    fn track(&mut self, arena: &Arena) {
+        self.arena = Some(arena);
+        for stmt in arena.statement_ids() {
+            let (line, _) = arena.stmt_span(stmt).line_col(&self.source);
+            self.statement_lines.insert(stmt, line);
+            for expr in statement_expressions(&arena[stmt]) {
+                self.locate(arena, expr, line);
+            }
+        }
+    }
+
+    fn locate(&mut self, arena: &Arena, expr: ExprId, line: usize) {
+        let line = match &arena[expr] {
+            Expression::Ident(_, span)
+            | Expression::Infix { span, .. }
+            | Expression::Import(_, span) => span.line_col(&self.source).0,
+            _ => line,
+        };
+        self.expression_lines.insert(expr, line);
+        for operand in operands(&arena[expr]) {
+            self.locate(arena, operand, line);
+        }
+    }
+}
+
+/// Each line something was evaluated on, with its counts and its source.
+impl fmt::Display for LineProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines = self.source.lines().collect::<Vec<_>>();
+        writeln!(
+            f,
+            "{:>6}  {:>10}  {:>11}  source",
+            "line", "statements", "expressions"
+        )?;
+        for (&line, count) in &self.counts {
+            let text = lines.get(line - 1).map_or("", |text| text.trim());
+            writeln!(
+                f,
+                "{:>6}  {:>10}  {:>11}  {}",
+                line, count.statements, count.expressions, text
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// The expressions directly in `stmt`, leaving out those of its blocks, which
+// are made of statements of their own.
+fn statement_expressions(stmt: &Statement) -> Vec<ExprId> {
+    match stmt {
+        Statement::Let { ident, value } | Statement::Const { ident, value } => {
+            vec![*ident, *value]
+        }
+        Statement::Return(expr) | Statement::Break(Some(expr)) | Statement::Expression(expr) => {
+            vec![*expr]
+        }
+        Statement::Break(None)
+        | Statement::Continue
+        | Statement::Import(_)
+        | Statement::Test { .. } => vec![],
+    }
+}
+
+// The sub-expressions of `expr`, leaving out those in its blocks.
+fn operands(expr: &Expression) -> Vec<ExprId> {
+    match expr {
+        Expression::Prefix { right, .. } | Expression::Spread(right) => vec![*right],
+        Expression::Infix { left, right, .. } => vec![*left, *right],
+        Expression::If { condition, .. } | Expression::While { condition, .. } => {
+            vec![*condition]
+        }
+        Expression::Call {
+            function,
+            arguments,
+        } => std::iter::once(*function)
+            .chain(arguments.iter().copied())
+            .collect(),
+        Expression::Index { left, index } => vec![*left, *index],
+        Expression::Slice { target, start, end } => {
+            std::iter::once(*target).chain(*start).chain(*end).collect()
+        }
+        Expression::Array(elements) => elements.clone(),
+        Expression::Hash(pairs) => pairs
+            .iter()
+            .flat_map(|(key, value)| vec![*key, *value])
+            .collect(),
+        Expression::Ident(..)
+        | Expression::Int(_)
+        | Expression::Float(_)
+        | Expression::String(_)
+        | Expression::Boolean(_)
+        | Expression::Import(..)
+        | Expression::Function { .. }
+        | Expression::Macro { .. }
+        | Expression::Block(_)
+        | Expression::Try { .. }
+        | Expression::Loop(_) => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::interpreter::Interpreter;
+    use crate::line_profile::{LineCount, LineProfile};
+
+    // The profile of evaluating `source`.
+    fn profile(source: &str) -> LineProfile {
+        let profile = Rc::new(RefCell::new(LineProfile::new(source)));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Rc::new(RefCell::new(Vec::new())));
+        interpreter.set_hook(Some(LineProfile::hook(&profile)));
+        interpreter.eval(source).unwrap();
+        drop(interpreter);
+        Rc::try_unwrap(profile).unwrap().into_inner()
+    }
+
+    fn count(statements: usize, expressions: usize) -> LineCount {
+        LineCount {
+            statements,
+            expressions,
+        }
+    }
+
+    #[test]
+    fn test_loop_body() {
+        let source = "let xs = [1, 2, 3, 4, 5];
+each(xs, fn(x) {
+  puts(x)
+});
+";
+        let profile = profile(source);
+        let counts = profile.counts();
+        assert_eq!(counts[&1], count(1, 6));
+        // the call of `each`, its arguments, and the function literal
+        assert_eq!(counts[&2], count(1, 4));
+        // `puts(x)`: the call, `puts` and `x`, once for each element
+        assert_eq!(counts[&3], count(5, 15));
+        assert_eq!(counts.len(), 3);
+    }
+
+    #[test]
+    fn test_lines_of_expressions() {
+        // an infix operation and the identifiers in it know their own line
+        let source = "let total = 1 +
+  two();
+";
+        let profile = Rc::new(RefCell::new(LineProfile::new(source)));
+        let mut interpreter = Interpreter::new();
+        interpreter.register("two", |_| Ok(crate::object::Object::Int(2)));
+        interpreter.set_hook(Some(LineProfile::hook(&profile)));
+        interpreter.eval(source).unwrap();
+        let counts = profile.borrow().counts().clone();
+        assert_eq!(counts[&1], count(1, 3));
+        // `two()` is part of the operation, and only `two` is counted apart
+        assert_eq!(counts[&2], count(0, 1));
+    }
+
+    #[test]
+    fn test_other_programs_not_counted() {
+        let profile = profile("eval(\"1 + 2\");\nlet f = fn() { eval(\"3\") };\nf()");
+        let counts = profile.counts();
+        assert_eq!(counts[&1], count(1, 3));
+        // the body of `f` is on the same line
+        assert_eq!(counts[&2], count(2, 4));
+        assert_eq!(counts[&3], count(1, 2));
+    }
+
+    #[test]
+    fn test_display() {
+        let profile = profile("let x = 1;\n\nputs(x);\n");
+        let expect = "  line  statements  expressions  source
+     1           1            1  let x = 1;
+     3           1            3  puts(x);
+";
+        assert_eq!(profile.to_string(), expect);
+    }
+}
@@ -0,0 +1,210 @@
+//! Checks a program for code that runs but likely does not do what was meant,
+//! reported as warnings before the program is evaluated.
+
+use crate::ast::{
+    walk_block, walk_program, Arena, BlockStatement, ExprId, Expression, Program, Span, Statement,
+    Visitor,
+};
+use crate::interner::Symbol;
+
+/// The expressions whose value a function with `body` returns as it is: the
+/// last statement of the body, if it is an expression or a `return`, and in
+/// turn the last statements of the branches of an `if` or of a block there.
+///
+/// These are the places where the evaluator lets a function call itself
+/// without nesting the call, so that the recursion does not grow the stack.
+pub fn tail_positions(arena: &Arena, body: &BlockStatement) -> Vec<ExprId> {
+    let mut positions = vec![];
+    collect_tail_positions(arena, body, &mut positions);
+    positions
+}
+
+fn collect_tail_positions(arena: &Arena, block: &BlockStatement, positions: &mut Vec<ExprId>) {
+    let expr = match block.statements.last().map(|&stmt| &arena[stmt]) {
+        Some(Statement::Expression(expr)) | Some(Statement::Return(expr)) => *expr,
+        _ => return,
+    };
+    positions.push(expr);
+    match &arena[expr] {
+        Expression::If {
+            consequence,
+            alternative,
+            ..
+        } => {
+            collect_tail_positions(arena, consequence, positions);
+            if let Some(alternative) = alternative {
+                collect_tail_positions(arena, alternative, positions);
+            }
+        }
+        Expression::Block(block) => collect_tail_positions(arena, block, positions),
+        _ => (),
+    }
+}
+
+/// Finds each call of a function from its own body that is not in tail
+/// position, where each call nests another on the stack. A function is known
+/// by the name a `let` binds it to, and the span returned is that of the name
+/// in the call.
+pub fn non_tail_recursion(program: &Program) -> Vec<(Symbol, Span)> {
+    let mut functions = Functions {
+        arena: &program.arena,
+        found: vec![],
+    };
+    walk_program(&mut functions, program);
+    functions.found
+}
+
+// Checks each function bound by a `let`.
+struct Functions<'a> {
+    arena: &'a Arena,
+    found: Vec<(Symbol, Span)>,
+}
+
+impl Visitor for Functions<'_> {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        let (ident, value) = match stmt {
+            Statement::Let { ident, value } | Statement::Const { ident, value } => (ident, value),
+            _ => return,
+        };
+        let (name, body) = match (&self.arena[*ident], &self.arena[*value]) {
+            (Expression::Ident(name, _), Expression::Function { body, .. }) => (*name, body),
+            _ => return,
+        };
+        let tail = tail_positions(self.arena, body);
+        let mut calls = SelfCalls {
+            arena: self.arena,
+            name,
+            tail: &tail,
+            found: &mut self.found,
+        };
+        walk_block(&mut calls, self.arena, body);
+    }
+}
+
+// Finds the calls of `name` that are not in tail position.
+struct SelfCalls<'a> {
+    arena: &'a Arena,
+    name: Symbol,
+    tail: &'a [ExprId],
+    found: &'a mut Vec<(Symbol, Span)>,
+}
+
+impl Visitor for SelfCalls<'_> {
+    fn visit_expression(&mut self, expr: &Expression) {
+        let function = match expr {
+            Expression::Call { function, .. } => *function,
+            _ => return,
+        };
+        let span = match &self.arena[function] {
+            Expression::Ident(name, span) if *name == self.name => *span,
+            _ => return,
+        };
+        let in_tail = self.tail.iter().any(
+            |&id| matches!(&self.arena[id], Expression::Call { function: f, .. } if *f == function),
+        );
+        if !in_tail {
+            self.found.push((self.name, span));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::ast::{Expression, Statement};
+    use crate::interpreter::{parse_source, Interpreter};
+    use crate::lint::{non_tail_recursion, tail_positions};
+
+    #[test]
+    fn test_tail_positions() {
+        let tests: Vec<(&str, Vec<&str>)> = vec![
+            ("fn(n) { n + 1 }", vec!["(n + 1)"]),
+            ("fn(n) { f(n); g(n) }", vec!["g(n)"]),
+            ("fn(n) { return f(n); }", vec!["f(n)"]),
+            ("fn(n) { if (n) { return f(n); } g(n) }", vec!["g(n)"]),
+            ("fn(n) { let x = f(n); }", vec![]),
+            ("fn(n) { }", vec![]),
+            (
+                "fn(n) { if (n) { f(n) } else { g(n); h(n) } }",
+                vec!["if (n) { f(n) } else { g(n); h(n) }", "f(n)", "h(n)"],
+            ),
+            ("fn(n) { if (n) { f(n) } }", vec!["if (n) { f(n) }", "f(n)"]),
+            ("fn(n) { { f(n) } }", vec!["{ f(n) }", "f(n)"]),
+            // an argument or an operand is evaluated before the function returns
+            ("fn(n) { f(f(n)) }", vec!["f(f(n))"]),
+            ("fn(n) { 1 + f(n) }", vec!["(1 + f(n))"]),
+            ("fn(n) { [f(n)] }", vec!["[f(n)]"]),
+            ("fn(n) { fn() { f(n) } }", vec!["fn () { f(n) }"]),
+        ];
+        for (input, expect) in tests {
+            let program = parse_source(input).unwrap();
+            let arena = &program.arena;
+            let body = match &arena[program.statements[0]] {
+                Statement::Expression(expr) => match &arena[*expr] {
+                    Expression::Function { body, .. } => body,
+                    expr => panic!("not a function: {:?}", expr),
+                },
+                stmt => panic!("not an expression: {:?}", stmt),
+            };
+            let positions = tail_positions(arena, body)
+                .into_iter()
+                .map(|expr| arena.display(expr).to_string())
+                .collect::<Vec<_>>();
+            assert_eq!(positions, expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_non_tail_recursion() {
+        let tests: Vec<(&str, Vec<&str>)> = vec![
+            (
+                "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } };",
+                vec![],
+            ),
+            (
+                "let f = fn(n) { if (n == 0) { return 0; } return f(n - 1); };",
+                vec![],
+            ),
+            (
+                "let f = fn(n) { if (n == 0) { 1 } else { n * f(n - 1) } };",
+                vec!["f"],
+            ),
+            ("let f = fn(n) { f(n - 1); 0 };", vec!["f"]),
+            ("let f = fn(n) { f(f(n)) };", vec!["f"]),
+            ("let f = fn(n) { let g = fn() { f(n) }; g() };", vec!["f"]),
+            (
+                "let f = fn(n) { let g = fn(m) { 1 + g(m) }; f(n) };",
+                vec!["g"],
+            ),
+            // calls of other functions are not checked
+            ("let f = fn(n) { 1 + g(n) };", vec![]),
+            ("let f = 1; f(1) + f(2)", vec![]),
+        ];
+        for (input, expect) in tests {
+            let program = parse_source(input).unwrap();
+            let found = non_tail_recursion(&program)
+                .into_iter()
+                .map(|(name, _)| name.to_string())
+                .collect::<Vec<_>>();
+            assert_eq!(found, expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_warning() {
+        let warnings = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_warnings(warnings.clone());
+        let source = "let fact = fn(n) {
+  if (n == 0) { 1 } else { n * fact(n - 1) }
+};
+fact(5)";
+        assert_eq!(interpreter.eval(source).unwrap().to_string(), "120");
+        assert_eq!(
+            String::from_utf8(warnings.borrow().clone()).unwrap(),
+            "warning: line 2, col 32: this recursive call to `fact` is not in tail position and may cause stack overflow\n"
+        );
+    }
+}
@@ -0,0 +1,342 @@
+//! Macros, which rewrite a program before it is evaluated.
+//!
+//! `let name = macro(parameters) { body };` at the top level of a program
+//! defines a macro. Each call `name(arguments)` is then replaced by what its
+//! body evaluates to, with the parameters bound to the arguments unevaluated,
+//! as `quote`s. The body has to evaluate to a `quote` too, whose expression
+//! takes the place of the call.
+
+use std::rc::Rc;
+
+use crate::ast::{
+    walk_program, Arena, BlockStatement, ExprId, Expression, Program, Statement, Visitor,
+};
+use crate::environment::Environment;
+use crate::errors::MonkeyError;
+use crate::evaluator::Evaluator;
+use crate::interner::Symbol;
+use crate::object::Object;
+
+// How many expansions may be nested in each other, so that a macro expanding
+// to a call of itself fails instead of overflowing the stack.
+const MAX_EXPANSION_DEPTH: usize = 100;
+
+/// Binds each macro defined by a top-level statement of `program` in `env`,
+/// and removes those statements from `program`.
+pub fn define_macros(program: &mut Program, env: &mut Environment) {
+    let arena = program.arena.clone();
+    program.statements.retain(|&stmt| {
+        let (name, value) = match &arena[stmt] {
+            Statement::Let { ident, value } => match &arena[*ident] {
+                Expression::Ident(name, _) => (*name, *value),
+                _ => return true,
+            },
+            _ => return true,
+        };
+        let (parameters, body) = match &arena[value] {
+            Expression::Macro { parameters, body } => (parameters.clone(), body.clone()),
+            _ => return true,
+        };
+        let environment = env.clone();
+        env.set(
+            name,
+            Object::Macro {
+                parameters,
+                body,
+                arena: arena.clone(),
+                environment,
+            },
+        );
+        false
+    });
+}
+
+/// Replaces each call of a macro bound in `env` with its expansion, then
+/// expands the calls of macros in the expansion in turn. A program that calls
+/// no macro is returned as it is.
+pub fn expand_macros(program: Program, env: &Environment) -> Result<Program, MonkeyError> {
+    let mut calls = MacroCalls {
+        arena: &program.arena,
+        env,
+        found: false,
+    };
+    walk_program(&mut calls, &program);
+    if !calls.found {
+        return Ok(program);
+    }
+
+    let mut arena = Arena::new();
+    let mut expander = Expander {
+        env,
+        depth: 0,
+        error: None,
+    };
+    let statements = program
+        .statements
+        .iter()
+        .map(|&stmt| {
+            arena.copy_stmt_with(
+                &program.arena,
+                &mut |arena, id| expander.expand(arena, &program.arena, id),
+                stmt,
+            )
+        })
+        .collect();
+    match expander.error {
+        Some(message) => Err(MonkeyError::Runtime(message)),
+        None => Ok(Program {
+            arena: Rc::new(arena),
+            statements,
+        }),
+    }
+}
+
+// Finds whether a program calls a macro.
+struct MacroCalls<'a> {
+    arena: &'a Arena,
+    env: &'a Environment,
+    found: bool,
+}
+
+impl Visitor for MacroCalls<'_> {
+    fn visit_expression(&mut self, expr: &Expression) {
+        if let Expression::Call { function, .. } = expr {
+            self.found = self.found || macro_called(self.arena, *function, self.env).is_some();
+        }
+    }
+}
+
+// The macro that `function`, the callee of a call, names.
+fn macro_called(arena: &Arena, function: ExprId, env: &Environment) -> Option<Object> {
+    match &arena[function] {
+        Expression::Ident(name, _) => env
+            .get(*name)
+            .filter(|obj| matches!(obj, Object::Macro { .. })),
+        _ => None,
+    }
+}
+
+struct Expander<'a> {
+    env: &'a Environment,
+    depth: usize,
+    // The first error, after which nothing more is expanded.
+    error: Option<String>,
+}
+
+impl Expander<'_> {
+    // The expansion of `id`, an expression of `from`, in `to`, if it is a
+    // call of a macro.
+    fn expand(&mut self, to: &mut Arena, from: &Rc<Arena>, id: ExprId) -> Option<ExprId> {
+        if self.error.is_some() {
+            return None;
+        }
+        let (function, arguments) = match &from[id] {
+            Expression::Call {
+                function,
+                arguments,
+            } => (*function, arguments),
+            _ => return None,
+        };
+        let (parameters, body, arena, environment) = match macro_called(from, function, self.env)? {
+            Object::Macro {
+                parameters,
+                body,
+                arena,
+                environment,
+            } => (parameters, body, arena, environment),
+            _ => return None,
+        };
+        if self.depth >= MAX_EXPANSION_DEPTH {
+            self.error = Some("maximum macro expansion depth exceeded".to_string());
+            return None;
+        }
+        if arguments.len() != parameters.len() {
+            self.error = Some(format!(
+                "wrong number of arguments. got={}, want={}",
+                arguments.len(),
+                parameters.len()
+            ));
+            return None;
+        }
+
+        let (expr, quoted) =
+            match apply_macro(&parameters, &body, &arena, &environment, from, arguments) {
+                Ok(expansion) => expansion,
+                Err(message) => {
+                    self.error = Some(message);
+                    return None;
+                }
+            };
+        self.depth += 1;
+        let expansion =
+            to.copy_expr_with(&quoted, &mut |to, id| self.expand(to, &quoted, id), expr);
+        self.depth -= 1;
+        Some(expansion)
+    }
+}
+
+// Evaluates the body of a macro with its parameters bound to the quoted
+// arguments, which are expressions of `from`, returning the quoted expression
+// it evaluates to.
+fn apply_macro(
+    parameters: &[Symbol],
+    body: &BlockStatement,
+    arena: &Rc<Arena>,
+    environment: &Environment,
+    from: &Rc<Arena>,
+    arguments: &[ExprId],
+) -> Result<(ExprId, Rc<Arena>), String> {
+    let mut env = Environment::new_enclosed(environment);
+    for (param, &arg) in parameters.iter().zip(arguments) {
+        let quote = Object::Quote {
+            expr: arg,
+            arena: from.clone(),
+        };
+        env.set(*param, quote);
+    }
+    let obj = match Evaluator::new(&mut env).evaluate_block_statement(arena, body) {
+        Object::Return(obj) => *obj,
+        obj => obj,
+    };
+    match obj {
+        Object::Quote { expr, arena } => Ok((expr, arena)),
+        Object::Error(err) => Err(err.message),
+        obj => Err(format!("macro must return QUOTE, got {}", obj.type_info())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::environment::Environment;
+    use crate::evaluator::Evaluator;
+    use crate::interpreter::{parse_source, Interpreter};
+    use crate::macros::{define_macros, expand_macros};
+    use crate::object::Object;
+
+    #[test]
+    fn test_define_macros() {
+        let mut program = parse_source(
+            "let number = 1;
+             let function = fn(x, y) { x + y };
+             let mymacro = macro(x, y) { x + y; };",
+        )
+        .unwrap();
+        let mut env = Environment::new();
+        define_macros(&mut program, &mut env);
+
+        assert_eq!(program.statements.len(), 2);
+        assert!(!env.contains("number"));
+        assert!(!env.contains("function"));
+        match env.get("mymacro") {
+            Some(obj @ Object::Macro { .. }) => {
+                assert_eq!(obj.to_string(), "macro (x, y) { (x + y) }")
+            }
+            obj => panic!("object is not Macro. got={:?}", obj),
+        }
+    }
+
+    #[test]
+    fn test_expand_macros() {
+        let tests = [
+            (
+                "let infixExpression = macro() { quote(1 + 2); };
+                 infixExpression();",
+                "(1 + 2)",
+            ),
+            (
+                "let reverse = macro(a, b) { quote(unquote(b) - unquote(a)); };
+                 reverse(2 + 2, 10 - 5);",
+                "(10 - 5) - (2 + 2)",
+            ),
+            (
+                r#"let unless = macro(condition, consequence, alternative) {
+                     quote(if (!(unquote(condition))) {
+                       unquote(consequence);
+                     } else {
+                       unquote(alternative);
+                     });
+                   };
+                   unless(10 > 5, puts("not greater"), puts("greater"));"#,
+                r#"if (!(10 > 5)) { puts("not greater") } else { puts("greater") }"#,
+            ),
+            // expansions of nested calls are expanded too
+            (
+                "let twice = macro(x) { quote(unquote(x) + unquote(x)) };
+                 let f = fn() { twice(twice(1)) };",
+                "let f = fn() { ((1 + 1) + (1 + 1)) };",
+            ),
+        ];
+        for (input, expect) in tests.iter() {
+            let mut program = parse_source(input).unwrap();
+            let mut env = Environment::new();
+            define_macros(&mut program, &mut env);
+            let expanded = expand_macros(program, &env).unwrap();
+            assert_eq!(expanded, parse_source(expect).unwrap(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_expansion_is_evaluable() {
+        let mut program = parse_source(
+            "let square = macro(x) { quote(unquote(x) * unquote(x)) };
+             let f = fn(n) { square(n + 1) };
+             f(2)",
+        )
+        .unwrap();
+        let mut env = Environment::new();
+        define_macros(&mut program, &mut env);
+        let expanded = expand_macros(program, &env).unwrap();
+
+        // the expansion runs without the macros it was expanded with
+        let mut env = Environment::new();
+        assert_eq!(Evaluator::new(&mut env).evaluate(expanded), Object::Int(9));
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        let tests = [
+            (
+                "let m = macro() { 1 }; m()",
+                "macro must return QUOTE, got INTEGER",
+            ),
+            (
+                "let m = macro(x) { quote(x) }; m(1, 2)",
+                "wrong number of arguments. got=2, want=1",
+            ),
+            ("let m = macro() { 1 / 0 }; m()", "division by zero"),
+            (
+                "let m = macro() { quote(m()) }; m()",
+                "maximum macro expansion depth exceeded",
+            ),
+        ];
+        for (input, expect) in tests.iter() {
+            let mut program = parse_source(input).unwrap();
+            let mut env = Environment::new();
+            define_macros(&mut program, &mut env);
+            let err = expand_macros(program, &env).unwrap_err();
+            assert_eq!(err.to_string(), *expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_unless() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            let unless = macro(cond, cons, alt) {
+              quote(if (!(unquote(cond))) { unquote(cons) } else { unquote(alt) })
+            };
+            unless(10 > 5, "not greater", "greater")
+        "#;
+        assert_eq!(
+            interpreter.eval(source).unwrap(),
+            Object::String("greater".into())
+        );
+        // macros stay defined for later programs
+        assert_eq!(
+            interpreter
+                .eval(r#"unless(1 > 5, "not greater", "greater")"#)
+                .unwrap(),
+            Object::String("not greater".into())
+        );
+    }
+}
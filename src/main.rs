@@ -1,31 +1,184 @@
-use crate::environment::Environment;
-use crate::parser::Parser;
-use crate::{evaluator::Evaluator, lexer::Lexer};
+use monkey_rust::compiler::Compiler;
+use monkey_rust::coverage::annotate;
+use monkey_rust::macros::{define_macros, expand_macros};
+use monkey_rust::repl::{run_repl, run_scripts, Engine, ReplConfig, Script};
+#[cfg(feature = "readline")]
+use monkey_rust::repl::{run_repl_with, Editor};
+use monkey_rust::runner::{report, Runner};
+use monkey_rust::{Environment, Evaluator, Lexer, Object, Parser};
 
-mod ast;
-mod environment;
-mod errors;
-mod evaluator;
-mod lexer;
-mod object;
-mod parser;
-mod token;
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::path::PathBuf;
+use std::process;
+use std::rc::Rc;
 
-use std::io::{self, Write};
+// What the command line asks for.
+struct Args {
+    config: ReplConfig,
+    dump: bool,
+    coverage: bool,
+    test_file: Option<String>,
+    // Files and `-e` sources, in the order they were given.
+    scripts: Vec<Script>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        config: ReplConfig {
+            status: true,
+            ..ReplConfig::default()
+        },
+        dump: false,
+        coverage: false,
+        test_file: None,
+        scripts: vec![],
+    };
+    let config = &mut parsed.config;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--optimize" => config.optimize = true,
+            "--strict" => config.strict = true,
+            "--plain" => config.status = false,
+            "--profile" => config.profile = true,
+            "--dump-bytecode" => parsed.dump = true,
+            "--coverage" => parsed.coverage = true,
+            "--test" => {
+                let file = args.next().ok_or("--test requires a file")?;
+                parsed.test_file = Some(file);
+            }
+            "-e" | "--eval" => {
+                let source = args
+                    .next()
+                    .ok_or_else(|| format!("{} requires a source", arg))?;
+                parsed.scripts.push(Script::Source(source));
+            }
+            _ => {
+                if let Some(engine) = arg.strip_prefix("--engine=") {
+                    config.engine = engine.parse::<Engine>()?;
+                } else if arg.starts_with('-') {
+                    return Err(format!("unknown option: {}", arg));
+                } else {
+                    parsed.scripts.push(Script::File(PathBuf::from(arg)));
+                }
+            }
+        }
+    }
+    let modes = [parsed.test_file.is_some(), parsed.coverage, parsed.dump];
+    if modes.iter().filter(|&&mode| mode).count() > 1 {
+        return Err("--test, --coverage and --dump-bytecode cannot be combined".to_string());
+    }
+    if parsed.test_file.is_some() && !parsed.scripts.is_empty() {
+        return Err("--test does not take scripts".to_string());
+    }
+    if (parsed.coverage || parsed.dump) && parsed.scripts.len() > 1 {
+        return Err("--coverage and --dump-bytecode take a single script".to_string());
+    }
+    Ok(parsed)
+}
 
 fn main() {
-    let mut env = Environment::new();
-    loop {
-        print!(">> ");
-        io::stdout().flush().unwrap();
-        let mut line = String::new();
-        io::stdin().read_line(&mut line).unwrap();
-        let lexer = Lexer::new(&line);
-        let mut parser = Parser::new(lexer);
-        let mut evaluator = Evaluator::new(&mut env);
-        match parser.parse_program() {
-            Ok(program) => println!("{}", evaluator.evaluate(program)),
-            Err(e) => eprintln!("{}", e),
+    let args = parse_args(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(2);
+    });
+    if let Some(path) = args.test_file {
+        match run_tests(&path) {
+            Ok(true) => return,
+            Ok(false) => process::exit(1),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(2);
+            }
+        }
+    }
+    if args.coverage {
+        match run_with_coverage(args.scripts.first()) {
+            Ok(true) => return,
+            Ok(false) => process::exit(1),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+    }
+    if args.dump {
+        if let Err(err) = dump_bytecode(args.scripts.first()) {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        return;
+    }
+    if !args.scripts.is_empty() {
+        let output = Rc::new(RefCell::new(io::stdout()));
+        let code = run_scripts(&args.config, &args.scripts, output, io::stderr()).unwrap();
+        process::exit(code);
+    }
+    let mut config = args.config;
+    config.interactive = io::stdin().is_terminal();
+    #[cfg(feature = "readline")]
+    if config.interactive {
+        match Editor::new(Editor::default_history()) {
+            Ok(editor) => return run_repl_with(&config, editor, io::stdout()).unwrap(),
+            Err(err) => eprintln!("cannot start the line editor: {}", err),
         }
     }
+    let stdin = io::stdin();
+    run_repl(&config, stdin.lock(), io::stdout()).unwrap();
+}
+
+// Runs the tests declared in the file at `path`, returning whether they all
+// passed.
+fn run_tests(path: &str) -> anyhow::Result<bool> {
+    let source = fs::read_to_string(path)?;
+    let mut program = Parser::new(Lexer::new(&source)).parse_program()?;
+    let mut env = Environment::with_builtins();
+    define_macros(&mut program, &mut env);
+    let program = expand_macros(program, &env)?;
+    let results = Runner::run_tests(program, &mut env);
+    print!("{}", report(&results));
+    Ok(results.iter().all(|result| result.passed))
+}
+
+// The source of `script`, or all of standard input without one.
+fn read_source(script: Option<&Script>) -> anyhow::Result<String> {
+    match script {
+        Some(Script::File(path)) => fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("cannot read {}: {}", path.display(), err)),
+        Some(Script::Source(source)) => Ok(source.clone()),
+        None => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            Ok(source)
+        }
+    }
+}
+
+// Evaluates `script`, then prints it with the number of times each line ran.
+// Returns whether the program ran without an error.
+fn run_with_coverage(script: Option<&Script>) -> anyhow::Result<bool> {
+    let source = read_source(script)?;
+    let program = Parser::new(Lexer::new(&source)).parse_program()?;
+    let mut env = Environment::with_builtins();
+    let mut evaluator = Evaluator::new(&mut env);
+    evaluator.set_coverage(true);
+    let result = evaluator.evaluate(program.clone());
+    if let Object::Error(err) = &result {
+        eprintln!("{}", err.report(program.arena.source()));
+    }
+    let hits = evaluator.coverage().unwrap_or_default();
+    print!("{}", annotate(&source, &program, &hits));
+    Ok(!matches!(result, Object::Error(_)))
+}
+
+// Compiles `script` and prints the bytecode listing.
+fn dump_bytecode(script: Option<&Script>) -> anyhow::Result<()> {
+    let source = read_source(script)?;
+    let program = Parser::new(Lexer::new(&source)).parse_program()?;
+    let mut compiler = Compiler::new();
+    compiler.compile(&program)?;
+    print!("{}", compiler.bytecode());
+    Ok(())
 }
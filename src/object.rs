@@ -1,53 +1,616 @@
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
 use std::fmt;
+use std::iter::FromIterator;
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::{ast::BlockStatement, environment::Environment};
+#[cfg(feature = "bigint")]
+use num_bigint::BigInt;
+
+use crate::{
+    ast::{Arena, BlockStatement, ExprId, Source, Span},
+    code::Instructions,
+    environment::Environment,
+    errors::MonkeyError,
+    evaluator::Evaluator,
+    interner::Symbol,
+};
+
+pub type BuiltinFunction = fn(&mut Evaluator, Vec<Object>) -> Object;
+
+#[derive(Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub func: BuiltinFunction,
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Builtin({})", self.name)
+    }
+}
+
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+/// A function registered by the embedding application.
+pub type HostFunction = Rc<dyn Fn(&[Object]) -> Result<Object, MonkeyError>>;
+
+#[derive(Clone)]
+pub struct Host {
+    pub name: String,
+    pub func: HostFunction,
+}
+
+impl fmt::Debug for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Host({})", self.name)
+    }
+}
+
+impl PartialEq for Host {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.func, &other.func)
+    }
+}
+
+/// A function lowered to bytecode by the `compiler` module.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledFunction {
+    pub instructions: Instructions,
+    /// How many local slots its frame needs, parameters included.
+    pub num_locals: usize,
+    pub num_parameters: usize,
+}
+
+/// A compiled function together with the values of the enclosing functions'
+/// locals it refers to, captured when the closure was made.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    pub free: Vec<Object>,
+}
+
+/// A lazy sequence made by `iter` or one of the `iter_*` builtins. Calling it
+/// with no arguments returns the next element, or `null` once it is exhausted.
+#[derive(Debug, Clone)]
+pub struct LazyIterator(pub(crate) Rc<RefCell<IteratorState>>);
+
+impl LazyIterator {
+    pub(crate) fn new(state: IteratorState) -> Self {
+        Self(Rc::new(RefCell::new(state)))
+    }
+}
+
+impl PartialEq for LazyIterator {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Where a `LazyIterator` takes its elements from. `source` is any zero-argument
+/// function, including another iterator.
+#[derive(Debug)]
+pub(crate) enum IteratorState {
+    Array { elements: Vec<Object>, index: usize },
+    Map { source: Object, func: Object },
+    Filter { source: Object, func: Object },
+    Take { source: Object, remaining: usize },
+    Drop { source: Object, skip: usize },
+}
+
+/// An event emitter made by the `emitter` builtin: the listeners added with
+/// `on`, by event name, in the order they were added. Clones share listeners.
+#[derive(Debug, Clone, Default)]
+pub struct Emitter(pub(crate) Rc<RefCell<HashMap<String, Vec<Object>>>>);
+
+impl PartialEq for Emitter {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// A function made by the `memoize` builtin, with the results of its calls
+/// so far by their arguments. Clones share the results.
+#[derive(Debug, Clone)]
+pub struct Memoized {
+    pub(crate) func: Box<Object>,
+    pub(crate) cache: Rc<RefCell<HashMap<Vec<i64>, Object>>>,
+}
+
+impl PartialEq for Memoized {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.cache, &other.cache)
+    }
+}
+
+/// The pairs of a hash, in the order their keys were first inserted, each key
+/// once. Two hashes are equal when they have the same pairs, in any order.
+#[derive(Debug, Clone, Default)]
+pub struct HashPairs(Vec<(Object, Object)>);
+
+impl PartialEq for HashPairs {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|pair| other.iter().any(|other| other == pair))
+    }
+}
+
+impl HashPairs {
+    /// Adds `key` with `value`, or replaces the value of `key` in place if
+    /// the hash has it already, returning the value replaced.
+    pub fn insert(&mut self, key: Object, value: Object) -> Option<Object> {
+        match self.0.iter_mut().find(|(k, _)| *k == key) {
+            Some(pair) => Some(std::mem::replace(&mut pair.1, value)),
+            None => {
+                self.0.push((key, value));
+                None
+            }
+        }
+    }
+}
+
+// Only shared access to the pairs is given, so that nothing but `insert` can
+// add a key.
+impl Deref for HashPairs {
+    type Target = Vec<(Object, Object)>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+// A later pair replaces the value of an earlier one with the same key.
+impl From<Vec<(Object, Object)>> for HashPairs {
+    fn from(pairs: Vec<(Object, Object)>) -> Self {
+        pairs.into_iter().collect()
+    }
+}
+
+impl FromIterator<(Object, Object)> for HashPairs {
+    fn from_iter<I: IntoIterator<Item = (Object, Object)>>(iter: I) -> Self {
+        let mut pairs = Self::default();
+        for (key, value) in iter {
+            pairs.insert(key, value);
+        }
+        pairs
+    }
+}
+
+impl IntoIterator for HashPairs {
+    type Item = (Object, Object);
+    type IntoIter = std::vec::IntoIter<(Object, Object)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a HashPairs {
+    type Item = &'a (Object, Object);
+    type IntoIter = std::slice::Iter<'a, (Object, Object)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Object {
     Int(i64),
+    /// An integer outside the range of `i64`; see the `bigint` module.
+    #[cfg(feature = "bigint")]
+    BigInt(BigInt),
+    Float(f64),
+    String(Rc<str>),
     Boolean(bool),
     Null,
     Return(Box<Object>),
+    /// Unwinds to the nearest enclosing `loop`, which evaluates to the value.
+    Break(Box<Object>),
+    /// Unwinds to the nearest enclosing `loop`, which starts its next
+    /// iteration.
+    Continue,
     Function {
-        parameters: Vec<String>,
+        id: usize,
+        /// The name of the `let` binding the function was first bound to.
+        name: Option<Symbol>,
+        /// The parts of the function are shared, so that looking it up to
+        /// call it does not copy them.
+        parameters: Rc<[Symbol]>,
+        body: Rc<BlockStatement>,
+        /// The arena `body` was parsed into.
+        arena: Rc<Arena>,
+        environment: Rc<Environment>,
+    },
+    /// A macro, bound by `define_macros`. Calling it expands the call into
+    /// the expression its body quotes.
+    Macro {
+        parameters: Vec<Symbol>,
         body: BlockStatement,
+        /// The arena `body` was parsed into.
+        arena: Rc<Arena>,
         environment: Environment,
     },
-    Error(String),
+    CompiledFunction(Rc<CompiledFunction>),
+    Closure(Rc<Closure>),
+    Builtin(Builtin),
+    Host(Host),
+    Memoized(Memoized),
+    /// A function made by `partial`, with the arguments to call it with first.
+    Partial(Box<Object>, Vec<Object>),
+    /// A function made by `compose`, which calls the last function with its
+    /// arguments and each one before it with the result of the one after.
+    Composed(Vec<Object>),
+    Iterator(LazyIterator),
+    Array(Vec<Object>),
+    Hash(HashPairs),
+    Pair(Box<Object>, Box<Object>),
+    Emitter(Emitter),
+    /// An expression made by `quote`, unevaluated.
+    Quote {
+        expr: ExprId,
+        /// The arena `expr` lives in.
+        arena: Rc<Arena>,
+    },
+    Error(RuntimeError),
 }
 
 impl Object {
+    /// An error with `message`, located by the evaluator where it is raised.
+    pub fn error(message: String) -> Object {
+        Object::Error(RuntimeError {
+            message,
+            location: None,
+        })
+    }
+
     pub fn type_info(&self) -> String {
         match self {
             Object::Int(_) => "INTEGER",
+            #[cfg(feature = "bigint")]
+            Object::BigInt(_) => "INTEGER",
+            Object::Float(_) => "FLOAT",
+            Object::String(_) => "STRING",
             Object::Boolean(_) => "BOOLEAN",
-            Object::Error(_) => "FUNCTION",
-            _ => unreachable!(),
+            Object::Null => "NULL",
+            Object::Return(_) => "RETURN_VALUE",
+            Object::Break(_) => "BREAK",
+            Object::Continue => "CONTINUE",
+            Object::Function { .. } => "FUNCTION",
+            Object::Macro { .. } => "MACRO",
+            Object::CompiledFunction(_) | Object::Closure(_) => "FUNCTION",
+            Object::Builtin(_) | Object::Host(_) => "BUILTIN",
+            Object::Memoized(_)
+            | Object::Partial(..)
+            | Object::Composed(_)
+            | Object::Iterator(_) => "FUNCTION",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Pair(..) => "PAIR",
+            Object::Emitter(_) => "EMITTER",
+            Object::Quote { .. } => "QUOTE",
+            Object::Error(_) => "ERROR",
         }
         .to_string()
     }
+
+    pub fn is_callable(&self) -> bool {
+        matches!(
+            self,
+            Object::Function { .. }
+                | Object::Builtin(_)
+                | Object::Host(_)
+                | Object::Memoized(_)
+                | Object::Partial(..)
+                | Object::Composed(_)
+                | Object::Iterator(_)
+        )
+    }
+
+    pub fn is_hashable(&self) -> bool {
+        match self {
+            Object::Int(_) | Object::String(_) | Object::Boolean(_) => true,
+            #[cfg(feature = "bigint")]
+            Object::BigInt(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Integers, floats, strings and booleans (`false < true`) are ordered within their
+/// type, and arrays and pairs lexicographically by their elements. Objects of
+/// different types, hashes and functions are unordered, so comparing them gives
+/// `None` unless they are equal.
+impl PartialOrd for Object {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        match (self, other) {
+            (Object::Int(l), Object::Int(r)) => l.partial_cmp(r),
+            #[cfg(feature = "bigint")]
+            (Object::BigInt(l), Object::BigInt(r)) => l.partial_cmp(r),
+            #[cfg(feature = "bigint")]
+            (Object::BigInt(l), Object::Int(r)) => l.partial_cmp(&BigInt::from(*r)),
+            #[cfg(feature = "bigint")]
+            (Object::Int(l), Object::BigInt(r)) => BigInt::from(*l).partial_cmp(r),
+            (Object::Float(l), Object::Float(r)) => l.partial_cmp(r),
+            (Object::String(l), Object::String(r)) => l.partial_cmp(r),
+            (Object::Boolean(l), Object::Boolean(r)) => l.partial_cmp(r),
+            (Object::Array(l), Object::Array(r)) => l.partial_cmp(r),
+            (Object::Pair(lk, lv), Object::Pair(rk, rv)) => (lk, lv).partial_cmp(&(rk, rv)),
+            _ if self == other => Some(cmp::Ordering::Equal),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Object {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Object::Int(value) => write!(f, "{}", value),
+            #[cfg(feature = "bigint")]
+            Object::BigInt(value) => write!(f, "{}", value),
+            Object::Float(value) => write!(f, "{}", format_float(*value)),
+            Object::String(value) => write!(f, "{}", value),
             Object::Boolean(value) => write!(f, "{}", value),
             Object::Null => write!(f, "null"),
-            Object::Return(obj) => write!(f, "{}", *obj),
+            Object::Return(obj) | Object::Break(obj) => write!(f, "{}", *obj),
+            Object::Continue => write!(f, "continue"),
             Object::Function {
                 parameters,
                 body,
-                environment: _,
+                arena,
+                ..
             } => {
                 let params = parameters
                     .iter()
                     .map(|x| x.to_string())
                     .collect::<Vec<_>>()
                     .join(", ");
-                write!(f, "fn ({}) {{ {} }}", params, body)
+                write!(f, "fn ({}) {}", params, arena.display(&**body))
             }
+            Object::Macro {
+                parameters,
+                body,
+                arena,
+                ..
+            } => {
+                let params = parameters
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "macro ({}) {}", params, arena.display(body))
+            }
+            Object::CompiledFunction(_) | Object::Closure(_) => write!(f, "compiled function"),
+            Object::Builtin(_) | Object::Host(_) => write!(f, "builtin function"),
+            Object::Memoized(memoized) => write!(f, "memoize({})", memoized.func),
+            Object::Partial(func, args) => {
+                write!(f, "partial({}", func)?;
+                for arg in args {
+                    write!(f, ", {}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Object::Composed(funcs) => {
+                let funcs = funcs
+                    .iter()
+                    .map(|func| func.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "compose({})", funcs)
+            }
+            Object::Iterator(_) => write!(f, "iterator"),
+            Object::Array(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "[{}]", elements)
+            }
+            Object::Hash(pairs) => {
+                let pairs = pairs
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "{{{}}}", pairs)
+            }
+            Object::Pair(key, value) => write!(f, "({}, {})", key, value),
+            Object::Emitter(_) => write!(f, "emitter"),
+            Object::Quote { expr, arena } => write!(f, "QUOTE({})", arena.display(*expr)),
             Object::Error(obj) => write!(f, "Error: {}", obj),
         }
     }
 }
+
+/// Where an error was raised: the span of the expression that raised it, in
+/// the source of the arena the expression is in.
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub source: Rc<Source>,
+    pub span: Span,
+}
+
+/// The value of an error. Where it was raised is kept apart from the message,
+/// so that `catch` binds only the message, and is shown when the error is
+/// reported; see `report`.
+///
+/// Errors are compared by message, like nodes ignore their spans.
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+impl RuntimeError {
+    /// The message, after the line and column the error was raised at when
+    /// known, like `line 2, col 5: division by zero`. When that is not in
+    /// `main`, the source of the program being run, its source is named too,
+    /// like `in lib.monkey: line 2, col 5: division by zero`, or the position
+    /// left out if the source has no name.
+    pub fn report(&self, main: Option<&Rc<Source>>) -> String {
+        let location = match &self.location {
+            Some(location) => location,
+            None => return self.message.clone(),
+        };
+        let (line, col) = location.span.line_col(&location.source.text);
+        let located = format!("line {}, col {}: {}", line, col, self.message);
+        if main.is_some_and(|main| Rc::ptr_eq(main, &location.source)) {
+            return located;
+        }
+        match &location.source.name {
+            Some(name) => format!("in {}: {}", name, located),
+            None => self.message.clone(),
+        }
+    }
+}
+
+impl PartialEq for RuntimeError {
+    fn eq(&self, other: &Self) -> bool {
+        self.message == other.message
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Returns a fresh identity for a function object, stable across clones.
+pub fn next_function_id() -> usize {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Formats a float so that it always reads as one, e.g. `3.0` rather than `3`.
+pub fn format_float(value: f64) -> String {
+    let formatted = value.to_string();
+    if value.is_finite() && !formatted.contains('.') {
+        formatted + ".0"
+    } else {
+        formatted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::object::{HashPairs, Object};
+
+    #[test]
+    fn test_ordering() {
+        let int = Object::Int;
+        let string = |s: &str| Object::String(s.into());
+        let pair = |k, v| Object::Pair(Box::new(k), Box::new(v));
+
+        assert!(int(1) < int(2));
+        assert!(int(-5) < int(0));
+        assert!(string("abc") < string("abd"));
+        assert!(string("Z") < string("a"));
+        assert!(Object::Boolean(false) < Object::Boolean(true));
+        assert!(Object::Array(vec![int(1), int(2)]) < Object::Array(vec![int(1), int(3)]));
+        assert!(Object::Array(vec![int(1)]) < Object::Array(vec![int(1), int(0)]));
+        assert!(pair(string("a"), int(9)) < pair(string("b"), int(0)));
+        assert_eq!(
+            Object::Null.partial_cmp(&Object::Null),
+            Some(Ordering::Equal)
+        );
+
+        let mut values = vec![int(3), int(-1), int(2)];
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(values, vec![int(-1), int(2), int(3)]);
+    }
+
+    #[test]
+    fn test_cross_type_ordering() {
+        let tests = vec![
+            (Object::Int(1), Object::String("1".into())),
+            (Object::Boolean(true), Object::Int(1)),
+            (Object::Null, Object::Int(0)),
+            (
+                Object::Array(vec![Object::Int(1)]),
+                Object::Array(vec![Object::Boolean(true)]),
+            ),
+            (
+                Object::Hash(vec![].into()),
+                Object::Hash(vec![(Object::Int(1), Object::Null)].into()),
+            ),
+        ];
+
+        for (left, right) in tests {
+            assert_eq!(left.partial_cmp(&right), None, "{} {}", left, right);
+            assert_eq!(right.partial_cmp(&left), None, "{} {}", right, left);
+        }
+    }
+
+    #[test]
+    fn test_hash_equality() {
+        let string = |s: &str| Object::String(s.into());
+        let hash = |pairs: Vec<(Object, Object)>| Object::Hash(pairs.into());
+
+        let ab = hash(vec![
+            (string("a"), Object::Int(1)),
+            (string("b"), Object::Int(2)),
+        ]);
+        let ba = hash(vec![
+            (string("b"), Object::Int(2)),
+            (string("a"), Object::Int(1)),
+        ]);
+        assert_eq!(ab, ba);
+        assert_eq!(ab.partial_cmp(&ba), Some(Ordering::Equal));
+        assert_eq!(Object::Array(vec![ab.clone()]), Object::Array(vec![ba]));
+
+        let tests = vec![
+            hash(vec![(string("a"), Object::Int(1))]),
+            hash(vec![
+                (string("a"), Object::Int(1)),
+                (string("b"), Object::Int(3)),
+            ]),
+            hash(vec![
+                (string("a"), Object::Int(1)),
+                (string("c"), Object::Int(2)),
+            ]),
+            hash(vec![]),
+        ];
+        for other in tests {
+            assert_ne!(ab, other, "{}", other);
+            assert_ne!(other, ab, "{}", other);
+        }
+    }
+
+    #[test]
+    fn test_hash_insert() {
+        let mut pairs = HashPairs::default();
+        assert_eq!(pairs.insert(Object::Int(1), Object::Int(10)), None);
+        assert_eq!(pairs.insert(Object::Int(2), Object::Int(20)), None);
+        // an existing key keeps its place
+        assert_eq!(
+            pairs.insert(Object::Int(1), Object::Int(11)),
+            Some(Object::Int(10))
+        );
+        assert_eq!(
+            pairs.as_slice(),
+            [
+                (Object::Int(1), Object::Int(11)),
+                (Object::Int(2), Object::Int(20))
+            ]
+        );
+
+        let pairs = HashPairs::from(vec![
+            (Object::Int(1), Object::Int(10)),
+            (Object::Int(1), Object::Int(11)),
+        ]);
+        assert_eq!(pairs.as_slice(), [(Object::Int(1), Object::Int(11))]);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::ast::{
+    walk_block, walk_program_mut, Arena, BlockStatement, ExprId, Expression, Program, Statement,
+    StmtId, Visitor, VisitorMut,
+};
+
+/// Evaluates constant sub-expressions ahead of time, e.g. `2 * 3 + x` becomes
+/// `6 + x` and `if (true) { a } else { b }` becomes `{ a }`.
+///
+/// Only literals are folded, so identifiers and calls are never touched. An
+/// operation that would fail at runtime (division by zero, overflow, a type
+/// mismatch) is left alone so that it still reports its error.
+pub fn fold_constants(mut program: Program) -> Program {
+    walk_program_mut(&mut ConstantFolder, &mut program);
+    program
+}
+
+/// Drops statements that can never run: those after a `return` in the same
+/// block, and the untaken branch of an `if` whose condition is a literal.
+///
+/// A `return` nested in an `if` is conditional, so code after the `if` stays.
+pub fn eliminate_dead_code(program: Program) -> Program {
+    eliminate_dead_code_counted(program).0
+}
+
+/// Like `eliminate_dead_code`, also returning how many statements were removed.
+pub fn eliminate_dead_code_counted(mut program: Program) -> (Program, usize) {
+    let mut eliminator = DeadCodeEliminator { removed: 0 };
+    walk_program_mut(&mut eliminator, &mut program);
+    eliminator.removed += truncate_after_return(&program.arena, &mut program.statements);
+    (program, eliminator.removed)
+}
+
+struct ConstantFolder;
+
+impl VisitorMut for ConstantFolder {
+    fn visit_expression_mut(&mut self, arena: &mut Arena, id: ExprId) {
+        let folded = match &arena[id] {
+            Expression::Prefix { op, right } => fold_prefix(op, &arena[*right]),
+            Expression::Infix {
+                left, op, right, ..
+            } => fold_infix(&arena[*left], op, &arena[*right]),
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => fold_if(arena, *condition, consequence, alternative),
+            Expression::Block(block) => fold_block(arena, block),
+            _ => None,
+        };
+        if let Some(folded) = folded {
+            arena[id] = folded;
+        }
+    }
+}
+
+struct DeadCodeEliminator {
+    removed: usize,
+}
+
+impl VisitorMut for DeadCodeEliminator {
+    fn visit_block_mut(&mut self, arena: &mut Arena, block: &mut BlockStatement) {
+        self.removed += truncate_after_return(arena, &mut block.statements);
+    }
+
+    fn visit_expression_mut(&mut self, arena: &mut Arena, id: ExprId) {
+        if let Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } = &arena[id]
+        {
+            let untaken = match truthiness(&arena[*condition]) {
+                Some(true) => alternative
+                    .as_ref()
+                    .map_or(0, |block| block.statements.len()),
+                Some(false) => consequence.statements.len(),
+                None => return,
+            };
+            if let Some(folded) = fold_if(arena, *condition, consequence, alternative) {
+                self.removed += untaken;
+                arena[id] = folded;
+            }
+        }
+    }
+}
+
+// Removes the statements after the first `return`, returning how many there
+// were.
+fn truncate_after_return(arena: &Arena, statements: &mut Vec<StmtId>) -> usize {
+    match statements
+        .iter()
+        .position(|&stmt| matches!(arena[stmt], Statement::Return(_)))
+    {
+        Some(index) => {
+            let removed = statements.len() - index - 1;
+            statements.truncate(index + 1);
+            removed
+        }
+        None => 0,
+    }
+}
+
+fn fold_prefix(op: &str, right: &Expression) -> Option<Expression> {
+    match (op, right) {
+        ("!", right) => truthiness(right).map(|value| Expression::Boolean(!value)),
+        ("-", Expression::Int(value)) => value.checked_neg().map(Expression::Int),
+        _ => None,
+    }
+}
+
+fn fold_infix(left: &Expression, op: &str, right: &Expression) -> Option<Expression> {
+    match (left, right) {
+        (Expression::Int(l), Expression::Int(r)) => {
+            let (l, r) = (*l, *r);
+            match op {
+                "+" => l.checked_add(r).map(Expression::Int),
+                "-" => l.checked_sub(r).map(Expression::Int),
+                "*" => l.checked_mul(r).map(Expression::Int),
+                "/" => l.checked_div(r).map(Expression::Int),
+                "%" => l.checked_rem(r).map(Expression::Int),
+                "<" => Some(Expression::Boolean(l < r)),
+                ">" => Some(Expression::Boolean(l > r)),
+                "==" => Some(Expression::Boolean(l == r)),
+                "!=" => Some(Expression::Boolean(l != r)),
+                _ => None,
+            }
+        }
+        (Expression::String(l), Expression::String(r)) => match op {
+            "+" => Some(Expression::String(format!("{}{}", l, r))),
+            "==" => Some(Expression::Boolean(l == r)),
+            "!=" => Some(Expression::Boolean(l != r)),
+            _ => None,
+        },
+        (Expression::Boolean(l), Expression::Boolean(r)) => match op {
+            "==" => Some(Expression::Boolean(l == r)),
+            "!=" => Some(Expression::Boolean(l != r)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// The taken branch of an `if` runs in the current scope while a block
+// expression gets its own, so branches that may declare names are kept as they
+// are.
+fn fold_if(
+    arena: &Arena,
+    condition: ExprId,
+    consequence: &BlockStatement,
+    alternative: &Option<BlockStatement>,
+) -> Option<Expression> {
+    let taken = if truthiness(&arena[condition])? {
+        consequence
+    } else {
+        alternative.as_ref()?
+    };
+    if declares(arena, taken) {
+        return None;
+    }
+    Some(fold_block(arena, taken).unwrap_or_else(|| Expression::Block(taken.clone())))
+}
+
+fn fold_block(arena: &Arena, block: &BlockStatement) -> Option<Expression> {
+    match block.statements.as_slice() {
+        [stmt] => match &arena[*stmt] {
+            Statement::Expression(expr) if truthiness(&arena[*expr]).is_some() => {
+                Some(arena[*expr].clone())
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn declares(arena: &Arena, block: &BlockStatement) -> bool {
+    struct Declarations(bool);
+
+    impl Visitor for Declarations {
+        fn visit_statement(&mut self, stmt: &Statement) {
+            if let Statement::Let { .. } | Statement::Const { .. } | Statement::Import(_) = stmt {
+                self.0 = true;
+            }
+        }
+    }
+
+    let mut declarations = Declarations(false);
+    walk_block(&mut declarations, arena, block);
+    declarations.0
+}
+
+// Truthiness of a literal, or `None` for anything that is not one.
+fn truthiness(expr: &Expression) -> Option<bool> {
+    match expr {
+        Expression::Boolean(value) => Some(*value),
+        Expression::Int(_) | Expression::String(_) => Some(true),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::Program,
+        environment::Environment,
+        evaluator::Evaluator,
+        lexer::Lexer,
+        object::Object,
+        optimizer::{eliminate_dead_code, eliminate_dead_code_counted, fold_constants},
+        parser::Parser,
+    };
+
+    #[test]
+    fn test_fold_constants() {
+        let tests = vec![
+            ("2 * 3 + x", "(6 + x)"),
+            ("!true", "false"),
+            ("-(1 + 2) * 4", "-12"),
+            ("if (true) { a } else { b }", "{ a }"),
+            ("if (1 < 2) { 10 } else { 20 }", "10"),
+            ("if (false) { a }", "if (false) { a }"),
+            ("if (true) { let a = 1; a }", "if (true) { let a = 1; a }"),
+            (r#""a" + "b" == "ab""#, "true"),
+            ("1 / 0", "(1 / 0)"),
+            ("1 + true", "(1 + true)"),
+            ("f(1 + 1, x * (2 - 2))", "f(2, (x * 0))"),
+            ("fn(x) { x + 2 * 2 }", "fn (x) { (x + 4) }"),
+        ];
+
+        for (input, expect) in tests {
+            let program = fold_constants(parse(input));
+            let actual = program.arena.display(program.statements[0]).to_string();
+            assert_eq!(actual, expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_fold_constants_preserves_results() {
+        let inputs = vec![
+            "2 * 3 + 4 % 3",
+            "let x = 5; 2 * 3 + x",
+            "!true == !!false",
+            "if (10 > 1) { 1 } else { 2 }",
+            "if (false) { 1 }",
+            "if (true) { let y = 3; }; y",
+            "let f = fn(n) { if (n < 1) { 0 } else { f(n - 1) } }; f(2 * 50)",
+            r#"len("ab" + "cd") - 1"#,
+            "[1 + 1, 2 * 2]",
+            "let x = true; if (true) { if (x) { let z = 1; } }; z",
+            "1 / (2 - 2)",
+            "-true",
+            "if (true) { return 1 + 1; }; 3",
+        ];
+
+        for input in inputs {
+            let folded = evaluate(fold_constants(parse(input)));
+            assert_eq!(evaluate(parse(input)), folded, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_eliminate_dead_code() {
+        let tests = vec![
+            ("return 1; 2; 3", "return 1;", 2),
+            (
+                "let f = fn(x) { return x; x + 1; puts(x) }",
+                "let f = fn (x) { return x; };",
+                2,
+            ),
+            (
+                "let f = fn(x) { if (x) { return 1; } 2 }",
+                "let f = fn (x) { if (x) { return 1; }; 2 };",
+                0,
+            ),
+            ("if (true) { 1; 2 } else { 3 }", "{ 1; 2 }", 1),
+            ("if (false) { 1; 2 } else { 3 }", "3", 2),
+            ("if (false) { 1 }", "if (false) { 1 }", 0),
+            (
+                "if (true) { let a = 1; } else { 2 }",
+                "if (true) { let a = 1; } else { 2 }",
+                0,
+            ),
+        ];
+
+        for (input, expect, removed) in tests {
+            let (program, count) = eliminate_dead_code_counted(parse(input));
+            let actual = program.arena.display(program.statements[0]).to_string();
+            assert_eq!(actual, expect, "{}", input);
+            assert_eq!(count, removed, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_eliminate_dead_code_preserves_results() {
+        let inputs = vec![
+            "return 1; 2",
+            "let f = fn(x) { if (x > 1) { return 1; } return 2; 3 }; f(2) + f(0)",
+            "let f = fn() { return 5; puts(1) }; f()",
+            "if (true) { 1 } else { 2 }",
+            "if (false) { 1 } else { return 2; 3 }; 4",
+            "let g = fn(n) { if (n == 0) { return 0; 1 } g(n - 1) }; g(10)",
+        ];
+
+        for input in inputs {
+            let optimized = evaluate(eliminate_dead_code(parse(input)));
+            assert_eq!(evaluate(parse(input)), optimized, "{}", input);
+        }
+    }
+
+    fn parse(input: &str) -> Program {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse_program().unwrap()
+    }
+
+    fn evaluate(program: Program) -> Object {
+        let mut env = Environment::with_builtins();
+        let mut evaluator = Evaluator::new(&mut env);
+        evaluator.evaluate(program)
+    }
+}
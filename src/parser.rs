@@ -1,15 +1,35 @@
-use crate::ast::{BlockStatement, Expression, Precedence, Program, Statement};
+use std::mem;
+use std::rc::Rc;
+
+use crate::ast::{
+    Arena, BlockStatement, ExprId, Expression, Precedence, Program, Span, Statement, StmtId,
+};
 
 use crate::errors::MonkeyError;
+use crate::interner::Symbol;
 use crate::lexer::Lexer;
 use crate::token::{Token, TokenKind};
 use anyhow::Result;
 
-#[derive(Debug, Clone)]
+/// How deeply expressions may nest before parsing fails, so that hostile input
+/// cannot overflow the stack of the recursive descent.
+pub const MAX_NESTING_DEPTH: usize = 128;
+
+#[derive(Debug)]
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     cur_token: Token,
     peek_token: Token,
+    // Byte offsets of `cur_token` and `peek_token` in the input.
+    cur_position: usize,
+    peek_position: usize,
+    // The names of `cur_token` and `peek_token`, interned by the lexer, when
+    // they are identifiers.
+    cur_symbol: Option<Symbol>,
+    peek_symbol: Option<Symbol>,
+    depth: usize,
+    // The nodes parsed so far, handed over to the program at the end.
+    arena: Arena,
 }
 
 impl<'a> Parser<'a> {
@@ -24,6 +44,12 @@ impl<'a> Parser<'a> {
                 kind: TokenKind::Eof,
                 literal: String::from(""),
             },
+            cur_position: 0,
+            peek_position: 0,
+            cur_symbol: None,
+            peek_symbol: None,
+            depth: 0,
+            arena: Arena::new(),
         };
         parser.next_token();
         parser.next_token();
@@ -31,42 +57,108 @@ impl<'a> Parser<'a> {
     }
 
     fn next_token(&mut self) {
-        self.cur_token = self.peek_token.clone();
-        self.peek_token = self.lexer.next_token();
+        self.cur_token = mem::replace(&mut self.peek_token, self.lexer.next_token());
+        self.cur_position = self.peek_position;
+        self.cur_symbol = self.peek_symbol;
+        self.peek_position = self.lexer.token_position();
+        self.peek_symbol = self.lexer.token_symbol();
+    }
+
+    // The name of the current token, which must be an identifier.
+    fn cur_symbol(&self) -> Symbol {
+        self.cur_symbol
+            .expect("identifiers are interned by the lexer")
     }
 
     pub fn parse_program(&mut self) -> Result<Program> {
-        let mut statements: Vec<Statement> = vec![];
+        let mut statements = vec![];
 
         while !self.cur_token_is(TokenKind::Eof) {
-            let stmt = self.parse_statement()?;
+            // Tests may only be declared at the top level.
+            let stmt = match self.cur_token.kind {
+                TokenKind::Test => self.parse_test_statement()?,
+                _ => self.parse_statement()?,
+            };
             statements.push(stmt);
             self.next_token();
         }
 
-        Ok(Program { statements })
+        Ok(Program {
+            arena: Rc::new(mem::take(&mut self.arena)),
+            statements,
+        })
     }
 
-    fn parse_statement(&mut self) -> Result<Statement> {
-        match self.cur_token.kind {
-            TokenKind::Let => Ok(self.parse_let_statement()?),
-            TokenKind::Return => Ok(self.parse_return_statement()?),
-            _ => Ok(self.parse_expression_statement()?),
+    fn expr(&mut self, expr: Expression) -> ExprId {
+        self.arena.alloc_expr(expr)
+    }
+
+    fn stmt(&mut self, stmt: Statement) -> StmtId {
+        self.arena.alloc_stmt(stmt)
+    }
+
+    fn parse_statement(&mut self) -> Result<StmtId> {
+        let span = Span::new(self.cur_position);
+        let stmt = match self.cur_token.kind {
+            TokenKind::Let => self.parse_let_statement()?,
+            TokenKind::Const => self.parse_const_statement()?,
+            TokenKind::Return => self.parse_return_statement()?,
+            TokenKind::Import if !self.peek_token_is(TokenKind::Lparen) => {
+                self.parse_import_statement()?
+            }
+            TokenKind::Break => self.parse_break_statement()?,
+            TokenKind::Continue => {
+                if self.peek_token_is(TokenKind::Semicolon) {
+                    self.next_token();
+                }
+                self.stmt(Statement::Continue)
+            }
+            _ => self.parse_expression_statement()?,
+        };
+        self.arena.set_stmt_span(stmt, span);
+        Ok(stmt)
+    }
+
+    fn parse_let_statement(&mut self) -> Result<StmtId> {
+        self.next_token();
+        let ident = self.parse_pattern()?;
+        if !self.expect_peek(TokenKind::Assign) {
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Assign,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
         }
+
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        if self.peek_token_is(TokenKind::Semicolon) {
+            self.next_token();
+        }
+
+        Ok(self.stmt(Statement::Let { ident, value }))
     }
 
-    fn parse_let_statement(&mut self) -> Result<Statement> {
+    fn parse_const_statement(&mut self) -> Result<StmtId> {
         if !self.expect_peek(TokenKind::Ident) {
-            return Err(
-                MonkeyError::UnexpectedToken(TokenKind::Ident, self.cur_token.clone()).into(),
-            );
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Ident,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
         }
-
-        let ident = Expression::Ident(self.cur_token.literal.clone());
+        let ident = self.parse_identifier()?;
         if !self.expect_peek(TokenKind::Assign) {
-            return Err(
-                MonkeyError::UnexpectedToken(TokenKind::Assign, self.peek_token.clone()).into(),
-            );
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Assign,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
         }
 
         self.next_token();
@@ -77,53 +169,262 @@ impl<'a> Parser<'a> {
             self.next_token();
         }
 
-        let stmt = Statement::Let { ident, value };
-        Ok(stmt)
+        Ok(self.stmt(Statement::Const { ident, value }))
+    }
+
+    // What a `let` binds: an identifier, an array of patterns to destructure
+    // an array into, like `[a, [b, c]]`, or a hash of patterns to destructure
+    // a hash into, like `{name, "address": [street, city]}`.
+    fn parse_pattern(&mut self) -> Result<ExprId> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(MonkeyError::NestingTooDeep(MAX_NESTING_DEPTH).into());
+        }
+        match self.cur_token.kind {
+            TokenKind::Ident => {
+                let ident = Expression::Ident(self.cur_symbol(), Span::new(self.cur_position));
+                Ok(self.expr(ident))
+            }
+            TokenKind::Lbracket => {
+                self.depth += 1;
+                let patterns = self.parse_patterns(TokenKind::Rbracket, Self::parse_pattern);
+                self.depth -= 1;
+                Ok(self.expr(Expression::Array(patterns?)))
+            }
+            TokenKind::Lbrace => {
+                self.depth += 1;
+                let pairs = self.parse_patterns(TokenKind::Rbrace, Self::parse_key_pattern);
+                self.depth -= 1;
+                Ok(self.expr(Expression::Hash(pairs?)))
+            }
+            _ => Err(MonkeyError::UnexpectedToken(
+                TokenKind::Ident,
+                self.cur_token.clone(),
+                self.cur_position,
+            )
+            .into()),
+        }
+    }
+
+    // A key of a hash pattern and the pattern its value is bound to. `name`
+    // is short for `"name": name`.
+    fn parse_key_pattern(&mut self) -> Result<(ExprId, ExprId)> {
+        match self.cur_token.kind {
+            TokenKind::Ident => {
+                let key = Expression::String(self.cur_token.literal.clone());
+                let key = self.expr(key);
+                Ok((key, self.parse_pattern()?))
+            }
+            TokenKind::String => {
+                let key = self.parse_string()?;
+                if !self.expect_peek(TokenKind::Colon) {
+                    return Err(MonkeyError::UnexpectedToken(
+                        TokenKind::Colon,
+                        self.peek_token.clone(),
+                        self.peek_position,
+                    )
+                    .into());
+                }
+                self.next_token();
+                Ok((key, self.parse_pattern()?))
+            }
+            _ => Err(MonkeyError::UnexpectedToken(
+                TokenKind::Ident,
+                self.cur_token.clone(),
+                self.cur_position,
+            )
+            .into()),
+        }
+    }
+
+    fn parse_patterns<T>(
+        &mut self,
+        end: TokenKind,
+        parse: fn(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let mut patterns = vec![];
+
+        if self.peek_token_is(end.clone()) {
+            self.next_token();
+            return Ok(patterns);
+        }
+
+        self.next_token();
+        patterns.push(parse(self)?);
+
+        while self.peek_token_is(TokenKind::Comma) {
+            self.next_token();
+            self.next_token();
+            patterns.push(parse(self)?);
+        }
+
+        if !self.expect_peek(end.clone()) {
+            return Err(MonkeyError::UnexpectedToken(
+                end,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
+        }
+
+        Ok(patterns)
+    }
+
+    // The value is optional, so `break` directly followed by the end of the
+    // statement or block has none.
+    fn parse_break_statement(&mut self) -> Result<StmtId> {
+        let value = match self.peek_token.kind {
+            TokenKind::Semicolon | TokenKind::Rbrace | TokenKind::Eof => None,
+            _ => {
+                self.next_token();
+                Some(self.parse_expression(Precedence::Lowest)?)
+            }
+        };
+
+        if self.peek_token_is(TokenKind::Semicolon) {
+            self.next_token();
+        }
+
+        Ok(self.stmt(Statement::Break(value)))
     }
 
-    fn parse_return_statement(&mut self) -> Result<Statement> {
+    fn parse_return_statement(&mut self) -> Result<StmtId> {
         self.next_token();
         let value = self.parse_expression(Precedence::Lowest)?;
         if self.peek_token_is(TokenKind::Semicolon) {
             self.next_token();
         }
 
-        let stmt = Statement::Return(value);
-        Ok(stmt)
+        Ok(self.stmt(Statement::Return(value)))
+    }
+
+    fn parse_import_statement(&mut self) -> Result<StmtId> {
+        if !self.expect_peek(TokenKind::String) {
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::String,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
+        }
+
+        let path = self.cur_token.literal.clone();
+        if self.peek_token_is(TokenKind::Semicolon) {
+            self.next_token();
+        }
+
+        Ok(self.stmt(Statement::Import(path)))
+    }
+
+    fn parse_import_expression(&mut self) -> Result<ExprId> {
+        let span = Span::new(self.cur_position);
+        for kind in [TokenKind::Lparen, TokenKind::String] {
+            if !self.expect_peek(kind.clone()) {
+                return Err(MonkeyError::UnexpectedToken(
+                    kind,
+                    self.peek_token.clone(),
+                    self.peek_position,
+                )
+                .into());
+            }
+        }
+        let path = self.cur_token.literal.clone();
+        if !self.expect_peek(TokenKind::Rparen) {
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Rparen,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
+        }
+
+        Ok(self.expr(Expression::Import(path, span)))
+    }
+
+    fn parse_test_statement(&mut self) -> Result<StmtId> {
+        if !self.expect_peek(TokenKind::String) {
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::String,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
+        }
+        let name = self.cur_token.literal.clone();
+
+        if !self.expect_peek(TokenKind::Lbrace) {
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Lbrace,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
+        }
+        let body = self.parse_block_statement()?;
+        if self.peek_token_is(TokenKind::Semicolon) {
+            self.next_token();
+        }
+
+        Ok(self.stmt(Statement::Test { name, body }))
     }
 
-    fn parse_expression_statement(&mut self) -> Result<Statement> {
+    fn parse_expression_statement(&mut self) -> Result<StmtId> {
         let expr = self.parse_expression(Precedence::Lowest)?;
         if self.peek_token_is(TokenKind::Semicolon) {
             self.next_token();
         }
 
-        let stmt = Statement::Expression(expr);
-        Ok(stmt)
+        Ok(self.stmt(Statement::Expression(expr)))
     }
 
-    fn parse_prefix_expression(&mut self) -> Result<Expression> {
+    fn parse_prefix_expression(&mut self) -> Result<ExprId> {
         let op = self.cur_token.literal.clone();
         self.next_token();
         let right = self.parse_expression(Precedence::Prefix)?;
 
-        let expression = Expression::Prefix {
-            op,
-            right: Box::new(right),
-        };
-        Ok(expression)
+        Ok(self.expr(Expression::Prefix { op, right }))
     }
 
-    fn parse_expression(&mut self, precedence: Precedence) -> Result<Expression> {
+    // Every recursive path of the parser runs through here, so this is where
+    // the nesting depth is tracked.
+    fn parse_expression(&mut self, precedence: Precedence) -> Result<ExprId> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(MonkeyError::NestingTooDeep(MAX_NESTING_DEPTH).into());
+        }
+        self.depth += 1;
+        let expr = self.parse_nested_expression(precedence);
+        self.depth -= 1;
+        expr
+    }
+
+    fn parse_nested_expression(&mut self, precedence: Precedence) -> Result<ExprId> {
         let mut left_expr = match &self.cur_token.kind {
+            // `try` is only a keyword before a block, so that the `try`
+            // builtin can still be called.
+            TokenKind::Ident
+                if self.cur_token.literal == "try" && self.peek_token_is(TokenKind::Lbrace) =>
+            {
+                self.parse_try_expression()
+            }
             TokenKind::Ident => self.parse_prefix(),
             TokenKind::Int => self.parse_int(),
+            TokenKind::Float => self.parse_float(),
+            TokenKind::String => self.parse_string(),
             TokenKind::True | TokenKind::False => self.parse_boolean(),
             TokenKind::Bang | TokenKind::Minus => self.parse_prefix_expression(),
             TokenKind::Lparen => self.parse_group_expression(),
             TokenKind::If => self.parse_if_expression(),
+            TokenKind::Loop => self.parse_loop_expression(),
+            TokenKind::While => self.parse_while_expression(),
+            TokenKind::Import => self.parse_import_expression(),
             TokenKind::Function => self.parse_function_literal(),
-            _ => return Err(MonkeyError::InvalidToken(self.cur_token.clone()).into()),
+            TokenKind::Macro => self.parse_macro_literal(),
+            TokenKind::Lbracket => self.parse_array_literal(),
+            TokenKind::Lbrace => self.parse_brace_expression(),
+            _ => {
+                return Err(
+                    MonkeyError::InvalidToken(self.cur_token.clone(), self.cur_position).into(),
+                )
+            }
         }?;
 
         while !self.peek_token_is(TokenKind::Semicolon) && precedence < self.peek_precedence() {
@@ -132,6 +433,7 @@ impl<'a> Parser<'a> {
                 | TokenKind::Minus
                 | TokenKind::Slash
                 | TokenKind::Aster
+                | TokenKind::Percent
                 | TokenKind::Equal
                 | TokenKind::NotEqual
                 | TokenKind::LessThan
@@ -143,6 +445,10 @@ impl<'a> Parser<'a> {
                     self.next_token();
                     self.parse_call_expression(left_expr)?
                 }
+                TokenKind::Lbracket => {
+                    self.next_token();
+                    self.parse_index_expression(left_expr)?
+                }
                 _ => left_expr,
             };
         }
@@ -150,65 +456,226 @@ impl<'a> Parser<'a> {
         Ok(left_expr)
     }
 
-    fn parse_call_expression(&mut self, function: Expression) -> Result<Expression> {
-        let arguments = self.parse_call_arguments()?;
-        let expr = Expression::Call {
-            function: Box::new(function),
+    fn parse_call_expression(&mut self, function: ExprId) -> Result<ExprId> {
+        let arguments = self.parse_expression_list(TokenKind::Rparen)?;
+        Ok(self.expr(Expression::Call {
+            function,
             arguments,
+        }))
+    }
+
+    // `left[index]`, or a slice `left[start..end]` when there is a `..`.
+    fn parse_index_expression(&mut self, left: ExprId) -> Result<ExprId> {
+        self.next_token();
+        let start = if self.cur_token_is(TokenKind::DotDot) {
+            None
+        } else {
+            let index = self.parse_expression(Precedence::Lowest)?;
+            if !self.peek_token_is(TokenKind::DotDot) {
+                if !self.expect_peek(TokenKind::Rbracket) {
+                    return Err(MonkeyError::UnexpectedToken(
+                        TokenKind::Rbracket,
+                        self.peek_token.clone(),
+                        self.peek_position,
+                    )
+                    .into());
+                }
+                return Ok(self.expr(Expression::Index { left, index }));
+            }
+            self.next_token();
+            Some(index)
         };
 
-        Ok(expr)
+        let end = if self.peek_token_is(TokenKind::Rbracket) {
+            None
+        } else {
+            self.next_token();
+            Some(self.parse_expression(Precedence::Lowest)?)
+        };
+        if !self.expect_peek(TokenKind::Rbracket) {
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Rbracket,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
+        }
+
+        Ok(self.expr(Expression::Slice {
+            target: left,
+            start,
+            end,
+        }))
     }
 
-    fn parse_call_arguments(&mut self) -> Result<Vec<Expression>> {
-        let mut args = vec![];
+    fn parse_array_literal(&mut self) -> Result<ExprId> {
+        let elements = self.parse_expression_list(TokenKind::Rbracket)?;
+        Ok(self.expr(Expression::Array(elements)))
+    }
+
+    // `{` starts either a hash literal or a block expression. A block is told
+    // apart by its first statement: a `let`, a `return` or another statement
+    // keyword, or an expression that is not followed by the `:` of a hash
+    // pair. `{}` stays an empty hash.
+    fn parse_brace_expression(&mut self) -> Result<ExprId> {
+        match self.peek_token.kind {
+            TokenKind::Rbrace => return self.parse_hash_literal(None),
+            TokenKind::Let
+            | TokenKind::Const
+            | TokenKind::Return
+            | TokenKind::Import
+            | TokenKind::Break
+            | TokenKind::Continue => {
+                let block = self.parse_block_statement()?;
+                return Ok(self.expr(Expression::Block(block)));
+            }
+            _ => (),
+        }
+
+        self.next_token();
+        let first = self.parse_expression(Precedence::Lowest)?;
+        if self.peek_token_is(TokenKind::Colon) {
+            return self.parse_hash_literal(Some(first));
+        }
+
+        if self.peek_token_is(TokenKind::Semicolon) {
+            self.next_token();
+        }
+        self.next_token();
+        let first = self.stmt(Statement::Expression(first));
+        let mut block = self.parse_block_statement_rest()?;
+        block.statements.insert(0, first);
+        Ok(self.expr(Expression::Block(block)))
+    }
+
+    // Continues a hash literal whose first key, if any, was already parsed and
+    // is the current token.
+    fn parse_hash_literal(&mut self, mut first: Option<ExprId>) -> Result<ExprId> {
+        let mut pairs = vec![];
+
+        loop {
+            let key = match first.take() {
+                Some(key) => key,
+                None if self.peek_token_is(TokenKind::Rbrace) => break,
+                None => {
+                    self.next_token();
+                    self.parse_expression(Precedence::Lowest)?
+                }
+            };
+
+            if !self.expect_peek(TokenKind::Colon) {
+                return Err(MonkeyError::UnexpectedToken(
+                    TokenKind::Colon,
+                    self.peek_token.clone(),
+                    self.peek_position,
+                )
+                .into());
+            }
 
-        if self.peek_token_is(TokenKind::Rparen) {
             self.next_token();
-            return Ok(args);
+            let value = self.parse_expression(Precedence::Lowest)?;
+            pairs.push((key, value));
+
+            if !self.peek_token_is(TokenKind::Rbrace) && !self.expect_peek(TokenKind::Comma) {
+                return Err(MonkeyError::UnexpectedToken(
+                    TokenKind::Comma,
+                    self.peek_token.clone(),
+                    self.peek_position,
+                )
+                .into());
+            }
+        }
+
+        if !self.expect_peek(TokenKind::Rbrace) {
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Rbrace,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
+        }
+
+        Ok(self.expr(Expression::Hash(pairs)))
+    }
+
+    fn parse_expression_list(&mut self, end: TokenKind) -> Result<Vec<ExprId>> {
+        let mut list = vec![];
+
+        if self.peek_token_is(end.clone()) {
+            self.next_token();
+            return Ok(list);
         }
 
         self.next_token();
-        args.push(self.parse_expression(Precedence::Lowest)?);
+        list.push(self.parse_element()?);
 
         while self.peek_token_is(TokenKind::Comma) {
             self.next_token();
             self.next_token();
-            args.push(self.parse_expression(Precedence::Lowest)?);
+            list.push(self.parse_element()?);
         }
 
-        if !self.expect_peek(TokenKind::Rparen) {
-            return Err(
-                MonkeyError::UnexpectedToken(TokenKind::Rparen, self.cur_token.clone()).into(),
-            );
+        if !self.expect_peek(end.clone()) {
+            return Err(MonkeyError::UnexpectedToken(
+                end,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
         }
 
-        Ok(args)
+        Ok(list)
     }
 
-    fn parse_function_literal(&mut self) -> Result<Expression> {
+    // An element of an array literal or an argument of a call, which may be
+    // spread with `...`.
+    fn parse_element(&mut self) -> Result<ExprId> {
+        if !self.cur_token_is(TokenKind::Ellipsis) {
+            return self.parse_expression(Precedence::Lowest);
+        }
+        self.next_token();
+        let array = self.parse_expression(Precedence::Lowest)?;
+        Ok(self.expr(Expression::Spread(array)))
+    }
+
+    fn parse_function_literal(&mut self) -> Result<ExprId> {
+        let (parameters, body) = self.parse_function_parts()?;
+        Ok(self.expr(Expression::Function { parameters, body }))
+    }
+
+    fn parse_macro_literal(&mut self) -> Result<ExprId> {
+        let (parameters, body) = self.parse_function_parts()?;
+        Ok(self.expr(Expression::Macro { parameters, body }))
+    }
+
+    // The parameters and body that follow `fn` or `macro`.
+    fn parse_function_parts(&mut self) -> Result<(Vec<Symbol>, BlockStatement)> {
         if !self.expect_peek(TokenKind::Lparen) {
-            return Err(
-                MonkeyError::UnexpectedToken(TokenKind::Lparen, self.cur_token.clone()).into(),
-            );
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Lparen,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
         }
 
         let parameters = self.parse_function_parameters()?;
 
         if !self.expect_peek(TokenKind::Lbrace) {
-            return Err(
-                MonkeyError::UnexpectedToken(TokenKind::Lbrace, self.cur_token.clone()).into(),
-            );
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Lbrace,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
         }
 
         let body = self.parse_block_statement()?;
 
-        let func = Expression::Function { parameters, body };
-
-        Ok(func)
+        Ok((parameters, body))
     }
 
-    fn parse_function_parameters(&mut self) -> Result<Vec<String>> {
+    fn parse_function_parameters(&mut self) -> Result<Vec<Symbol>> {
         let mut idents = vec![];
 
         if self.peek_token_is(TokenKind::Rparen) {
@@ -218,45 +685,67 @@ impl<'a> Parser<'a> {
 
         self.next_token();
 
-        let ident = self.cur_token.literal.clone();
-        idents.push(ident);
+        idents.push(self.parse_parameter()?);
 
         while self.peek_token_is(TokenKind::Comma) {
             self.next_token();
             self.next_token();
-            let ident = self.cur_token.literal.clone();
-            idents.push(ident);
+            idents.push(self.parse_parameter()?);
         }
 
         if !self.expect_peek(TokenKind::Rparen) {
-            return Err(
-                MonkeyError::UnexpectedToken(TokenKind::Rparen, self.cur_token.clone()).into(),
-            );
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Rparen,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
         }
 
         Ok(idents)
     }
 
-    fn parse_if_expression(&mut self) -> Result<Expression> {
+    fn parse_parameter(&mut self) -> Result<Symbol> {
+        match self.cur_symbol {
+            Some(name) => Ok(name),
+            None => Err(MonkeyError::UnexpectedToken(
+                TokenKind::Ident,
+                self.cur_token.clone(),
+                self.cur_position,
+            )
+            .into()),
+        }
+    }
+
+    fn parse_if_expression(&mut self) -> Result<ExprId> {
         if !self.expect_peek(TokenKind::Lparen) {
-            return Err(
-                MonkeyError::UnexpectedToken(TokenKind::Lparen, self.peek_token.clone()).into(),
-            );
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Lparen,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
         }
 
         self.next_token();
         let condition = self.parse_expression(Precedence::Lowest)?;
 
         if !self.expect_peek(TokenKind::Rparen) {
-            return Err(
-                MonkeyError::UnexpectedToken(TokenKind::Rparen, self.peek_token.clone()).into(),
-            );
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Rparen,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
         }
 
         if !self.expect_peek(TokenKind::Lbrace) {
-            return Err(
-                MonkeyError::UnexpectedToken(TokenKind::Lbrace, self.peek_token.clone()).into(),
-            );
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Lbrace,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
         }
 
         let consequence = self.parse_block_statement()?;
@@ -269,6 +758,7 @@ impl<'a> Parser<'a> {
                 return Err(MonkeyError::UnexpectedToken(
                     TokenKind::Lbrace,
                     self.peek_token.clone(),
+                    self.peek_position,
                 )
                 .into());
             }
@@ -276,17 +766,97 @@ impl<'a> Parser<'a> {
             alternative = self.parse_block_statement()?.into();
         }
 
-        let expr = Expression::If {
-            condition: Box::new(condition),
+        Ok(self.expr(Expression::If {
+            condition,
             consequence,
             alternative,
-        };
+        }))
+    }
 
-        Ok(expr)
+    fn parse_loop_expression(&mut self) -> Result<ExprId> {
+        if !self.expect_peek(TokenKind::Lbrace) {
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Lbrace,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
+        }
+
+        let body = self.parse_block_statement()?;
+        Ok(self.expr(Expression::Loop(body)))
+    }
+
+    fn parse_while_expression(&mut self) -> Result<ExprId> {
+        if !self.expect_peek(TokenKind::Lparen) {
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Lparen,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
+        }
+
+        self.next_token();
+        let condition = self.parse_expression(Precedence::Lowest)?;
+
+        for kind in [TokenKind::Rparen, TokenKind::Lbrace] {
+            if !self.expect_peek(kind.clone()) {
+                return Err(MonkeyError::UnexpectedToken(
+                    kind,
+                    self.peek_token.clone(),
+                    self.peek_position,
+                )
+                .into());
+            }
+        }
+
+        let body = self.parse_block_statement()?;
+        Ok(self.expr(Expression::While { condition, body }))
+    }
+
+    fn parse_try_expression(&mut self) -> Result<ExprId> {
+        self.next_token();
+        let body = self.parse_block_statement()?;
+
+        for kind in [TokenKind::Catch, TokenKind::Lparen, TokenKind::Ident] {
+            if !self.expect_peek(kind.clone()) {
+                return Err(MonkeyError::UnexpectedToken(
+                    kind,
+                    self.peek_token.clone(),
+                    self.peek_position,
+                )
+                .into());
+            }
+        }
+        let error = self.cur_symbol();
+
+        for kind in [TokenKind::Rparen, TokenKind::Lbrace] {
+            if !self.expect_peek(kind.clone()) {
+                return Err(MonkeyError::UnexpectedToken(
+                    kind,
+                    self.peek_token.clone(),
+                    self.peek_position,
+                )
+                .into());
+            }
+        }
+        let handler = self.parse_block_statement()?;
+
+        Ok(self.expr(Expression::Try {
+            body,
+            error,
+            handler,
+        }))
     }
 
     fn parse_block_statement(&mut self) -> Result<BlockStatement> {
         self.next_token();
+        self.parse_block_statement_rest()
+    }
+
+    // Parses statements from the current token up to the closing `}`.
+    fn parse_block_statement_rest(&mut self) -> Result<BlockStatement> {
         let mut statements = vec![];
         while !self.cur_token_is(TokenKind::Rbrace) && !self.cur_token_is(TokenKind::Eof) {
             let stmt = self.parse_statement()?;
@@ -299,53 +869,71 @@ impl<'a> Parser<'a> {
         Ok(block)
     }
 
-    fn parse_infix_expression(&mut self, left: Expression) -> Result<Expression> {
+    fn parse_infix_expression(&mut self, left: ExprId) -> Result<ExprId> {
         let op = self.cur_token.literal.clone();
+        let span = Span::new(self.cur_position);
         let precedence = self.cur_precedence();
         self.next_token();
         let right = self.parse_expression(precedence)?;
-        let expr = Expression::Infix {
-            left: Box::new(left),
+        Ok(self.expr(Expression::Infix {
+            left,
             op,
-            right: Box::new(right),
-        };
-
-        Ok(expr)
+            right,
+            span,
+        }))
     }
 
-    fn parse_prefix(&mut self) -> Result<Expression> {
+    fn parse_prefix(&mut self) -> Result<ExprId> {
         match self.cur_token.kind {
             TokenKind::Ident => self.parse_identifier(),
             TokenKind::Int => self.parse_int(),
-            _ => panic!("parse_prefix()"),
+            _ => Err(MonkeyError::InvalidToken(self.cur_token.clone(), self.cur_position).into()),
         }
     }
 
-    fn parse_group_expression(&mut self) -> Result<Expression> {
+    fn parse_group_expression(&mut self) -> Result<ExprId> {
         self.next_token();
-        let expr = self.parse_expression(Precedence::Lowest);
+        let expr = self.parse_expression(Precedence::Lowest)?;
         if !self.expect_peek(TokenKind::Rparen) {
-            return Err(
-                MonkeyError::UnexpectedToken(TokenKind::Rparen, self.cur_token.clone()).into(),
-            );
+            return Err(MonkeyError::UnexpectedToken(
+                TokenKind::Rparen,
+                self.peek_token.clone(),
+                self.peek_position,
+            )
+            .into());
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn parse_identifier(&self) -> Result<Expression> {
-        Ok(Expression::Ident(self.cur_token.literal.clone()))
+    fn parse_identifier(&mut self) -> Result<ExprId> {
+        let name = self.cur_symbol();
+        let ident = Expression::Ident(name, Span::new(self.cur_position));
+        Ok(self.expr(ident))
     }
 
-    fn parse_int(&self) -> Result<Expression> {
+    fn parse_int(&mut self) -> Result<ExprId> {
         match self.cur_token.literal.clone().parse::<i64>() {
-            Ok(num) => Ok(Expression::Int(num)),
-            Err(err) => Err(err.into()),
+            Ok(num) => Ok(self.expr(Expression::Int(num))),
+            Err(_) => Err(MonkeyError::InvalidInteger(self.cur_token.literal.clone()).into()),
+        }
+    }
+
+    fn parse_float(&mut self) -> Result<ExprId> {
+        match self.cur_token.literal.parse::<f64>() {
+            Ok(num) => Ok(self.expr(Expression::Float(num))),
+            Err(_) => Err(MonkeyError::InvalidFloat(self.cur_token.literal.clone()).into()),
         }
     }
 
-    fn parse_boolean(&self) -> Result<Expression> {
-        Ok(Expression::Boolean(self.cur_token_is(TokenKind::True)))
+    fn parse_string(&mut self) -> Result<ExprId> {
+        let string = Expression::String(self.cur_token.literal.clone());
+        Ok(self.expr(string))
+    }
+
+    fn parse_boolean(&mut self) -> Result<ExprId> {
+        let boolean = Expression::Boolean(self.cur_token_is(TokenKind::True));
+        Ok(self.expr(boolean))
     }
 
     fn cur_token_is(&self, tok: TokenKind) -> bool {
@@ -376,25 +964,74 @@ impl<'a> Parser<'a> {
 
 #[cfg(test)]
 mod tests {
+    use std::rc::Rc;
+
     use crate::{
-        ast::{Expression, Program, Statement},
+        ast::{programs_equivalent, Arena, Expression, Program, Span, Statement},
+        errors::MonkeyError,
+        interner::Symbol,
         lexer::Lexer,
-        parser::Parser,
+        parser::{Parser, MAX_NESTING_DEPTH},
     };
 
+    fn parse(input: &str) -> Program {
+        Parser::new(Lexer::new(input)).parse_program().unwrap()
+    }
+
+    // Parses both inputs and checks that they produce the same tree.
+    fn assert_parses_like(input: &str, expect: &str) {
+        let (actual, expect) = (parse(input), parse(expect));
+        assert!(
+            programs_equivalent(&actual, &expect),
+            "{}\n got: {:?}\nwant: {:?}",
+            input,
+            actual,
+            expect
+        );
+    }
+
     #[test]
     fn test_string() {
+        let mut arena = Arena::new();
+        let ident = arena.alloc_expr(Expression::Ident(Symbol::intern("myVar"), Span::default()));
+        let value = arena.alloc_expr(Expression::Ident(
+            Symbol::intern("anotherVar"),
+            Span::default(),
+        ));
+        let stmt = arena.alloc_stmt(Statement::Let { ident, value });
         let program = Program {
-            statements: vec![Statement::Let {
-                ident: Expression::Ident("myVar".to_string()),
-                value: Expression::Ident("anotherVar".to_string()),
-            }],
+            arena: Rc::new(arena),
+            statements: vec![stmt],
         };
 
-        let stmt = program.statements[0].to_string();
+        let stmt = program.arena.display(program.statements[0]).to_string();
         assert_eq!(stmt, "let myVar = anotherVar;");
     }
 
+    #[test]
+    fn test_destructuring_let() {
+        let program = parse("let [a, [b, c], []] = x;");
+        let stmt = program.arena.display(program.statements[0]).to_string();
+        assert_eq!(stmt, "let [a, [b, c], []] = x;");
+        match program.statement(0) {
+            Statement::Let { ident, .. } => {
+                assert!(
+                    matches!(&program.arena[*ident], Expression::Array(patterns) if patterns.len() == 3)
+                )
+            }
+            stmt => panic!(
+                "stmt is not Statement::Let. got={}",
+                program.arena.display(stmt)
+            ),
+        }
+
+        assert_parses_like(
+            "let {name, \"address\": [city], age} = person;",
+            "let {\"name\": name, \"address\": [city], \"age\": age} = person;",
+        );
+        assert_parses_like("let [{a}, {}] = x;", "let [{\"a\": a}, {}] = x;");
+    }
+
     #[test]
     fn test_if_expression() {
         let input = "if (x < y) { x } else { y }";
@@ -408,30 +1045,31 @@ mod tests {
             len
         );
 
-        let stmt = program.statements[0].clone();
+        let arena = &program.arena;
+        let stmt = program.statement(0);
 
         let expr = match stmt {
-            Statement::Expression(expr) => expr,
+            Statement::Expression(expr) => *expr,
             _ => panic!(
                 "program.statements[0] is not Statement::Expression. got={}",
-                stmt
+                arena.display(stmt)
             ),
         };
 
-        let (_, consequence, _) = match expr {
+        let consequence = match &arena[expr] {
             Expression::If {
                 condition,
                 consequence,
                 alternative,
             } => {
-                assert_eq!(condition.to_string(), "(x < y)");
-                assert_eq!(consequence.to_string(), "x");
-                if let Some(ref alternative) = alternative {
-                    assert_eq!(alternative.to_string(), "y");
+                assert_eq!(arena.display(*condition).to_string(), "(x < y)");
+                assert_eq!(arena.display(consequence).to_string(), "{ x }");
+                if let Some(alternative) = alternative {
+                    assert_eq!(arena.display(alternative).to_string(), "{ y }");
                 }
-                (*condition, consequence, alternative)
+                consequence
             }
-            _ => panic!("expr is not Expression::If. got={}", expr),
+            _ => panic!("expr is not Expression::If. got={}", arena.display(expr)),
         };
 
         assert_eq!(
@@ -442,6 +1080,28 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_const_statement() {
+        let program = parse("const PI = 3; { const E = 2 }");
+        assert_eq!(
+            program.arena.display(program.statements[0]).to_string(),
+            "const PI = 3;"
+        );
+        match program.statement(0) {
+            Statement::Const { ident, value } => {
+                assert!(
+                    matches!(&program.arena[*ident], Expression::Ident(name, _) if name == "PI")
+                );
+                assert_eq!(program.expr(*value), &Expression::Int(3));
+            }
+            stmt => panic!(
+                "stmt is not Statement::Const. got={}",
+                program.arena.display(stmt)
+            ),
+        }
+        assert_parses_like("{ const E = 2 }", "{ const E = 2; }");
+    }
+
     #[test]
     fn test_return_statement() {
         let input = r#"return 5;
@@ -452,10 +1112,10 @@ return 993322;"#;
         let mut parser = Parser::new(lexer);
         let actual = parser.parse_program().unwrap();
         assert_eq!(actual.statements.len(), 3);
-        let want = vec!["5", "10", "993322"];
-        for (stmt, w) in actual.statements.iter().zip(want) {
-            match stmt {
-                Statement::Return(value) => assert_eq!(value.to_string(), w),
+        let want = vec![5, 10, 993322];
+        for (&stmt, w) in actual.statements.iter().zip(want) {
+            match actual.stmt(stmt) {
+                Statement::Return(value) => assert_eq!(actual.expr(*value), &Expression::Int(w)),
                 _ => panic!(),
             }
         }
@@ -467,6 +1127,8 @@ return 993322;"#;
             ("-a * b", "((-a) * b)"),
             ("!-a", "(!(-a))"),
             ("a + b + c", "((a + b) + c)"),
+            ("a + b % c", "(a + (b % c))"),
+            ("-1.5 * 2.0", "((-1.5) * 2.0)"),
             ("a + b - c", "((a + b) - c)"),
             ("true", "true"),
             ("false", "false"),
@@ -486,24 +1148,330 @@ return 993322;"#;
                 "add(a + b + c * d / f + g)",
                 "add((((a + b) + ((c * d) / f)) + g))",
             ),
+            (
+                "a * [1, 2, 3, 4][b * c] * d",
+                "((a * ([1, 2, 3, 4][(b * c)])) * d)",
+            ),
+            (
+                "add(a * b[2], b[1], 2 * [1, 2][1])",
+                "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))",
+            ),
+            ("-a[-1]", "(-(a[(-1)]))"),
+            ("f(x)[0][1]", "((f(x)[0])[1])"),
         ];
 
         for (input, expect) in tests {
-            let lexer = Lexer::new(input);
-            let mut parser = Parser::new(lexer);
-            let actual = parser.parse_program().unwrap().statements[0].to_owned();
-            assert_eq!(actual.to_string(), expect);
+            assert_parses_like(input, expect);
         }
     }
 
     #[test]
-    fn test_function_expression() {
-        let input = "fn (x, y) { x + y }";
-        let test = "fn (x, y) { (x + y) }";
+    fn test_slice_expression() {
+        let tests = vec![
+            ("a[1..3]", "(a[1..3])"),
+            ("a[..n - 1]", "(a[..(n - 1)])"),
+            ("a[-2..]", "(a[(-2)..])"),
+            ("a[..]", "(a[..])"),
+            ("f(x)[1..][0]", "((f(x)[1..])[0])"),
+        ];
+
+        for (input, expect) in tests {
+            let program = parse(input);
+            let expr = match program.statement(0) {
+                Statement::Expression(expr) => *expr,
+                stmt => panic!(
+                    "stmt is not Statement::Expression. got={}",
+                    program.arena.display(stmt)
+                ),
+            };
+            assert_eq!(program.arena.display(expr).to_string(), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_spread() {
+        let tests = vec![
+            ("[1, ...rest, 4]", "[1, ...rest, 4]"),
+            ("f(...args)", "f(...args)"),
+            ("f(a, ...b + c)", "f(a, ...(b + c))"),
+            ("[...[...a]]", "[...[...a]]"),
+        ];
+
+        for (input, expect) in tests {
+            let program = parse(input);
+            let expr = match program.statement(0) {
+                Statement::Expression(expr) => *expr,
+                stmt => panic!(
+                    "stmt is not Statement::Expression. got={}",
+                    program.arena.display(stmt)
+                ),
+            };
+            assert_eq!(program.arena.display(expr).to_string(), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_array_literal() {
+        let input = "[1, 2 * 2, 3 + 3]";
         let lexer = Lexer::new(input);
         let mut parser = Parser::new(lexer);
         let program = parser.parse_program().unwrap();
-        let stmts = program.statements[0].clone();
-        assert_eq!(stmts.to_string(), test);
+        let arena = &program.arena;
+        match program.statement(0) {
+            Statement::Expression(expr) => match &arena[*expr] {
+                Expression::Array(elements) => {
+                    assert_eq!(elements.len(), 3);
+                    assert_eq!(arena[elements[0]], Expression::Int(1));
+                    assert_eq!(arena.display(elements[1]).to_string(), "(2 * 2)");
+                    assert_eq!(arena.display(elements[2]).to_string(), "(3 + 3)");
+                }
+                expr => panic!("expr is not Expression::Array. got={}", arena.display(expr)),
+            },
+            stmt => panic!(
+                "stmt is not Statement::Expression. got={}",
+                arena.display(stmt)
+            ),
+        }
+    }
+
+    #[test]
+    fn test_hash_literal() {
+        let tests = vec![
+            ("{}", "{}"),
+            (r#"{"one": 1, "two": 2}"#, r#"{"one": 1, "two": 2}"#),
+            (
+                r#"{"one": 0 + 1, true: 10 / 5}"#,
+                r#"{"one": (0 + 1), true: (10 / 5)}"#,
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let program = parse(input);
+            match program.statement(0) {
+                Statement::Expression(expr)
+                    if matches!(program.expr(*expr), Expression::Hash(_)) =>
+                {
+                    assert_parses_like(input, expect)
+                }
+                stmt => panic!(
+                    "stmt is not Expression::Hash. got={}",
+                    program.arena.display(stmt)
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_test_statement() {
+        let input = r#"test "adds" { let a = 1; assert(a + 1 == 2) }; test "empty" {}"#;
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        assert_eq!(program.statements.len(), 2);
+        let arena = &program.arena;
+        match program.statement(0) {
+            Statement::Test { name, body } => {
+                assert_eq!(name, "adds");
+                assert_eq!(
+                    arena.display(body).to_string(),
+                    "{ let a = 1; assert(((a + 1) == 2)) }"
+                );
+            }
+            stmt => panic!(
+                "statement is not Statement::Test. got={}",
+                arena.display(stmt)
+            ),
+        }
+        assert_eq!(
+            arena.display(program.statements[1]).to_string(),
+            r#"test "empty" {}"#
+        );
+
+        // Tests may not be nested.
+        let input = r#"let f = fn() { test "inner" { } };"#;
+        let err = Parser::new(Lexer::new(input)).parse_program().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<MonkeyError>(),
+            Some(MonkeyError::InvalidToken(..))
+        ));
+    }
+
+    #[test]
+    fn test_block_expression() {
+        let tests = vec![
+            ("{ let x = 1; x }", "{ let x = 1; x }"),
+            ("{ x; y }", "{ x; y }"),
+            ("1 + { 2 + 3 }", "(1 + { (2 + 3) })"),
+            ("{ { 1 } }", "{ { 1 } }"),
+        ];
+
+        for (input, expect) in tests {
+            assert_parses_like(input, expect);
+        }
+    }
+
+    #[test]
+    fn test_try_expression() {
+        let tests = vec![
+            (
+                "try { 1 / 0 } catch (e) { e }",
+                "try { (1 / 0) } catch (e) { e }",
+            ),
+            (
+                "let x = try { f() } catch (err) { 0 }; x",
+                "let x = try { f() } catch (err) { 0 };x",
+            ),
+            ("try(f, 1)", "try(f, 1)"),
+        ];
+
+        for (input, expect) in tests {
+            assert_parses_like(input, expect);
+        }
+    }
+
+    #[test]
+    fn test_loop_expression() {
+        let tests = vec![
+            ("loop { break }", "loop { break; }"),
+            (
+                "let x = loop { if (x) { continue; }; break x + 1 }; x",
+                "let x = loop { if (x) { continue; }; break (x + 1); };x",
+            ),
+            ("loop { }", "loop {}"),
+            ("loop { { continue } }", "loop { { continue; } }"),
+        ];
+
+        for (input, expect) in tests {
+            assert_parses_like(input, expect);
+        }
+    }
+
+    #[test]
+    fn test_while_expression() {
+        let tests = vec![
+            ("while (x < 3) { x }", "while ((x < 3)) { x }"),
+            (
+                "let last = while (true) { break 1 }; last",
+                "let last = while (true) { break 1; };last",
+            ),
+            ("while (f()) { }", "while (f()) {}"),
+        ];
+
+        for (input, expect) in tests {
+            assert_parses_like(input, expect);
+        }
+    }
+
+    #[test]
+    fn test_import() {
+        let program =
+            parse(r#"import "lib.monkey"; let lib = import("lib.monkey"); { import("a") }"#);
+        assert_eq!(
+            program.to_string(),
+            "import \"lib.monkey\";\nlet lib = import(\"lib.monkey\");\n{ import(\"a\") };\n"
+        );
+        assert!(matches!(program.statement(0), Statement::Import(path) if path == "lib.monkey"));
+    }
+
+    #[test]
+    fn test_function_expression() {
+        let program = parse("fn (x, y) { x + y }");
+        let arena = &program.arena;
+        let expr = match program.statement(0) {
+            Statement::Expression(expr) => *expr,
+            stmt => panic!(
+                "stmt is not Statement::Expression. got={}",
+                arena.display(stmt)
+            ),
+        };
+        match &arena[expr] {
+            Expression::Function { parameters, body } => {
+                assert_eq!(parameters, &["x", "y"]);
+                assert_eq!(body.statements.len(), 1);
+                assert_eq!(arena.display(body).to_string(), "{ (x + y) }");
+            }
+            expr => panic!(
+                "expr is not Expression::Function. got={}",
+                arena.display(expr)
+            ),
+        }
+    }
+
+    #[test]
+    fn test_macro_literal() {
+        let program = parse("macro(x, y) { x + y; }");
+        let arena = &program.arena;
+        let expr = match program.statement(0) {
+            Statement::Expression(expr) => *expr,
+            stmt => panic!(
+                "stmt is not Statement::Expression. got={}",
+                arena.display(stmt)
+            ),
+        };
+        match &arena[expr] {
+            Expression::Macro { parameters, body } => {
+                assert_eq!(parameters, &["x", "y"]);
+                assert_eq!(arena.display(body).to_string(), "{ (x + y) }");
+            }
+            expr => panic!("expr is not Expression::Macro. got={}", arena.display(expr)),
+        }
+    }
+
+    #[test]
+    fn test_nesting_depth() {
+        for (open, close) in [("(", ")"), ("[", "]"), ("-", ""), ("fn() {", "}")] {
+            let within = format!(
+                "{}1{}",
+                open.repeat(MAX_NESTING_DEPTH - 1),
+                close.repeat(MAX_NESTING_DEPTH - 1)
+            );
+            let mut parser = Parser::new(Lexer::new(&within));
+            assert!(parser.parse_program().is_ok(), "{}", open);
+
+            let beyond = format!("{}1{}", open.repeat(100_000), close.repeat(100_000));
+            let mut parser = Parser::new(Lexer::new(&beyond));
+            let err = parser.parse_program().unwrap_err();
+            assert!(
+                matches!(
+                    err.downcast_ref::<MonkeyError>(),
+                    Some(MonkeyError::NestingTooDeep(MAX_NESTING_DEPTH))
+                ),
+                "{}: {}",
+                open,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_position() {
+        let tests = vec![
+            ("let = 5;", 4),
+            ("let x 5;", 6),
+            ("1 + );", 4),
+            ("if (x) { 1 } else 2", 18),
+            ("let x = 1 +", 11),
+            ("test adds { }", 5),
+            ("fn(x, 1) { x }", 6),
+            (r#"test "adds" 1"#, 12),
+            ("try { 1 } (e) { 2 }", 10),
+            ("let [a, 1] = x;", 8),
+            ("let {a: b} = x;", 6),
+            ("import(lib)", 7),
+            ("const [a] = x;", 6),
+            ("a[1..2..3]", 6),
+            ("let a = ...b;", 8),
+            ("let {\"a\"} = x;", 8),
+            ("let [a b] = x;", 7),
+            ("try { 1 } catch e { 2 }", 16),
+            ("loop 1", 5),
+            ("while x { 1 }", 6),
+            ("while (x) 1", 10),
+        ];
+
+        for (input, expect) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let err = parser.parse_program().unwrap_err();
+            let position = err.downcast_ref::<MonkeyError>().unwrap().position();
+            assert_eq!(position, Some(expect), "{}", input);
+        }
     }
 }
@@ -0,0 +1,350 @@
+//! Measures how many times each function is called and how long its calls
+//! take, started by the `profile_start` builtin or the `--profile` flag.
+//!
+//! Functions are known by the name of the `let` binding they were first bound
+//! to, builtins and host functions by their own name. Functions that were
+//! never bound to a name are reported together as `<anonymous>`.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::object::{HashPairs, Object};
+
+/// The time elapsed since some fixed point, read when a call starts and ends.
+pub type Clock = Rc<dyn Fn() -> Duration>;
+
+/// What was measured for one function.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub calls: usize,
+    /// Time from entering the function to returning from it. A recursive call
+    /// is not counted again on top of the call it is nested in.
+    pub total: Duration,
+    /// Time spent in the function itself, not in the functions it called.
+    pub self_time: Duration,
+}
+
+// A call in progress.
+struct Frame {
+    name: String,
+    start: Duration,
+    // Time spent in calls made by this one.
+    children: Duration,
+}
+
+pub struct Profiler {
+    enabled: bool,
+    epoch: Instant,
+    clock: Option<Clock>,
+    functions: HashMap<String, FunctionProfile>,
+    stack: Vec<Frame>,
+    // How many calls of each function are on the stack.
+    active: HashMap<String, usize>,
+}
+
+impl Profiler {
+    /// A profiler that is not measuring anything until `start` is called.
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            epoch: Instant::now(),
+            clock: None,
+            functions: HashMap::new(),
+            stack: vec![],
+            active: HashMap::new(),
+        }
+    }
+
+    /// Replaces the wall clock, so that tests can control the time.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = Some(clock);
+    }
+
+    /// Starts measuring, discarding what was measured so far. Calls already in
+    /// progress are not measured.
+    pub fn start(&mut self) {
+        self.enabled = true;
+        self.functions.clear();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// What was measured for each function, the slowest first.
+    pub fn report(&self) -> Vec<(String, FunctionProfile)> {
+        let mut report = self
+            .functions
+            .iter()
+            .map(|(name, profile)| (name.clone(), *profile))
+            .collect::<Vec<_>>();
+        report.sort_by(|(a_name, a), (b_name, b)| {
+            b.total.cmp(&a.total).then_with(|| a_name.cmp(b_name))
+        });
+        report
+    }
+
+    /// The report as a hash from each function name to a hash of `calls`,
+    /// `total_us` and `self_us`, with times in microseconds.
+    pub fn report_object(&self) -> Object {
+        let mut hash = HashPairs::default();
+        for (name, profile) in self.report() {
+            let mut entry = HashPairs::default();
+            let calls = i64::try_from(profile.calls).unwrap_or(i64::MAX);
+            entry.insert(key("calls"), Object::Int(calls));
+            entry.insert(key("total_us"), micros(profile.total));
+            entry.insert(key("self_us"), micros(profile.self_time));
+            hash.insert(Object::String(name.into()), Object::Hash(entry));
+        }
+        Object::Hash(hash)
+    }
+
+    /// Records the start of a call of `func`, returning whether it is being
+    /// measured. Each measured call must be ended with `exit`.
+    pub(crate) fn enter(&mut self, func: &Object) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let name = match func {
+            Object::Function {
+                name: Some(name), ..
+            } => name.to_string(),
+            Object::Function { name: None, .. } => "<anonymous>".to_string(),
+            Object::Builtin(builtin) => builtin.name.to_string(),
+            Object::Host(host) => host.name.clone(),
+            // These call another function, which is measured instead.
+            _ => return false,
+        };
+        self.functions.entry(name.clone()).or_default().calls += 1;
+        *self.active.entry(name.clone()).or_default() += 1;
+        let start = self.now();
+        self.stack.push(Frame {
+            name,
+            start,
+            children: Duration::ZERO,
+        });
+        true
+    }
+
+    /// Counts a tail call of the function being measured, which reuses its
+    /// frame instead of entering a new one.
+    pub(crate) fn tail_call(&mut self) {
+        if let Some(frame) = self.stack.last() {
+            self.functions.entry(frame.name.clone()).or_default().calls += 1;
+        }
+    }
+
+    /// Records the end of the call last entered.
+    pub(crate) fn exit(&mut self) {
+        let frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+        let elapsed = self.now().saturating_sub(frame.start);
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+        }
+        let active = self.active.get_mut(&frame.name).map_or(0, |active| {
+            *active -= 1;
+            *active
+        });
+        let profile = self.functions.entry(frame.name).or_default();
+        profile.self_time += elapsed.saturating_sub(frame.children);
+        if active == 0 {
+            profile.total += elapsed;
+        }
+    }
+
+    fn now(&self) -> Duration {
+        match &self.clock {
+            Some(clock) => clock(),
+            None => self.epoch.elapsed(),
+        }
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A table of the report with one line per function, the slowest first.
+impl fmt::Display for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let report = self.report();
+        let width = report
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .chain(std::iter::once("function".len()))
+            .max()
+            .unwrap_or_default();
+        writeln!(
+            f,
+            "{:<width$}  {:>10}  {:>14}  {:>14}",
+            "function",
+            "calls",
+            "total (μs)",
+            "self (μs)",
+            width = width
+        )?;
+        for (name, profile) in report {
+            writeln!(
+                f,
+                "{:<width$}  {:>10}  {:>14}  {:>14}",
+                name,
+                profile.calls,
+                profile.total.as_micros(),
+                profile.self_time.as_micros(),
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Profiler")
+            .field("enabled", &self.enabled)
+            .field("functions", &self.functions)
+            .finish()
+    }
+}
+
+fn key(name: &str) -> Object {
+    Object::String(name.into())
+}
+
+fn micros(duration: Duration) -> Object {
+    Object::Int(i64::try_from(duration.as_micros()).unwrap_or(i64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use crate::interpreter::Interpreter;
+    use crate::object::Object;
+    use crate::profiler::FunctionProfile;
+
+    // An interpreter whose profiler only sees time pass when the script calls
+    // `tick(ms)`.
+    fn interpreter() -> Interpreter {
+        let now = Rc::new(Cell::new(Duration::ZERO));
+        let mut interpreter = Interpreter::new();
+        let clock = now.clone();
+        interpreter
+            .profiler()
+            .borrow_mut()
+            .set_clock(Rc::new(move || clock.get()));
+        interpreter.register("tick", move |args| {
+            if let [Object::Int(ms)] = args {
+                now.set(now.get() + Duration::from_millis(*ms as u64));
+            }
+            Ok(Object::Null)
+        });
+        interpreter
+    }
+
+    fn profile(calls: usize, total: u64, self_time: u64) -> FunctionProfile {
+        FunctionProfile {
+            calls,
+            total: Duration::from_millis(total),
+            self_time: Duration::from_millis(self_time),
+        }
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        let mut interpreter = interpreter();
+        let source = "
+            let inner = fn() { tick(5) };
+            let outer = fn() { tick(1); inner(); inner(); tick(2) };
+            profile_start();
+            outer();
+            fn() { tick(3) }();
+        ";
+        interpreter.eval(source).unwrap();
+        let profiler = interpreter.profiler();
+        let report = profiler.borrow().report();
+        assert_eq!(
+            report,
+            vec![
+                ("tick".to_string(), profile(5, 16, 16)),
+                ("outer".to_string(), profile(1, 13, 0)),
+                ("inner".to_string(), profile(2, 10, 0)),
+                ("<anonymous>".to_string(), profile(1, 3, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recursion() {
+        let mut interpreter = interpreter();
+        let source = "
+            let f = fn(n) { if (n == 0) { tick(1); 0 } else { tick(1); 1 + f(n - 1) } };
+            let g = fn(n) { if (n == 0) { 0 } else { tick(1); g(n - 1) } };
+            profile_start();
+            f(3);
+            g(2);
+        ";
+        interpreter.eval(source).unwrap();
+        let profiler = interpreter.profiler();
+        let report = profiler.borrow().report();
+        // `g` calls itself in tail position, reusing its frame.
+        assert_eq!(
+            report,
+            vec![
+                ("tick".to_string(), profile(6, 6, 6)),
+                ("f".to_string(), profile(4, 4, 0)),
+                ("g".to_string(), profile(3, 2, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_builtin() {
+        let mut interpreter = interpreter();
+        assert_eq!(
+            interpreter
+                .eval("profile_report()")
+                .unwrap_err()
+                .to_string(),
+            "profiling is not enabled; call `profile_start()` first"
+        );
+        let report = interpreter
+            .eval("let f = fn() { tick(2) }; profile_start(); f(); profile_report()[\"f\"]")
+            .unwrap();
+        assert_eq!(report.to_string(), "{calls: 1, total_us: 2000, self_us: 0}");
+    }
+
+    #[test]
+    fn test_display() {
+        let mut interpreter = interpreter();
+        interpreter
+            .eval("let long_name = fn() { tick(1) }; profile_start(); long_name();")
+            .unwrap();
+        let profiler = interpreter.profiler();
+        let expect = "\
+function        calls      total (μs)       self (μs)
+long_name           1            1000               0
+tick                1            1000            1000
+";
+        assert_eq!(profiler.borrow().to_string(), expect);
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        let mut interpreter = interpreter();
+        interpreter.eval("let f = fn() { 1 }; f()").unwrap();
+        let profiler: Rc<RefCell<_>> = interpreter.profiler();
+        assert!(!profiler.borrow().is_enabled());
+        assert!(profiler.borrow().report().is_empty());
+    }
+}
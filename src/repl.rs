@@ -0,0 +1,1047 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::ast::{Program, Span};
+use crate::compiler::Compiler;
+use crate::environment::Environment;
+use crate::errors::MonkeyError;
+use crate::evaluator::{Evaluator, Output};
+use crate::interpreter::{parse_source, Interpreter};
+use crate::line_profile::LineProfile;
+use crate::object::Object;
+use crate::optimizer::fold_constants;
+use crate::vm::VM;
+
+/// Which implementation `run_repl` evaluates lines with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// The tree-walking `Evaluator`.
+    Eval,
+    /// The bytecode `Compiler` and `VM`, which support less of the language.
+    Vm,
+}
+
+impl FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eval" => Ok(Engine::Eval),
+            "vm" => Ok(Engine::Vm),
+            _ => Err(format!("unknown engine: {} (expected eval or vm)", s)),
+        }
+    }
+}
+
+/// Settings for `run_repl`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplConfig {
+    /// Printed before each line of input.
+    pub prompt: String,
+    /// Whether to prefix the prompt with the number of names bound, as in
+    /// `[2]>> `, and color it green, or red after a line that failed.
+    pub status: bool,
+    /// Whether to print a name and version line on startup.
+    pub banner: bool,
+    /// Whether the input is typed in at a terminal. When it is not, as when
+    /// a program is piped in, no banner or prompts are written and null
+    /// values are not printed, so that the output is the program's own.
+    pub interactive: bool,
+    /// Whether to print the value of each evaluated line. Errors are always
+    /// printed.
+    pub echo: bool,
+    /// Whether to run `fold_constants` over each line before evaluating it.
+    pub optimize: bool,
+    /// Whether to evaluate in strict mode; see `Evaluator::set_strict`. The
+    /// vm engine ignores it.
+    pub strict: bool,
+    /// The implementation each line is evaluated with.
+    pub engine: Engine,
+    /// Whether to profile function calls from the start and print the report
+    /// when the input ends. `run_scripts` also prints how many nodes of each
+    /// line of a script were evaluated, after running it. Only the eval
+    /// engine is profiled.
+    pub profile: bool,
+}
+
+impl Default for ReplConfig {
+    fn default() -> Self {
+        Self {
+            prompt: ">> ".to_string(),
+            status: false,
+            banner: true,
+            interactive: true,
+            echo: true,
+            optimize: false,
+            strict: false,
+            engine: Engine::Eval,
+            profile: false,
+        }
+    }
+}
+
+/// Where `run_repl_with` reads its input from, one line at a time.
+pub trait LineReader {
+    /// Reads the next line, including its line break, after showing `prompt`.
+    /// A reader that does not show prompts itself writes it to `writer`.
+    /// Returns `None` at the end of the input, and an empty string, without
+    /// a line break, when the line was cancelled.
+    fn read_line(&mut self, prompt: &str, writer: &mut dyn Write) -> io::Result<Option<String>>;
+}
+
+impl<L: LineReader + ?Sized> LineReader for &mut L {
+    fn read_line(&mut self, prompt: &str, writer: &mut dyn Write) -> io::Result<Option<String>> {
+        (**self).read_line(prompt, writer)
+    }
+}
+
+/// Reads lines from a `BufRead` as they are, writing prompts to the writer.
+pub struct PlainReader<R>(pub R);
+
+impl<R: BufRead> LineReader for PlainReader<R> {
+    fn read_line(&mut self, prompt: &str, writer: &mut dyn Write) -> io::Result<Option<String>> {
+        write!(writer, "{}", prompt)?;
+        writer.flush()?;
+        let mut line = String::new();
+        match self.0.read_line(&mut line)? {
+            0 => Ok(None),
+            _ => Ok(Some(line)),
+        }
+    }
+}
+
+/// Reads lines from the terminal with a line editor, which supports moving
+/// the cursor within the line and going back to the lines entered before.
+/// Ctrl-C cancels the line being typed, and Ctrl-D on an empty line ends the
+/// input.
+#[cfg(feature = "readline")]
+pub struct Editor {
+    editor: rustyline::DefaultEditor,
+    history: Option<PathBuf>,
+}
+
+#[cfg(feature = "readline")]
+impl Editor {
+    /// An editor keeping the lines entered in the file at `history`, if
+    /// given, so that later sessions can go back to them too. The lines
+    /// already in the file are loaded now.
+    pub fn new(history: Option<PathBuf>) -> io::Result<Self> {
+        let mut editor = rustyline::DefaultEditor::new().map_err(readline_error)?;
+        if let Some(path) = &history {
+            match editor.load_history(path) {
+                Ok(()) => (),
+                Err(rustyline::error::ReadlineError::Io(err))
+                    if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(readline_error(err)),
+            }
+        }
+        Ok(Self { editor, history })
+    }
+
+    /// `.monkey_history` in the home directory, if there is one.
+    pub fn default_history() -> Option<PathBuf> {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".monkey_history"))
+    }
+
+    // Adds `line` to the history, and to the end of the history file.
+    fn remember(&mut self, line: &str) -> io::Result<()> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        self.editor
+            .add_history_entry(line)
+            .map_err(readline_error)?;
+        if let Some(path) = &self.history {
+            self.editor.append_history(path).map_err(readline_error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "readline")]
+impl LineReader for Editor {
+    fn read_line(&mut self, prompt: &str, _: &mut dyn Write) -> io::Result<Option<String>> {
+        use rustyline::error::ReadlineError;
+
+        match self.editor.readline(prompt) {
+            Ok(line) => {
+                // A history that cannot be saved is no reason to stop.
+                let _ = self.remember(&line);
+                Ok(Some(line + "\n"))
+            }
+            Err(ReadlineError::Interrupted) => Ok(Some(String::new())),
+            Err(ReadlineError::Eof) => Ok(None),
+            Err(err) => Err(readline_error(err)),
+        }
+    }
+}
+
+#[cfg(feature = "readline")]
+fn readline_error(err: rustyline::error::ReadlineError) -> io::Error {
+    match err {
+        rustyline::error::ReadlineError::Io(err) => err,
+        err => io::Error::other(err),
+    }
+}
+
+/// Reads lines from `reader` and evaluates them in a single interpreter until
+/// the input ends, writing prompts, results and errors to `writer`. A line
+/// ending in `\` continues on the next, after a `.. ` prompt.
+///
+/// Errors with a position in the line are preceded by the line with a caret
+/// under the offending token.
+///
+/// `:unset <name>` removes a binding instead of being evaluated, and
+/// `:bench <expr> <n>` times `n` evaluations of `expr` with `bench`. The vm
+/// engine supports neither.
+pub fn run_repl<R, W>(config: &ReplConfig, reader: R, writer: W) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    run_repl_with(config, PlainReader(reader), writer)
+}
+
+/// Like `run_repl`, reading lines from `lines`.
+pub fn run_repl_with<L, W>(config: &ReplConfig, mut lines: L, mut writer: W) -> io::Result<()>
+where
+    L: LineReader,
+    W: Write,
+{
+    let mut interpreter = interpreter(config);
+    let mut machine = Machine::default();
+    if config.banner && config.interactive {
+        writeln!(writer, "Monkey {}", env!("CARGO_PKG_VERSION"))?;
+    }
+    let mut failed = false;
+    'lines: loop {
+        let bindings = match config.engine {
+            Engine::Eval => interpreter.env().len(),
+            Engine::Vm => machine.globals.len(),
+        };
+        let prompt_line = prompt(config, bindings, failed, false);
+        let mut line = match lines.read_line(&prompt_line, &mut writer)? {
+            Some(line) => line,
+            None => {
+                if config.profile {
+                    if config.interactive {
+                        writeln!(writer)?;
+                    }
+                    write!(writer, "{}", interpreter.profiler().borrow())?;
+                }
+                return Ok(());
+            }
+        };
+        if line.is_empty() {
+            continue;
+        }
+        // The lexer skips a `\` before a line break, so the lines are
+        // evaluated as one.
+        while line.trim_end_matches(&['\r', '\n'][..]).ends_with('\\') {
+            let prompt_line = prompt(config, bindings, failed, true);
+            match lines.read_line(&prompt_line, &mut writer)? {
+                // Cancelling a continued line drops the lines before it too.
+                Some(next) if next.is_empty() => continue 'lines,
+                Some(next) => line += &next,
+                None => break,
+            }
+        }
+        if let Some(name) = line.trim().strip_prefix(":unset ") {
+            let name = name.trim();
+            if config.engine == Engine::Vm {
+                writeln!(writer, "`:unset` is not supported by the vm engine")?;
+                continue;
+            }
+            if interpreter.env_mut().remove(name).is_some() {
+                writeln!(writer, "unset {}", name)?;
+            } else if interpreter.env().contains(name) {
+                writeln!(writer, "cannot unset builtin: {}", name)?;
+            } else {
+                writeln!(writer, "identifier not found: {}", name)?;
+            }
+            continue;
+        }
+        if let Some(args) = line.trim().strip_prefix(":bench ") {
+            if config.engine == Engine::Vm {
+                writeln!(writer, "`:bench` is not supported by the vm engine")?;
+                continue;
+            }
+            match run_bench(args, interpreter.env()) {
+                Ok(stats) => writeln!(writer, "{}", stats)?,
+                Err(err) => writeln!(writer, "{}", err)?,
+            }
+            continue;
+        }
+        let result = match config.engine {
+            Engine::Eval => interpreter.eval(&line),
+            Engine::Vm => machine.run(&line, config.optimize),
+        };
+        failed = result.is_err();
+        match result {
+            Ok(obj) => {
+                if config.echo && (config.interactive || obj != Object::Null) {
+                    writeln!(writer, "{}", obj)?;
+                }
+            }
+            Err(e) => {
+                if let Some(position) = e.position() {
+                    // Underline the error in the line it is on.
+                    let position = position.min(line.len());
+                    let start = line[..position].rfind('\n').map_or(0, |i| i + 1);
+                    let text = line[start..].lines().next().unwrap_or_default();
+                    let col = line[start..position].chars().count();
+                    writeln!(writer, "{}", render_caret(text.trim_end_matches('\r'), col))?;
+                }
+                writeln!(writer, "{}", e)?;
+            }
+        }
+    }
+}
+
+/// A program for `run_scripts` to run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Script {
+    /// The file at the path, which its imports are resolved relative to.
+    File(PathBuf),
+    /// Source given on the command line with `-e`.
+    Source(String),
+}
+
+/// Evaluates `scripts` one after the other in a single interpreter, stopping
+/// at the first that fails. What they print goes to `output` and the error
+/// they fail with to `errors`, prefixed with the file name, or `-e`, and the
+/// line and column of a parse error. Their values are not printed.
+///
+/// Returns the exit code: 0 if every script ran, 1 if one could not be read
+/// or parsed, and 2 if one failed while running.
+pub fn run_scripts<W: Write>(
+    config: &ReplConfig,
+    scripts: &[Script],
+    output: Output,
+    mut errors: W,
+) -> io::Result<i32> {
+    let mut interpreter = interpreter(config);
+    interpreter.set_output(output);
+    let mut machine = Machine::default();
+    for script in scripts {
+        let (name, source, file) = match script {
+            Script::File(path) => match fs::read_to_string(path) {
+                Ok(source) => (path.display().to_string(), source, Some(path.clone())),
+                Err(err) => {
+                    writeln!(errors, "cannot read {}: {}", path.display(), err)?;
+                    return Ok(1);
+                }
+            },
+            Script::Source(source) => ("-e".to_string(), source.clone(), None),
+        };
+        interpreter.set_file(file);
+        let result = match config.engine {
+            Engine::Eval if config.profile => {
+                let profile = Rc::new(RefCell::new(LineProfile::new(&source)));
+                interpreter.set_hook(Some(LineProfile::hook(&profile)));
+                let result = interpreter.eval(&source);
+                interpreter.set_hook(None);
+                writeln!(errors, "{}:", name)?;
+                write!(errors, "{}", profile.borrow())?;
+                result
+            }
+            Engine::Eval => interpreter.eval(&source),
+            Engine::Vm => machine.run(&source, config.optimize),
+        };
+        match result {
+            Ok(_) => (),
+            Err(MonkeyError::Runtime(message)) => {
+                writeln!(errors, "{}: {}", name, message)?;
+                return Ok(2);
+            }
+            Err(err) => {
+                match err.position() {
+                    Some(position) => {
+                        let (line, col) = Span::new(position).line_col(&source);
+                        writeln!(errors, "{}: line {}, col {}: {}", name, line, col, err)?;
+                    }
+                    None => writeln!(errors, "{}: {}", name, err)?,
+                }
+                return Ok(1);
+            }
+        }
+    }
+    if config.profile {
+        write!(errors, "{}", interpreter.profiler().borrow())?;
+    }
+    Ok(0)
+}
+
+// An interpreter with the settings of `config`.
+fn interpreter(config: &ReplConfig) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_optimize(config.optimize);
+    interpreter.set_strict(config.strict);
+    if config.profile {
+        interpreter.profiler().borrow_mut().start();
+    }
+    interpreter
+}
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+// The prompt before a line of input, or `.. ` before a line that continues
+// the one before it.
+fn prompt(config: &ReplConfig, bindings: usize, failed: bool, continuation: bool) -> String {
+    if !config.interactive {
+        return String::new();
+    }
+    let prompt = if continuation { ".. " } else { &config.prompt };
+    if !config.status {
+        return prompt.to_string();
+    }
+    let count = if bindings > 0 {
+        format!("[{}]", bindings)
+    } else {
+        String::new()
+    };
+    if continuation {
+        return format!("{}{}", count, prompt);
+    }
+    let marker = prompt.trim_end();
+    let color = if failed { RED } else { GREEN };
+    format!(
+        "{}{}{}{}{}",
+        count,
+        color,
+        marker,
+        RESET,
+        &prompt[marker.len()..]
+    )
+}
+
+/// Evaluation times measured by `bench`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchStats {
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub median: Duration,
+}
+
+/// Shows each time in whole microseconds, e.g.
+/// `min: 5μs, max: 42μs, mean: 8μs, median: 7μs`.
+impl fmt::Display for BenchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "min: {}μs, max: {}μs, mean: {}μs, median: {}μs",
+            self.min.as_micros(),
+            self.max.as_micros(),
+            self.mean.as_micros(),
+            self.median.as_micros()
+        )
+    }
+}
+
+/// Evaluates `program` `iterations` times and returns how long it took. Each
+/// evaluation runs on a clone of `program` in a fresh environment enclosed by
+/// `env`, so bindings it makes do not leak into `env` or later evaluations.
+/// Output from `puts` is discarded.
+///
+/// Before the measured evaluations, 10% as many (at least 5) are run to warm
+/// up and left out of the statistics. Fails with the first runtime error.
+pub fn bench(
+    program: &Program,
+    iterations: usize,
+    env: &Environment,
+) -> Result<BenchStats, MonkeyError> {
+    assert!(iterations > 0, "cannot benchmark zero iterations");
+    let warm_up = (iterations / 10).max(5);
+    let mut times = Vec::with_capacity(iterations);
+    let source = program.arena.source();
+    for i in 0..warm_up + iterations {
+        let mut scope = Environment::new_enclosed(env);
+        let mut evaluator = Evaluator::new(&mut scope);
+        evaluator.set_output(Rc::new(RefCell::new(io::sink())));
+        let program = program.clone();
+        let start = Instant::now();
+        let result = evaluator.evaluate(program);
+        let elapsed = start.elapsed();
+        if let Object::Error(err) = result {
+            return Err(MonkeyError::Runtime(err.report(source)));
+        }
+        if i >= warm_up {
+            times.push(elapsed);
+        }
+    }
+
+    times.sort();
+    let n = times.len();
+    let median = if n % 2 == 0 {
+        (times[n / 2 - 1] + times[n / 2]) / 2
+    } else {
+        times[n / 2]
+    };
+    Ok(BenchStats {
+        min: times[0],
+        max: times[n - 1],
+        mean: times.iter().sum::<Duration>() / n as u32,
+        median,
+    })
+}
+
+// Parses the arguments of `:bench`: an expression followed by the number of
+// times to evaluate it.
+fn run_bench(args: &str, env: &Environment) -> Result<BenchStats, String> {
+    let usage = || "usage: :bench <expr> <n>".to_string();
+    let (source, iterations) = args
+        .trim()
+        .rsplit_once(char::is_whitespace)
+        .ok_or_else(usage)?;
+    let iterations = match iterations.parse::<usize>() {
+        Ok(0) => return Err("the number of iterations must be positive".to_string()),
+        Ok(iterations) => iterations,
+        Err(_) => return Err(usage()),
+    };
+    let program = parse_source(source).map_err(|err| err.to_string())?;
+    bench(&program, iterations, env).map_err(|err| err.to_string())
+}
+
+/// Renders `line` with a `^` beneath the character at `col`, counting
+/// characters from zero, the way rustc underlines errors. Tabs before the
+/// column are kept so that the caret lines up however wide they are shown.
+pub fn render_caret(line: &str, col: usize) -> String {
+    let mut underline = line
+        .chars()
+        .take(col)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    let past_end = col.saturating_sub(line.chars().count());
+    underline.push_str(&" ".repeat(past_end));
+    format!("{}\n{}^", line, underline)
+}
+
+// What the vm engine keeps between lines: the compiler's names and constants,
+// and the values of the globals.
+#[derive(Default)]
+struct Machine {
+    compiler: Compiler,
+    globals: Vec<Object>,
+}
+
+impl Machine {
+    fn run(&mut self, source: &str, optimize: bool) -> Result<Object, MonkeyError> {
+        let mut program = parse_source(source)?;
+        if optimize {
+            program = fold_constants(program);
+        }
+        self.compiler.compile(&program)?;
+        let globals = std::mem::take(&mut self.globals);
+        let mut vm = VM::with_globals(self.compiler.take_bytecode(), globals);
+        let result = vm.run();
+        self.globals = vm.into_globals();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::fs;
+    use std::io::{self, Cursor, Write};
+    use std::rc::Rc;
+
+    use crate::environment::Environment;
+    use crate::interpreter::parse_source;
+    #[cfg(feature = "readline")]
+    use crate::repl::Editor;
+    use crate::repl::{
+        bench, render_caret, run_repl, run_repl_with, run_scripts, Engine, LineReader, PlainReader,
+        ReplConfig, Script,
+    };
+
+    #[test]
+    fn test_run_repl() {
+        let config = ReplConfig {
+            prompt: "monkey> ".to_string(),
+            banner: false,
+            ..ReplConfig::default()
+        };
+        let input = "let x = 2;\nx * 3\n:unset x\nx\n";
+        let mut output = Vec::new();
+        run_repl(&config, input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "monkey> 2\nmonkey> 6\nmonkey> unset x\nmonkey> line 1, col 1: identifier not found: x\nmonkey> "
+        );
+    }
+
+    #[test]
+    fn test_run_repl_unset() {
+        let config = ReplConfig {
+            prompt: String::new(),
+            banner: false,
+            ..ReplConfig::default()
+        };
+        // builtins cannot be unset, and functions keep what they captured
+        let input = ":unset len\nlen([1])\nlet x = 2;\nlet f = fn() { x };\n:unset x\nf()\nx\n";
+        let mut output = Vec::new();
+        run_repl(&config, input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "cannot unset builtin: len\n1\n2\nfn () { x }\nunset x\n2\nline 1, col 1: identifier not found: x\n"
+        );
+    }
+
+    #[test]
+    fn test_plain_reader() {
+        let mut reader = PlainReader(Cursor::new("1 + 2\nlet x = 1;"));
+        let mut output = Vec::new();
+        let mut lines = vec![];
+        while let Some(line) = reader.read_line("> ", &mut output).unwrap() {
+            lines.push(line);
+        }
+        assert_eq!(lines, vec!["1 + 2\n", "let x = 1;"]);
+        assert_eq!(String::from_utf8(output).unwrap(), "> > > ");
+    }
+
+    // Gives out scripted lines, without their line breaks as a line editor
+    // does, and keeps the prompts instead of writing them. `^C` stands for a
+    // cancelled line.
+    struct Scripted {
+        lines: VecDeque<&'static str>,
+        prompts: Vec<String>,
+    }
+
+    impl LineReader for Scripted {
+        fn read_line(&mut self, prompt: &str, _: &mut dyn Write) -> io::Result<Option<String>> {
+            self.prompts.push(prompt.to_string());
+            Ok(self.lines.pop_front().map(|line| match line {
+                "^C" => String::new(),
+                line => line.to_string() + "\n",
+            }))
+        }
+    }
+
+    #[test]
+    fn test_run_repl_cancelled_lines() {
+        let config = ReplConfig {
+            banner: false,
+            ..ReplConfig::default()
+        };
+        let mut lines = Scripted {
+            lines: vec!["1", "^C", "let x = \\", "^C", "x", "2"].into(),
+            prompts: vec![],
+        };
+        let mut output = Vec::new();
+        run_repl_with(&config, &mut lines, &mut output).unwrap();
+        // nothing of the lines cancelled is evaluated
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "1\nline 1, col 1: identifier not found: x\n2\n"
+        );
+        assert_eq!(
+            lines.prompts,
+            vec![">> ", ">> ", ">> ", ".. ", ">> ", ">> ", ">> "]
+        );
+    }
+
+    #[cfg(feature = "readline")]
+    #[test]
+    fn test_editor_history() {
+        let dir = std::env::temp_dir().join(format!("monkey-history-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".monkey_history");
+        let _ = fs::remove_file(&path);
+
+        // the file is created with the first line
+        let mut editor = Editor::new(Some(path.clone())).unwrap();
+        editor.remember("let x = 1;").unwrap();
+        editor.remember("  ").unwrap();
+        editor.remember("x + 1").unwrap();
+        drop(editor);
+
+        let mut editor = Editor::new(Some(path.clone())).unwrap();
+        let history = editor.editor.history().iter().cloned().collect::<Vec<_>>();
+        assert_eq!(history, vec!["let x = 1;", "x + 1"]);
+        // lines are added to those of earlier sessions
+        editor.remember("x * 2").unwrap();
+        let editor = Editor::new(Some(path)).unwrap();
+        assert_eq!(editor.editor.history().iter().count(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_repl_with() {
+        let config = ReplConfig {
+            banner: false,
+            ..ReplConfig::default()
+        };
+        let mut lines = Scripted {
+            lines: vec!["let add = fn(a, b) { \\", "a + b };", "add(1, 2)", "-true"].into(),
+            prompts: vec![],
+        };
+        let mut output = Vec::new();
+        run_repl_with(&config, &mut lines, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "fn (a, b) { (a + b) }\n3\nunknown operator: -BOOLEAN\n"
+        );
+        assert_eq!(lines.prompts, vec![">> ", ".. ", ">> ", ">> ", ">> "]);
+    }
+
+    #[test]
+    fn test_run_repl_not_interactive() {
+        let config = ReplConfig {
+            interactive: false,
+            ..ReplConfig::default()
+        };
+        let input = "let x = 1; x\nif (x > 1) { x }\nlet f = fn(a) { \\\n  a * 2 };\nf(x)\n-true\n";
+        let mut output = Vec::new();
+        run_repl(&config, Cursor::new(input), &mut output).unwrap();
+        // no banner or prompts, and the null value of the `if` is left out
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "1\nfn (a) { (a * 2) }\n2\nunknown operator: -BOOLEAN\n"
+        );
+    }
+
+    #[test]
+    fn test_run_repl_status() {
+        let config = ReplConfig {
+            status: true,
+            banner: false,
+            ..ReplConfig::default()
+        };
+        let input = "1\nlet x = 1;\nlet y = \\\n  -true\nlet y = 2;\n";
+        let mut output = Vec::new();
+        run_repl(&config, input.as_bytes(), &mut output).unwrap();
+        let expect = [
+            "\x1b[32m>>\x1b[0m 1",
+            "\x1b[32m>>\x1b[0m 1",
+            "[1]\x1b[32m>>\x1b[0m [1].. unknown operator: -BOOLEAN",
+            "[1]\x1b[31m>>\x1b[0m 2",
+            "[2]\x1b[32m>>\x1b[0m ",
+        ];
+        assert_eq!(String::from_utf8(output).unwrap(), expect.join("\n"));
+    }
+
+    #[test]
+    fn test_run_repl_continuation() {
+        let config = ReplConfig {
+            banner: false,
+            ..ReplConfig::default()
+        };
+        let input = "let add = fn(a, b) { \\\n  a + b \\\n};\nadd(1, 2)\n1 + \\\n  )\n";
+        let mut output = Vec::new();
+        run_repl(&config, input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            ">> .. .. fn (a, b) { (a + b) }\n>> 3\n>> .. \
+             \x20 )\n  ^\ninvalid token \"Token { kind: Rparen, literal: \")\" }\"\n>> "
+        );
+    }
+
+    #[test]
+    fn test_run_repl_without_echo() {
+        let config = ReplConfig {
+            echo: false,
+            ..ReplConfig::default()
+        };
+        let mut output = Vec::new();
+        run_repl(&config, "1 + 1\n-true\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "Monkey {}\n>> >> unknown operator: -BOOLEAN\n>> ",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+
+    #[test]
+    fn test_run_repl_with_vm() {
+        let config = ReplConfig {
+            banner: false,
+            prompt: String::new(),
+            engine: Engine::Vm,
+            ..ReplConfig::default()
+        };
+        let input = "let x = 2;\n1 / (x - 2)\nlet f = fn() { x * 3 };\nf()\n\"a\"\n:unset x\n";
+        let mut output = Vec::new();
+        run_repl(&config, input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "2\ndivision by zero\ncompiled function\n6\nthe compiler does not support string literals\n`:unset` is not supported by the vm engine\n"
+        );
+    }
+
+    #[test]
+    fn test_bench() {
+        let program = parse_source(
+            "let count = fn(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } }; count(50)",
+        )
+        .unwrap();
+        let stats = bench(&program, 20, &Environment::with_builtins()).unwrap();
+        assert!(stats.min <= stats.median && stats.median <= stats.max);
+        assert!(stats.min <= stats.mean && stats.mean <= stats.max);
+        assert!(stats.max.as_secs() < 1, "{:?}", stats);
+
+        let program = parse_source("1 / 0").unwrap();
+        assert_eq!(
+            bench(&program, 1, &Environment::new())
+                .unwrap_err()
+                .to_string(),
+            "line 1, col 3: division by zero"
+        );
+    }
+
+    #[test]
+    fn test_run_repl_bench() {
+        let config = ReplConfig {
+            banner: false,
+            prompt: String::new(),
+            ..ReplConfig::default()
+        };
+        let input = "let x = 2;\n:bench let y = x * 3; y 10\ny\n:bench x\n:bench x 0\n:bench x +\n";
+        let mut output = Vec::new();
+        run_repl(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[0], "2");
+        assert!(lines[1].starts_with("min: "), "{}", lines[1]);
+        assert!(lines[1].ends_with("μs"), "{}", lines[1]);
+        // Bindings made while benchmarking do not leak.
+        assert_eq!(lines[2], "line 1, col 1: identifier not found: y");
+        assert_eq!(lines[3], "usage: :bench <expr> <n>");
+        assert_eq!(lines[4], "the number of iterations must be positive");
+        assert_eq!(lines[5], "usage: :bench <expr> <n>");
+    }
+
+    #[test]
+    fn test_run_repl_profile() {
+        let config = ReplConfig {
+            prompt: String::new(),
+            banner: false,
+            echo: false,
+            profile: true,
+            ..ReplConfig::default()
+        };
+        let input = "let f = fn(x) { x };\nf(1); f(2);\n";
+        let mut output = Vec::new();
+        run_repl(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3, "{}", output);
+        assert!(lines[1].starts_with("function"), "{}", output);
+        assert!(lines[2].starts_with("f  "), "{}", output);
+        assert_eq!(lines[2].split_whitespace().nth(1), Some("2"), "{}", output);
+    }
+
+    #[test]
+    fn test_parse_engine() {
+        assert_eq!("vm".parse(), Ok(Engine::Vm));
+        assert_eq!("eval".parse(), Ok(Engine::Eval));
+        assert_eq!(
+            "jit".parse::<Engine>(),
+            Err("unknown engine: jit (expected eval or vm)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_render_caret() {
+        let tests = vec![
+            ("let = 5;", 4, "let = 5;\n    ^"),
+            ("1 + )", 0, "1 + )\n^"),
+            ("let x = 1 +", 11, "let x = 1 +\n           ^"),
+            ("\t1 + )", 5, "\t1 + )\n\t    ^"),
+            ("\"é\" + )", 6, "\"é\" + )\n      ^"),
+        ];
+
+        for (line, col, expect) in tests {
+            assert_eq!(render_caret(line, col), expect, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn test_run_repl_underlines_errors() {
+        let config = ReplConfig {
+            banner: false,
+            prompt: String::new(),
+            ..ReplConfig::default()
+        };
+        let mut output = Vec::new();
+        run_repl(&config, "let = 5;\n".as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let mut lines = output.lines();
+        assert_eq!(lines.next(), Some("let = 5;"));
+        assert_eq!(lines.next(), Some("    ^"));
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("expected next token to be \"Ident\""));
+    }
+
+    // The exit code of `run_scripts`, and what it printed and the errors it
+    // reported.
+    fn run(config: &ReplConfig, scripts: &[Script]) -> (i32, String, String) {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut errors = Vec::new();
+        let code = run_scripts(config, scripts, output.clone(), &mut errors).unwrap();
+        let output = String::from_utf8(output.borrow().clone()).unwrap();
+        (code, output, String::from_utf8(errors).unwrap())
+    }
+
+    fn source(source: &str) -> Script {
+        Script::Source(source.to_string())
+    }
+
+    #[test]
+    fn test_run_scripts() {
+        let config = ReplConfig::default();
+        assert_eq!(
+            run(&config, &[source("puts(1 + 2)")]),
+            (0, "3\n".to_string(), String::new())
+        );
+        // the value of the last script is not printed
+        assert_eq!(
+            run(
+                &config,
+                &[source("let x = 2;"), source("puts(x * 3)"), source("x")]
+            ),
+            (0, "6\n".to_string(), String::new())
+        );
+        assert_eq!(run(&config, &[]), (0, String::new(), String::new()));
+    }
+
+    #[test]
+    fn test_run_scripts_errors() {
+        let config = ReplConfig::default();
+        // nothing after the script that fails runs
+        assert_eq!(
+            run(
+                &config,
+                &[
+                    source("puts(1)"),
+                    source("puts(2); 1 / 0"),
+                    source("puts(3)")
+                ]
+            ),
+            (
+                2,
+                "1\n2\n".to_string(),
+                "-e: line 1, col 12: division by zero\n".to_string()
+            )
+        );
+        assert_eq!(
+            run(
+                &config,
+                &[source("puts(1)"), source("let x = 1;\nlet = 2;")]
+            ),
+            (
+                1,
+                "1\n".to_string(),
+                "-e: line 2, col 5: expected next token to be \"Ident\", \
+                 got \"Token { kind: Assign, literal: \"=\" }\" instead\n"
+                    .to_string()
+            )
+        );
+        let (code, output, errors) = run(&config, &[Script::File("missing.monkey".into())]);
+        assert_eq!((code, output.as_str()), (1, ""));
+        assert!(
+            errors.starts_with("cannot read missing.monkey: "),
+            "{}",
+            errors
+        );
+    }
+
+    #[test]
+    fn test_run_scripts_import_errors() {
+        let dir = std::env::temp_dir().join(format!("monkey-imports-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("main.mky"), "let z = 1;\nimport \"lib/a.mky\";").unwrap();
+        fs::write(dir.join("lib/a.mky"), "let q = import(\"../main.mky\");").unwrap();
+        fs::write(dir.join("nf.mky"), "\nimport \"missing.mky\";").unwrap();
+        let canonical = dir.canonicalize().unwrap();
+        let (main, a) = (canonical.join("main.mky"), canonical.join("lib/a.mky"));
+
+        // each file is named once, with the location of the import in it
+        let (code, _, errors) = run(
+            &ReplConfig::default(),
+            &[Script::File(dir.join("main.mky"))],
+        );
+        assert_eq!(code, 2);
+        assert_eq!(
+            errors,
+            format!(
+                "{}: line 2, col 1: in {}: line 1, col 9: in {}: line 2, col 1: \
+                 circular import: {} -> {} -> {}\n",
+                dir.join("main.mky").display(),
+                a.display(),
+                main.display(),
+                a.display(),
+                main.display(),
+                a.display()
+            )
+        );
+
+        let (code, _, errors) = run(&ReplConfig::default(), &[Script::File(dir.join("nf.mky"))]);
+        assert_eq!(code, 2);
+        assert!(
+            errors.starts_with(&format!(
+                "{}: line 2, col 1: cannot import missing.mky: ",
+                dir.join("nf.mky").display()
+            )),
+            "{}",
+            errors
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_scripts_settings() {
+        let config = ReplConfig {
+            strict: true,
+            ..ReplConfig::default()
+        };
+        assert_eq!(
+            run(&config, &[source("let x = 1;"), source("let x = 2;")]).0,
+            2
+        );
+        let config = ReplConfig {
+            engine: Engine::Vm,
+            ..ReplConfig::default()
+        };
+        assert_eq!(
+            run(&config, &[source("let x = 2;"), source("x / 0")]),
+            (2, String::new(), "-e: division by zero\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_run_scripts_profile() {
+        let config = ReplConfig {
+            profile: true,
+            ..ReplConfig::default()
+        };
+        let (code, _, errors) = run(&config, &[source("let f = fn() { 1 };\nf()")]);
+        assert_eq!(code, 0);
+        let lines = errors.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "-e:");
+        assert_eq!(lines[1], "  line  statements  expressions  source");
+        assert_eq!(
+            lines[2],
+            "     1           2            2  let f = fn() { 1 };"
+        );
+        assert_eq!(lines[3], "     2           1            2  f()");
+        // the report of the function profiler follows
+        assert!(lines[4].starts_with("function"), "{}", errors);
+    }
+}
@@ -0,0 +1,193 @@
+//! Runs the `test "name" { ... }` statements of a program.
+//!
+//! ```
+//! use monkey_rust::runner::Runner;
+//! use monkey_rust::{Environment, Lexer, Parser};
+//!
+//! let source = r#"let one = 1; test "one" { assert(one == 1) }"#;
+//! let program = Parser::new(Lexer::new(source)).parse_program().unwrap();
+//! let results = Runner::run_tests(program, &mut Environment::with_builtins());
+//! assert!(results[0].passed);
+//! ```
+
+use crate::ast::{Program, Statement};
+use crate::environment::Environment;
+use crate::evaluator::Evaluator;
+use crate::object::Object;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    pub passed: bool,
+    /// The error the test failed with.
+    pub error: Option<String>,
+}
+
+pub struct Runner;
+
+impl Runner {
+    /// Evaluates the statements of `program` other than tests in `env`, then
+    /// runs each test in its own scope enclosed by `env`, in the order they
+    /// are declared. A test fails if its body evaluates to an error, such as
+    /// a failed `assert`. If the rest of the program fails, every test fails
+    /// with its error.
+    pub fn run_tests(program: Program, env: &mut Environment) -> Vec<TestResult> {
+        let arena = program.arena;
+        let (tests, statements): (Vec<_>, Vec<_>) = program
+            .statements
+            .into_iter()
+            .partition(|&stmt| matches!(arena[stmt], Statement::Test { .. }));
+
+        let setup = Evaluator::new(env).evaluate(Program {
+            arena: arena.clone(),
+            statements,
+        });
+        tests
+            .into_iter()
+            .filter_map(|stmt| match &arena[stmt] {
+                Statement::Test { name, body } => Some((name.clone(), body.statements.clone())),
+                _ => None,
+            })
+            .map(|(name, statements)| {
+                let result = match &setup {
+                    Object::Error(err) => {
+                        Object::error(format!("setup failed: {}", err.report(arena.source())))
+                    }
+                    _ => {
+                        let mut scope = Environment::new_enclosed(env);
+                        Evaluator::new(&mut scope).evaluate(Program {
+                            arena: arena.clone(),
+                            statements,
+                        })
+                    }
+                };
+                match result {
+                    Object::Error(err) => TestResult {
+                        name,
+                        passed: false,
+                        error: Some(err.report(arena.source())),
+                    },
+                    _ => TestResult {
+                        name,
+                        passed: true,
+                        error: None,
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+/// One line per test, then a summary line, e.g. `test result: FAILED. 1
+/// passed; 1 failed`.
+pub fn report(results: &[TestResult]) -> String {
+    let mut report = String::new();
+    for result in results {
+        match &result.error {
+            None => report.push_str(&format!("test {} ... ok\n", result.name)),
+            Some(err) => report.push_str(&format!("test {} ... FAILED: {}\n", result.name, err)),
+        }
+    }
+    let failed = results.iter().filter(|result| !result.passed).count();
+    report.push_str(&format!(
+        "\ntest result: {}. {} passed; {} failed\n",
+        if failed == 0 { "ok" } else { "FAILED" },
+        results.len() - failed,
+        failed
+    ));
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        environment::Environment,
+        lexer::Lexer,
+        parser::Parser,
+        runner::{report, Runner, TestResult},
+    };
+
+    fn run_tests(input: &str) -> Vec<TestResult> {
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        Runner::run_tests(program, &mut Environment::with_builtins())
+    }
+
+    fn result(name: &str, error: Option<&str>) -> TestResult {
+        TestResult {
+            name: name.to_string(),
+            passed: error.is_none(),
+            error: error.map(|err| err.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_run_tests() {
+        let results = run_tests(
+            r#"
+            let add = fn(a, b) { a + b };
+            test "addition works" { assert(add(1, 1) == 2) }
+            test "addition is broken" {
+                let sum = add(2, 2);
+                assert(sum == 5, "2 + 2 is not 5");
+                puts("unreachable");
+            }
+            "#,
+        );
+        assert_eq!(
+            results,
+            vec![
+                result("addition works", None),
+                result(
+                    "addition is broken",
+                    Some("assertion failed: 2 + 2 is not 5")
+                ),
+            ]
+        );
+        assert_eq!(
+            report(&results),
+            "test addition works ... ok\n\
+             test addition is broken ... FAILED: assertion failed: 2 + 2 is not 5\n\
+             \n\
+             test result: FAILED. 1 passed; 1 failed\n"
+        );
+    }
+
+    #[test]
+    fn test_tests_are_isolated() {
+        // Tests see every top-level binding, even one made after them, but
+        // not each other's.
+        let results = run_tests(
+            r#"
+            test "first" { let a = 1; assert(b == 2) }
+            let b = 2;
+            test "second" { a }
+            "#,
+        );
+        assert_eq!(
+            results,
+            vec![
+                result("first", None),
+                result("second", Some("identifier not found: a")),
+            ]
+        );
+        assert!(report(&results).ends_with("test result: FAILED. 1 passed; 1 failed\n"));
+    }
+
+    #[test]
+    fn test_setup_failure() {
+        let results = run_tests(r#"let a = 1 + true; test "t" { true }"#);
+        assert_eq!(
+            results,
+            vec![result(
+                "t",
+                Some("setup failed: type mismatch: INTEGER + BOOLEAN")
+            )]
+        );
+    }
+
+    #[test]
+    fn test_no_tests() {
+        assert_eq!(run_tests("let a = 1;"), vec![]);
+        assert_eq!(report(&[]), "\ntest result: ok. 0 passed; 0 failed\n");
+    }
+}
@@ -0,0 +1,279 @@
+//! Resolves names to the slots the `compiler` addresses them by.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolScope {
+    /// A top-level binding, stored in the VM's globals.
+    Global,
+    /// A parameter or `let` binding of the function being compiled, stored in
+    /// its frame.
+    Local,
+    /// A local of an enclosing function that the function being compiled
+    /// refers to.
+    Free,
+    /// The name of the function being compiled, bound by the `let` that
+    /// defines it, through which its body refers to itself.
+    Function,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub scope: SymbolScope,
+    pub index: usize,
+}
+
+/// The names defined in one scope, enclosed by the table of the scope around
+/// it, if any.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    outer: Option<Box<SymbolTable>>,
+    store: HashMap<String, Symbol>,
+    num_definitions: usize,
+    free_symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the table for a function body inside `outer`.
+    pub fn new_enclosed(outer: SymbolTable) -> Self {
+        Self {
+            outer: Some(Box::new(outer)),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the enclosing table, discarding this one.
+    pub fn into_outer(self) -> Option<SymbolTable> {
+        self.outer.map(|outer| *outer)
+    }
+
+    /// Defines `name` in this scope. Defining a name again in the same scope
+    /// reuses its slot, as rebinding it with `let` does in the evaluator.
+    pub fn define(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.store.get(name) {
+            if matches!(symbol.scope, SymbolScope::Global | SymbolScope::Local) {
+                return symbol.clone();
+            }
+        }
+        let scope = match self.outer {
+            Some(_) => SymbolScope::Local,
+            None => SymbolScope::Global,
+        };
+        let symbol = Symbol {
+            name: name.to_string(),
+            scope,
+            index: self.num_definitions,
+        };
+        self.num_definitions += 1;
+        self.store.insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    /// Binds `name` to the function whose body this scope is. Parameters and
+    /// locals of the same name shadow it.
+    pub fn define_function_name(&mut self, name: &str) -> Symbol {
+        let symbol = Symbol {
+            name: name.to_string(),
+            scope: SymbolScope::Function,
+            index: 0,
+        };
+        self.store.insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    /// Looks `name` up in this scope and then the enclosing ones. A local or
+    /// function name of an enclosing function is recorded as a free symbol of
+    /// this one.
+    pub fn resolve(&mut self, name: &str) -> Option<Symbol> {
+        if let Some(symbol) = self.store.get(name) {
+            return Some(symbol.clone());
+        }
+        let symbol = self.outer.as_mut()?.resolve(name)?;
+        match symbol.scope {
+            SymbolScope::Global => Some(symbol),
+            SymbolScope::Local | SymbolScope::Free | SymbolScope::Function => {
+                Some(self.define_free(symbol))
+            }
+        }
+    }
+
+    /// The number of slots defined in this scope, which for a function is the
+    /// number of locals its frame needs.
+    pub fn num_definitions(&self) -> usize {
+        self.num_definitions
+    }
+
+    /// The symbols of enclosing scopes that this one refers to, in the order
+    /// their free indices were given out.
+    pub fn free_symbols(&self) -> &[Symbol] {
+        &self.free_symbols
+    }
+
+    fn define_free(&mut self, original: Symbol) -> Symbol {
+        let symbol = Symbol {
+            name: original.name.clone(),
+            scope: SymbolScope::Free,
+            index: self.free_symbols.len(),
+        };
+        self.free_symbols.push(original);
+        self.store.insert(symbol.name.clone(), symbol.clone());
+        symbol
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::symbol_table::{Symbol, SymbolScope, SymbolTable};
+
+    fn symbol(name: &str, scope: SymbolScope, index: usize) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            scope,
+            index,
+        }
+    }
+
+    #[test]
+    fn test_define() {
+        let mut global = SymbolTable::new();
+        assert_eq!(global.define("a"), symbol("a", SymbolScope::Global, 0));
+        assert_eq!(global.define("b"), symbol("b", SymbolScope::Global, 1));
+
+        let mut first_local = SymbolTable::new_enclosed(global);
+        assert_eq!(first_local.define("c"), symbol("c", SymbolScope::Local, 0));
+        assert_eq!(first_local.define("d"), symbol("d", SymbolScope::Local, 1));
+
+        let mut second_local = SymbolTable::new_enclosed(first_local);
+        assert_eq!(second_local.define("e"), symbol("e", SymbolScope::Local, 0));
+        assert_eq!(second_local.define("f"), symbol("f", SymbolScope::Local, 1));
+    }
+
+    #[test]
+    fn test_define_again() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+        global.define("b");
+        assert_eq!(global.define("a"), symbol("a", SymbolScope::Global, 0));
+        assert_eq!(global.num_definitions(), 2);
+    }
+
+    #[test]
+    fn test_resolve_global() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+        global.define("b");
+
+        assert_eq!(
+            global.resolve("a"),
+            Some(symbol("a", SymbolScope::Global, 0))
+        );
+        assert_eq!(
+            global.resolve("b"),
+            Some(symbol("b", SymbolScope::Global, 1))
+        );
+        assert_eq!(global.resolve("c"), None);
+    }
+
+    #[test]
+    fn test_resolve_local() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+        global.define("b");
+
+        let mut local = SymbolTable::new_enclosed(global);
+        local.define("c");
+        local.define("d");
+
+        let expected = vec![
+            symbol("a", SymbolScope::Global, 0),
+            symbol("b", SymbolScope::Global, 1),
+            symbol("c", SymbolScope::Local, 0),
+            symbol("d", SymbolScope::Local, 1),
+        ];
+        for expect in expected {
+            assert_eq!(local.resolve(&expect.name), Some(expect));
+        }
+    }
+
+    #[test]
+    fn test_resolve_free() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+        global.define("b");
+
+        let mut first_local = SymbolTable::new_enclosed(global);
+        first_local.define("c");
+        first_local.define("d");
+
+        let mut second_local = SymbolTable::new_enclosed(first_local);
+        second_local.define("e");
+        second_local.define("f");
+
+        let expected = vec![
+            symbol("a", SymbolScope::Global, 0),
+            symbol("b", SymbolScope::Global, 1),
+            symbol("c", SymbolScope::Free, 0),
+            symbol("d", SymbolScope::Free, 1),
+            symbol("e", SymbolScope::Local, 0),
+            symbol("f", SymbolScope::Local, 1),
+        ];
+        for expect in expected {
+            assert_eq!(second_local.resolve(&expect.name), Some(expect));
+        }
+        assert_eq!(
+            second_local.free_symbols(),
+            &[
+                symbol("c", SymbolScope::Local, 0),
+                symbol("d", SymbolScope::Local, 1)
+            ]
+        );
+        assert_eq!(second_local.resolve("g"), None);
+    }
+
+    #[test]
+    fn test_function_name() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+
+        let mut local = SymbolTable::new_enclosed(global);
+        local.define_function_name("f");
+        assert_eq!(
+            local.resolve("f"),
+            Some(symbol("f", SymbolScope::Function, 0))
+        );
+        assert_eq!(local.num_definitions(), 0);
+
+        let mut inner = SymbolTable::new_enclosed(local);
+        assert_eq!(inner.resolve("f"), Some(symbol("f", SymbolScope::Free, 0)));
+        assert_eq!(
+            inner.free_symbols(),
+            &[symbol("f", SymbolScope::Function, 0)]
+        );
+
+        // A parameter or local of the same name shadows the function.
+        let mut local = inner.into_outer().unwrap();
+        assert_eq!(local.define("f"), symbol("f", SymbolScope::Local, 0));
+        assert_eq!(local.resolve("f"), Some(symbol("f", SymbolScope::Local, 0)));
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+
+        let mut local = SymbolTable::new_enclosed(global);
+        assert_eq!(local.define("a"), symbol("a", SymbolScope::Local, 0));
+        assert_eq!(local.resolve("a"), Some(symbol("a", SymbolScope::Local, 0)));
+
+        let mut global = local.into_outer().unwrap();
+        assert_eq!(
+            global.resolve("a"),
+            Some(symbol("a", SymbolScope::Global, 0))
+        );
+    }
+}
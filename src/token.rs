@@ -3,10 +3,19 @@ use crate::ast::Precedence;
 pub fn look_up_ident(ident: &str) -> TokenKind {
     match ident {
         "let" => TokenKind::Let,
+        "const" => TokenKind::Const,
         "fn" => TokenKind::Function,
+        "macro" => TokenKind::Macro,
         "if" => TokenKind::If,
         "else" => TokenKind::Else,
         "return" => TokenKind::Return,
+        "import" => TokenKind::Import,
+        "catch" => TokenKind::Catch,
+        "loop" => TokenKind::Loop,
+        "while" => TokenKind::While,
+        "break" => TokenKind::Break,
+        "continue" => TokenKind::Continue,
+        "test" => TokenKind::Test,
         "false" => TokenKind::False,
         "true" => TokenKind::True,
         _ => TokenKind::Ident,
@@ -16,15 +25,19 @@ pub fn look_up_ident(ident: &str) -> TokenKind {
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenKind {
     Eof,
+    Illegal,
 
     Ident,
     Int,
+    Float,
+    String,
 
     Assign,
     Plus,
     Minus,
     Slash,
     Aster,
+    Percent,
     Bang,
 
     Semicolon,
@@ -32,15 +45,29 @@ pub enum TokenKind {
     Lparen,
     Rbrace,
     Lbrace,
+    Rbracket,
+    Lbracket,
     Comma,
+    Colon,
+    DotDot,
+    Ellipsis,
 
     Let,
+    Const,
     Function,
+    Macro,
     True,
     False,
     If,
     Else,
     Return,
+    Import,
+    Test,
+    Catch,
+    Loop,
+    While,
+    Break,
+    Continue,
 
     GreaterThan,
     LessThan,
@@ -66,7 +93,9 @@ impl Token {
             TokenKind::Minus => Precedence::Sum,
             TokenKind::Slash => Precedence::Product,
             TokenKind::Aster => Precedence::Product,
-            TokenKind::Lparen => Precedence::Call,
+            TokenKind::Percent => Precedence::Product,
+            // Indexing binds as tightly as a call.
+            TokenKind::Lparen | TokenKind::Lbracket => Precedence::Call,
             _ => Precedence::Lowest,
         }
     }
@@ -0,0 +1,513 @@
+//! A stack machine that executes the bytecode produced by the `compiler`
+//! module.
+//!
+//! Operators are applied with the same functions as the tree-walking
+//! evaluator, so both produce the same values and error messages.
+
+use std::rc::Rc;
+
+use crate::code::{read_instruction, OpCode};
+use crate::compiler::Bytecode;
+use crate::errors::MonkeyError;
+use crate::evaluator::Evaluator;
+use crate::object::{Closure, CompiledFunction, Object};
+
+/// The most values the stack may hold at once.
+pub const STACK_SIZE: usize = 2048;
+/// The most nested calls, including the top-level program.
+pub const MAX_FRAMES: usize = 1024;
+
+// A closure being executed: the closure, the offset of its next instruction,
+// and where its arguments start on the stack.
+#[derive(Debug)]
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    base_pointer: usize,
+}
+
+#[derive(Debug)]
+pub struct VM {
+    constants: Vec<Object>,
+    stack: Vec<Object>,
+    globals: Vec<Object>,
+    frames: Vec<Frame>,
+    last_popped: Object,
+}
+
+impl VM {
+    pub fn new(bytecode: Bytecode) -> Self {
+        Self::with_globals(bytecode, vec![])
+    }
+
+    /// Creates a machine that starts with `globals`, as left by `into_globals`
+    /// after running earlier bytecode from the same `Compiler`.
+    pub fn with_globals(bytecode: Bytecode, globals: Vec<Object>) -> Self {
+        let main = Closure {
+            function: Rc::new(CompiledFunction {
+                instructions: bytecode.instructions,
+                num_locals: 0,
+                num_parameters: 0,
+            }),
+            free: vec![],
+        };
+        Self {
+            constants: bytecode.constants,
+            stack: vec![],
+            globals,
+            frames: vec![Frame {
+                closure: Rc::new(main),
+                ip: 0,
+                base_pointer: 0,
+            }],
+            last_popped: Object::Null,
+        }
+    }
+
+    pub fn into_globals(self) -> Vec<Object> {
+        self.globals
+    }
+
+    /// Runs the program to completion, returning the value of the last
+    /// expression statement or `let` binding, or of a top-level `return`.
+    pub fn run(&mut self) -> Result<Object, MonkeyError> {
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let bytes = &frame.closure.function.instructions.0;
+            if frame.ip >= bytes.len() {
+                if self.frames.len() == 1 {
+                    return Ok(self.last_popped.clone());
+                }
+                self.return_from_frame(Object::Null)?;
+                continue;
+            }
+            let (op, operands, read) = read_instruction(bytes, frame.ip)?;
+            frame.ip += read;
+
+            match op {
+                OpCode::Constant => self.push(self.constants[operands[0]].clone())?,
+                OpCode::Pop => self.last_popped = self.pop(),
+                OpCode::Add
+                | OpCode::Sub
+                | OpCode::Mul
+                | OpCode::Div
+                | OpCode::Mod
+                | OpCode::Equal
+                | OpCode::NotEqual
+                | OpCode::GreaterThan
+                | OpCode::LessThan => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let result =
+                        Evaluator::evaluate_infix_expression(operator(op).to_string(), left, right);
+                    self.push_result(result)?;
+                }
+                OpCode::Minus | OpCode::Bang => {
+                    let right = self.pop();
+                    let result =
+                        Evaluator::evaluate_prefix_expression(operator(op).to_string(), right);
+                    self.push_result(result)?;
+                }
+                OpCode::True => self.push(Object::Boolean(true))?,
+                OpCode::False => self.push(Object::Boolean(false))?,
+                OpCode::Null => self.push(Object::Null)?,
+                OpCode::JumpNotTruthy => {
+                    let condition = self.pop();
+                    if !Evaluator::is_truthy(condition) {
+                        self.frames.last_mut().unwrap().ip = operands[0];
+                    }
+                }
+                OpCode::Jump => self.frames.last_mut().unwrap().ip = operands[0],
+                OpCode::GetGlobal => {
+                    let value = self.globals.get(operands[0]).cloned();
+                    self.push(value.unwrap_or(Object::Null))?;
+                }
+                OpCode::SetGlobal => {
+                    let index = operands[0];
+                    if index >= self.globals.len() {
+                        self.globals.resize(index + 1, Object::Null);
+                    }
+                    let value = self.pop();
+                    // A `let` evaluates to the bound value, as in the evaluator.
+                    self.last_popped = value.clone();
+                    self.globals[index] = value;
+                }
+                OpCode::GetLocal => {
+                    let base_pointer = self.frames.last().unwrap().base_pointer;
+                    self.push(self.stack[base_pointer + operands[0]].clone())?;
+                }
+                OpCode::SetLocal => {
+                    let base_pointer = self.frames.last().unwrap().base_pointer;
+                    let value = self.pop();
+                    self.stack[base_pointer + operands[0]] = value;
+                }
+                OpCode::GetFree => {
+                    let closure = &self.frames.last().unwrap().closure;
+                    self.push(closure.free[operands[0]].clone())?;
+                }
+                OpCode::CurrentClosure => {
+                    let closure = self.frames.last().unwrap().closure.clone();
+                    self.push(Object::Closure(closure))?;
+                }
+                OpCode::Closure => self.push_closure(operands[0], operands[1])?,
+                OpCode::Call => self.call(operands[0])?,
+                OpCode::ReturnValue => {
+                    let value = self.pop();
+                    if self.frames.len() == 1 {
+                        return Ok(value);
+                    }
+                    self.return_from_frame(value)?;
+                }
+                OpCode::Return => {
+                    if self.frames.len() == 1 {
+                        return Ok(Object::Null);
+                    }
+                    self.return_from_frame(Object::Null)?;
+                }
+            }
+        }
+    }
+
+    // Replaces the captured values on top of the stack with a closure over
+    // them.
+    fn push_closure(&mut self, index: usize, num_free: usize) -> Result<(), MonkeyError> {
+        let function = match &self.constants[index] {
+            Object::CompiledFunction(function) => function.clone(),
+            obj => {
+                return Err(MonkeyError::MalformedBytecode(format!(
+                    "constant {} is a {}, not a function",
+                    index,
+                    obj.type_info()
+                )))
+            }
+        };
+        let free = self.stack.split_off(self.stack.len() - num_free);
+        self.push(Object::Closure(Rc::new(Closure { function, free })))
+    }
+
+    fn call(&mut self, arguments: usize) -> Result<(), MonkeyError> {
+        let base_pointer = self.stack.len() - arguments;
+        let closure = match &self.stack[base_pointer - 1] {
+            Object::Closure(closure) => closure.clone(),
+            obj => return Err(runtime(format!("not a function: {}", obj.type_info()))),
+        };
+        let function = &closure.function;
+        if self.frames.len() >= MAX_FRAMES {
+            return Err(runtime(format!(
+                "maximum call depth of {} exceeded",
+                MAX_FRAMES
+            )));
+        }
+        if arguments != function.num_parameters {
+            return Err(runtime(format!(
+                "wrong number of arguments. got={}, want={}",
+                arguments, function.num_parameters
+            )));
+        }
+        let num_locals = function.num_locals;
+        let frame = Frame {
+            closure,
+            ip: 0,
+            base_pointer,
+        };
+        if base_pointer + num_locals > STACK_SIZE {
+            return Err(runtime("stack overflow".to_string()));
+        }
+        // The locals other than the arguments start out as `null`.
+        self.stack.resize(base_pointer + num_locals, Object::Null);
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    // Pops the current frame along with its function and arguments, and pushes
+    // `value` for the caller.
+    fn return_from_frame(&mut self, value: Object) -> Result<(), MonkeyError> {
+        let frame = self.frames.pop().unwrap();
+        self.stack.truncate(frame.base_pointer - 1);
+        self.push(value)
+    }
+
+    fn push_result(&mut self, result: Object) -> Result<(), MonkeyError> {
+        match result {
+            Object::Error(err) => Err(MonkeyError::Runtime(err.message)),
+            obj => self.push(obj),
+        }
+    }
+
+    fn push(&mut self, obj: Object) -> Result<(), MonkeyError> {
+        if self.stack.len() >= STACK_SIZE {
+            return Err(runtime("stack overflow".to_string()));
+        }
+        self.stack.push(obj);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().expect("stack underflow")
+    }
+}
+
+fn operator(op: OpCode) -> &'static str {
+    match op {
+        OpCode::Add => "+",
+        OpCode::Sub | OpCode::Minus => "-",
+        OpCode::Mul => "*",
+        OpCode::Div => "/",
+        OpCode::Mod => "%",
+        OpCode::Equal => "==",
+        OpCode::NotEqual => "!=",
+        OpCode::GreaterThan => ">",
+        OpCode::LessThan => "<",
+        OpCode::Bang => "!",
+        _ => unreachable!("{:?} is not an operator", op),
+    }
+}
+
+fn runtime(message: String) -> MonkeyError {
+    MonkeyError::Runtime(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compiler::Compiler,
+        errors::MonkeyError,
+        lexer::Lexer,
+        object::Object,
+        parser::Parser,
+        vm::{MAX_FRAMES, VM},
+    };
+
+    fn run(input: &str) -> Result<Object, MonkeyError> {
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        let mut compiler = Compiler::new();
+        compiler.compile(&program)?;
+        VM::new(compiler.bytecode()).run()
+    }
+
+    #[test]
+    fn test_run() {
+        let tests = vec![
+            ("", Object::Null),
+            ("1; 2", Object::Int(2)),
+            ("(1 + 2) * 3 - 4 / 2 % 3", Object::Int(7)),
+            ("-5 < 3 == !false", Object::Boolean(true)),
+            ("if (1 > 2) { 10 }", Object::Null),
+            ("if (1 < 2) { 10 } else { 20 }", Object::Int(10)),
+            ("let a = 2; let b = a * a;", Object::Int(4)),
+            ("let f = fn() { 1; 2 }; f() + f()", Object::Int(4)),
+            ("let f = fn() { }; f()", Object::Null),
+            ("let f = fn() { return 1; 2 }; f()", Object::Int(1)),
+            (
+                "let a = 1; let f = fn() { a * 10 }; let a = 2; f()",
+                Object::Int(20),
+            ),
+            ("return 3; 4", Object::Int(3)),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(run(input).unwrap(), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_locals() {
+        let tests = vec![
+            ("let f = fn(a, b) { a - b }; f(5, 3)", Object::Int(2)),
+            ("let f = fn() { let a = 1; let b = 2; a + b }; f()", Object::Int(3)),
+            ("let f = fn(n) { let n = n * 2; n }; f(4)", Object::Int(8)),
+            ("let f = fn() { let a = 1; }; f()", Object::Int(1)),
+            // Parameters and locals shadow globals without changing them.
+            ("let a = 1; let f = fn(a) { a * 10 }; f(2) + a", Object::Int(21)),
+            ("let a = 1; let f = fn() { let a = 2; a }; f() * 10 + a", Object::Int(21)),
+            // Each call gets its own locals.
+            (
+                "let g = fn(x) { let y = x + 1; y }; let f = fn(x) { let y = g(x) * 2; x + y }; f(1)",
+                Object::Int(5),
+            ),
+            (
+                "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(10)",
+                Object::Int(3628800),
+            ),
+            ("let f = fn(a) { let b = 2; b }; f(1)", Object::Int(2)),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(run(input).unwrap(), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_closures() {
+        let tests = vec![
+            (
+                "let newClosure = fn(a) { fn() { a; }; }; let closure = newClosure(99); closure();",
+                99,
+            ),
+            (
+                "let newAdder = fn(a, b) { fn(c) { a + b + c }; }; let adder = newAdder(1, 2); adder(8);",
+                11,
+            ),
+            (
+                "let newAdder = fn(a, b) { let c = a + b; fn(d) { c + d }; }; let adder = newAdder(1, 2); adder(8);",
+                11,
+            ),
+            (
+                r#"
+                let newAdderOuter = fn(a, b) {
+                    let c = a + b;
+                    fn(d) {
+                        let e = d + c;
+                        fn(f) { e + f; };
+                    };
+                };
+                let newAdderInner = newAdderOuter(1, 2);
+                let adder = newAdderInner(3);
+                adder(8);
+                "#,
+                14,
+            ),
+            (
+                r#"
+                let a = 1;
+                let newAdderOuter = fn(b) {
+                    fn(c) {
+                        fn(d) { a + b + c + d };
+                    };
+                };
+                let newAdderInner = newAdderOuter(2);
+                let adder = newAdderInner(3);
+                adder(8);
+                "#,
+                14,
+            ),
+            (
+                r#"
+                let newClosure = fn(a, b) {
+                    let one = fn() { a; };
+                    let two = fn() { b; };
+                    fn() { one() + two(); };
+                };
+                let closure = newClosure(9, 90);
+                closure();
+                "#,
+                99,
+            ),
+            // Each closure keeps its own captured values.
+            (
+                "let make = fn(n) { fn() { n } }; let a = make(1); let b = make(2); a() * 10 + b()",
+                12,
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(run(input).unwrap(), Object::Int(expect), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_recursive_closures() {
+        let tests = vec![
+            (
+                "let countDown = fn(x) { if (x == 0) { return 0; } else { countDown(x - 1); } }; countDown(1);",
+                0,
+            ),
+            (
+                r#"
+                let countDown = fn(x) { if (x == 0) { return 0; } else { countDown(x - 1); } };
+                let wrapper = fn() { countDown(1); };
+                wrapper();
+                "#,
+                0,
+            ),
+            (
+                r#"
+                let wrapper = fn() {
+                    let countDown = fn(x) { if (x == 0) { return 0; } else { countDown(x - 1); } };
+                    countDown(1);
+                };
+                wrapper();
+                "#,
+                0,
+            ),
+            (
+                r#"
+                let fibonacci = fn(x) {
+                    if (x == 0) {
+                        return 0;
+                    } else {
+                        if (x == 1) {
+                            return 1;
+                        } else {
+                            fibonacci(x - 1) + fibonacci(x - 2);
+                        }
+                    }
+                };
+                fibonacci(15);
+                "#,
+                610,
+            ),
+            // A nested function refers to its enclosing recursive function.
+            (
+                r#"
+                let wrapper = fn(n) {
+                    let sum = fn(x) { if (x == 0) { 0 } else { let add = fn() { sum(x - 1) }; x + add() } };
+                    sum(n);
+                };
+                wrapper(4);
+                "#,
+                10,
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(run(input).unwrap(), Object::Int(expect), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_run_errors() {
+        let tests = vec![
+            ("1 + true", "type mismatch: INTEGER + BOOLEAN"),
+            ("-true", "unknown operator: -BOOLEAN"),
+            ("5 / 0", "division by zero"),
+            ("1(2)", "not a function: INTEGER"),
+            (
+                "let f = fn(a, b) { a }; f(1)",
+                "wrong number of arguments. got=1, want=2",
+            ),
+            (
+                "let f = fn(a) { a }; f(1, 2)",
+                "wrong number of arguments. got=2, want=1",
+            ),
+            ("if (true + 1) { 1 }", "type mismatch: BOOLEAN + INTEGER"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(run(input).unwrap_err().to_string(), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_call_depth() {
+        // The body refers to the global `f`, which is rebound to itself.
+        let err = run("let f = fn() { 0 }; let f = fn() { f() }; f()").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("maximum call depth of {} exceeded", MAX_FRAMES)
+        );
+    }
+
+    #[test]
+    fn test_with_globals() {
+        let mut compiler = Compiler::new();
+        let mut globals = vec![];
+        for (input, expect) in [("let a = 2;", 2), ("let b = a + 1;", 3), ("a * b", 6)] {
+            let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+            compiler.compile(&program).unwrap();
+            let mut vm = VM::with_globals(compiler.take_bytecode(), globals);
+            assert_eq!(vm.run().unwrap(), Object::Int(expect), "{}", input);
+            globals = vm.into_globals();
+        }
+    }
+}
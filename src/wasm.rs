@@ -0,0 +1,177 @@
+//! A JavaScript API for running Monkey in the browser, enabled by the `wasm`
+//! feature. Build the module with
+//! `wasm-pack build --target web -- --features wasm`.
+//!
+//! ```js
+//! const monkey = new MonkeyWasm();
+//! monkey.onOutput((line) => console.log(line));
+//! monkey.eval('let x = 2; puts(x * 3)');
+//! ```
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+
+use crate::interpreter::Interpreter;
+
+/// Receives each line written by `puts`, without its newline.
+pub type Sink = Box<dyn FnMut(&str)>;
+
+// Buffers output until a newline, then hands the complete line to the sink.
+// Without a sink, output is discarded.
+#[derive(Default)]
+struct LineWriter {
+    sink: Option<Sink>,
+    buffer: Vec<u8>,
+}
+
+impl Write for LineWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            if let Some(sink) = &mut self.sink {
+                sink(&String::from_utf8_lossy(&line[..end]));
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An interpreter whose bindings persist across calls to `eval`, like the
+/// REPL.
+#[wasm_bindgen]
+pub struct MonkeyWasm {
+    interpreter: Interpreter,
+    output: Rc<RefCell<LineWriter>>,
+}
+
+#[wasm_bindgen]
+impl MonkeyWasm {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        let output = Rc::new(RefCell::new(LineWriter::default()));
+        Self {
+            interpreter: interpreter(&output),
+            output,
+        }
+    }
+
+    /// Evaluates `source`, returning the value of its last statement or the
+    /// text of the error it failed with.
+    pub fn eval(&mut self, source: &str) -> String {
+        match self.interpreter.eval(source) {
+            Ok(obj) => obj.to_string(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    /// Forgets every binding made so far. The output callback is kept.
+    pub fn reset(&mut self) {
+        self.interpreter = interpreter(&self.output);
+    }
+
+    /// Calls `callback` with each line written by `puts`.
+    #[wasm_bindgen(js_name = onOutput)]
+    pub fn on_output(&mut self, callback: js_sys::Function) {
+        self.set_sink(move |line| {
+            // Output has nowhere else to go if the callback throws.
+            let _ = callback.call1(&JsValue::NULL, &JsValue::from_str(line));
+        });
+    }
+}
+
+impl MonkeyWasm {
+    /// Sends each line written by `puts` to `sink`, as `onOutput` does for a
+    /// JavaScript callback.
+    pub fn set_sink<F>(&mut self, sink: F)
+    where
+        F: FnMut(&str) + 'static,
+    {
+        self.output.borrow_mut().sink = Some(Box::new(sink));
+    }
+}
+
+impl Default for MonkeyWasm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn interpreter(output: &Rc<RefCell<LineWriter>>) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(output.clone());
+    interpreter
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::wasm::MonkeyWasm;
+
+    fn collect_output(monkey: &mut MonkeyWasm) -> Rc<RefCell<Vec<String>>> {
+        let lines = Rc::new(RefCell::new(vec![]));
+        let sink = lines.clone();
+        monkey.set_sink(move |line| sink.borrow_mut().push(line.to_string()));
+        lines
+    }
+
+    #[test]
+    fn test_eval() {
+        let mut monkey = MonkeyWasm::new();
+        let tests = vec![
+            ("let add = fn(a, b) { a + b };", "fn (a, b) { (a + b) }"),
+            ("add(1, 2)", "3"),
+            ("[1, add(1, 1)]", "[1, 2]"),
+            ("1 / 0", "line 1, col 3: division by zero"),
+            (
+                "let = 1",
+                r#"expected next token to be "Ident", got "Token { kind: Assign, literal: "=" }" instead"#,
+            ),
+            ("missing", "line 1, col 1: identifier not found: missing"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(monkey.eval(input), expect, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut monkey = MonkeyWasm::new();
+        monkey.eval("let a = 1;");
+        assert_eq!(monkey.eval("a"), "1");
+        monkey.reset();
+        assert_eq!(monkey.eval("a"), "line 1, col 1: identifier not found: a");
+    }
+
+    #[test]
+    fn test_sink() {
+        let mut monkey = MonkeyWasm::new();
+        let lines = collect_output(&mut monkey);
+        assert_eq!(monkey.eval(r#"puts("a", 1); puts([2])"#), "null");
+        assert_eq!(*lines.borrow(), vec!["a", "1", "[2]"]);
+
+        // The sink survives a reset.
+        monkey.reset();
+        monkey.eval(r#"puts("b")"#);
+        assert_eq!(*lines.borrow(), vec!["a", "1", "[2]", "b"]);
+    }
+
+    #[test]
+    fn test_output_without_sink_is_discarded() {
+        let mut monkey = MonkeyWasm::new();
+        assert_eq!(monkey.eval(r#"puts("unseen")"#), "null");
+        let lines = collect_output(&mut monkey);
+        monkey.eval(r#"puts("seen")"#);
+        assert_eq!(*lines.borrow(), vec!["seen"]);
+    }
+}
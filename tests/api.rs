@@ -0,0 +1,38 @@
+use monkey_rust::{eval, eval_str, Environment, MonkeyError, Object};
+
+#[test]
+fn test_eval_str() {
+    assert_eq!(eval_str("1 + 2 * 3").unwrap(), Object::Int(7));
+    assert_eq!(
+        eval_str(r#""foo" + "bar""#).unwrap(),
+        Object::String("foobar".into())
+    );
+    assert_eq!(eval_str("").unwrap(), Object::Null);
+}
+
+#[test]
+fn test_eval_keeps_bindings() {
+    let mut env = Environment::with_builtins();
+    eval("let x = 5;", &mut env).unwrap();
+    eval("let double = fn(n) { n * 2 };", &mut env).unwrap();
+    assert_eq!(eval("double(x)", &mut env).unwrap(), Object::Int(10));
+    assert!(env.contains("double"));
+}
+
+#[test]
+fn test_eval_errors() {
+    match eval_str("let = 5;") {
+        Err(MonkeyError::UnexpectedToken(..)) => (),
+        result => panic!("expected a parse error. got={:?}", result),
+    }
+    match eval_str("99999999999999999999") {
+        Err(MonkeyError::InvalidInteger(literal)) => assert_eq!(literal, "99999999999999999999"),
+        result => panic!("expected an integer error. got={:?}", result),
+    }
+    match eval_str("foobar") {
+        Err(MonkeyError::Runtime(message)) => {
+            assert_eq!(message, "line 1, col 1: identifier not found: foobar")
+        }
+        result => panic!("expected a runtime error. got={:?}", result),
+    }
+}
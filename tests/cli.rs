@@ -0,0 +1,166 @@
+//! Runs the `monkey-rust` binary on the scripts in `tests/cli`.
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+// Runs the binary with `args` and `stdin` as its standard input.
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_monkey-rust"))
+        .args(args)
+        .current_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cli"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[test]
+fn test_script() {
+    let output = run(&["hello.monkey"], "");
+    assert_eq!(output.status.code(), Some(0));
+    // the value the script evaluates to is not printed
+    assert_eq!(text(&output.stdout), "hello, world\n");
+    assert_eq!(text(&output.stderr), "");
+}
+
+#[test]
+fn test_parse_error() {
+    let output = run(&["parse_error.monkey"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(text(&output.stdout), "");
+    assert_eq!(
+        text(&output.stderr),
+        "parse_error.monkey: line 3, col 5: expected next token to be \"Ident\", got \"Token { kind: Assign, literal: \"=\" }\" instead\n"
+    );
+}
+
+#[test]
+fn test_runtime_error() {
+    let output = run(&["runtime_error.monkey"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(text(&output.stdout), "before\n");
+    assert_eq!(
+        text(&output.stderr),
+        "runtime_error.monkey: line 2, col 19: division by zero\n"
+    );
+}
+
+#[test]
+fn test_missing_script() {
+    let output = run(&["missing.monkey"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(text(&output.stdout), "");
+    assert!(
+        text(&output.stderr).starts_with("cannot read missing.monkey: "),
+        "{}",
+        text(&output.stderr)
+    );
+}
+
+#[test]
+fn test_repl_without_script() {
+    // input that is not a terminal gets no banner or prompts, and the null
+    // value of `puts` is not printed
+    let output = run(&[], "let x = 1; x\nputs(x + 2)\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(text(&output.stdout), "1\n3\n");
+}
+
+#[test]
+fn test_eval() {
+    let output = run(&["-e", "let x = 2;", "--eval", "puts(x * 3)"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(text(&output.stdout), "6\n");
+
+    let output = run(&["--strict", "-e", "let x = 1;", "-e", "let x = 2;"], "");
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = run(&["-e", "let x = 1;\nx + y"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        text(&output.stderr),
+        "-e: line 2, col 5: identifier not found: y\n"
+    );
+
+    // the vm does not know where an error happened
+    let output = run(&["--engine=vm", "-e", "1 / 0"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(text(&output.stderr), "-e: division by zero\n");
+}
+
+#[test]
+fn test_bad_arguments() {
+    let output = run(&["-e"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(text(&output.stderr), "-e requires a source\n");
+
+    let output = run(&["--frobnicate"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(text(&output.stderr), "unknown option: --frobnicate\n");
+}
+
+#[test]
+fn test_modes_with_scripts() {
+    let output = run(&["--coverage", "hello.monkey"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        text(&output.stdout),
+        "hello, world\n     1 | let greet = fn(name) { \"hello, \" + name };\n     1 | puts(greet(\"world\"));\n     1 | 42\n"
+    );
+
+    let output = run(&["--dump-bytecode", "-e", "1 + 2"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        text(&output.stdout).starts_with("0000 OpConstant 0\n0003 OpConstant 1\n0006 OpAdd\n"),
+        "{}",
+        text(&output.stdout)
+    );
+
+    // without a script, standard input is used
+    let output = run(&["--dump-bytecode"], "1 + 2");
+    assert!(text(&output.stdout).starts_with("0000 OpConstant 0\n"));
+
+    let tests = [
+        (
+            &["--test", "hello.monkey", "hello.monkey"][..],
+            "--test does not take scripts\n",
+        ),
+        (
+            &["--coverage", "hello.monkey", "-e", "1"][..],
+            "--coverage and --dump-bytecode take a single script\n",
+        ),
+        (
+            &["--coverage", "--dump-bytecode"][..],
+            "--test, --coverage and --dump-bytecode cannot be combined\n",
+        ),
+    ];
+    for (args, expect) in tests {
+        let output = run(args, "");
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert_eq!(text(&output.stdout), "");
+        assert_eq!(text(&output.stderr), expect);
+    }
+}
+
+#[test]
+fn test_runaway_eval() {
+    let output = run(&["-e", r#"let s = "eval(s)"; eval(s)"#], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        text(&output.stderr),
+        "-e: maximum recursion depth exceeded\n"
+    );
+}
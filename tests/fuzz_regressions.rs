@@ -0,0 +1,45 @@
+//! Replays inputs that once crashed the `parse` fuzz target, so regressions are
+//! caught without running the fuzzer.
+
+use std::fs;
+use std::path::Path;
+
+use monkey_rust::token::TokenKind;
+use monkey_rust::{Lexer, MonkeyError, Parser};
+
+#[test]
+fn test_fuzz_regressions() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/regressions");
+    let mut entries = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert!(!entries.is_empty(), "no inputs in {}", dir.display());
+
+    for path in entries {
+        let data = fs::read(&path).unwrap();
+        let source = String::from_utf8_lossy(&data);
+
+        let mut lexer = Lexer::new(&source);
+        let mut count = 0;
+        while lexer.next_token().kind != TokenKind::Eof {
+            count += 1;
+            assert!(
+                count <= source.len(),
+                "{}: lexer did not reach Eof",
+                path.display()
+            );
+        }
+
+        let mut parser = Parser::new(Lexer::new(&source));
+        if let Err(err) = parser.parse_program() {
+            assert!(
+                err.downcast_ref::<MonkeyError>().is_some(),
+                "{}: parse failed with a foreign error: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+}
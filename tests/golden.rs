@@ -0,0 +1,89 @@
+//! Runs each script in `tests/scripts` and compares what it prints, followed
+//! by its result, with the `.expected` file next to it.
+//!
+//! The last line of an expected file is `=> ` and the value the script
+//! evaluated to, or `error: ` and the message it failed with. To add a case,
+//! write the script and run `BLESS=1 cargo test --test golden`, which writes
+//! the expected files from the current output; check them before committing.
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use monkey_rust::Interpreter;
+
+// What `path` prints and evaluates to, in the format of the expected files.
+fn run(path: &Path) -> String {
+    let source = fs::read_to_string(path).unwrap();
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(output.clone());
+    interpreter.set_file(Some(path.to_path_buf()));
+    let result = interpreter.eval(&source);
+
+    let mut actual = String::from_utf8(output.borrow().clone()).unwrap();
+    match result {
+        Ok(obj) => actual += &format!("=> {}\n", obj),
+        Err(err) => actual += &format!("error: {}\n", err),
+    }
+    actual
+}
+
+// The lines of `expect` and `actual` from the first one they differ on.
+fn diff(expect: &str, actual: &str) -> String {
+    let (expect, actual) = (
+        expect.lines().collect::<Vec<_>>(),
+        actual.lines().collect::<Vec<_>>(),
+    );
+    let same = expect
+        .iter()
+        .zip(&actual)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut diff = format!("first difference on line {}:\n", same + 1);
+    for line in &expect[same..] {
+        diff += &format!("-{}\n", line);
+    }
+    for line in &actual[same..] {
+        diff += &format!("+{}\n", line);
+    }
+    diff
+}
+
+#[test]
+fn test_golden_scripts() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts");
+    let mut scripts = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "monkey"))
+        .collect::<Vec<PathBuf>>();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no scripts in {}", dir.display());
+
+    let bless = env::var_os("BLESS").is_some();
+    let mut failures = vec![];
+    for script in scripts {
+        let actual = run(&script);
+        let expected = script.with_extension("expected");
+        if bless {
+            fs::write(&expected, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&expected) {
+            Ok(expect) if expect == actual => (),
+            Ok(expect) => {
+                failures.push(format!("{}: {}", script.display(), diff(&expect, &actual)))
+            }
+            Err(err) => failures.push(format!("{}: {}\n", expected.display(), err)),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\nrun with BLESS=1 to update the expected files",
+        failures.concat()
+    );
+}
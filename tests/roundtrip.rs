@@ -0,0 +1,272 @@
+use std::rc::Rc;
+
+use monkey_rust::ast::{Arena, BlockStatement, ExprId, Expression, Program, Span, Statement};
+use monkey_rust::interner::Symbol;
+use monkey_rust::{Lexer, Parser};
+use proptest::prelude::*;
+
+fn parse(source: &str) -> Program {
+    let mut parser = Parser::new(Lexer::new(source));
+    parser
+        .parse_program()
+        .unwrap_or_else(|err| panic!("failed to parse {:?}: {}", source, err))
+}
+
+#[test]
+fn test_corpus_roundtrip() {
+    let corpus = vec![
+        "let x = 5; let y = x * 2 + 1; y",
+        "a\n(b)",
+        "a\n[b]",
+        "xs[-1] + f(x)[0][i * 2]",
+        "a; -b",
+        "if (x) { y } else { z }",
+        "if (x < y) { return x; } else { let z = y; z }",
+        "if (!ok) { 1 }; 2",
+        "let add = fn(x, y) { x + y; }; add(1, add(2, 3))",
+        "fn() { }()",
+        r#"let s = "hello world"; puts(s, "!")"#,
+        r#"{"a": 1, true: [1, 2], 3: {"b": fn(x) { x }}}"#,
+        "{}",
+        "let v = { let a = 1; a + 1 }; { v }",
+        "{ {} }",
+        "[[], [1, [2]], -3 % 2]",
+        "1.5 * 2.0 + 0.25",
+        r#"import "lib/util.monkey"; util(1)"#,
+        r#"let util = import("lib/util.monkey"); util["f"](1)"#,
+        "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(10) != 1",
+        r#"test "adds" { let a = 1; assert(a + 1 == 2, "sum") } (a)"#,
+        "try { 1 / 0 } catch (e) { error(e) }; try(f)",
+        "let [a, [b, c], []] = [1, [2, 3], []]; a + b",
+        r#"let {a, "b": [c, {d}]} = x; a + c"#,
+        r#""hello"[1..3] + s[..-1] + s[2..][0] + [1, 2][..]"#,
+        "f(...args, [1, ...rest, 4])",
+        "const PI = 3.14; let area = fn(r) { const TWO = 2; PI * r * r }",
+        "let x = loop { if (x) { continue } else { break } }; loop { break x + 1; }",
+    ];
+
+    for source in corpus {
+        let program = parse(source);
+        let printed = program.to_string();
+        assert_eq!(
+            parse(&printed),
+            program,
+            "{:?} printed as {:?}",
+            source,
+            printed
+        );
+    }
+}
+
+fn ident() -> impl Strategy<Value = Symbol> {
+    // `try` is an identifier except before a block, which a generated
+    // statement may be followed by.
+    "[a-z][a-z_]{0,4}"
+        .prop_filter("keywords are not identifiers", |name| {
+            !matches!(
+                name.as_str(),
+                "let"
+                    | "const"
+                    | "fn"
+                    | "macro"
+                    | "if"
+                    | "else"
+                    | "return"
+                    | "true"
+                    | "false"
+                    | "import"
+                    | "test"
+                    | "try"
+                    | "catch"
+                    | "loop"
+                    | "break"
+                    | "continue"
+            )
+        })
+        .prop_map(|name| Symbol::intern(&name))
+}
+
+// A generated expression with the arena its nodes live in. Fragments are
+// combined by copying their nodes into the arena of the new parent.
+#[derive(Debug, Clone)]
+struct Fragment {
+    arena: Arena,
+    root: ExprId,
+}
+
+#[derive(Debug, Clone)]
+struct Block {
+    arena: Arena,
+    block: BlockStatement,
+}
+
+fn build(make: impl FnOnce(&mut Arena) -> Expression) -> Fragment {
+    let mut arena = Arena::new();
+    let expr = make(&mut arena);
+    let root = arena.alloc_expr(expr);
+    Fragment { arena, root }
+}
+
+fn graft(arena: &mut Arena, fragment: &Fragment) -> ExprId {
+    arena.copy_expr(&fragment.arena, fragment.root)
+}
+
+fn graft_block(arena: &mut Arena, block: &Block) -> BlockStatement {
+    arena.copy_block(&block.arena, &block.block)
+}
+
+// A block of the one statement `make` returns.
+fn statement(make: impl FnOnce(&mut Arena) -> Statement) -> Block {
+    let mut arena = Arena::new();
+    let stmt = make(&mut arena);
+    let statements = vec![arena.alloc_stmt(stmt)];
+    Block {
+        arena,
+        block: BlockStatement { statements },
+    }
+}
+
+fn block(expr: BoxedStrategy<Fragment>, min: usize) -> impl Strategy<Value = Block> {
+    let stmt = prop_oneof![
+        (ident(), expr.clone()).prop_map(|(name, value)| statement(|arena| Statement::Let {
+            ident: arena.alloc_expr(Expression::Ident(name, Span::default())),
+            value: graft(arena, &value),
+        })),
+        (ident(), expr.clone()).prop_map(|(name, value)| statement(|arena| Statement::Const {
+            ident: arena.alloc_expr(Expression::Ident(name, Span::default())),
+            value: graft(arena, &value),
+        })),
+        expr.clone()
+            .prop_map(|value| statement(|arena| Statement::Return(graft(arena, &value)))),
+        expr.clone()
+            .prop_map(|value| statement(|arena| Statement::Expression(graft(arena, &value)))),
+        "[a-z/.]{1,8}".prop_map(|path| statement(|_| Statement::Import(path))),
+        prop::option::of(expr).prop_map(|value| statement(|arena| {
+            Statement::Break(value.map(|value| graft(arena, &value)))
+        })),
+        Just(statement(|_| Statement::Continue)),
+    ];
+    prop::collection::vec(stmt, min..3).prop_map(|stmts| {
+        let mut arena = Arena::new();
+        let statements = stmts
+            .iter()
+            .flat_map(|stmt| graft_block(&mut arena, stmt).statements)
+            .collect();
+        Block {
+            arena,
+            block: BlockStatement { statements },
+        }
+    })
+}
+
+fn expression() -> BoxedStrategy<Fragment> {
+    let leaf = prop_oneof![
+        ident().prop_map(|name| build(|_| Expression::Ident(name, Span::default()))),
+        (0..1000i64).prop_map(|value| build(|_| Expression::Int(value))),
+        (0.0..1000.0f64).prop_map(|value| build(|_| Expression::Float(value))),
+        "[a-z ]{0,5}".prop_map(|value| build(|_| Expression::String(value))),
+        any::<bool>().prop_map(|value| build(|_| Expression::Boolean(value))),
+    ];
+    leaf.prop_recursive(3, 24, 3, |inner| {
+        let op = prop::sample::select(vec!["+", "-", "*", "/", "%", "<", ">", "==", "!="]);
+        prop_oneof![
+            (prop::sample::select(vec!["!", "-"]), inner.clone()).prop_map(|(op, right)| {
+                build(|arena| Expression::Prefix {
+                    op: op.to_string(),
+                    right: graft(arena, &right),
+                })
+            }),
+            (inner.clone(), op, inner.clone()).prop_map(|(left, op, right)| build(|arena| {
+                Expression::Infix {
+                    left: graft(arena, &left),
+                    op: op.to_string(),
+                    right: graft(arena, &right),
+                    span: Span::default(),
+                }
+            })),
+            (
+                inner.clone(),
+                block(inner.clone(), 0),
+                prop::option::of(block(inner.clone(), 0))
+            )
+                .prop_map(|(condition, consequence, alternative)| build(|arena| {
+                    Expression::If {
+                        condition: graft(arena, &condition),
+                        consequence: graft_block(arena, &consequence),
+                        alternative: alternative.map(|block| graft_block(arena, &block)),
+                    }
+                })),
+            (
+                prop::collection::vec(ident(), 0..3),
+                block(inner.clone(), 0)
+            )
+                .prop_map(|(parameters, body)| build(|arena| Expression::Function {
+                    parameters,
+                    body: graft_block(arena, &body),
+                })),
+            (inner.clone(), prop::collection::vec(inner.clone(), 0..3)).prop_map(
+                |(function, arguments)| build(|arena| Expression::Call {
+                    function: graft(arena, &function),
+                    arguments: arguments.iter().map(|arg| graft(arena, arg)).collect(),
+                })
+            ),
+            (inner.clone(), inner.clone()).prop_map(|(left, index)| build(|arena| {
+                Expression::Index {
+                    left: graft(arena, &left),
+                    index: graft(arena, &index),
+                }
+            })),
+            (
+                inner.clone(),
+                prop::option::of(inner.clone()),
+                prop::option::of(inner.clone())
+            )
+                .prop_map(|(target, start, end)| build(|arena| Expression::Slice {
+                    target: graft(arena, &target),
+                    start: start.map(|start| graft(arena, &start)),
+                    end: end.map(|end| graft(arena, &end)),
+                })),
+            prop::collection::vec(inner.clone(), 0..3).prop_map(|elements| build(|arena| {
+                Expression::Array(elements.iter().map(|e| graft(arena, e)).collect())
+            })),
+            prop::collection::vec((inner.clone(), inner.clone()), 0..3).prop_map(|pairs| {
+                build(|arena| {
+                    Expression::Hash(
+                        pairs
+                            .iter()
+                            .map(|(key, value)| (graft(arena, key), graft(arena, value)))
+                            .collect(),
+                    )
+                })
+            }),
+            (block(inner.clone(), 0), ident(), block(inner.clone(), 0)).prop_map(
+                |(body, error, handler)| build(|arena| Expression::Try {
+                    body: graft_block(arena, &body),
+                    error,
+                    handler: graft_block(arena, &handler),
+                })
+            ),
+            block(inner.clone(), 0)
+                .prop_map(|block| build(|arena| Expression::Loop(graft_block(arena, &block)))),
+            // `{}` is an empty hash, so a block expression has a statement
+            block(inner, 1)
+                .prop_map(|block| build(|arena| Expression::Block(graft_block(arena, &block)))),
+        ]
+    })
+    .boxed()
+}
+
+fn program() -> impl Strategy<Value = Program> {
+    block(expression(), 0).prop_map(|block| Program {
+        arena: Rc::new(block.arena),
+        statements: block.block.statements,
+    })
+}
+
+proptest! {
+    #[test]
+    fn test_generated_roundtrip(program in program()) {
+        let printed = program.to_string();
+        prop_assert_eq!(parse(&printed), program, "printed as {:?}", printed);
+    }
+}
@@ -0,0 +1,57 @@
+//! Checks that the library builds for the browser with the `wasm` feature, so
+//! that nothing it depends on is missing from `wasm32-unknown-unknown`.
+//!
+//! Skipped when that target is not installed
+//! (`rustup target add wasm32-unknown-unknown`).
+#![cfg(feature = "wasm")]
+
+use std::path::Path;
+use std::process::Command;
+
+const TARGET: &str = "wasm32-unknown-unknown";
+
+fn target_installed() -> bool {
+    let output = match Command::new("rustc").args(["--print", "sysroot"]).output() {
+        Ok(output) => output,
+        Err(_) => return false,
+    };
+    let sysroot = String::from_utf8_lossy(&output.stdout);
+    Path::new(sysroot.trim())
+        .join("lib/rustlib")
+        .join(TARGET)
+        .exists()
+}
+
+#[test]
+fn test_wasm32_build() {
+    if !target_installed() {
+        eprintln!("skipping: the {} target is not installed", TARGET);
+        return;
+    }
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    // A separate target directory keeps this build from waiting on the lock
+    // held by the `cargo test` that runs it.
+    let status = Command::new(env!("CARGO"))
+        .current_dir(manifest_dir)
+        .env(
+            "CARGO_TARGET_DIR",
+            Path::new(manifest_dir).join("target/wasm32-check"),
+        )
+        // The default line editor needs a terminal, which the browser lacks.
+        .args([
+            "build",
+            "--lib",
+            "--no-default-features",
+            "--features",
+            "wasm",
+            "--target",
+            TARGET,
+        ])
+        .status()
+        .expect("failed to run cargo");
+    assert!(
+        status.success(),
+        "the library does not build for {}",
+        TARGET
+    );
+}